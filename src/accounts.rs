@@ -1,12 +1,21 @@
 use std::{
     cmp::min,
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Bound,
+    str::FromStr,
 };
 
 use serde::Serialize;
 
 use crate::{
-    transactions::{TransactionRecord, TransactionText},
+    dates::{Date, DateBasis},
+    ledger::{LedgerEvent, Projection},
+    memo::{sanitize, DEFAULT_MEMO_LENGTH},
+    pseudonym::ClientLabel,
+    seen::SeenFilter,
+    transactions::{
+        ClientId, LifecycleEvent, TransactionRecord, TransactionText, TxId, TxIdFormat,
+    },
     Money,
 };
 
@@ -17,80 +26,751 @@ pub enum AccountStatus {
     client account then we transition to an error state -- we don't actually know
     what the status of the account is.
     */
-    Unknown(TransactionText),
+    Unknown(Box<TransactionText>),
     Active,
     Locked,
+
+    /*
+    The client's data has been erased.  We keep the account around so the client id can't be
+    reused, but it holds no funds and never shows up in any output.
+    */
+    Erased,
 }
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct Account {
-    client_id: u16,
+    client_id: ClientId,
     available: Money,
     held: Money,
     status: AccountStatus,
+
+    /*
+    Whether anything has been applied to this account since the database last cleared changes,
+    so an incremental run can report only the accounts it touched.
+    */
+    changed: bool,
+}
+
+impl AccountStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountStatus::Unknown(text) => "unknown",
+            AccountStatus::Active => "active",
+            AccountStatus::Locked => "locked",
+            AccountStatus::Erased => "erased",
+        }
+    }
 }
 
 #[derive(Serialize)]
 pub struct AccountSummary {
-    client_id: u16,
-    available: String,
-    held: String,
-    total: String,
-    locked: bool,
+    pub client_id: ClientLabel,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
 }
 
-impl Into<AccountSummary> for &Account {
-    fn into(self) -> AccountSummary {
+impl From<&Account> for AccountSummary {
+    fn from(account: &Account) -> AccountSummary {
         AccountSummary {
-            client_id: self.client_id,
-            available: self.available.to_string(),
-            held: self.held.to_string(),
-            total: (self.available + self.held).to_string(),
-            locked: self.status == AccountStatus::Locked,
+            client_id: ClientLabel::Id(account.client_id),
+            available: account.available,
+            held: account.held,
+            total: account.available + account.held,
+            locked: account.status == AccountStatus::Locked,
         }
     }
 }
 
 impl Account {
-    pub fn create(client_id: u16) -> Account {
+    pub fn create(client_id: ClientId) -> Account {
         Account {
             client_id,
             available: Money::zero(),
             held: Money::zero(),
             status: AccountStatus::Active,
+            changed: true,
         }
     }
 
-    pub fn apply(&mut self, transaction: &TransactionRecord, disputed_amount: Money) {
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+
+    pub fn available(&self) -> Money {
+        self.available
+    }
+
+    pub fn held(&self) -> Money {
+        self.held
+    }
+
+    pub fn status(&self) -> &AccountStatus {
+        &self.status
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.status == AccountStatus::Locked
+    }
+
+    pub fn is_erased(&self) -> bool {
+        self.status == AccountStatus::Erased
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /*
+    Applies one transaction, and says how much of it the account's funds covered.  `requested`
+    in a shortfall is `disputed_amount` for disputes, resolves and chargebacks, and the
+    transaction's own amount for anything else.
+    */
+    pub fn apply(
+        &mut self,
+        transaction: &TransactionRecord,
+        disputed_amount: Money,
+        behavior: BehaviorVersion,
+    ) -> ApplyOutcome {
+        self.changed = true;
+
         match *transaction {
-            TransactionRecord::Deposit { id, amount } => self.available = self.available + amount,
+            TransactionRecord::Deposit { id, amount } => {
+                self.available = self.available + amount;
+                ApplyOutcome::Full
+            }
             TransactionRecord::Withdrawl { id, amount } => {
-                if amount < self.available {
+                let covered = match behavior {
+                    BehaviorVersion::V1 => amount < self.available,
+                    BehaviorVersion::V2 => amount <= self.available,
+                };
+
+                if covered {
                     self.available = self.available - amount;
+                    ApplyOutcome::Full
+                } else {
+                    ApplyOutcome::Refused
                 }
             }
-            TransactionRecord::Dispute { id } => {
-                self.held = self.held + min(self.available, disputed_amount);
-                self.available = self.available - min(self.available, disputed_amount);
+            TransactionRecord::Dispute { id, amount } => {
+                let moved = min(self.available, disputed_amount);
+
+                self.held = self.held + moved;
+                self.available = self.available - moved;
+                ApplyOutcome::covering(moved, disputed_amount)
             }
-            TransactionRecord::Resolve { id } => {
-                self.available = self.available + min(self.held, disputed_amount);
-                self.held = self.held - min(self.held, disputed_amount);
+            TransactionRecord::Resolve { id, amount } => {
+                let moved = min(self.held, disputed_amount);
+
+                self.available = self.available + moved;
+                self.held = self.held - moved;
+                ApplyOutcome::covering(moved, disputed_amount)
             }
-            TransactionRecord::Chargeback { id } => {
+            TransactionRecord::Chargeback { id, amount } => {
+                let moved = min(self.held, disputed_amount);
+
                 if disputed_amount > Money::zero() {
                     self.status = AccountStatus::Locked;
                 }
-                self.available = self.available + min(self.held, disputed_amount);
-                self.held = self.held - min(self.held, disputed_amount);
+                self.available = self.available + moved;
+                self.held = self.held - moved;
+                ApplyOutcome::covering(moved, disputed_amount)
+            }
+            TransactionRecord::Opening { id, amount } => {
+                self.available = self.available + amount;
+                ApplyOutcome::Full
+            }
+            TransactionRecord::Tombstone { id, amount } => {
+                self.available = Money::zero();
+                self.held = Money::zero();
+                self.status = AccountStatus::Erased;
+                ApplyOutcome::Full
+            }
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative: false,
+                operator,
+            } => {
+                self.available = self.available + amount;
+                ApplyOutcome::Full
+            }
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative: true,
+                operator,
+            } => {
+                if amount <= self.available {
+                    self.available = self.available - amount;
+                    ApplyOutcome::Full
+                } else {
+                    ApplyOutcome::Refused
+                }
             }
+            TransactionRecord::Approve { id, operator } => ApplyOutcome::Full,
+            TransactionRecord::Lifecycle { id, event } => ApplyOutcome::Full,
         }
     }
 }
 
-pub struct AccountDatabase {
-    accounts: BTreeMap<u16, Account>,
+/*
+How much of a transaction an account's funds covered.  Withdrawals and negative adjustments are
+all or nothing, but disputes, resolves and chargebacks move as much as there is -- a dispute on
+a deposit that has since been partly withdrawn only holds what's left.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ApplyOutcome {
+    Full,
+    Partial(Money),
+    Refused,
+}
+
+impl ApplyOutcome {
+    fn covering(moved: Money, requested: Money) -> ApplyOutcome {
+        if moved == requested {
+            ApplyOutcome::Full
+        } else if moved == Money::zero() {
+            ApplyOutcome::Refused
+        } else {
+            ApplyOutcome::Partial(moved)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApplyOutcome::Full => "full",
+            ApplyOutcome::Partial(_) => "partial",
+            ApplyOutcome::Refused => "refused",
+        }
+    }
+}
+
+/*
+A transaction that was applied, but that the account's funds didn't fully cover.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Shortfall {
+    pub transaction: TransactionRecord,
+    pub requested: Money,
+    pub outcome: ApplyOutcome,
+}
+
+impl Shortfall {
+    pub fn applied(&self) -> Money {
+        match self.outcome {
+            ApplyOutcome::Full => self.requested,
+            ApplyOutcome::Partial(applied) => applied,
+            ApplyOutcome::Refused => Money::zero(),
+        }
+    }
+}
+
+/*
+The accounts projection: the current balance and status of every account, built purely from
+ledger events.
+*/
+pub struct Balances {
+    accounts: BTreeMap<ClientId, Account>,
+
+    /*
+    Funds belonging to erased clients.  They no longer belong to any account we report on, but
+    they haven't left the system either, so the sum of every account's total plus this should
+    still add up to everything deposited and not withdrawn.
+    */
+    erased: Money,
+
+    /*
+    Taken from the ledger itself, so every replay applies the rules the events were recorded
+    under.
+    */
+    behavior: BehaviorVersion,
+}
 
+impl Balances {
+    pub fn new() -> Balances {
+        Balances {
+            accounts: BTreeMap::new(),
+            erased: Money::zero(),
+            behavior: BehaviorVersion::default(),
+        }
+    }
+
+    pub fn contains(&self, client_id: ClientId) -> bool {
+        self.accounts.contains_key(&client_id)
+    }
+
+    pub fn get(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts
+            .values()
+            .filter(|account| !account.is_erased())
+    }
+
+    pub fn all_accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
+    }
+
+    /*
+    Accounts in client order, starting just past `after` -- or from the first, without one.
+    */
+    pub fn accounts_after(&self, after: Option<ClientId>) -> impl Iterator<Item = &Account> {
+        let start = match after {
+            Some(client_id) => Bound::Excluded(client_id),
+            None => Bound::Unbounded,
+        };
+
+        self.accounts
+            .range((start, Bound::Unbounded))
+            .map(|(client_id, account)| account)
+            .filter(|account| !account.is_erased())
+    }
+
+    pub fn erased(&self) -> Money {
+        self.erased
+    }
+
+    pub fn clear_changes(&mut self) {
+        for account in self.accounts.values_mut() {
+            account.changed = false;
+        }
+    }
+}
+
+impl Balances {
+    /*
+    Projects one event, and says how much of it was covered if it applied a transaction.
+    */
+    pub fn apply_event(&mut self, event: &LedgerEvent) -> ApplyOutcome {
+        match *event {
+            LedgerEvent::AccountOpened { client_id } => {
+                self.accounts
+                    .entry(client_id)
+                    .or_insert(Account::create(client_id));
+                ApplyOutcome::Full
+            }
+            LedgerEvent::TransactionApplied {
+                transaction,
+                disputed_amount,
+                ..
+            } => {
+                if let Some(account) = self.accounts.get_mut(&transaction.id().client_id) {
+                    if let TransactionRecord::Tombstone { id, amount } = transaction {
+                        self.erased = self.erased + account.available + account.held + amount;
+                    }
+
+                    return account.apply(&transaction, disputed_amount, self.behavior);
+                }
+
+                ApplyOutcome::Full
+            }
+            LedgerEvent::BehaviorSelected { version } => {
+                self.behavior = version;
+                ApplyOutcome::Full
+            }
+            LedgerEvent::LowBalance {
+                client_id,
+                response: LowBalanceResponse::Lock,
+                ..
+            } => {
+                if let Some(account) = self.accounts.get_mut(&client_id) {
+                    if account.status == AccountStatus::Active {
+                        account.status = AccountStatus::Locked;
+                        account.changed = true;
+                    }
+                }
+
+                ApplyOutcome::Full
+            }
+            LedgerEvent::LowBalance { .. } => ApplyOutcome::Full,
+        }
+    }
+}
+
+impl Projection for Balances {
+    fn project(&mut self, event: &LedgerEvent) {
+        self.apply_event(event);
+    }
+}
+
+/*
+What to do with a dispute, resolve or chargeback naming a client we've never seen.  They can
+never apply -- there's no transaction of theirs to reference -- so the only question is whether
+the client shows up in the output anyway.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum UnknownClientPolicy {
+    /*
+    Drop the transaction, and count it so the run's stats can report it.
+    */
+    #[default]
+    Ignore,
+
+    /*
+    Open an empty account for the client, as we always used to.
+    */
+    OpenAccount,
+}
+
+impl FromStr for UnknownClientPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(UnknownClientPolicy::Ignore),
+            "open-account" => Ok(UnknownClientPolicy::OpenAccount),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+A dispute, resolve or chargeback naming a different client than the transaction it references.
+These are always rejected, but they usually mean something upstream is corrupting data, so we
+keep them around to be reported.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ClientMismatch {
+    pub kind: &'static str,
+    pub transaction_id: TxId,
+    pub original_client_id: ClientId,
+    pub claimed_client_id: ClientId,
+}
+
+/*
+A deposit or withdrawal reusing the id of an earlier one, but with a different type, client or
+amount.  An exact repeat is just a redelivery and is quietly dropped, but one that disagrees
+means two different transactions were given the same id upstream.  Either way the first one
+stands, but conflicting duplicates are kept to be reported with both versions.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ConflictingDuplicate {
+    pub original: TransactionRecord,
+    pub duplicate: TransactionRecord,
+}
+
+/*
+What to do with an amount on a dispute, resolve or chargeback row, which otherwise takes its
+amount from the transaction it references.  With partial disputes enabled, an amount on a
+dispute is expected and never subject to this.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum DisputeAmountPolicy {
+    #[default]
+    Ignore,
+
+    /*
+    Apply the row as if it had no amount, but count it so the run can warn about it.
+    */
+    Warn,
+
+    /*
+    Drop the row, and count it.
+    */
+    Reject,
+}
+
+impl FromStr for DisputeAmountPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(DisputeAmountPolicy::Ignore),
+            "warn" => Ok(DisputeAmountPolicy::Warn),
+            "reject" => Ok(DisputeAmountPolicy::Reject),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+The engine's semantics, versioned so that a batch processed under older rules can still be
+replayed exactly as it was first processed, even after those rules have been fixed.
+
+Never change what an existing version does -- add a new one.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum BehaviorVersion {
+    /*
+    The original semantics: a withdrawal of an account's entire available balance is refused,
+    and locked accounts keep accepting transactions.
+    */
+    #[default]
+    V1,
+
+    /*
+    A withdrawal may take available funds to exactly zero, and a locked account refuses any
+    further deposits, withdrawals and disputes.  Disputes already open can still be resolved
+    or charged back, and operators can still adjust or erase it.
+    */
+    V2,
+}
+
+impl FromStr for BehaviorVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches('v') {
+            "1" => Ok(BehaviorVersion::V1),
+            "2" => Ok(BehaviorVersion::V2),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+What to do with a deposit that would take an account over the balance cap.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum CapPolicy {
+    #[default]
+    Reject,
+
+    /*
+    Accept as much of the deposit as fits under the cap.  The rest is dropped, so a later dispute
+    only covers what was actually credited.
+    */
+    Partial,
+}
+
+impl FromStr for CapPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(CapPolicy::Reject),
+            "partial" => Ok(CapPolicy::Partial),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+Whether deposits and withdrawals wait for the client's lifecycle: an `open` row and an approved
+KYC check.  A client whose KYC is rejected afterwards is gated again.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum LifecyclePolicy {
+    /*
+    Lifecycle rows are recorded, but gate nothing -- as before they existed.
+    */
+    #[default]
+    Ungated,
+
+    /*
+    Drop deposits and withdrawals for clients who aren't cleared yet, and count them.
+    */
+    Reject,
+
+    /*
+    Hold them back, and apply them in order once the client is cleared.  If KYC is rejected
+    instead, everything held back is dropped and counted as rejected.
+    */
+    Park,
+}
+
+impl FromStr for LifecyclePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ungated" => Ok(LifecyclePolicy::Ungated),
+            "reject" => Ok(LifecyclePolicy::Reject),
+            "park" => Ok(LifecyclePolicy::Park),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+What happens when a transaction takes an account's available funds below the low-balance
+threshold.  Each response is recorded in the ledger as a LedgerEvent::LowBalance, so the
+alert, and a lock it caused, replay with everything else.
+
+Available funds never go below zero here, so the threshold is a floor at or above zero.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum LowBalanceResponse {
+    /*
+    Only record that it happened.
+    */
+    #[default]
+    Alert,
+
+    /*
+    Refuse the client's withdrawals for as long as available stays below the threshold.
+    */
+    Hold,
+
+    /*
+    Lock the account, as a chargeback would.
+    */
+    Lock,
+}
+
+impl LowBalanceResponse {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LowBalanceResponse::Alert => "alert",
+            LowBalanceResponse::Hold => "hold",
+            LowBalanceResponse::Lock => "lock",
+        }
+    }
+}
+
+impl FromStr for LowBalanceResponse {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alert" => Ok(LowBalanceResponse::Alert),
+            "hold" => Ok(LowBalanceResponse::Hold),
+            "lock" => Ok(LowBalanceResponse::Lock),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct LowBalance {
+    pub threshold: Money,
+    pub response: LowBalanceResponse,
+}
+
+/*
+Where a client is in its lifecycle, from the lifecycle rows seen so far.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+struct Lifecycle {
+    opened: bool,
+    kyc_approved: bool,
+}
+
+/*
+How to order a withdrawal and a dispute that arrive back to back for the same client.  Some
+upstream systems emit both with the same timestamp and no guarantee of which comes first, and
+whether the withdrawal goes through can depend on the order: a dispute moves funds to held,
+where a withdrawal can't touch them.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ConflictPolicy {
+    /*
+    Apply everything exactly in input order.
+    */
+    #[default]
+    InputOrder,
+
+    /*
+    When a withdrawal is immediately followed by a dispute from the same tenant and client with
+    the same date, apply the dispute first.  Only adjacent pairs are swapped, and only where both
+    carry a date; undated rows are always applied in input order.
+    */
+    DisputeFirst,
+}
+
+impl ConflictPolicy {
+    /*
+    Whether `next` should be applied before `withdrawal`, which came just before it.  A dispute
+    of the withdrawal itself is never moved ahead of it.
+    */
+    pub fn reorders(
+        &self,
+        withdrawal: (&str, &TransactionRecord, Option<Date>),
+        next: (&str, &TransactionRecord, Option<Date>),
+    ) -> bool {
+        let ((withdrawal_tenant, withdrawal, withdrawal_date), (tenant, next, date)) =
+            (withdrawal, next);
+
+        *self == ConflictPolicy::DisputeFirst
+            && matches!(withdrawal, TransactionRecord::Withdrawl { .. })
+            && matches!(next, TransactionRecord::Dispute { .. })
+            && withdrawal_tenant == tenant
+            && withdrawal.id().client_id == next.id().client_id
+            && withdrawal.id().transaction_id != next.id().transaction_id
+            && withdrawal_date.is_some()
+            && withdrawal_date == date
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "input-order" => Ok(ConflictPolicy::InputOrder),
+            "dispute-first" => Ok(ConflictPolicy::DisputeFirst),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct AccountOptions {
+    /*
+    Put a Bloom filter sized for this many transactions in front of the transaction index.
+    */
+    pub expected_transactions: Option<usize>,
+
+    pub unknown_clients: UnknownClientPolicy,
+
+    pub dispute_amounts: DisputeAmountPolicy,
+
+    /*
+    Treat an amount on a dispute as the part of the referenced transaction being disputed.
+    */
+    pub partial_disputes: bool,
+
+    /*
+    The most any one account may hold, available and held together -- e.g. a regulatory cap on
+    e-money balances.
+    */
+    pub balance_cap: Option<Money>,
+
+    pub cap_policy: CapPolicy,
+
+    /*
+    Apply adjustments as soon as they arrive, without waiting for approval -- for pipelines
+    whose adjustments were already approved upstream.
+    */
+    pub auto_approve_adjustments: bool,
+
+    pub behavior: BehaviorVersion,
+
+    pub conflicts: ConflictPolicy,
+
+    pub lifecycle: LifecyclePolicy,
+
+    /*
+    The longest memo kept, in characters -- memo::DEFAULT_MEMO_LENGTH if not set.
+    */
+    pub memo_max_length: Option<usize>,
+
+    pub tx_ids: TxIdFormat,
+
+    pub date_basis: DateBasis,
+
+    pub low_balance: Option<LowBalance>,
+}
+
+/*
+Where a manual adjustment is in its approval workflow.
+*/
+#[derive(Clone, Copy, Debug)]
+enum AdjustmentState {
+    Pending(TransactionRecord),
+    Applied,
+}
+
+/*
+A parked deposit or withdrawal, with its date and memo.
+*/
+type ParkedTransaction = (TransactionRecord, Option<Date>, Option<String>);
+
+pub struct AccountDatabase {
     /*
     We absolutely must persist all transactions such that we can always replay them to
     achieve the same final state.
@@ -101,7 +781,22 @@ pub struct AccountDatabase {
 
     For the purposes of this assignment though, I'm going to just store them in memory.
     */
-    transactions: HashMap<u32, TransactionRecord>,
+    events: Vec<LedgerEvent>,
+
+    balances: Balances,
+
+    /*
+    An index over the deposits and withdrawals in the ledger, so that disputes and friends can
+    find the transaction they reference without scanning the whole event log.
+    */
+    transactions: HashMap<TxId, TransactionRecord>,
+
+    /*
+    For huge ingests, most transaction ids are new, and checking that against the index is the
+    expensive part once it's too big to stay in cache.  When enabled, a Bloom filter in front of
+    the index answers most of those checks, and only possible duplicates reach the index.
+    */
+    seen: Option<SeenFilter>,
 
     /*
     Storing the actual set of disupted transactions may be a bit unorthodox vs.
@@ -111,121 +806,891 @@ pub struct AccountDatabase {
     under dispute vs. increasing memory on all undisputed transactions.
 
     If transactions had a more complex life cycle then we'd probably want a status enum.
+
+    Each is stored with the amount its dispute held, which is less than the transaction's
+    amount for a partial dispute.
+    */
+    disputed_transactions: HashMap<TxId, Money>,
+
+    unknown_clients: UnknownClientPolicy,
+
+    /*
+    Disputes, resolves and chargebacks dropped because they named a client we'd never seen.
+    */
+    unknown_client_references: usize,
+
+    client_mismatches: Vec<ClientMismatch>,
+
+    conflicting_duplicates: Vec<ConflictingDuplicate>,
+
+    shortfalls: Vec<Shortfall>,
+
+    dispute_amounts: DisputeAmountPolicy,
+
+    partial_disputes: bool,
+
+    /*
+    Disputes, resolves and chargebacks whose amount was warned about or rejected.
     */
-    disputed_transactions: HashSet<u32>,
+    unexpected_dispute_amounts: usize,
+
+    balance_cap: Option<Money>,
+
+    cap_policy: CapPolicy,
+
+    /*
+    Deposits rejected or trimmed for exceeding the balance cap.
+    */
+    capped_deposits: usize,
+
+    /*
+    Every adjustment we've seen, by transaction id, whether it's waiting for approval or has
+    been applied.  Kept apart from the transaction index so adjustments can't be disputed.
+    */
+    adjustments: HashMap<TxId, AdjustmentState>,
+
+    auto_approve_adjustments: bool,
+
+    behavior: BehaviorVersion,
+
+    lifecycle_policy: LifecyclePolicy,
+
+    lifecycles: HashMap<ClientId, Lifecycle>,
+
+    /*
+    Deposits and withdrawals waiting for their client to be cleared, in arrival order.
+    */
+    parked: HashMap<ClientId, Vec<ParkedTransaction>>,
+
+    /*
+    Deposits and withdrawals dropped for arriving before their client was cleared.
+    */
+    gated_transactions: usize,
+
+    /*
+    Memos from the input, sanitized, by the position of the event they're attached to.  Most
+    rows have none, so they're kept apart from the events themselves.
+    */
+    memos: HashMap<usize, String>,
+
+    memo_max_length: usize,
+
+    low_balance: Option<LowBalance>,
+
+    /*
+    Withdrawals refused while their account was below the low-balance threshold.
+    */
+    held_withdrawals: usize,
 }
 
 impl AccountDatabase {
     pub fn new() -> AccountDatabase {
         AccountDatabase {
-            accounts: BTreeMap::new(),
+            events: Vec::new(),
+            balances: Balances::new(),
             transactions: HashMap::new(),
-            disputed_transactions: HashSet::new(),
+            seen: None,
+            disputed_transactions: HashMap::new(),
+            unknown_clients: UnknownClientPolicy::default(),
+            unknown_client_references: 0,
+            client_mismatches: Vec::new(),
+            conflicting_duplicates: Vec::new(),
+            shortfalls: Vec::new(),
+            dispute_amounts: DisputeAmountPolicy::default(),
+            partial_disputes: false,
+            unexpected_dispute_amounts: 0,
+            balance_cap: None,
+            cap_policy: CapPolicy::default(),
+            capped_deposits: 0,
+            adjustments: HashMap::new(),
+            auto_approve_adjustments: false,
+            behavior: BehaviorVersion::default(),
+            lifecycle_policy: LifecyclePolicy::default(),
+            lifecycles: HashMap::new(),
+            parked: HashMap::new(),
+            gated_transactions: 0,
+            memos: HashMap::new(),
+            memo_max_length: DEFAULT_MEMO_LENGTH,
+            low_balance: None,
+            held_withdrawals: 0,
         }
     }
 
+    /*
+    Any behavior other than the original is recorded as the ledger's first event, which keeps
+    ledgers written under v1 exactly as they always were.
+    */
+    pub fn with_options(options: AccountOptions) -> AccountDatabase {
+        let mut database = AccountDatabase {
+            seen: options.expected_transactions.map(SeenFilter::with_capacity),
+            unknown_clients: options.unknown_clients,
+            dispute_amounts: options.dispute_amounts,
+            partial_disputes: options.partial_disputes,
+            balance_cap: options.balance_cap,
+            cap_policy: options.cap_policy,
+            auto_approve_adjustments: options.auto_approve_adjustments,
+            behavior: options.behavior,
+            lifecycle_policy: options.lifecycle,
+            memo_max_length: options.memo_max_length.unwrap_or(DEFAULT_MEMO_LENGTH),
+            low_balance: options.low_balance,
+            ..AccountDatabase::new()
+        };
+
+        if options.behavior != BehaviorVersion::V1 {
+            database.append(LedgerEvent::BehaviorSelected {
+                version: options.behavior,
+            });
+        }
+
+        database
+    }
+
     pub fn apply(&mut self, transaction: &TransactionRecord) {
+        self.apply_on(transaction, None);
+    }
+
+    pub fn apply_on(&mut self, transaction: &TransactionRecord, date: Option<Date>) {
+        self.apply_with_memo(transaction, date, None);
+    }
+
+    /*
+    Applies a transaction, and attaches its memo to the event that records it in the ledger.
+    A transaction that never reaches the ledger has nowhere to keep one, except that a parked
+    transaction keeps its memo until it's applied.
+    */
+    pub fn apply_with_memo(
+        &mut self,
+        transaction: &TransactionRecord,
+        date: Option<Date>,
+        memo: Option<&str>,
+    ) {
+        let first = self.events.len();
+
+        self.apply_record(transaction, date, memo);
+
+        let applied = (first..self.events.len()).rev().find(|&sequence| {
+            matches!(
+                self.events[sequence],
+                LedgerEvent::TransactionApplied { transaction: applied, .. }
+                    if applied.id() == transaction.id()
+            )
+        });
+
+        if let (Some(sequence), Some(memo)) = (
+            applied,
+            memo.and_then(|memo| sanitize(memo, self.memo_max_length)),
+        ) {
+            self.memos.insert(sequence, memo);
+        }
+    }
+
+    /*
+    The memo attached to the event at `sequence` in the ledger, if any.
+    */
+    pub fn memo(&self, sequence: usize) -> Option<&str> {
+        self.memos.get(&sequence).map(String::as_str)
+    }
+
+    fn apply_record(
+        &mut self,
+        transaction: &TransactionRecord,
+        date: Option<Date>,
+        memo: Option<&str>,
+    ) {
         let client_id = transaction.id().client_id;
-        let account = self
-            .accounts
-            .entry(client_id)
-            .or_insert(Account::create(client_id));
+        let gated = self.lifecycle_policy != LifecyclePolicy::Ungated
+            && matches!(
+                transaction,
+                TransactionRecord::Deposit { .. } | TransactionRecord::Withdrawl { .. }
+            )
+            && !self.is_cleared(client_id);
+
+        if gated {
+            match self.lifecycle_policy {
+                LifecyclePolicy::Park => self.parked.entry(client_id).or_default().push((
+                    *transaction,
+                    date,
+                    memo.map(str::to_owned),
+                )),
+                _ => self.gated_transactions += 1,
+            }
 
-        if AccountDatabase::can_process_transaction(
+            return;
+        }
+
+        let only_references_another_transaction = matches!(
             transaction,
-            &self.transactions,
-            &self.disputed_transactions,
-        ) {
+            TransactionRecord::Dispute { .. }
+                | TransactionRecord::Resolve { .. }
+                | TransactionRecord::Chargeback { .. }
+                | TransactionRecord::Approve { .. }
+        );
+        /*
+        The deposit or withdrawal this row's tx id already names, looked up once for all the
+        checks below.  The seen filter rules out most new ids without touching the index.
+        */
+        let recorded = self
+            .seen
+            .as_ref()
+            .is_none_or(|seen| seen.may_contain(transaction.id().transaction_id))
+            .then(|| self.transactions.get(&transaction.id().transaction_id))
+            .flatten()
+            .copied();
+        let original_client_id = recorded
+            .map(|original| original.id().client_id)
+            .filter(|&original_client_id| original_client_id != client_id);
+
+        if let (true, Some(original_client_id)) =
+            (only_references_another_transaction, original_client_id)
+        {
+            self.client_mismatches.push(ClientMismatch {
+                kind: transaction.kind(),
+                transaction_id: transaction.id().transaction_id,
+                original_client_id,
+                claimed_client_id: client_id,
+            });
+        }
+
+        let conflicting_original = match transaction {
+            TransactionRecord::Deposit { .. } | TransactionRecord::Withdrawl { .. } => {
+                recorded.filter(|original| original != transaction)
+            }
+            _ => None,
+        };
+
+        if let Some(original) = conflicting_original {
+            self.conflicting_duplicates.push(ConflictingDuplicate {
+                original,
+                duplicate: *transaction,
+            });
+        }
+
+        let amount_is_unexpected = match *transaction {
+            TransactionRecord::Dispute { id, amount } => amount.is_some() && !self.partial_disputes,
+            TransactionRecord::Resolve { id, amount } => amount.is_some(),
+            TransactionRecord::Chargeback { id, amount } => amount.is_some(),
+            _ => false,
+        };
+
+        if amount_is_unexpected && self.dispute_amounts != DisputeAmountPolicy::Ignore {
+            self.unexpected_dispute_amounts += 1;
+
+            if self.dispute_amounts == DisputeAmountPolicy::Reject {
+                return;
+            }
+        }
+
+        /*
+        A client whose transactions were only loaded from a tx index has no account yet, but
+        isn't unknown -- their dispute opens the account.
+        */
+        let references_own_transaction =
+            recorded.is_some_and(|original| original.id().client_id == client_id);
+
+        if !self.balances.contains(client_id) {
+            if only_references_another_transaction
+                && !references_own_transaction
+                && self.unknown_clients == UnknownClientPolicy::Ignore
+            {
+                if original_client_id.is_none() {
+                    self.unknown_client_references += 1;
+                }
+
+                return;
+            }
+
+            self.append(LedgerEvent::AccountOpened { client_id });
+        }
+
+        let account_is_erased = self
+            .balances
+            .get(client_id)
+            .is_some_and(|account| account.is_erased());
+
+        let account_refuses_transaction = self.behavior == BehaviorVersion::V2
+            && self
+                .balances
+                .get(client_id)
+                .is_some_and(|account| account.is_locked())
+            && matches!(
+                transaction,
+                TransactionRecord::Deposit { .. }
+                    | TransactionRecord::Withdrawl { .. }
+                    | TransactionRecord::Dispute { .. }
+            );
+
+        if account_refuses_transaction {
+            return;
+        }
+
+        let withdrawal_is_held = matches!(transaction, TransactionRecord::Withdrawl { .. })
+            && self.low_balance.is_some_and(|low_balance| {
+                low_balance.response == LowBalanceResponse::Hold
+                    && self
+                        .balances
+                        .get(client_id)
+                        .is_some_and(|account| account.available() < low_balance.threshold)
+            });
+
+        if withdrawal_is_held {
+            self.held_withdrawals += 1;
+            return;
+        }
+
+        if let TransactionRecord::Lifecycle { id, event } = *transaction {
+            if !account_is_erased {
+                self.apply_lifecycle(transaction, event, date);
+            }
+
+            return;
+        }
+
+        if let TransactionRecord::Adjustment { .. } | TransactionRecord::Approve { .. } =
+            transaction
+        {
+            if !account_is_erased {
+                self.apply_adjustment(transaction, date);
+            }
+
+            return;
+        }
+
+        if !account_is_erased
+            && !self
+                .adjustments
+                .contains_key(&transaction.id().transaction_id)
+            && AccountDatabase::can_process_transaction(
+                transaction,
+                recorded.as_ref(),
+                &self.disputed_transactions,
+            )
+        {
+            let Some(transaction) = &self.within_cap(transaction) else {
+                return;
+            };
+
+            let disputed_amount = AccountDatabase::get_disputed_amount(
+                transaction,
+                &self.transactions,
+                &self.disputed_transactions,
+                self.partial_disputes,
+            );
+
             AccountDatabase::record_transaction(
                 transaction,
                 &mut self.transactions,
+                self.seen.as_mut(),
                 &mut self.disputed_transactions,
+                disputed_amount,
             );
 
-            let disputed_amount =
-                AccountDatabase::get_disputed_amount(transaction, &self.transactions);
+            self.append(LedgerEvent::TransactionApplied {
+                transaction: *transaction,
+                disputed_amount,
+                date,
+            });
+        }
+    }
+
+    /*
+    Queues an adjustment for approval, or applies it if it's approved -- by an `approve` row from
+    a different operator for the same client, or up front if we're trusting our input.
+    */
+    fn apply_adjustment(&mut self, transaction: &TransactionRecord, date: Option<Date>) {
+        let transaction_id = transaction.id().transaction_id;
+        let seen = self.adjustments.contains_key(&transaction_id)
+            || self.transactions.contains_key(&transaction_id);
 
-            account.apply(transaction, disputed_amount);
+        let adjustment = match *transaction {
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } if seen => return,
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } if !self.auto_approve_adjustments => {
+                self.adjustments
+                    .insert(transaction_id, AdjustmentState::Pending(*transaction));
+                return;
+            }
+            TransactionRecord::Approve { id, operator } => {
+                match self.adjustments.get(&transaction_id) {
+                    Some(&AdjustmentState::Pending(
+                        adjustment @ TransactionRecord::Adjustment {
+                            id: requested,
+                            operator: requested_by,
+                            ..
+                        },
+                    )) if requested.client_id == id.client_id && requested_by != operator => {
+                        adjustment
+                    }
+                    _ => return,
+                }
+            }
+            _ => *transaction,
+        };
+
+        self.adjustments
+            .insert(transaction_id, AdjustmentState::Applied);
+        self.append(LedgerEvent::TransactionApplied {
+            transaction: adjustment,
+            disputed_amount: Money::zero(),
+            date,
+        });
+    }
+
+    /*
+    Records a lifecycle row in the ledger, and once the client is cleared, applies whatever was
+    parked waiting for it.
+    */
+    fn apply_lifecycle(
+        &mut self,
+        transaction: &TransactionRecord,
+        event: LifecycleEvent,
+        date: Option<Date>,
+    ) {
+        let client_id = transaction.id().client_id;
+        let lifecycle = self.lifecycles.entry(client_id).or_default();
+
+        match event {
+            LifecycleEvent::Open => lifecycle.opened = true,
+            LifecycleEvent::KycApproved => lifecycle.kyc_approved = true,
+            LifecycleEvent::KycRejected => lifecycle.kyc_approved = false,
+        }
+
+        self.append(LedgerEvent::TransactionApplied {
+            transaction: *transaction,
+            disputed_amount: Money::zero(),
+            date,
+        });
+
+        if event == LifecycleEvent::KycRejected {
+            self.gated_transactions += self
+                .parked
+                .remove(&client_id)
+                .map_or(0, |parked| parked.len());
+        } else if self.is_cleared(client_id) {
+            for (parked, parked_date, parked_memo) in
+                self.parked.remove(&client_id).unwrap_or_default()
+            {
+                self.apply_with_memo(&parked, parked_date, parked_memo.as_deref());
+            }
         }
     }
 
+    fn is_cleared(&self, client_id: ClientId) -> bool {
+        self.lifecycles
+            .get(&client_id)
+            .is_some_and(|lifecycle| lifecycle.opened && lifecycle.kyc_approved)
+    }
+
+    pub fn gated_transactions(&self) -> usize {
+        self.gated_transactions
+    }
+
+    /*
+    Clients with lifecycle rows, or with transactions parked waiting for them.
+    */
+    pub fn lifecycle_clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.lifecycles.keys().chain(self.parked.keys()).copied()
+    }
+
+    pub fn held_withdrawals(&self) -> usize {
+        self.held_withdrawals
+    }
+
+    /*
+    How many times an account fell below the low-balance threshold.
+    */
+    pub fn low_balance_events(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|event| matches!(event, LedgerEvent::LowBalance { .. }))
+            .count()
+    }
+
+    pub fn parked_transactions(&self) -> usize {
+        self.parked.values().map(Vec::len).sum()
+    }
+
+    /*
+    What the client's parked deposits add up to: funds that have arrived, but that the client
+    can't use, dispute or withdraw until it's cleared.  Released into available funds when it is.
+    */
+    pub fn pending(&self, client_id: ClientId) -> Money {
+        self.parked
+            .get(&client_id)
+            .into_iter()
+            .flatten()
+            .map(|(transaction, _, _)| match transaction {
+                TransactionRecord::Deposit { id, amount } => *amount,
+                _ => Money::zero(),
+            })
+            .sum()
+    }
+
+    /*
+    Applies the balance cap to a deposit: returns it unchanged if it fits, trimmed to what's
+    left under the cap if partial deposits are allowed, or None if it's rejected.
+    */
+    fn within_cap(&mut self, transaction: &TransactionRecord) -> Option<TransactionRecord> {
+        let (Some(cap), TransactionRecord::Deposit { id, amount }) =
+            (self.balance_cap, *transaction)
+        else {
+            return Some(*transaction);
+        };
+        let total = self
+            .balances
+            .get(id.client_id)
+            .map_or(Money::zero(), |account| {
+                account.available() + account.held()
+            });
+
+        if total.checked_add(amount).is_some_and(|after| after <= cap) {
+            return Some(*transaction);
+        }
+
+        self.capped_deposits += 1;
+
+        match self.cap_policy {
+            CapPolicy::Reject => None,
+            CapPolicy::Partial if total < cap => Some(TransactionRecord::Deposit {
+                id,
+                amount: cap - total,
+            }),
+            CapPolicy::Partial => None,
+        }
+    }
+
+    /*
+    Records a deposit or withdrawal applied in an earlier run, along with what its dispute
+    holds if it's still under one, so that disputes and friends can reference it.  Balances
+    are left alone: they're expected to come from the input, e.g. as opening balances.
+    */
+    pub fn index(&mut self, transaction: &TransactionRecord, disputed_amount: Option<Money>) {
+        AccountDatabase::record_transaction(
+            transaction,
+            &mut self.transactions,
+            self.seen.as_mut(),
+            &mut self.disputed_transactions,
+            Money::zero(),
+        );
+
+        if let Some(disputed_amount) = disputed_amount {
+            self.disputed_transactions
+                .insert(transaction.id().transaction_id, disputed_amount);
+        }
+    }
+
+    /*
+    Every deposit and withdrawal in the index, by transaction id, with what its dispute holds
+    if it's under one.
+    */
+    pub fn indexed_transactions(&self) -> Vec<(&TransactionRecord, Option<Money>)> {
+        let mut indexed: Vec<_> = self
+            .transactions
+            .iter()
+            .map(|(transaction_id, transaction)| {
+                (
+                    transaction,
+                    self.disputed_transactions.get(transaction_id).copied(),
+                )
+            })
+            .collect();
+
+        indexed.sort_by_key(|(transaction, _)| transaction.id().transaction_id);
+        indexed
+    }
+
+    /*
+    Every dispute still open, with the amount it holds.
+    */
+    pub fn open_disputes(&self) -> impl Iterator<Item = (TxId, Money)> + '_ {
+        self.disputed_transactions
+            .iter()
+            .map(|(&transaction_id, &amount)| (transaction_id, amount))
+    }
+
+    pub fn clients_with_open_disputes(&self) -> BTreeSet<ClientId> {
+        self.disputed_transactions
+            .keys()
+            .filter_map(|transaction_id| self.transactions.get(transaction_id))
+            .map(|transaction| transaction.id().client_id)
+            .collect()
+    }
+
+    /*
+    Whether an account's total is within 5% of the balance cap, if there is one.
+    */
+    pub fn is_near_cap(&self, account: &Account) -> bool {
+        self.balance_cap.is_some_and(|cap| {
+            (account.available().0 as u128 + account.held().0 as u128) * 20 >= cap.0 as u128 * 19
+        })
+    }
+
+    pub fn pending_adjustments(&self) -> usize {
+        self.adjustments
+            .values()
+            .filter(|state| matches!(state, AdjustmentState::Pending(_)))
+            .count()
+    }
+
+    pub fn capped_deposits(&self) -> usize {
+        self.capped_deposits
+    }
+
+    pub fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.balances.get(client_id)
+    }
+
     pub fn accounts(&self) -> impl Iterator<Item = &Account> {
-        self.accounts.values()
+        self.balances.accounts()
+    }
+
+    pub fn accounts_after(&self, after: Option<ClientId>) -> impl Iterator<Item = &Account> {
+        self.balances.accounts_after(after)
+    }
+
+    /*
+    Includes erased accounts, which are otherwise hidden.
+    */
+    pub fn all_accounts(&self) -> impl Iterator<Item = &Account> {
+        self.balances.all_accounts()
+    }
+
+    pub fn events(&self) -> &[LedgerEvent] {
+        &self.events
+    }
+
+    pub fn erased(&self) -> Money {
+        self.balances.erased()
+    }
+
+    pub fn unknown_client_references(&self) -> usize {
+        self.unknown_client_references
+    }
+
+    pub fn client_mismatches(&self) -> &[ClientMismatch] {
+        &self.client_mismatches
+    }
+
+    pub fn conflicting_duplicates(&self) -> &[ConflictingDuplicate] {
+        &self.conflicting_duplicates
+    }
+
+    pub fn shortfalls(&self) -> &[Shortfall] {
+        &self.shortfalls
+    }
+
+    pub fn unexpected_dispute_amounts(&self) -> usize {
+        self.unexpected_dispute_amounts
+    }
+
+    pub fn clear_changes(&mut self) {
+        self.balances.clear_changes();
+    }
+
+    fn append(&mut self, event: LedgerEvent) {
+        let available = |balances: &Balances, client_id| {
+            balances
+                .get(client_id)
+                .map_or(Money::zero(), |account| account.available())
+        };
+        let before = match event {
+            LedgerEvent::TransactionApplied { transaction, .. } => {
+                Some(available(&self.balances, transaction.id().client_id))
+            }
+            _ => None,
+        };
+        let outcome = self.balances.apply_event(&event);
+
+        if let (
+            LedgerEvent::TransactionApplied {
+                transaction,
+                disputed_amount,
+                date,
+            },
+            false,
+        ) = (event, outcome == ApplyOutcome::Full)
+        {
+            let requested = match transaction {
+                TransactionRecord::Dispute { .. }
+                | TransactionRecord::Resolve { .. }
+                | TransactionRecord::Chargeback { .. } => disputed_amount,
+                _ => transaction.amount(),
+            };
+
+            self.shortfalls.push(Shortfall {
+                transaction,
+                requested,
+                outcome,
+            });
+        }
+
+        self.events.push(event);
+
+        /*
+        Only a debit that takes an account below the threshold triggers a response; one that
+        leaves it below, or only starts it there, doesn't.  A dispute lowers available funds too,
+        but moves them to held rather than out of the account, so it's no sign the client is
+        running short.
+        */
+        if let (
+            Some(low_balance),
+            Some(before),
+            LedgerEvent::TransactionApplied {
+                transaction, date, ..
+            },
+        ) = (self.low_balance, before, event)
+        {
+            let client_id = transaction.id().client_id;
+            let after = available(&self.balances, client_id);
+            let debit = matches!(
+                transaction,
+                TransactionRecord::Withdrawl { .. }
+                    | TransactionRecord::Adjustment { negative: true, .. }
+            );
+
+            if debit && before >= low_balance.threshold && after < low_balance.threshold {
+                self.append(LedgerEvent::LowBalance {
+                    client_id,
+                    transaction_id: transaction.id().transaction_id,
+                    available: after,
+                    response: low_balance.response,
+                    date,
+                });
+            }
+        }
     }
 
     fn can_process_transaction(
         transaction: &TransactionRecord,
-        recorded_transactions: &HashMap<u32, TransactionRecord>,
-        disputed_transactions: &HashSet<u32>,
+        recorded: Option<&TransactionRecord>,
+        disputed_transactions: &HashMap<TxId, Money>,
     ) -> bool {
-        let transaction_has_been_recorded =
-            recorded_transactions.contains_key(&transaction.id().transaction_id);
+        let transaction_has_been_recorded = recorded.is_some();
         let transaction_is_currently_disputed =
-            disputed_transactions.contains(&transaction.id().transaction_id);
-        let client_ids_are_consistent = recorded_transactions
-            .get(&transaction.id().transaction_id)
-            .map_or(true, |t| t.id().client_id == transaction.id().client_id);
+            disputed_transactions.contains_key(&transaction.id().transaction_id);
+        let client_ids_are_consistent =
+            recorded.is_none_or(|t| t.id().client_id == transaction.id().client_id);
 
         match transaction {
             TransactionRecord::Deposit { id, amount } => !transaction_has_been_recorded,
             TransactionRecord::Withdrawl { id, amount } => !transaction_has_been_recorded,
-            TransactionRecord::Dispute { id } => {
+            TransactionRecord::Dispute { id, amount } => {
                 transaction_has_been_recorded
                     && !transaction_is_currently_disputed
                     && client_ids_are_consistent
             }
-            TransactionRecord::Resolve { id } => {
+            TransactionRecord::Resolve { id, amount } => {
                 transaction_has_been_recorded
                     && transaction_is_currently_disputed
                     && client_ids_are_consistent
             }
-            TransactionRecord::Chargeback { id } => {
+            TransactionRecord::Chargeback { id, amount } => {
                 transaction_has_been_recorded
                     && transaction_is_currently_disputed
                     && client_ids_are_consistent
             }
+            TransactionRecord::Opening { id, amount } => true,
+            TransactionRecord::Tombstone { id, amount } => true,
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } => false,
+            TransactionRecord::Approve { id, operator } => false,
+            TransactionRecord::Lifecycle { id, event } => false,
         }
     }
 
+    /*
+    A dispute covers the whole referenced transaction, unless partial disputes are enabled and
+    the row names a smaller amount.  Resolves and chargebacks release whatever their dispute
+    held, so this has to be worked out before the dispute is cleared.
+    */
     fn get_disputed_amount(
         transaction: &TransactionRecord,
-        recorded_transactions: &HashMap<u32, TransactionRecord>,
+        recorded_transactions: &HashMap<TxId, TransactionRecord>,
+        disputed_transactions: &HashMap<TxId, Money>,
+        partial_disputes: bool,
     ) -> Money {
-        let related_transaction = match transaction {
-            TransactionRecord::Deposit { id, amount } => None,
-            TransactionRecord::Withdrawl { id, amount } => None,
-            TransactionRecord::Dispute { id } => recorded_transactions.get(&id.transaction_id),
-            TransactionRecord::Resolve { id } => recorded_transactions.get(&id.transaction_id),
-            TransactionRecord::Chargeback { id } => recorded_transactions.get(&id.transaction_id),
+        let recorded_amount = || {
+            recorded_transactions
+                .get(&transaction.id().transaction_id)
+                .map_or(Money::zero(), |recorded| recorded.amount())
+        };
+        let currently_disputed_amount = || {
+            disputed_transactions
+                .get(&transaction.id().transaction_id)
+                .copied()
+                .unwrap_or(Money::zero())
         };
 
-        match related_transaction {
-            Some(disputed_transaction) => disputed_transaction.amount(),
-            None => Money::zero(),
+        match *transaction {
+            TransactionRecord::Deposit { id, amount } => Money::zero(),
+            TransactionRecord::Withdrawl { id, amount } => Money::zero(),
+            TransactionRecord::Dispute {
+                id,
+                amount: Some(amount),
+            } if partial_disputes => min(amount, recorded_amount()),
+            TransactionRecord::Dispute { id, amount } => recorded_amount(),
+            TransactionRecord::Resolve { id, amount } => currently_disputed_amount(),
+            TransactionRecord::Chargeback { id, amount } => currently_disputed_amount(),
+            TransactionRecord::Opening { id, amount } => Money::zero(),
+            TransactionRecord::Tombstone { id, amount } => Money::zero(),
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } => Money::zero(),
+            TransactionRecord::Approve { id, operator } => Money::zero(),
+            TransactionRecord::Lifecycle { id, event } => Money::zero(),
         }
     }
 
     fn record_transaction(
         transaction: &TransactionRecord,
-        transactions: &mut HashMap<u32, TransactionRecord>,
-        disputed_transactions: &mut HashSet<u32>,
+        transactions: &mut HashMap<TxId, TransactionRecord>,
+        seen: Option<&mut SeenFilter>,
+        disputed_transactions: &mut HashMap<TxId, Money>,
+        disputed_amount: Money,
     ) {
         match transaction {
-            TransactionRecord::Deposit { id, amount } => {
-                transactions.insert(transaction.id().transaction_id, *transaction);
-            }
-            TransactionRecord::Withdrawl { id, amount } => {
+            TransactionRecord::Deposit { id, amount }
+            | TransactionRecord::Withdrawl { id, amount } => {
                 transactions.insert(transaction.id().transaction_id, *transaction);
+
+                if let Some(seen) = seen {
+                    seen.insert(transaction.id().transaction_id);
+                }
             }
-            TransactionRecord::Dispute { id } => {
-                disputed_transactions.insert(transaction.id().transaction_id);
+            TransactionRecord::Dispute { id, amount } => {
+                disputed_transactions.insert(transaction.id().transaction_id, disputed_amount);
             }
-            TransactionRecord::Resolve { id } => {
+            TransactionRecord::Resolve { id, amount } => {
                 disputed_transactions.remove(&transaction.id().transaction_id);
             }
-            TransactionRecord::Chargeback { id } => {
+            TransactionRecord::Chargeback { id, amount } => {
                 disputed_transactions.remove(&transaction.id().transaction_id);
             }
+            TransactionRecord::Opening { id, amount } => {}
+            TransactionRecord::Tombstone { id, amount } => {}
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } => {}
+            TransactionRecord::Approve { id, operator } => {}
+            TransactionRecord::Lifecycle { id, event } => {}
         }
     }
 }
@@ -0,0 +1,299 @@
+use std::{error::Error, io};
+
+use csv::{Reader, StringRecord, Writer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::AdminAction,
+    tenants::TenantDatabase,
+    transactions::{parse_signed_amount, ClientId, TransactionText, TxId},
+};
+
+/*
+One line of an admin script.  Every operation names the account it's for, and carries the
+operator and reason code that go into the log with it:
+
+    adjustment: credit `amount` to the account, or debit it if negative, as a new adjustment
+                with id `tx`.  Like any other, it waits for a second operator's approval.
+    approve:    approve the pending adjustment `tx`, requested by a different operator.
+    reversal:   the deposit or withdrawal `tx` never happened, as with a backfill.  Its row is
+                taken out of the log, so the operator and reason are kept in the report only.
+    unlock:     always fails -- a chargeback's lock is permanent, and there's nothing in the log
+                that can lift it.
+*/
+#[derive(Deserialize, Debug)]
+pub struct AdminOperation {
+    pub op: String,
+    pub client: String,
+    pub tx: String,
+    #[serde(default)]
+    pub amount: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    pub operator: String,
+    pub reason: String,
+}
+
+/*
+What became of one line of the script: `applied`, `failed` with the reason in `error`, or
+`rolled-back` when it succeeded but another line failed in an all-or-nothing run.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct OperationResult {
+    pub line: u64,
+    pub op: String,
+    pub tenant: String,
+    pub client: String,
+    pub tx: String,
+    pub operator: String,
+    pub reason: String,
+    pub status: String,
+    pub error: String,
+}
+
+/*
+Runs an admin script against a transaction log, writing the log as it stands afterwards.  Each
+line is checked against the log as the lines before it left it, and one that fails leaves the
+log as it was and doesn't stop the rest.  With `all_or_nothing`, a single failure means the log
+is written back unchanged.
+
+The log gains operator and reason columns if it doesn't have them, and a tenant column if the
+script names a tenant.
+*/
+pub fn run_script<R: io::Read, S: io::Read, W: io::Write>(
+    reader: &mut Reader<R>,
+    script: &mut Reader<S>,
+    writer: &mut Writer<W>,
+    all_or_nothing: bool,
+) -> Result<Vec<OperationResult>, Box<dyn Error>> {
+    let script_headers = script.headers()?.clone();
+    let mut operations = Vec::new();
+
+    for record_result in script.records() {
+        let record = record_result?;
+        let line = record.position().map_or(0, |position| position.line());
+        let operation: AdminOperation = record.deserialize(Some(&script_headers))?;
+
+        operations.push((line, operation));
+    }
+
+    let mut headers = reader.headers()?.clone();
+    let mut columns = vec!["operator", "reason"];
+
+    if operations
+        .iter()
+        .any(|(line, operation)| !tenant_of(operation).is_empty())
+    {
+        columns.push("tenant");
+    }
+
+    for name in columns {
+        if !headers.iter().any(|header| header == name) {
+            headers.push_field(name);
+        }
+    }
+
+    let mut original = Vec::new();
+
+    for record_result in reader.records() {
+        let mut record = record_result?;
+
+        while record.len() < headers.len() {
+            record.push_field("");
+        }
+
+        original.push(record);
+    }
+
+    let mut rows = original.clone();
+    let mut results = Vec::new();
+
+    for (line, operation) in operations {
+        let outcome = apply_operation(&headers, &mut rows, &operation);
+
+        results.push(OperationResult {
+            line,
+            op: operation.op.trim().to_owned(),
+            tenant: tenant_of(&operation).to_owned(),
+            client: operation.client.trim().to_owned(),
+            tx: operation.tx.trim().to_owned(),
+            status: if outcome.is_ok() { "applied" } else { "failed" }.to_owned(),
+            error: outcome.err().unwrap_or_default(),
+            operator: operation.operator,
+            reason: operation.reason,
+        });
+    }
+
+    if all_or_nothing && results.iter().any(|result| result.status == "failed") {
+        rows = original;
+
+        for result in &mut results {
+            if result.status == "applied" {
+                result.status = "rolled-back".to_owned();
+            }
+        }
+    }
+
+    writer.write_record(&headers)?;
+
+    for row in &rows {
+        writer.write_record(row)?;
+    }
+
+    writer.flush()?;
+
+    Ok(results)
+}
+
+fn tenant_of(operation: &AdminOperation) -> &str {
+    operation.tenant.as_deref().unwrap_or_default().trim()
+}
+
+fn apply_operation(
+    headers: &StringRecord,
+    rows: &mut Vec<StringRecord>,
+    operation: &AdminOperation,
+) -> Result<(), String> {
+    let action = AdminAction::new(operation.operator.trim(), operation.reason.trim())?;
+    let tenant = tenant_of(operation);
+    let client_id: ClientId = operation
+        .client
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid client id: {}", operation.client))?;
+    let tx: TxId = operation
+        .tx
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid tx id: {}", operation.tx))?;
+    let field = |row: &StringRecord, name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .and_then(|index| row.get(index))
+            .unwrap_or_default()
+            .trim()
+            .to_owned()
+    };
+    let is_tx = |row: &StringRecord| {
+        field(row, "tenant") == tenant && field(row, "tx").parse::<TxId>() == Ok(tx)
+    };
+
+    match operation.op.trim() {
+        "adjustment" => {
+            let amount = operation.amount.as_deref().unwrap_or_default().trim();
+
+            if parse_signed_amount(amount).is_none() {
+                return Err(format!("invalid amount: {:?}", amount));
+            }
+
+            if rows.iter().any(is_tx) {
+                return Err(format!("tx {} is already in use", tx));
+            }
+
+            rows.push(admin_row(
+                headers,
+                "adjustment",
+                client_id,
+                tx,
+                amount,
+                tenant,
+                &action,
+            ));
+        }
+        "approve" => {
+            let pending = pending_adjustments(headers, rows, tenant)?;
+
+            rows.push(admin_row(
+                headers, "approve", client_id, tx, "", tenant, &action,
+            ));
+
+            if pending_adjustments(headers, rows, tenant)? == pending {
+                rows.pop();
+
+                return Err(format!(
+                    "no adjustment with tx {} for client {} awaiting another operator's approval",
+                    tx, client_id
+                ));
+            }
+        }
+        "reversal" => {
+            let index = rows
+                .iter()
+                .position(|row| {
+                    is_tx(row) && matches!(&*field(row, "type"), "deposit" | "withdrawal")
+                })
+                .ok_or_else(|| format!("no deposit or withdrawal with tx {}", tx))?;
+
+            if field(&rows[index], "client").parse::<ClientId>() != Ok(client_id) {
+                return Err(format!("tx {} isn't for client {}", tx, client_id));
+            }
+
+            rows.remove(index);
+        }
+        "unlock" => return Err("a chargeback's lock can't be lifted".to_owned()),
+        op => return Err(format!("unknown operation: {:?}", op)),
+    }
+
+    Ok(())
+}
+
+fn admin_row(
+    headers: &StringRecord,
+    kind: &str,
+    client_id: ClientId,
+    tx: TxId,
+    amount: &str,
+    tenant: &str,
+    action: &AdminAction,
+) -> StringRecord {
+    let mut row = StringRecord::new();
+
+    for header in headers.iter() {
+        row.push_field(
+            match header {
+                "type" => kind.to_owned(),
+                "client" => client_id.to_string(),
+                "tx" => tx.to_string(),
+                "amount" => amount.to_owned(),
+                "tenant" => tenant.to_owned(),
+                "operator" => action.operator.clone(),
+                "reason" => action.reason.clone(),
+                _ => String::new(),
+            }
+            .as_str(),
+        );
+    }
+
+    row
+}
+
+/*
+Replays the rows to count the adjustments still waiting for approval in `tenant`.
+*/
+fn pending_adjustments(
+    headers: &StringRecord,
+    rows: &[StringRecord],
+    tenant: &str,
+) -> Result<usize, String> {
+    let mut database = TenantDatabase::new();
+
+    for row in rows {
+        let transaction_text: TransactionText = row
+            .deserialize(Some(headers))
+            .map_err(|error| error.to_string())?;
+
+        transaction_text
+            .validate(row.position().map_or(0, |position| position.line()))
+            .map_err(|error| error.to_string())?;
+
+        let row_tenant = transaction_text.tenant().to_owned();
+        let date = transaction_text.date().map_err(|error| error.to_string())?;
+
+        database.apply(&row_tenant, &transaction_text.into(), date);
+    }
+
+    Ok(database
+        .tenant(tenant)
+        .map_or(0, |accounts| accounts.pending_adjustments()))
+}
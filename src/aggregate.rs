@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    accounts::{Account, AccountDatabase},
+    metadata::ClientMetadata,
+    transactions::ClientId,
+    Money,
+};
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Grouping {
+    All,
+    ClientRange(u16),
+    Field(String),
+}
+
+/*
+Averages and medians are truncated to our 4 decimal places of precision rather than rounded, so
+they can come out a hair under the true value.  That's fine for reporting; nothing feeds these
+numbers back into balances.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct CohortSummary {
+    pub group: String,
+    pub accounts: usize,
+    pub locked: usize,
+    pub total_available: String,
+    pub average_available: String,
+    pub median_available: String,
+    pub total_held: String,
+}
+
+pub fn aggregate(
+    accounts: &AccountDatabase,
+    grouping: &Grouping,
+    metadata: &ClientMetadata,
+) -> Vec<CohortSummary> {
+    let mut cohorts: BTreeMap<String, Vec<&Account>> = BTreeMap::new();
+
+    for account in accounts.accounts() {
+        cohorts
+            .entry(group_of(account.client_id(), grouping, metadata))
+            .or_default()
+            .push(account);
+    }
+
+    cohorts
+        .into_iter()
+        .map(|(group, members)| summarize(group, &members))
+        .collect()
+}
+
+fn group_of(client_id: ClientId, grouping: &Grouping, metadata: &ClientMetadata) -> String {
+    match grouping {
+        Grouping::All => "all".to_owned(),
+        Grouping::ClientRange(size) => {
+            let start = client_id.0 - client_id.0 % size;
+            let end = start.saturating_add(size - 1);
+
+            format!("{:05}-{:05}", start, end)
+        }
+        Grouping::Field(name) => metadata
+            .field(client_id, name)
+            .unwrap_or_default()
+            .to_owned(),
+    }
+}
+
+fn summarize(group: String, members: &[&Account]) -> CohortSummary {
+    let mut available: Vec<Money> = members.iter().map(|account| account.available()).collect();
+    available.sort();
+
+    let count = available.len() as u64;
+    let total_available: Money = available.iter().copied().sum();
+    let middle = available.len() / 2;
+    let median_available = if available.len().is_multiple_of(2) {
+        (available[middle - 1] + available[middle]) / 2
+    } else {
+        available[middle]
+    };
+
+    CohortSummary {
+        group,
+        accounts: members.len(),
+        locked: members.iter().filter(|account| account.is_locked()).count(),
+        total_available: total_available.to_string(),
+        average_available: (total_available / count).to_string(),
+        median_available: median_available.to_string(),
+        total_held: members
+            .iter()
+            .map(|account| account.held())
+            .sum::<Money>()
+            .to_string(),
+    }
+}
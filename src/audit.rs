@@ -0,0 +1,178 @@
+use std::{error::Error, io};
+
+use csv::{Reader, Writer};
+use serde::Serialize;
+
+use crate::{
+    accounts::AccountOptions, ledger::LedgerEvent, tenants::TenantDatabase,
+    transactions::TransactionText,
+};
+
+/*
+Who performed a manual intervention on the ledger, and why.  Every administrative row written
+to a transaction log carries both, in its `operator` and `reason` columns, so the log itself is
+the audit trail.
+
+Reason codes are short tokens agreed with compliance (e.g. `gdpr-request`), not free text.
+*/
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AdminAction {
+    pub operator: String,
+    pub reason: String,
+}
+
+impl AdminAction {
+    pub fn new(operator: &str, reason: &str) -> Result<AdminAction, String> {
+        if operator.trim().is_empty() {
+            return Err("an operator id is required".to_owned());
+        }
+
+        if reason.is_empty()
+            || !reason
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("invalid reason code: {:?}", reason));
+        }
+
+        Ok(AdminAction {
+            operator: operator.to_owned(),
+            reason: reason.to_owned(),
+        })
+    }
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct AuditEntry {
+    pub tenant: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub client: String,
+    pub tx: String,
+    pub date: String,
+    pub operator: String,
+    pub reason: String,
+
+    /*
+    Whether the account's funds covered what the row did -- empty unless it fell short, as a
+    negative adjustment can.  For an approval, this is about the adjustment it approved.
+    */
+    pub shortfall: String,
+}
+
+/*
+The rows of a transaction log that were written by an operator rather than received from
+upstream.
+*/
+pub const ADMIN_KINDS: [&str; 3] = ["tombstone", "adjustment", "approve"];
+
+/*
+The operator named on entries the engine made itself, by rule, rather than any person.
+*/
+pub const RULE_OPERATOR: &str = "rule";
+
+/*
+Exports every administrative row in a transaction log, with who made it and why, for review.
+
+The whole log is applied along the way, under `options`, so each row can say whether it fell
+short -- and responses the engine made by rule, such as a low-balance alert or lock, appear
+right after the row that triggered them.
+*/
+pub fn audit_log<R: io::Read, W: io::Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    options: AccountOptions,
+) -> Result<usize, Box<dyn Error>> {
+    let mut database = TenantDatabase::with_options(options);
+    let mut count = 0;
+    let headers = reader.headers()?.clone();
+
+    for record_result in reader.records() {
+        let record = record_result?;
+        let transaction: TransactionText = record.deserialize(Some(&headers))?;
+
+        transaction.validate(record.position().map_or(0, |position| position.line()))?;
+
+        let field = |name: &str| transaction.field(name).unwrap_or_default().to_owned();
+        let entry = AuditEntry {
+            tenant: field("tenant"),
+            kind: field("type"),
+            client: field("client"),
+            tx: field("tx"),
+            date: field("date"),
+            operator: field("operator"),
+            reason: field("reason"),
+            shortfall: String::new(),
+        };
+        let shortfalls = |database: &TenantDatabase| {
+            database
+                .tenant(&entry.tenant)
+                .map_or(&[][..], |accounts| accounts.shortfalls())
+                .len()
+        };
+        let events = |database: &TenantDatabase| {
+            database
+                .tenant(&entry.tenant)
+                .map_or(&[][..], |accounts| accounts.events())
+                .len()
+        };
+        let before = shortfalls(&database);
+        let first_event = events(&database);
+        let date = transaction.date()?;
+
+        database.apply(&entry.tenant, &transaction.into(), date);
+
+        let rule_entries: Vec<AuditEntry> = database
+            .tenant(&entry.tenant)
+            .map_or(&[][..], |accounts| &accounts.events()[first_event..])
+            .iter()
+            .filter_map(|event| match *event {
+                LedgerEvent::LowBalance {
+                    client_id,
+                    transaction_id,
+                    available,
+                    response,
+                    date,
+                } => Some(AuditEntry {
+                    tenant: entry.tenant.clone(),
+                    kind: "low_balance".to_owned(),
+                    client: client_id.to_string(),
+                    tx: transaction_id.to_string(),
+                    date: date.map(|date| date.to_string()).unwrap_or_default(),
+                    operator: RULE_OPERATOR.to_owned(),
+                    reason: format!("low-balance-{}", response.name()),
+                    shortfall: String::new(),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        if !ADMIN_KINDS.contains(&entry.kind.to_lowercase().as_str()) {
+            for rule_entry in rule_entries {
+                writer.serialize(rule_entry)?;
+                count += 1;
+            }
+
+            continue;
+        }
+
+        let shortfall = match database.tenant(&entry.tenant) {
+            Some(accounts) if shortfalls(&database) > before => {
+                accounts.shortfalls()[before].outcome.name().to_owned()
+            }
+            _ => String::new(),
+        };
+
+        writer.serialize(AuditEntry { shortfall, ..entry })?;
+        count += 1;
+
+        for rule_entry in rule_entries {
+            writer.serialize(rule_entry)?;
+            count += 1;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    io,
+};
+
+use csv::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cdc::BalanceState,
+    tenants::TenantDatabase,
+    transactions::{ClientId, TransactionRecord, TransactionText, TxId},
+    Money,
+};
+
+/*
+One row of a correction file, keyed to the deposit or withdrawal it corrects:
+
+    reversal:   the original never happened.  Disputes that referenced it are left with
+                nothing to dispute, and are ignored as they would have been.
+    correction: the original happened, for `amount` instead.
+*/
+#[derive(Deserialize, Debug)]
+pub struct CorrectionRow {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub amount: Option<String>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Correction {
+    Reversal,
+    Amount(Money),
+}
+
+/*
+An account the corrections changed, before and after.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct AccountDelta {
+    pub tenant: String,
+    pub client_id: ClientId,
+    pub available_before: String,
+    pub available_after: String,
+    pub held_before: String,
+    pub held_after: String,
+    pub locked_before: bool,
+    pub locked_after: bool,
+}
+
+pub struct Backfill {
+    pub original: TenantDatabase,
+    pub corrected: TenantDatabase,
+    pub deltas: Vec<AccountDelta>,
+}
+
+/*
+Replays a transaction log twice, as it was and with the corrections merged in, so a fix to
+history lands exactly where the original did -- a corrected deposit that was later disputed
+is disputed for its corrected amount, and a withdrawal it funded may now be refused.
+
+Every correction must match a deposit or withdrawal in the log, for the same client; otherwise
+nothing is applied and the mismatch is returned as an error.
+*/
+pub fn backfill<R: io::Read, C: io::Read>(
+    reader: &mut Reader<R>,
+    corrections: &mut Reader<C>,
+) -> Result<Backfill, Box<dyn Error>> {
+    let mut pending: HashMap<(String, TxId), (ClientId, Correction)> = HashMap::new();
+
+    for row_result in corrections.deserialize() {
+        let row: CorrectionRow = row_result?;
+        let correction = match (row.kind.trim(), row.amount.as_deref().map(str::trim)) {
+            ("reversal", None | Some("")) => Correction::Reversal,
+            ("correction", Some(amount)) => Correction::Amount(
+                amount
+                    .parse()
+                    .map_err(|_| format!("invalid amount in correction of tx {}", row.tx))?,
+            ),
+            (kind, _) => {
+                return Err(format!("unexpected correction of tx {}: {}", row.tx, kind).into())
+            }
+        };
+        let key = (row.tenant.unwrap_or_default(), row.tx);
+
+        if pending.insert(key, (row.client, correction)).is_some() {
+            return Err(format!("tx {} is corrected more than once", row.tx).into());
+        }
+    }
+
+    let mut original = TenantDatabase::new();
+    let mut corrected = TenantDatabase::new();
+
+    let headers = reader.headers()?.clone();
+
+    for record_result in reader.records() {
+        let record = record_result?;
+        let transaction_text: TransactionText = record.deserialize(Some(&headers))?;
+
+        transaction_text.validate(record.position().map_or(0, |position| position.line()))?;
+
+        let tenant = transaction_text.tenant().to_owned();
+        let date = transaction_text.date()?;
+        let transaction: TransactionRecord = transaction_text.into();
+
+        original.apply(&tenant, &transaction, date);
+
+        let correctable = matches!(
+            transaction,
+            TransactionRecord::Deposit { .. } | TransactionRecord::Withdrawl { .. }
+        );
+        let id = transaction.id();
+        let correction = match pending.remove(&(tenant.clone(), id.transaction_id)) {
+            Some((client_id, correction)) if correctable && client_id == id.client_id => correction,
+            Some((client_id, correction)) => {
+                return Err(format!(
+                    "tx {} is a {} for client {}, not a deposit or withdrawal for client {}",
+                    id.transaction_id,
+                    transaction.kind(),
+                    id.client_id,
+                    client_id
+                )
+                .into())
+            }
+            None => {
+                corrected.apply(&tenant, &transaction, date);
+                continue;
+            }
+        };
+
+        match (correction, transaction) {
+            (Correction::Reversal, _) => {}
+            (Correction::Amount(amount), TransactionRecord::Deposit { id, .. }) => {
+                corrected.apply(&tenant, &TransactionRecord::Deposit { id, amount }, date)
+            }
+            (Correction::Amount(amount), TransactionRecord::Withdrawl { id, .. }) => {
+                corrected.apply(&tenant, &TransactionRecord::Withdrawl { id, amount }, date)
+            }
+            (Correction::Amount(amount), _) => unreachable!("only deposits and withdrawals"),
+        }
+    }
+
+    if let Some(((tenant, transaction_id), _)) = pending.into_iter().next() {
+        return Err(format!(
+            "no deposit or withdrawal with tx {} to correct",
+            transaction_id
+        )
+        .into());
+    }
+
+    let deltas = deltas(&original, &corrected);
+
+    Ok(Backfill {
+        original,
+        corrected,
+        deltas,
+    })
+}
+
+fn deltas(original: &TenantDatabase, corrected: &TenantDatabase) -> Vec<AccountDelta> {
+    let mut deltas = Vec::new();
+
+    for (tenant, accounts) in original.tenants() {
+        let client_ids: BTreeSet<ClientId> = [Some(accounts), corrected.tenant(tenant)]
+            .into_iter()
+            .flatten()
+            .flat_map(|accounts| accounts.accounts().map(|account| account.client_id()))
+            .collect();
+
+        for client_id in client_ids {
+            let before: BalanceState = accounts.account(client_id).into();
+            let after: BalanceState = corrected
+                .tenant(tenant)
+                .and_then(|accounts| accounts.account(client_id))
+                .into();
+
+            if before != after {
+                deltas.push(AccountDelta {
+                    tenant: tenant.to_owned(),
+                    client_id,
+                    available_before: before.available,
+                    available_after: after.available,
+                    held_before: before.held,
+                    held_after: after.held,
+                    locked_before: before.locked,
+                    locked_after: after.locked,
+                });
+            }
+        }
+    }
+
+    deltas
+}
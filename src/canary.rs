@@ -0,0 +1,71 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::{cdc::BalanceState, tenants::TenantDatabase, transactions::ClientId};
+
+/*
+One field of one account on which the canary run disagreed with the current one.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct AccountDivergence {
+    pub tenant: String,
+    pub client_id: ClientId,
+    pub field: &'static str,
+    pub current: String,
+    pub canary: String,
+}
+
+/*
+Compares every account in two runs over the same input -- one under the current behavior
+version, one under the candidate -- field by field.  An account only one of them has is
+compared against an empty one, so it shows up wherever the other has funds or is locked.
+*/
+pub fn compare(current: &TenantDatabase, canary: &TenantDatabase) -> Vec<AccountDivergence> {
+    let tenants: BTreeSet<&str> = current
+        .tenants()
+        .chain(canary.tenants())
+        .map(|(tenant, _)| tenant)
+        .collect();
+    let mut divergences = Vec::new();
+
+    for tenant in tenants {
+        let client_ids: BTreeSet<ClientId> = [current.tenant(tenant), canary.tenant(tenant)]
+            .into_iter()
+            .flatten()
+            .flat_map(|accounts| accounts.accounts().map(|account| account.client_id()))
+            .collect();
+
+        for client_id in client_ids {
+            let state = |database: &TenantDatabase| -> BalanceState {
+                database
+                    .tenant(tenant)
+                    .and_then(|accounts| accounts.account(client_id))
+                    .into()
+            };
+            let (expected, actual) = (state(current), state(canary));
+
+            for (field, expected, actual) in [
+                ("available", expected.available, actual.available),
+                ("held", expected.held, actual.held),
+                (
+                    "locked",
+                    expected.locked.to_string(),
+                    actual.locked.to_string(),
+                ),
+            ] {
+                if expected != actual {
+                    divergences.push(AccountDivergence {
+                        tenant: tenant.to_owned(),
+                        client_id,
+                        field,
+                        current: expected,
+                        canary: actual,
+                    });
+                }
+            }
+        }
+    }
+
+    divergences
+}
@@ -0,0 +1,104 @@
+use std::io;
+
+use serde::Serialize;
+
+use crate::{
+    accounts::{Account, Balances},
+    ledger::{LedgerEvent, Projection},
+    transactions::{ClientId, TxId},
+};
+
+/*
+One line of the change feed: an applied transaction and the balances of the account it touched,
+immediately before and after.  A consumer can maintain its own copy of every balance by
+applying `after`, or check it's in sync by comparing `before`.
+
+`sequence` is the event's position in the tenant's ledger, so a consumer can spot gaps and
+skip events it has already seen.
+*/
+#[derive(Serialize, PartialEq, Debug)]
+pub struct BalanceChange<'a> {
+    pub tenant: &'a str,
+    pub sequence: usize,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub client_id: ClientId,
+    pub tx: TxId,
+    pub date: Option<String>,
+    pub before: BalanceState,
+    pub after: BalanceState,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Default)]
+pub struct BalanceState {
+    pub available: String,
+    pub held: String,
+    pub locked: bool,
+}
+
+impl From<Option<&Account>> for BalanceState {
+    fn from(account: Option<&Account>) -> BalanceState {
+        match account {
+            Some(account) => BalanceState {
+                available: account.available().to_string(),
+                held: account.held().to_string(),
+                locked: account.is_locked(),
+            },
+            None => BalanceState {
+                available: "0.0".to_owned(),
+                held: "0.0".to_owned(),
+                locked: false,
+            },
+        }
+    }
+}
+
+/*
+Writes the change feed, as JSON lines, for the events of one tenant's ledger from `first` on.
+Earlier events are replayed to establish the starting balances but aren't written, so an
+incremental run only emits what it changed.
+*/
+pub fn write_changes<W: io::Write>(
+    tenant: &str,
+    events: &[LedgerEvent],
+    first: usize,
+    writer: &mut W,
+) -> io::Result<usize> {
+    let mut balances = Balances::new();
+    let mut count = 0;
+
+    for (sequence, event) in events.iter().enumerate() {
+        let LedgerEvent::TransactionApplied {
+            transaction, date, ..
+        } = event
+        else {
+            balances.project(event);
+            continue;
+        };
+        let client_id = transaction.id().client_id;
+        let before = balances.get(client_id).into();
+
+        balances.project(event);
+
+        if sequence < first {
+            continue;
+        }
+
+        let change = BalanceChange {
+            tenant,
+            sequence,
+            kind: transaction.kind(),
+            client_id,
+            tx: transaction.id().transaction_id,
+            date: date.map(|date| date.to_string()),
+            before,
+            after: balances.get(client_id).into(),
+        };
+
+        serde_json::to_writer(&mut *writer, &change)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+
+    Ok(count)
+}
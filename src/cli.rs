@@ -0,0 +1,1056 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    accounts::{AccountOptions, BehaviorVersion, LowBalance},
+    aggregate::Grouping,
+    audit::AdminAction,
+    clock::FixedClock,
+    completions::Shell,
+    dates::{Date, DateBasis},
+    errors::ErrorCode,
+    export_db::ExportTables,
+    filter::TransactionFilter,
+    generate::Profile,
+    minimize::Predicate,
+    rounding::Rounding,
+    schema::SchemaVersion,
+    search::AccountFilter,
+    sinks::SinkSpec,
+    transactions::ClientId,
+    Money,
+};
+
+pub const USAGE: &str = "\
+usage: notfizzbuzz [--prior <transactions.csv>] [--changed-only] [--stats <stats.json>]
+                   [--pseudonymize --salt <secret>] [--expected-transactions <n>]
+                   [--unknown-clients ignore|open-account] [--writer-thread] [--mmap]
+                   [--client-mismatches <report.csv>] [--dispute-amounts ignore|warn|reject]
+                   [--partial-disputes] [--mapping <mapping.csv>] [--rules <rules.rhai>]
+                   [--schema-version 1|2|3|4] [--provenance <sidecar.json> [--now <timestamp>]]
+                   [--balance-cap <amount> [--cap-policy reject|partial]]
+                   [--tx-index <index.csv>] [--tx-map <map.csv>] [--auto-approve-adjustments]
+                   [--latencies] [--errors-format text|json]
+                   [--export-db <postgres://...> [--summary-table <name>] [--ledger-table <name>]]
+                   [--publish-redis <redis://...> [--redis-prefix <prefix>]]
+                   [--cdc <events.jsonl>|-] [--mirror <accepted.csv>] [--behavior-version 1|2]
+                   [--sink csv|json[pseudonymize,drop=<column>]:<path>|-]...
+                   [--conflict-policy input-order|dispute-first] [--lifecycle-gate reject|park]
+                   [--memo-max-length <n>] [--tx-ids numeric|uuid] [--date-basis booking|value]
+                   [--seal <seal.json> [--late-transactions <late.csv>]]
+                   [--low-balance <amount> [--low-balance-response alert|hold|lock]]
+                   [--conflicting-duplicates <report.csv>] [--shortfalls <report.csv>]
+                   [--metadata <clients.csv> [--exclude-tag <tag>]...
+                    [--sandbox-tag <tag>... --sandbox-summary <sandbox.csv>]
+                    [--group-cap <amount>] [--group-summary <groups.csv>]]
+                   [--canary-behavior 1|2 --canary-report <divergences.csv>]
+                   input.csv > output.csv
+       notfizzbuzz balance-history [--client <id>] [--tenant <name>] [--date-basis booking|value]
+                                   [--pseudonymize --salt <secret>] input.csv > history.csv
+       notfizzbuzz status-history [--client <id>] [--tenant <name>]
+                                  [--pseudonymize --salt <secret>] input.csv > statuses.csv
+       notfizzbuzz aggregate [--bucket-size <n> | --group-by <field> --metadata <clients.csv>]
+                             [--format csv|json] [--tenant <name>] input.csv > cohorts.csv
+       notfizzbuzz movers [--top <n>] [--snapshot <summary.csv>]... [--threshold <z>]
+                          [--tenant <name>] [--pseudonymize --salt <secret>] input.csv > movers.csv
+       notfizzbuzz diff <before.csv> <after.csv> > diff.csv
+       notfizzbuzz search [--locked] [--open-dispute] [--min-total <amount>] [--max-total <amount>]
+                          [--after <client>] [--limit <n>] [--tenant <name>] input.csv > page.csv
+       notfizzbuzz prove --client <id> [--tenant <name>] input.csv > proof.json
+       notfizzbuzz erase-client <id> --operator <id> --reason <code> [--tenant <name>]
+                                [--preview] input.csv > erased.csv
+       notfizzbuzz admin --script <ops.csv> --report <report.csv> [--all-or-nothing]
+                         input.csv > updated.csv
+       notfizzbuzz audit [--low-balance <amount> [--low-balance-response alert|hold|lock]]
+                         input.csv > audit.csv
+       notfizzbuzz query input.csv \"<sql>\" > results.csv
+       notfizzbuzz backfill --corrections <fix.csv> (--delta <report.csv> | --preview)
+                            input.csv > corrected.csv
+       notfizzbuzz compact --before <yyyy-mm-dd> [--lifecycle-gate reject|park]
+                           [--behavior-version 1|2] input.csv > compacted.csv
+       notfizzbuzz finalize --through <yyyy-mm-dd> [--date-basis booking|value]
+                            input.csv > seal.json
+       notfizzbuzz simulate --schedule <schedule.csv> --until <yyyy-mm-dd> [--period-days <n>]
+                            [--rounding toward-zero|half-up|half-even] [--tenant <name>]
+                            [--date-basis booking|value] input.csv > projection.csv
+       notfizzbuzz reidentify --salt <secret> [--tenant <name>] <token>... > clients.csv
+       notfizzbuzz export-tx-index [--tx-index <index.csv>] input.csv > index.csv
+       notfizzbuzz split --shards <n> --output <dir> input.csv
+       notfizzbuzz filter [--kind <type>,...] [--client <id>|<first>-<last>] [--from <yyyy-mm-dd>]
+                          input.csv > subset.csv
+       notfizzbuzz minimize --predicate panics|fails|locked:<client> [--tenant <name>]
+                            input.csv > minimal.csv
+       notfizzbuzz merge [--conflicts <report.csv>] <summary.csv>... > summary.csv
+       notfizzbuzz generate --profile retail|high-dispute|whales --transactions <n> [--seed <n>]
+                            > input.csv
+       notfizzbuzz regress --baseline <binary> --candidate <binary> --inputs <dir>
+                           > divergences.csv
+       notfizzbuzz explain [<code>]
+       notfizzbuzz completions bash|zsh|fish > completions
+       notfizzbuzz man > notfizzbuzz.1";
+
+/*
+Summarize carries far more options than any other command, but there's only ever one Command,
+so the size difference costs nothing worth boxing for.
+*/
+#[allow(clippy::large_enum_variant)]
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    Summarize {
+        input: PathBuf,
+        prior: Option<PathBuf>,
+        changed_only: bool,
+        stats: Option<PathBuf>,
+        salt: Option<String>,
+        account_options: AccountOptions,
+        writer_thread: bool,
+        mmap: bool,
+        latencies: bool,
+        errors_format: ErrorFormat,
+        client_mismatches: Option<PathBuf>,
+        conflicting_duplicates: Option<PathBuf>,
+        shortfalls: Option<PathBuf>,
+        metadata: Option<PathBuf>,
+        exclude_tags: Vec<String>,
+        sandbox_tags: Vec<String>,
+        sandbox_summary: Option<PathBuf>,
+        group_cap: Option<Money>,
+        group_summary: Option<PathBuf>,
+        canary_behavior: Option<BehaviorVersion>,
+        canary_report: Option<PathBuf>,
+        mapping: Option<PathBuf>,
+        rules: Option<PathBuf>,
+        schema: SchemaVersion,
+        provenance: Option<PathBuf>,
+        now: Option<FixedClock>,
+        tx_index: Option<PathBuf>,
+        tx_map: Option<PathBuf>,
+        seal: Option<PathBuf>,
+        late_transactions: Option<PathBuf>,
+        export_db: Option<String>,
+        export_tables: ExportTables,
+        publish_redis: Option<String>,
+        redis_prefix: String,
+        cdc: Option<PathBuf>,
+        mirror: Option<PathBuf>,
+        sinks: Vec<SinkSpec>,
+        sink_salt: Option<String>,
+    },
+    BalanceHistory {
+        input: PathBuf,
+        client_id: Option<ClientId>,
+        tenant: String,
+        salt: Option<String>,
+        date_basis: DateBasis,
+    },
+    StatusHistory {
+        input: PathBuf,
+        client_id: Option<ClientId>,
+        tenant: String,
+        salt: Option<String>,
+    },
+    Aggregate {
+        input: PathBuf,
+        grouping: Grouping,
+        metadata: Option<PathBuf>,
+        format: OutputFormat,
+        tenant: String,
+    },
+    Movers {
+        input: PathBuf,
+        snapshots: Vec<PathBuf>,
+        count: usize,
+        threshold: f64,
+        tenant: String,
+        salt: Option<String>,
+    },
+    Diff {
+        before: PathBuf,
+        after: PathBuf,
+    },
+    Search {
+        input: PathBuf,
+        filter: AccountFilter,
+        after: Option<ClientId>,
+        limit: usize,
+        tenant: String,
+    },
+    Prove {
+        input: PathBuf,
+        client_id: ClientId,
+        tenant: String,
+    },
+    EraseClient {
+        input: PathBuf,
+        client_id: ClientId,
+        tenant: String,
+        action: AdminAction,
+        preview: bool,
+    },
+    Admin {
+        input: PathBuf,
+        script: PathBuf,
+        report: PathBuf,
+        all_or_nothing: bool,
+    },
+    Audit {
+        input: PathBuf,
+        low_balance: Option<LowBalance>,
+    },
+    Query {
+        input: PathBuf,
+        sql: String,
+    },
+    /*
+    With `preview`, only the per-account changes are written, to stdout, in place of both
+    outputs.
+    */
+    Backfill {
+        input: PathBuf,
+        corrections: PathBuf,
+        delta: Option<PathBuf>,
+        preview: bool,
+    },
+    Compact {
+        input: PathBuf,
+        horizon: Date,
+        options: AccountOptions,
+    },
+    Finalize {
+        input: PathBuf,
+        through: Date,
+        date_basis: DateBasis,
+    },
+    Simulate {
+        input: PathBuf,
+        schedule: PathBuf,
+        until: Date,
+        period_days: u32,
+        rounding: Rounding,
+        date_basis: DateBasis,
+        tenant: String,
+    },
+    Reidentify {
+        tokens: Vec<String>,
+        salt: String,
+        tenant: String,
+    },
+    ExportTxIndex {
+        input: PathBuf,
+        tx_index: Option<PathBuf>,
+    },
+    Split {
+        input: PathBuf,
+        shards: u32,
+        output: PathBuf,
+    },
+    Filter {
+        input: PathBuf,
+        filter: TransactionFilter,
+    },
+    Minimize {
+        input: PathBuf,
+        predicate: Predicate,
+        tenant: String,
+    },
+    Merge {
+        summaries: Vec<PathBuf>,
+        conflicts: Option<PathBuf>,
+    },
+    Generate {
+        profile: Profile,
+        transactions: usize,
+        seed: u64,
+    },
+    Regress {
+        baseline: PathBuf,
+        candidate: PathBuf,
+        inputs: PathBuf,
+    },
+    Completions {
+        shell: Shell,
+    },
+    Man,
+    /*
+    Without a code, lists them all.
+    */
+    Explain {
+        code: Option<ErrorCode>,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+How errors, warnings and the closing report reach stderr: as free text for people, or as one JSON
+object per line for tooling -- see errors::ErrorReport.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+Hand rolled rather than pulling in an argument parsing crate -- there are only a handful of
+commands and flags, and each command validates its own.
+*/
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    match args {
+        [command, rest @ ..] if command == "balance-history" => {
+            let mut flags = Flags::parse(
+                rest,
+                &["--client", "--tenant", "--salt", "--date-basis"],
+                &["--pseudonymize"],
+            )?;
+
+            Ok(Command::BalanceHistory {
+                client_id: flags.parsed("--client")?,
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                salt: flags.pseudonymize()?,
+                date_basis: flags.parsed("--date-basis")?.unwrap_or_default(),
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "status-history" => {
+            let mut flags = Flags::parse(
+                rest,
+                &["--client", "--tenant", "--salt"],
+                &["--pseudonymize"],
+            )?;
+
+            Ok(Command::StatusHistory {
+                client_id: flags.parsed("--client")?,
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                salt: flags.pseudonymize()?,
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "aggregate" => {
+            let mut flags = Flags::parse(
+                rest,
+                &[
+                    "--bucket-size",
+                    "--group-by",
+                    "--metadata",
+                    "--format",
+                    "--tenant",
+                ],
+                &[],
+            )?;
+            let metadata = flags.take("--metadata").map(PathBuf::from);
+            let grouping = match (flags.parsed("--bucket-size")?, flags.take("--group-by")) {
+                (None, None) => Grouping::All,
+                (Some(0), None) => return Err("--bucket-size must be at least 1".to_owned()),
+                (Some(size), None) => Grouping::ClientRange(size),
+                (None, Some(_)) if metadata.is_none() => {
+                    return Err("--group-by requires --metadata".to_owned())
+                }
+                (None, Some(field)) => Grouping::Field(field),
+                (Some(_), Some(_)) => {
+                    return Err("--bucket-size and --group-by are mutually exclusive".to_owned())
+                }
+            };
+
+            Ok(Command::Aggregate {
+                grouping,
+                metadata,
+                format: flags.parsed("--format")?.unwrap_or(OutputFormat::Csv),
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "movers" => {
+            let mut flags = Flags::parse(
+                rest,
+                &["--top", "--snapshot", "--threshold", "--tenant", "--salt"],
+                &["--pseudonymize"],
+            )?;
+
+            Ok(Command::Movers {
+                snapshots: flags
+                    .take_all("--snapshot")
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect(),
+                count: flags.parsed("--top")?.unwrap_or(10),
+                threshold: flags.parsed("--threshold")?.unwrap_or(3.0),
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                salt: flags.pseudonymize()?,
+                input: flags.input()?,
+            })
+        }
+        [command, before, after] if command == "diff" => Ok(Command::Diff {
+            before: before.into(),
+            after: after.into(),
+        }),
+        [command, rest @ ..] if command == "search" => {
+            let mut flags = Flags::parse(
+                rest,
+                &[
+                    "--min-total",
+                    "--max-total",
+                    "--after",
+                    "--limit",
+                    "--tenant",
+                ],
+                &["--locked", "--open-dispute"],
+            )?;
+
+            Ok(Command::Search {
+                filter: AccountFilter {
+                    locked_only: flags.switch("--locked"),
+                    open_dispute_only: flags.switch("--open-dispute"),
+                    min_total: flags.parsed("--min-total")?,
+                    max_total: flags.parsed("--max-total")?,
+                },
+                after: flags.parsed("--after")?,
+                limit: match flags.parsed("--limit")? {
+                    Some(0) => return Err("--limit must be at least 1".to_owned()),
+                    Some(limit) => limit,
+                    None => 100,
+                },
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "prove" => {
+            let mut flags = Flags::parse(rest, &["--client", "--tenant"], &[])?;
+
+            Ok(Command::Prove {
+                client_id: flags.parsed("--client")?.ok_or("prove requires --client")?,
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                input: flags.input()?,
+            })
+        }
+        [command, client_id, rest @ ..] if command == "erase-client" => {
+            let mut flags = Flags::parse(
+                rest,
+                &["--tenant", "--operator", "--reason"],
+                &["--preview"],
+            )?;
+
+            Ok(Command::EraseClient {
+                client_id: client_id
+                    .parse()
+                    .map_err(|_| format!("invalid client id: {}", client_id))?,
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                action: flags.admin_action()?,
+                preview: flags.switch("--preview"),
+                input: flags.input()?,
+            })
+        }
+        [command, _, _] if command == "query" && !cfg!(feature = "sql") => {
+            Err("query requires building with the sql feature".to_owned())
+        }
+        [command, input, sql] if command == "query" => Ok(Command::Query {
+            input: PathBuf::from(input),
+            sql: sql.clone(),
+        }),
+        [command, rest @ ..] if command == "admin" => {
+            let mut flags = Flags::parse(rest, &["--script", "--report"], &["--all-or-nothing"])?;
+
+            Ok(Command::Admin {
+                script: flags
+                    .take("--script")
+                    .ok_or("admin requires --script")?
+                    .into(),
+                report: flags
+                    .take("--report")
+                    .ok_or("admin requires --report")?
+                    .into(),
+                all_or_nothing: flags.switch("--all-or-nothing"),
+                input: flags.input()?,
+            })
+        }
+        [command] if command == "explain" => Ok(Command::Explain { code: None }),
+        [command, code] if command == "explain" => Ok(Command::Explain {
+            code: Some(
+                code.parse()
+                    .map_err(|_| format!("unknown error code: {}", code))?,
+            ),
+        }),
+        [command, rest @ ..] if command == "audit" => {
+            let mut flags = Flags::parse(rest, &["--low-balance", "--low-balance-response"], &[])?;
+
+            Ok(Command::Audit {
+                low_balance: flags.low_balance()?,
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "backfill" => {
+            let mut flags = Flags::parse(rest, &["--corrections", "--delta"], &["--preview"])?;
+            let delta = flags.take("--delta").map(PathBuf::from);
+            let preview = flags.switch("--preview");
+
+            match (&delta, preview) {
+                (None, false) => return Err("backfill requires --delta or --preview".to_owned()),
+                (Some(_), true) => return Err("--preview replaces --delta".to_owned()),
+                _ => {}
+            }
+
+            Ok(Command::Backfill {
+                corrections: flags
+                    .take("--corrections")
+                    .ok_or("backfill requires --corrections")?
+                    .into(),
+                delta,
+                preview,
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "compact" => {
+            let mut flags = Flags::parse(
+                rest,
+                &["--before", "--lifecycle-gate", "--behavior-version"],
+                &[],
+            )?;
+
+            Ok(Command::Compact {
+                horizon: flags
+                    .parsed("--before")?
+                    .ok_or("compact requires --before")?,
+                options: AccountOptions {
+                    lifecycle: flags.parsed("--lifecycle-gate")?.unwrap_or_default(),
+                    behavior: flags.parsed("--behavior-version")?.unwrap_or_default(),
+                    ..Default::default()
+                },
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "finalize" => {
+            let mut flags = Flags::parse(rest, &["--through", "--date-basis"], &[])?;
+
+            Ok(Command::Finalize {
+                through: flags
+                    .parsed("--through")?
+                    .ok_or("finalize requires --through")?,
+                date_basis: flags.parsed("--date-basis")?.unwrap_or_default(),
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "simulate" => {
+            let mut flags = Flags::parse(
+                rest,
+                &[
+                    "--schedule",
+                    "--until",
+                    "--period-days",
+                    "--rounding",
+                    "--tenant",
+                    "--date-basis",
+                ],
+                &[],
+            )?;
+
+            Ok(Command::Simulate {
+                schedule: flags
+                    .take("--schedule")
+                    .ok_or("simulate requires --schedule")?
+                    .into(),
+                until: flags
+                    .parsed("--until")?
+                    .ok_or("simulate requires --until")?,
+                period_days: match flags.parsed("--period-days")? {
+                    Some(0) => return Err("--period-days must be at least 1".to_owned()),
+                    Some(days) => days,
+                    None => 30,
+                },
+                rounding: flags.parsed("--rounding")?.unwrap_or_default(),
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                date_basis: flags.parsed("--date-basis")?.unwrap_or_default(),
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "reidentify" => {
+            let mut flags = Flags::parse(rest, &["--salt", "--tenant"], &[])?;
+
+            if flags.positional.is_empty() {
+                return Err(USAGE.to_owned());
+            }
+
+            Ok(Command::Reidentify {
+                salt: flags.take("--salt").ok_or("reidentify requires --salt")?,
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                tokens: flags.positional,
+            })
+        }
+        [command, rest @ ..] if command == "export-tx-index" => {
+            let mut flags = Flags::parse(rest, &["--tx-index"], &[])?;
+
+            Ok(Command::ExportTxIndex {
+                tx_index: flags.take("--tx-index").map(PathBuf::from),
+                input: flags.input()?,
+            })
+        }
+        [command, shell] if command == "completions" => Ok(Command::Completions {
+            shell: shell
+                .parse()
+                .map_err(|_| format!("unsupported shell: {}", shell))?,
+        }),
+        [command] if command == "man" => Ok(Command::Man),
+        [command, rest @ ..] if command == "split" => {
+            let mut flags = Flags::parse(rest, &["--shards", "--output"], &[])?;
+
+            Ok(Command::Split {
+                shards: match flags.parsed("--shards")? {
+                    Some(0) => return Err("--shards must be at least 1".to_owned()),
+                    Some(shards) => shards,
+                    None => return Err("split requires --shards".to_owned()),
+                },
+                output: flags
+                    .take("--output")
+                    .ok_or("split requires --output")?
+                    .into(),
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "filter" => {
+            let mut flags = Flags::parse(rest, &["--kind", "--client", "--from"], &[])?;
+
+            Ok(Command::Filter {
+                filter: TransactionFilter {
+                    kinds: match flags.take("--kind") {
+                        Some(kinds) => TransactionFilter::parse_kinds(&kinds)?,
+                        None => Vec::new(),
+                    },
+                    clients: flags.parsed("--client")?,
+                    from: flags.parsed("--from")?,
+                },
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "minimize" => {
+            let mut flags = Flags::parse(rest, &["--predicate", "--tenant"], &[])?;
+
+            Ok(Command::Minimize {
+                predicate: flags
+                    .parsed("--predicate")?
+                    .ok_or("minimize requires --predicate")?,
+                tenant: flags.take("--tenant").unwrap_or_default(),
+                input: flags.input()?,
+            })
+        }
+        [command, rest @ ..] if command == "merge" => {
+            let mut flags = Flags::parse(rest, &["--conflicts"], &[])?;
+
+            if flags.positional.is_empty() {
+                return Err(USAGE.to_owned());
+            }
+
+            Ok(Command::Merge {
+                conflicts: flags.take("--conflicts").map(PathBuf::from),
+                summaries: flags.positional.iter().map(PathBuf::from).collect(),
+            })
+        }
+        [command, rest @ ..] if command == "generate" => {
+            let mut flags = Flags::parse(rest, &["--profile", "--transactions", "--seed"], &[])?;
+
+            if !flags.positional.is_empty() {
+                return Err(USAGE.to_owned());
+            }
+
+            Ok(Command::Generate {
+                profile: flags
+                    .parsed("--profile")?
+                    .ok_or("generate requires --profile")?,
+                transactions: flags
+                    .parsed("--transactions")?
+                    .ok_or("generate requires --transactions")?,
+                seed: flags.parsed("--seed")?.unwrap_or_default(),
+            })
+        }
+        [command, rest @ ..] if command == "regress" => {
+            let mut flags = Flags::parse(rest, &["--baseline", "--candidate", "--inputs"], &[])?;
+
+            if !flags.positional.is_empty() {
+                return Err(USAGE.to_owned());
+            }
+
+            Ok(Command::Regress {
+                baseline: flags
+                    .take("--baseline")
+                    .ok_or("regress requires --baseline")?
+                    .into(),
+                candidate: flags
+                    .take("--candidate")
+                    .ok_or("regress requires --candidate")?
+                    .into(),
+                inputs: flags
+                    .take("--inputs")
+                    .ok_or("regress requires --inputs")?
+                    .into(),
+            })
+        }
+        [] => Err(USAGE.to_owned()),
+        _ => {
+            let mut flags = Flags::parse(
+                args,
+                &[
+                    "--prior",
+                    "--stats",
+                    "--salt",
+                    "--expected-transactions",
+                    "--unknown-clients",
+                    "--client-mismatches",
+                    "--conflicting-duplicates",
+                    "--shortfalls",
+                    "--metadata",
+                    "--exclude-tag",
+                    "--sandbox-tag",
+                    "--sandbox-summary",
+                    "--group-cap",
+                    "--group-summary",
+                    "--canary-behavior",
+                    "--canary-report",
+                    "--dispute-amounts",
+                    "--mapping",
+                    "--rules",
+                    "--schema-version",
+                    "--provenance",
+                    "--balance-cap",
+                    "--cap-policy",
+                    "--tx-index",
+                    "--tx-map",
+                    "--export-db",
+                    "--summary-table",
+                    "--ledger-table",
+                    "--publish-redis",
+                    "--redis-prefix",
+                    "--cdc",
+                    "--mirror",
+                    "--behavior-version",
+                    "--conflict-policy",
+                    "--lifecycle-gate",
+                    "--memo-max-length",
+                    "--tx-ids",
+                    "--date-basis",
+                    "--low-balance",
+                    "--low-balance-response",
+                    "--seal",
+                    "--late-transactions",
+                    "--now",
+                    "--errors-format",
+                    "--sink",
+                ],
+                &[
+                    "--changed-only",
+                    "--pseudonymize",
+                    "--writer-thread",
+                    "--mmap",
+                    "--partial-disputes",
+                    "--auto-approve-adjustments",
+                    "--latencies",
+                ],
+            )?;
+            let metadata = flags.take("--metadata").map(PathBuf::from);
+            let exclude_tags = flags.take_all("--exclude-tag");
+            let sandbox_tags = flags.take_all("--sandbox-tag");
+            let sandbox_summary = flags.take("--sandbox-summary").map(PathBuf::from);
+
+            if !exclude_tags.is_empty() && metadata.is_none() {
+                return Err("--exclude-tag requires --metadata".to_owned());
+            }
+
+            let group_cap = flags.parsed("--group-cap")?;
+            let group_summary = flags.take("--group-summary").map(PathBuf::from);
+
+            if group_cap.is_some() && metadata.is_none() {
+                return Err("--group-cap requires --metadata".to_owned());
+            }
+
+            if group_summary.is_some() && metadata.is_none() {
+                return Err("--group-summary requires --metadata".to_owned());
+            }
+
+            let seal = flags.take("--seal").map(PathBuf::from);
+            let late_transactions = flags.take("--late-transactions").map(PathBuf::from);
+
+            if late_transactions.is_some() && seal.is_none() {
+                return Err("--late-transactions requires --seal".to_owned());
+            }
+
+            let low_balance = flags.low_balance()?;
+            let sinks = flags
+                .take_all("--sink")
+                .iter()
+                .map(|sink| sink.parse())
+                .collect::<Result<Vec<SinkSpec>, String>>()?;
+            let cdc = flags.take("--cdc").map(PathBuf::from);
+
+            if cdc.as_deref() == Some(Path::new("-")) && sinks.iter().any(SinkSpec::is_stdout) {
+                return Err("--cdc - and a sink to stdout can't share it".to_owned());
+            }
+
+            /*
+            A pseudonymizing sink keys its tokens with --salt, which it can have without
+            --pseudonymize pseudonymizing every output.
+            */
+            let (salt, sink_salt) = if sinks.iter().any(|sink| sink.redaction.pseudonymize)
+                && !flags.switch("--pseudonymize")
+            {
+                match flags.take("--salt") {
+                    Some(salt) => (None, Some(salt)),
+                    None => return Err("a pseudonymizing sink requires --salt".to_owned()),
+                }
+            } else {
+                let salt = flags.pseudonymize()?;
+
+                (salt.clone(), salt)
+            };
+
+            let canary_behavior = flags.parsed("--canary-behavior")?;
+            let canary_report = flags.take("--canary-report").map(PathBuf::from);
+
+            match (&canary_behavior, &canary_report) {
+                (Some(_), None) => {
+                    return Err("--canary-behavior requires --canary-report".to_owned())
+                }
+                (None, Some(_)) => {
+                    return Err("--canary-report requires --canary-behavior".to_owned())
+                }
+                _ => {}
+            }
+
+            match (sandbox_tags.is_empty(), &sandbox_summary) {
+                (false, None) => return Err("--sandbox-tag requires --sandbox-summary".to_owned()),
+                (true, Some(_)) => {
+                    return Err("--sandbox-summary requires --sandbox-tag".to_owned())
+                }
+                (false, Some(_)) if metadata.is_none() => {
+                    return Err("--sandbox-tag requires --metadata".to_owned())
+                }
+                _ => {}
+            }
+
+            Ok(Command::Summarize {
+                prior: flags.take("--prior").map(PathBuf::from),
+                changed_only: flags.switch("--changed-only"),
+                stats: flags.take("--stats").map(PathBuf::from),
+                salt,
+                account_options: AccountOptions {
+                    expected_transactions: flags.parsed("--expected-transactions")?,
+                    unknown_clients: flags.parsed("--unknown-clients")?.unwrap_or_default(),
+                    dispute_amounts: flags.parsed("--dispute-amounts")?.unwrap_or_default(),
+                    partial_disputes: flags.switch("--partial-disputes"),
+                    balance_cap: flags.parsed("--balance-cap")?,
+                    cap_policy: flags.parsed("--cap-policy")?.unwrap_or_default(),
+                    auto_approve_adjustments: flags.switch("--auto-approve-adjustments"),
+                    behavior: flags.parsed("--behavior-version")?.unwrap_or_default(),
+                    conflicts: flags.parsed("--conflict-policy")?.unwrap_or_default(),
+                    lifecycle: flags.parsed("--lifecycle-gate")?.unwrap_or_default(),
+                    memo_max_length: flags.parsed("--memo-max-length")?,
+                    tx_ids: flags.parsed("--tx-ids")?.unwrap_or_default(),
+                    date_basis: flags.parsed("--date-basis")?.unwrap_or_default(),
+                    low_balance,
+                },
+                writer_thread: flags.switch("--writer-thread"),
+                mmap: flags.switch("--mmap"),
+                latencies: flags.switch("--latencies"),
+                errors_format: flags.parsed("--errors-format")?.unwrap_or_default(),
+                client_mismatches: flags.take("--client-mismatches").map(PathBuf::from),
+                conflicting_duplicates: flags.take("--conflicting-duplicates").map(PathBuf::from),
+                shortfalls: flags.take("--shortfalls").map(PathBuf::from),
+                metadata,
+                exclude_tags,
+                sandbox_tags,
+                sandbox_summary,
+                group_cap,
+                group_summary,
+                canary_behavior,
+                canary_report,
+                mapping: flags.take("--mapping").map(PathBuf::from),
+                rules: match flags.take("--rules") {
+                    Some(_) if !cfg!(feature = "scripting") => {
+                        return Err(
+                            "--rules requires building with the scripting feature".to_owned()
+                        )
+                    }
+                    path => path.map(PathBuf::from),
+                },
+                schema: flags.parsed("--schema-version")?.unwrap_or_default(),
+                provenance: flags.take("--provenance").map(PathBuf::from),
+                now: flags.parsed("--now")?,
+                tx_index: flags.take("--tx-index").map(PathBuf::from),
+                tx_map: flags.take("--tx-map").map(PathBuf::from),
+                seal,
+                late_transactions,
+                export_db: match flags.take("--export-db") {
+                    Some(_) if !cfg!(feature = "sqlx") => {
+                        return Err("--export-db requires building with the sqlx feature".to_owned())
+                    }
+                    url => url,
+                },
+                publish_redis: match flags.take("--publish-redis") {
+                    Some(_) if !cfg!(feature = "redis") => {
+                        return Err(
+                            "--publish-redis requires building with the redis feature".to_owned()
+                        )
+                    }
+                    url => url,
+                },
+                redis_prefix: flags
+                    .take("--redis-prefix")
+                    .unwrap_or_else(|| "balances".to_owned()),
+                cdc,
+                mirror: flags.take("--mirror").map(PathBuf::from),
+                sinks,
+                sink_salt,
+                export_tables: {
+                    let defaults = ExportTables::default();
+
+                    ExportTables {
+                        summaries: flags.take("--summary-table").unwrap_or(defaults.summaries),
+                        ledger: flags.take("--ledger-table").unwrap_or(defaults.ledger),
+                    }
+                },
+                input: flags.input()?,
+            })
+        }
+    }
+}
+
+struct Flags {
+    values: Vec<(String, String)>,
+    switches: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Flags {
+    fn parse(args: &[String], known: &[&str], known_switches: &[&str]) -> Result<Flags, String> {
+        let mut values = Vec::new();
+        let mut switches = Vec::new();
+        let mut positional = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            if known_switches.contains(&arg.as_str()) {
+                switches.push(arg.clone());
+            } else if arg.starts_with("--") {
+                if !known.contains(&arg.as_str()) {
+                    return Err(format!("unknown flag {}\n{}", arg, USAGE));
+                }
+
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("{} requires a value", arg))?;
+
+                values.push((arg.clone(), value.clone()));
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+
+        Ok(Flags {
+            values,
+            switches,
+            positional,
+        })
+    }
+
+    fn switch(&mut self, switch: &str) -> bool {
+        self.switches.iter().any(|name| name == switch)
+    }
+
+    fn take(&mut self, flag: &str) -> Option<String> {
+        let index = self.values.iter().position(|(name, _)| name == flag)?;
+
+        Some(self.values.remove(index).1)
+    }
+
+    fn take_all(&mut self, flag: &str) -> Vec<String> {
+        let mut values = Vec::new();
+
+        while let Some(value) = self.take(flag) {
+            values.push(value);
+        }
+
+        values
+    }
+
+    fn parsed<T: FromStr>(&mut self, flag: &str) -> Result<Option<T>, String> {
+        match self.take(flag) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid value for {}: {}", flag, value)),
+            None => Ok(None),
+        }
+    }
+
+    /*
+    The salt to pseudonymize client ids with, if asked to.  It has to come from the caller --
+    a default salt would make every token trivially reversible.
+    */
+    fn pseudonymize(&mut self) -> Result<Option<String>, String> {
+        let salt = self.take("--salt");
+
+        match (self.switch("--pseudonymize"), salt) {
+            (true, None) => Err("--pseudonymize requires --salt".to_owned()),
+            (false, Some(_)) => Err("--salt requires --pseudonymize".to_owned()),
+            (true, salt) => Ok(salt),
+            (false, None) => Ok(None),
+        }
+    }
+
+    fn low_balance(&mut self) -> Result<Option<LowBalance>, String> {
+        match (
+            self.parsed("--low-balance")?,
+            self.parsed("--low-balance-response")?,
+        ) {
+            (None, Some(_)) => Err("--low-balance-response requires --low-balance".to_owned()),
+            (threshold, response) => Ok(threshold.map(|threshold| LowBalance {
+                threshold,
+                response: response.unwrap_or_default(),
+            })),
+        }
+    }
+
+    /*
+    Administrative commands can't run anonymously -- see audit::AdminAction.
+    */
+    fn admin_action(&mut self) -> Result<AdminAction, String> {
+        match (self.take("--operator"), self.take("--reason")) {
+            (Some(operator), Some(reason)) => AdminAction::new(&operator, &reason),
+            _ => Err("administrative commands require --operator and --reason".to_owned()),
+        }
+    }
+
+    fn input(&mut self) -> Result<PathBuf, String> {
+        match self.positional.as_slice() {
+            [input] => Ok(PathBuf::from(input)),
+            _ => Err(USAGE.to_owned()),
+        }
+    }
+}
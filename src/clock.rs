@@ -0,0 +1,55 @@
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{dates::Date, provenance::parse_timestamp};
+
+/*
+Where "now" comes from.  Anything that records or depends on the time a run happened asks a
+Clock rather than the system, so a replay can be run as of when the original run happened, and
+tests can pin the time down.
+*/
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+
+    /*
+    The current day in UTC.
+    */
+    fn today(&self) -> Date {
+        let seconds = self
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        Date::from_days((seconds / 86400) as i32)
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/*
+A clock stopped at one instant.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+impl FromStr for FixedClock {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_timestamp(s).map(FixedClock).ok_or(())
+    }
+}
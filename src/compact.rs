@@ -0,0 +1,184 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    io,
+};
+
+use csv::{Reader, StringRecord, Writer};
+
+use crate::{
+    accounts::AccountOptions,
+    dates::Date,
+    tenants::TenantDatabase,
+    transactions::{ClientId, TransactionText, TxId},
+    Money,
+};
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Compaction {
+    pub folded_rows: usize,
+    pub kept_rows: usize,
+    pub opening_rows: usize,
+}
+
+/*
+Rewrites a transaction log so that history before `horizon` is folded into one opening balance
+per client, while replaying the compacted log still produces exactly the same final state.
+The prefix is folded under `options`, so that holds when the compacted log is replayed under the
+same ones -- a deposit the lifecycle gate refused isn't in the opening balance.
+
+Only the prefix of the log dated before the horizon is considered -- the first row on or after
+the horizon (or without a date) ends it, since we can't know the state at the horizon if rows
+are out of order after that point.
+
+Within that prefix a client's rows are folded into a single `opening` row for their available
+funds, unless something could still observe the individual transactions:
+
+1. Their account is locked, erased, or has funds held -- an opening balance can't express that.
+2. A row we're keeping reuses one of their transaction ids, whether to dispute it or as a
+   (rejected) duplicate.  Dropping the original would change how that row is handled.
+3. They have lifecycle rows, or transactions parked waiting for them.  Where a client is in
+   its lifecycle gates what comes after the horizon, and an opening row carries none of it.
+
+Those clients keep every row, and since keeping rows can make more ids live, we repeat until
+nothing changes.  Clients are otherwise independent of one another, so kept rows can follow
+the opening rows without changing any outcome.
+*/
+pub fn compact<R: io::Read, W: io::Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    horizon: Date,
+    options: AccountOptions,
+) -> Result<Compaction, Box<dyn Error>> {
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let client_column = column("client").ok_or("input has no client column")?;
+    let tx_column = column("tx").ok_or("input has no tx column")?;
+    let tenant_column = column("tenant");
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let mut database = TenantDatabase::with_options(options);
+    let mut prefix_length = records.len();
+
+    for (index, record) in records.iter().enumerate() {
+        let transaction_text: TransactionText = record.deserialize(Some(&headers))?;
+
+        transaction_text.validate(record.position().map_or(0, |position| position.line()))?;
+
+        let date = transaction_text.date()?;
+
+        if date.is_none_or(|date| date >= horizon) {
+            prefix_length = index;
+            break;
+        }
+
+        let tenant = transaction_text.tenant().to_owned();
+
+        database.apply(&tenant, &transaction_text.into(), date);
+    }
+
+    let (prefix, rest) = records.split_at(prefix_length);
+    let field = |record: &StringRecord, index: Option<usize>| {
+        index
+            .and_then(|index| record.get(index))
+            .unwrap_or_default()
+            .to_owned()
+    };
+    let owner = |record: &StringRecord| {
+        (
+            field(record, tenant_column),
+            field(record, Some(client_column)).parse::<ClientId>().ok(),
+        )
+    };
+    let transaction = |record: &StringRecord| {
+        (
+            field(record, tenant_column),
+            field(record, Some(tx_column)).parse::<TxId>().ok(),
+        )
+    };
+
+    let mut kept: HashSet<(String, Option<ClientId>)> = HashSet::new();
+
+    for (tenant, accounts) in database.tenants() {
+        for account in accounts.all_accounts() {
+            if account.is_locked() || account.is_erased() || account.held() > Money::zero() {
+                kept.insert((tenant.to_owned(), Some(account.client_id())));
+            }
+        }
+
+        for client_id in accounts.lifecycle_clients() {
+            kept.insert((tenant.to_owned(), Some(client_id)));
+        }
+    }
+
+    loop {
+        let live: HashSet<(String, Option<TxId>)> = rest
+            .iter()
+            .chain(prefix.iter().filter(|record| kept.contains(&owner(record))))
+            .map(transaction)
+            .collect();
+        let newly_kept: Vec<(String, Option<ClientId>)> = prefix
+            .iter()
+            .filter(|record| !kept.contains(&owner(record)) && live.contains(&transaction(record)))
+            .map(owner)
+            .collect();
+
+        if newly_kept.is_empty() {
+            break;
+        }
+
+        kept.extend(newly_kept);
+    }
+
+    let mut openings: BTreeMap<(String, ClientId), Money> = BTreeMap::new();
+
+    for (tenant, accounts) in database.tenants() {
+        for account in accounts.accounts() {
+            if !kept.contains(&(tenant.to_owned(), Some(account.client_id()))) {
+                openings.insert(
+                    (tenant.to_owned(), account.client_id()),
+                    account.available(),
+                );
+            }
+        }
+    }
+
+    writer.write_record(&headers)?;
+
+    for ((tenant, client_id), available) in &openings {
+        let mut opening = StringRecord::new();
+
+        for header in headers.iter() {
+            opening.push_field(
+                match header {
+                    "type" => "opening".to_owned(),
+                    "client" => client_id.to_string(),
+                    "tx" => "0".to_owned(),
+                    "amount" => available.to_string(),
+                    "tenant" => tenant.clone(),
+                    _ => String::new(),
+                }
+                .as_str(),
+            );
+        }
+
+        writer.write_record(&opening)?;
+    }
+
+    let mut kept_rows = 0;
+
+    for record in prefix.iter().filter(|record| kept.contains(&owner(record))) {
+        writer.write_record(record)?;
+        kept_rows += 1;
+    }
+
+    for record in rest {
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+
+    Ok(Compaction {
+        folded_rows: prefix.len() - kept_rows,
+        kept_rows: kept_rows + rest.len(),
+        opening_rows: openings.len(),
+    })
+}
@@ -0,0 +1,215 @@
+use std::{io, str::FromStr};
+
+use crate::cli::USAGE;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+A command as described by the usage text: its name -- None for summarize, which has none -- and
+every flag it takes.
+*/
+#[derive(PartialEq, Eq, Debug)]
+pub struct CommandUsage {
+    pub name: Option<&'static str>,
+    pub flags: Vec<&'static str>,
+}
+
+/*
+The usage text is the one place every command and flag is written down, so completions and the
+man page are generated from it rather than kept in step by hand.  Each command's entry starts on
+a line naming the program, and runs until the next one.
+*/
+pub fn commands() -> Vec<CommandUsage> {
+    let mut commands: Vec<CommandUsage> = Vec::new();
+
+    for line in USAGE.lines() {
+        let line = line.trim_start().trim_start_matches("usage:").trim_start();
+        let words = match line.strip_prefix("notfizzbuzz ") {
+            Some(rest) => {
+                let name = rest
+                    .split_whitespace()
+                    .next()
+                    .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic()));
+
+                commands.push(CommandUsage {
+                    name,
+                    flags: Vec::new(),
+                });
+                rest
+            }
+            None => line,
+        };
+
+        let Some(command) = commands.last_mut() else {
+            continue;
+        };
+
+        for (start, _) in words.match_indices("--") {
+            let flag = &words[start..];
+            let end = flag
+                .find(|c: char| !(c.is_ascii_lowercase() || c == '-'))
+                .unwrap_or(flag.len());
+
+            if !command.flags.contains(&&flag[..end]) {
+                command.flags.push(&flag[..end]);
+            }
+        }
+    }
+
+    commands
+}
+
+/*
+For bash and zsh, summarize's flags go in the catch-all case, so the commands are written in
+reverse to put it last.
+*/
+pub fn write_completions<W: io::Write>(shell: Shell, writer: &mut W) -> io::Result<()> {
+    let commands = commands();
+    let names: Vec<&str> = commands.iter().filter_map(|command| command.name).collect();
+
+    match shell {
+        Shell::Bash => {
+            writeln!(writer, "_notfizzbuzz() {{")?;
+            writeln!(writer, "    local cur=${{COMP_WORDS[COMP_CWORD]}}")?;
+            writeln!(writer, "    local opts")?;
+            writeln!(writer, "    case \"${{COMP_WORDS[1]}}\" in")?;
+
+            for command in commands.iter().rev() {
+                match command.name {
+                    Some(name) => writeln!(writer, "        {})", name)?,
+                    None => writeln!(writer, "        *)")?,
+                }
+
+                let mut words = command.flags.clone();
+
+                if command.name.is_none() {
+                    words.splice(0..0, names.iter().copied());
+                }
+
+                writeln!(writer, "            opts=\"{}\" ;;", words.join(" "))?;
+            }
+
+            writeln!(writer, "    esac")?;
+            writeln!(
+                writer,
+                "    COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\") $(compgen -f -- \"$cur\"))"
+            )?;
+            writeln!(writer, "}}")?;
+            writeln!(writer, "complete -F _notfizzbuzz notfizzbuzz")?;
+        }
+        Shell::Zsh => {
+            writeln!(writer, "#compdef notfizzbuzz")?;
+            writeln!(writer)?;
+            writeln!(writer, "_notfizzbuzz() {{")?;
+            writeln!(writer, "    local -a opts")?;
+            writeln!(writer, "    case $words[2] in")?;
+
+            for command in commands.iter().rev() {
+                match command.name {
+                    Some(name) => writeln!(writer, "        {})", name)?,
+                    None => writeln!(writer, "        *)")?,
+                }
+
+                let mut words = command.flags.clone();
+
+                if command.name.is_none() {
+                    words.splice(0..0, names.iter().copied());
+                }
+
+                writeln!(writer, "            opts=({}) ;;", words.join(" "))?;
+            }
+
+            writeln!(writer, "    esac")?;
+            writeln!(writer, "    compadd -a opts")?;
+            writeln!(writer, "    _files")?;
+            writeln!(writer, "}}")?;
+            writeln!(writer)?;
+            writeln!(writer, "_notfizzbuzz \"$@\"")?;
+        }
+        Shell::Fish => {
+            let seen = format!("__fish_seen_subcommand_from {}", names.join(" "));
+
+            for name in &names {
+                writeln!(
+                    writer,
+                    "complete -c notfizzbuzz -n '__fish_use_subcommand' -a {}",
+                    name
+                )?;
+            }
+
+            for command in &commands {
+                let condition = match command.name {
+                    Some(name) => format!("__fish_seen_subcommand_from {}", name),
+                    None => format!("not {}", seen),
+                };
+
+                for flag in &command.flags {
+                    writeln!(
+                        writer,
+                        "complete -c notfizzbuzz -n '{}' -l {}",
+                        condition,
+                        flag.trim_start_matches("--")
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/*
+A section 1 man page, with the usage text as its synopsis.
+*/
+pub fn write_man<W: io::Write>(writer: &mut W) -> io::Result<()> {
+    writeln!(
+        writer,
+        ".TH NOTFIZZBUZZ 1 \"\" \"notfizzbuzz {}\"",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, ".SH NAME")?;
+    writeln!(
+        writer,
+        "notfizzbuzz \\- summarize client balances from a CSV of transactions"
+    )?;
+    writeln!(writer, ".SH SYNOPSIS")?;
+    writeln!(writer, ".nf")?;
+
+    for line in USAGE.lines() {
+        let line = line
+            .trim_start_matches("usage: ")
+            .replace('\\', "\\e")
+            .replace('-', "\\-");
+
+        writeln!(writer, "\\&{}", line)?;
+    }
+
+    writeln!(writer, ".fi")?;
+    writeln!(writer, ".SH DESCRIPTION")?;
+    writeln!(
+        writer,
+        "Without a command, reads every transaction in input.csv and writes one row per \
+         client with their available, held and total funds, and whether their account is \
+         locked.  Errors are reported on standard output."
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+/*
+A calendar date, stored as the number of days since 1970-01-01.
+
+Storing a day count rather than year/month/day keeps the type Copy and cheap to compare, and
+makes date arithmetic (ages, windows, periods) plain integer arithmetic.  The conversions to and
+from the civil calendar are Howard Hinnant's well known days_from_civil/civil_from_days.
+
+We only care about whole days, so there's no time of day or timezone here.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+pub struct Date(i32);
+
+#[derive(Debug)]
+pub enum DateParseError {
+    Malformed,
+    OutOfRange,
+}
+
+impl Display for DateParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DateParseError::Malformed => "date is not YYYY-MM-DD",
+            DateParseError::OutOfRange => "date is not on the calendar",
+        })
+    }
+}
+
+impl std::error::Error for DateParseError {}
+
+impl Date {
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Result<Date, DateParseError> {
+        if !(1..=12).contains(&month) || day == 0 || day > Date::days_in_month(year, month) {
+            return Err(DateParseError::OutOfRange);
+        }
+
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month_from_march = (month + 9) % 12;
+        let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+        let day_of_era =
+            year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year as i32;
+
+        Ok(Date(era * 146097 + day_of_era - 719468))
+    }
+
+    pub fn ymd(&self) -> (i32, u32, u32) {
+        let days = self.0 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        (year, month, day)
+    }
+
+    pub fn from_days(days_since_epoch: i32) -> Date {
+        Date(days_since_epoch)
+    }
+
+    pub fn days_since(&self, earlier: Date) -> i32 {
+        self.0 - earlier.0
+    }
+
+    pub fn plus_days(&self, days: i32) -> Date {
+        Date(self.0 + days)
+    }
+
+    fn days_in_month(year: i32, month: u32) -> u32 {
+        let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+
+        match month {
+            2 if leap => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+impl FromStr for Date {
+    type Err = DateParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split('-').collect();
+
+        if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+            return Err(DateParseError::Malformed);
+        }
+
+        let year = parts[0].parse().map_err(|_| DateParseError::Malformed)?;
+        let month = parts[1].parse().map_err(|_| DateParseError::Malformed)?;
+        let day = parts[2].parse().map_err(|_| DateParseError::Malformed)?;
+
+        Date::from_ymd(year, month, day)
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = self.ymd();
+
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl Debug for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.to_string().as_str())
+    }
+}
+
+/*
+Which of a row's two dates places it in time.  The booking date (`date`) is when the row was
+recorded, and input arrives in booking order; the value date (`value_date`) is when the money
+actually moved, which is what interest and statements are usually worked out on.
+
+Whichever is chosen dates the ledger, so it decides where a row falls in balance histories,
+simulations and sealed periods.  A row without a value date falls back to its booking date.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum DateBasis {
+    #[default]
+    Booking,
+    Value,
+}
+
+impl FromStr for DateBasis {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "booking" => Ok(DateBasis::Booking),
+            "value" => Ok(DateBasis::Value),
+            _ => Err(()),
+        }
+    }
+}
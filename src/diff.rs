@@ -0,0 +1,199 @@
+use std::{collections::BTreeMap, error::Error, io};
+
+use csv::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::{tenants::TenantDatabase, transactions::ClientId, Money};
+
+/*
+Where one account stood at some point: enough to tell whether it moved or changed status.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct AccountState {
+    pub available: Money,
+    pub held: Money,
+    pub locked: bool,
+}
+
+/*
+Every account of every tenant at some point, read back from a summary (any schema version) or
+taken from a database.
+*/
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Snapshot {
+    accounts: BTreeMap<(String, ClientId), AccountState>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotRow {
+    #[serde(default)]
+    tenant: Option<String>,
+    client_id: ClientId,
+    available: String,
+    held: String,
+    locked: bool,
+}
+
+impl Snapshot {
+    pub fn load<R: io::Read>(reader: &mut Reader<R>) -> Result<Snapshot, Box<dyn Error>> {
+        let mut accounts = BTreeMap::new();
+
+        for record_result in reader.deserialize() {
+            let row: SnapshotRow = record_result?;
+            let amount = |text: &str| -> Result<Money, String> {
+                text.parse()
+                    .map_err(|_| format!("invalid amount for client {}: {}", row.client_id, text))
+            };
+            let state = AccountState {
+                available: amount(&row.available)?,
+                held: amount(&row.held)?,
+                locked: row.locked,
+            };
+
+            accounts.insert((row.tenant.unwrap_or_default(), row.client_id), state);
+        }
+
+        Ok(Snapshot { accounts })
+    }
+
+    pub fn from_database(database: &TenantDatabase) -> Snapshot {
+        Snapshot {
+            accounts: database
+                .tenants()
+                .flat_map(|(tenant, accounts)| {
+                    accounts.accounts().map(move |account| {
+                        (
+                            (tenant.to_owned(), account.client_id()),
+                            AccountState {
+                                available: account.available(),
+                                held: account.held(),
+                                locked: account.is_locked(),
+                            },
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn account(&self, tenant: &str, client_id: ClientId) -> Option<AccountState> {
+        self.accounts.get(&(tenant.to_owned(), client_id)).copied()
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AccountChange {
+    Created(AccountState),
+    Removed(AccountState),
+    Changed {
+        before: AccountState,
+        after: AccountState,
+    },
+}
+
+impl AccountChange {
+    pub fn balance_changed(&self) -> bool {
+        match self {
+            AccountChange::Created(state) | AccountChange::Removed(state) => true,
+            AccountChange::Changed { before, after } => {
+                (before.available, before.held) != (after.available, after.held)
+            }
+        }
+    }
+
+    pub fn status_changed(&self) -> bool {
+        match self {
+            AccountChange::Created(state) | AccountChange::Removed(state) => state.locked,
+            AccountChange::Changed { before, after } => before.locked != after.locked,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ClientDiff {
+    pub tenant: String,
+    pub client_id: ClientId,
+    pub change: AccountChange,
+}
+
+/*
+Every account that differs between two snapshots, in tenant and client order.  Accounts that
+are the same in both are left out.
+*/
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct AccountSummaryDiff {
+    pub clients: Vec<ClientDiff>,
+}
+
+impl AccountSummaryDiff {
+    pub fn between(before: &Snapshot, after: &Snapshot) -> AccountSummaryDiff {
+        let mut keys: Vec<&(String, ClientId)> = before
+            .accounts
+            .keys()
+            .chain(after.accounts.keys())
+            .collect();
+
+        keys.sort();
+        keys.dedup();
+
+        let clients = keys
+            .into_iter()
+            .filter_map(|key| {
+                let change = match (before.accounts.get(key), after.accounts.get(key)) {
+                    (Some(before), Some(after)) if before == after => return None,
+                    (Some(&before), Some(&after)) => AccountChange::Changed { before, after },
+                    (None, Some(&after)) => AccountChange::Created(after),
+                    (Some(&before), None) => AccountChange::Removed(before),
+                    (None, None) => return None,
+                };
+
+                Some(ClientDiff {
+                    tenant: key.0.clone(),
+                    client_id: key.1,
+                    change,
+                })
+            })
+            .collect();
+
+        AccountSummaryDiff { clients }
+    }
+}
+
+/*
+A client's diff as one CSV row, with the before or after columns empty for an account that
+was created or removed.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct DiffRow<'a> {
+    pub tenant: &'a str,
+    pub client_id: ClientId,
+    pub change: &'static str,
+    pub available_before: Option<Money>,
+    pub available_after: Option<Money>,
+    pub held_before: Option<Money>,
+    pub held_after: Option<Money>,
+    pub locked_before: Option<bool>,
+    pub locked_after: Option<bool>,
+}
+
+impl<'a> From<&'a ClientDiff> for DiffRow<'a> {
+    fn from(diff: &'a ClientDiff) -> DiffRow<'a> {
+        let (change, before, after) = match diff.change {
+            AccountChange::Created(after) => ("created", None, Some(after)),
+            AccountChange::Removed(before) => ("removed", Some(before), None),
+            AccountChange::Changed { before, after } => ("changed", Some(before), Some(after)),
+        };
+
+        DiffRow {
+            tenant: &diff.tenant,
+            client_id: diff.client_id,
+            change,
+            available_before: before.map(|state| state.available),
+            available_after: after.map(|state| state.available),
+            held_before: before.map(|state| state.held),
+            held_after: after.map(|state| state.held),
+            locked_before: before.map(|state| state.locked),
+            locked_after: after.map(|state| state.locked),
+        }
+    }
+}
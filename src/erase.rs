@@ -0,0 +1,139 @@
+use std::{collections::HashSet, error::Error, io};
+
+use csv::{Reader, StringRecord, Writer};
+
+use crate::{
+    accounts::AccountDatabase,
+    audit::AdminAction,
+    transactions::{ClientId, TransactionText, TxId},
+    Money,
+};
+
+/*
+Rewrites a transaction log with every trace of one client removed.
+
+All of the client's rows are dropped, and a single tombstone row is appended in their place.
+The tombstone carries the client's final total but nothing else -- no history, no individual
+amounts, no dates.  Replaying the rewritten log produces the same summaries for every other
+client, and books the erased total into the database's erased funds, so the totals across the
+ledger still add up.
+
+Every other row is copied verbatim, including any columns we don't otherwise understand.  The
+tombstone records who erased the client and why, adding `operator` and `reason` columns to the
+log if it doesn't have them yet.
+
+The erase is refused if another client's deposit or withdrawal reuses a tx id the client took
+first.  That row was rejected as a duplicate, and with the client's row gone it would be
+applied on replay.
+
+Returns the erased total.
+*/
+pub fn erase_client<R: io::Read, W: io::Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    client_id: ClientId,
+    tenant: &str,
+    action: &AdminAction,
+) -> Result<Money, Box<dyn Error>> {
+    let mut headers = reader.headers()?.clone();
+
+    for name in ["operator", "reason"] {
+        if !headers.iter().any(|header| header == name) {
+            headers.push_field(name);
+        }
+    }
+
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let client_column = column("client").ok_or("input has no client column")?;
+    let tenant_column = column("tenant");
+    let type_column = column("type").ok_or("input has no type column")?;
+    let tx_column = column("tx").ok_or("input has no tx column")?;
+    let mut taken: HashSet<(String, TxId)> = HashSet::new();
+    let mut erased_ids: HashSet<(String, TxId)> = HashSet::new();
+    let mut erased = AccountDatabase::new();
+
+    writer.write_record(&headers)?;
+
+    for record_result in reader.records() {
+        let mut record = record_result?;
+
+        while record.len() < headers.len() {
+            record.push_field("");
+        }
+
+        let row_tenant = tenant_column
+            .and_then(|index| record.get(index))
+            .unwrap_or_default();
+        let row_client_id: Option<ClientId> =
+            record.get(client_column).and_then(|text| text.parse().ok());
+        let takes_id = record.get(type_column).is_some_and(|kind| {
+            kind.eq_ignore_ascii_case("deposit") || kind.eq_ignore_ascii_case("withdrawal")
+        });
+        let tx_id: Option<TxId> = record.get(tx_column).and_then(|text| text.parse().ok());
+        let erasing = row_client_id == Some(client_id) && row_tenant == tenant;
+        let mut duplicate = false;
+
+        if let Some(tx_id) = tx_id.filter(|_| takes_id) {
+            let key = (row_tenant.to_owned(), tx_id);
+
+            if taken.insert(key.clone()) {
+                if erasing {
+                    erased_ids.insert(key);
+                }
+            } else if erased_ids.contains(&key) && !erasing {
+                return Err(format!(
+                    "line {} reuses tx {} of client {}, and would be applied once it's erased",
+                    record.position().map_or(0, |position| position.line()),
+                    tx_id,
+                    client_id
+                )
+                .into());
+            } else {
+                duplicate = true;
+            }
+        }
+
+        if erasing {
+            let transaction_text: TransactionText = record.deserialize(Some(&headers))?;
+
+            transaction_text.validate(record.position().map_or(0, |position| position.line()))?;
+
+            /*
+            A row whose tx id another client took first was rejected, so it isn't in the total.
+            */
+            if !duplicate {
+                erased.apply(&transaction_text.into());
+            }
+        } else {
+            writer.write_record(&record)?;
+        }
+    }
+
+    let total = erased
+        .accounts()
+        .map(|account| account.available() + account.held())
+        .sum::<Money>()
+        + erased.erased();
+    let mut tombstone = StringRecord::new();
+
+    for header in headers.iter() {
+        tombstone.push_field(
+            match header {
+                "type" => "tombstone".to_owned(),
+                "client" => client_id.to_string(),
+                "tx" => "0".to_owned(),
+                "amount" => total.to_string(),
+                "tenant" => tenant.to_owned(),
+                "operator" => action.operator.clone(),
+                "reason" => action.reason.clone(),
+                _ => String::new(),
+            }
+            .as_str(),
+        );
+    }
+
+    writer.write_record(&tombstone)?;
+    writer.flush()?;
+
+    Ok(total)
+}
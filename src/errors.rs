@@ -0,0 +1,291 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use csv::{ErrorKind, StringRecord};
+use serde::{Serialize, Serializer};
+
+use crate::stats::RunStats;
+
+/*
+Every error and warning the engine reports, with a code that never changes meaning once given
+out, so tooling can key on the code rather than the message.  Codes are grouped by what went
+wrong: E1 for input rows that couldn't be read, E2 for transactions the engine turned away or
+couldn't fully apply, and E3 for warnings.  Retired codes are never reused.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ErrorCode {
+    DuplicateTx,
+    InvalidClient,
+    InvalidTx,
+    NonNumericTx,
+    UnknownType,
+    InvalidAmount,
+    InvalidDate,
+    InvalidField,
+    InvalidUtf8,
+    WrongFieldCount,
+    MalformedCsv,
+    ReadFailed,
+    InvalidOperator,
+    ClientMismatch,
+    InsufficientFunds,
+    PartiallyCovered,
+    UnexpectedDisputeAmount,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 17] = [
+        ErrorCode::DuplicateTx,
+        ErrorCode::InvalidClient,
+        ErrorCode::InvalidTx,
+        ErrorCode::NonNumericTx,
+        ErrorCode::UnknownType,
+        ErrorCode::InvalidAmount,
+        ErrorCode::InvalidDate,
+        ErrorCode::InvalidField,
+        ErrorCode::InvalidUtf8,
+        ErrorCode::WrongFieldCount,
+        ErrorCode::MalformedCsv,
+        ErrorCode::ReadFailed,
+        ErrorCode::InvalidOperator,
+        ErrorCode::ClientMismatch,
+        ErrorCode::InsufficientFunds,
+        ErrorCode::PartiallyCovered,
+        ErrorCode::UnexpectedDisputeAmount,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::DuplicateTx => "E1001",
+            ErrorCode::InvalidClient => "E1002",
+            ErrorCode::InvalidTx => "E1003",
+            ErrorCode::NonNumericTx => "E1004",
+            ErrorCode::UnknownType => "E1005",
+            ErrorCode::InvalidAmount => "E1006",
+            ErrorCode::InvalidDate => "E1007",
+            ErrorCode::InvalidField => "E1008",
+            ErrorCode::InvalidUtf8 => "E1009",
+            ErrorCode::WrongFieldCount => "E1010",
+            ErrorCode::MalformedCsv => "E1011",
+            ErrorCode::ReadFailed => "E1012",
+            ErrorCode::InvalidOperator => "E1013",
+            ErrorCode::ClientMismatch => "E2001",
+            ErrorCode::InsufficientFunds => "E2002",
+            ErrorCode::PartiallyCovered => "E2003",
+            ErrorCode::UnexpectedDisputeAmount => "E3001",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::DuplicateTx => "DuplicateTx",
+            ErrorCode::InvalidClient => "InvalidClient",
+            ErrorCode::InvalidTx => "InvalidTx",
+            ErrorCode::NonNumericTx => "NonNumericTx",
+            ErrorCode::UnknownType => "UnknownType",
+            ErrorCode::InvalidAmount => "InvalidAmount",
+            ErrorCode::InvalidDate => "InvalidDate",
+            ErrorCode::InvalidField => "InvalidField",
+            ErrorCode::InvalidUtf8 => "InvalidUtf8",
+            ErrorCode::WrongFieldCount => "WrongFieldCount",
+            ErrorCode::MalformedCsv => "MalformedCsv",
+            ErrorCode::ReadFailed => "ReadFailed",
+            ErrorCode::InvalidOperator => "InvalidOperator",
+            ErrorCode::ClientMismatch => "ClientMismatch",
+            ErrorCode::InsufficientFunds => "InsufficientFunds",
+            ErrorCode::PartiallyCovered => "PartiallyCovered",
+            ErrorCode::UnexpectedDisputeAmount => "UnexpectedDisputeAmount",
+        }
+    }
+
+    /*
+    What the code means, in a line.
+    */
+    pub fn summary(self) -> &'static str {
+        match self {
+            ErrorCode::DuplicateTx => "a deposit or withdrawal reuses an earlier one's tx id with a different type, client or amount",
+            ErrorCode::InvalidClient => "the client id isn't a number from 0 to 65535",
+            ErrorCode::InvalidTx => "the tx id is neither a number nor a UUID",
+            ErrorCode::NonNumericTx => "the tx id is a UUID, but the run only accepts numeric ids",
+            ErrorCode::UnknownType => "the row's type isn't one the engine knows",
+            ErrorCode::InvalidAmount => "the amount isn't a number the row's type accepts",
+            ErrorCode::InvalidDate => "a date isn't a valid yyyy-mm-dd date",
+            ErrorCode::InvalidField => "a field doesn't hold the kind of value its column needs",
+            ErrorCode::InvalidUtf8 => "a field isn't valid UTF-8",
+            ErrorCode::WrongFieldCount => "a row has a different number of fields from the header",
+            ErrorCode::MalformedCsv => "the input isn't valid CSV",
+            ErrorCode::ReadFailed => "an input couldn't be read",
+            ErrorCode::InvalidOperator => "an adjustment or approval has no operator id, or one that's too long",
+            ErrorCode::ClientMismatch => "a dispute, resolve or chargeback names a different client from the transaction it references",
+            ErrorCode::InsufficientFunds => "the account's funds didn't cover the transaction at all",
+            ErrorCode::PartiallyCovered => "the account's funds only covered part of the transaction",
+            ErrorCode::UnexpectedDisputeAmount => "a dispute, resolve or chargeback carried an amount, which was ignored",
+        }
+    }
+}
+
+impl ErrorCode {
+    /*
+    The long form, for `explain`: what happened, an example where one helps, common causes and
+    what to do about it.  Kept one file per code under src/explanations.
+    */
+    pub fn explanation(self) -> &'static str {
+        match self {
+            ErrorCode::DuplicateTx => include_str!("explanations/E1001.md"),
+            ErrorCode::InvalidClient => include_str!("explanations/E1002.md"),
+            ErrorCode::InvalidTx => include_str!("explanations/E1003.md"),
+            ErrorCode::NonNumericTx => include_str!("explanations/E1004.md"),
+            ErrorCode::UnknownType => include_str!("explanations/E1005.md"),
+            ErrorCode::InvalidAmount => include_str!("explanations/E1006.md"),
+            ErrorCode::InvalidDate => include_str!("explanations/E1007.md"),
+            ErrorCode::InvalidField => include_str!("explanations/E1008.md"),
+            ErrorCode::InvalidUtf8 => include_str!("explanations/E1009.md"),
+            ErrorCode::WrongFieldCount => include_str!("explanations/E1010.md"),
+            ErrorCode::MalformedCsv => include_str!("explanations/E1011.md"),
+            ErrorCode::ReadFailed => include_str!("explanations/E1012.md"),
+            ErrorCode::InvalidOperator => include_str!("explanations/E1013.md"),
+            ErrorCode::ClientMismatch => include_str!("explanations/E2001.md"),
+            ErrorCode::InsufficientFunds => include_str!("explanations/E2002.md"),
+            ErrorCode::PartiallyCovered => include_str!("explanations/E2003.md"),
+            ErrorCode::UnexpectedDisputeAmount => include_str!("explanations/E3001.md"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/*
+Accepts the code or the name.
+*/
+impl FromStr for ErrorCode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ErrorCode::ALL
+            .into_iter()
+            .find(|code| code.code().eq_ignore_ascii_case(s) || code.name() == s)
+            .ok_or(())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+/*
+An input row the engine can't go on from, found after the row parsed -- the row's line and the
+column at fault travel with it, as they do with the CSV reader's own errors.
+*/
+#[derive(Debug)]
+pub struct RowError {
+    pub line: u64,
+    pub column: &'static str,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {} {}", self.line, self.code, self.message)
+    }
+}
+
+impl Error for RowError {}
+
+/*
+An error as --errors-format json writes it: where it was, a code for tooling to key on, and the
+message a person would read.  Line and column are left out when the error isn't about any one
+row -- a file that can't be read, say.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct ErrorReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ErrorReport {
+    /*
+    `headers` names the column of a CSV error, which only knows the field's index.
+    */
+    pub fn new(error: &(dyn Error + 'static), headers: Option<&StringRecord>) -> ErrorReport {
+        if let Some(error) = error.downcast_ref::<RowError>() {
+            return ErrorReport {
+                line: Some(error.line),
+                column: Some(error.column.to_owned()),
+                code: error.code,
+                message: error.message.clone(),
+            };
+        }
+
+        let Some(error) = error.downcast_ref::<csv::Error>() else {
+            return ErrorReport {
+                line: None,
+                column: None,
+                code: ErrorCode::ReadFailed,
+                message: error.to_string(),
+            };
+        };
+        let column = |field: Option<u64>| {
+            field.map(|index| {
+                headers
+                    .and_then(|headers| headers.get(index as usize))
+                    .map_or_else(|| index.to_string(), str::to_owned)
+            })
+        };
+        let line = error.position().map(|position| position.line());
+
+        match error.kind() {
+            ErrorKind::Deserialize { pos, err } => ErrorReport {
+                line,
+                column: column(err.field()),
+                code: ErrorCode::InvalidField,
+                message: err.kind().to_string(),
+            },
+            ErrorKind::Utf8 { pos, err } => ErrorReport {
+                line,
+                column: column(Some(err.field() as u64)),
+                code: ErrorCode::InvalidUtf8,
+                message: error.to_string(),
+            },
+            ErrorKind::UnequalLengths {
+                pos,
+                expected_len,
+                len,
+            } => ErrorReport {
+                line,
+                column: None,
+                code: ErrorCode::WrongFieldCount,
+                message: error.to_string(),
+            },
+            ErrorKind::Io(err) => ErrorReport {
+                line,
+                column: None,
+                code: ErrorCode::ReadFailed,
+                message: err.to_string(),
+            },
+            _ => ErrorReport {
+                line,
+                column: None,
+                code: ErrorCode::MalformedCsv,
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+/*
+The stats a run closes with, as --errors-format json writes them after any warnings.
+*/
+#[derive(Serialize)]
+pub struct RunReport<'a> {
+    pub report: &'a RunStats,
+}
@@ -0,0 +1,366 @@
+use std::{collections::BTreeMap, error::Error};
+
+use crate::{ledger::LedgerEvent, tenants::TenantDatabase, transactions::TransactionRecord};
+
+/*
+Rows per INSERT statement, and per database transaction.  Each batch either lands completely
+or not at all, so a failed export can simply be rerun -- summaries are upserted, and the
+ledger rows of a batch that failed were never written.
+*/
+const BATCH_SIZE: usize = 1000;
+
+/*
+Where in the reporting database a run's results go.
+*/
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ExportTables {
+    pub summaries: String,
+    pub ledger: String,
+}
+
+impl Default for ExportTables {
+    fn default() -> ExportTables {
+        ExportTables {
+            summaries: "account_summaries".to_owned(),
+            ledger: "ledger_events".to_owned(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Export {
+    pub summaries: usize,
+    pub ledger_rows: usize,
+}
+
+/*
+Table names can't be bound as query parameters, so they're spliced into the SQL -- only plain
+(optionally schema-qualified) identifiers are allowed.
+*/
+pub fn is_valid_table_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').all(|part| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/*
+One account of the summary, as it's exported.  Amounts are decimal strings, for the database to
+store as NUMERIC, so nothing is lost on the way in.
+*/
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SummaryExportRow<'a> {
+    pub tenant: &'a str,
+    pub client_id: i32,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+}
+
+/*
+One applied transaction from the ledger, as it's exported.  A numeric tx id goes in `tx` and a
+UUID in `tx_uuid`, the other left empty.
+*/
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct LedgerExportRow<'a> {
+    pub tenant: &'a str,
+    pub sequence: i64,
+    pub kind: &'static str,
+    pub client_id: i32,
+    pub tx: Option<i64>,
+    pub tx_uuid: Option<String>,
+    pub amount: String,
+    pub disputed_amount: String,
+    pub date: Option<String>,
+    pub memo: Option<&'a str>,
+}
+
+/*
+A database a run's results can be exported to.  Each call writes one batch, in a database
+transaction of its own.
+*/
+pub trait ExportTarget {
+    /*
+    Creates the tables if they don't exist, and brings older ones up to date:
+
+        account_summaries(tenant, client_id, available, held, total, locked)
+        ledger_events(tenant, sequence, type, client_id, tx, tx_uuid, amount, disputed_amount, date,
+                      memo)
+    */
+    fn create_tables(&mut self, tables: &ExportTables) -> Result<(), Box<dyn Error>>;
+
+    /*
+    Inserts the accounts, replacing any already there with the same tenant and client id.
+    */
+    fn upsert_summaries(
+        &mut self,
+        table: &str,
+        rows: &[SummaryExportRow],
+    ) -> Result<(), Box<dyn Error>>;
+
+    fn append_ledger(
+        &mut self,
+        table: &str,
+        rows: &[LedgerExportRow],
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/*
+Writes a run's results to `target`: every account in the summary is upserted by tenant and
+client id, and every ledger event applied during the run (those after `first_events`, the
+number each tenant already had) is appended.
+*/
+pub fn export(
+    target: &mut dyn ExportTarget,
+    tables: &ExportTables,
+    database: &TenantDatabase,
+    first_events: &BTreeMap<String, usize>,
+    changed_only: bool,
+) -> Result<Export, Box<dyn Error>> {
+    for table in [&tables.summaries, &tables.ledger] {
+        if !is_valid_table_name(table) {
+            return Err(format!("invalid table name: {}", table).into());
+        }
+    }
+
+    target.create_tables(tables)?;
+
+    let mut export = Export::default();
+    let summaries: Vec<_> = database
+        .tenants()
+        .flat_map(|(tenant, accounts)| {
+            accounts
+                .accounts()
+                .filter(move |account| !changed_only || account.is_changed())
+                .map(move |account| SummaryExportRow {
+                    tenant,
+                    client_id: account.client_id().0 as i32,
+                    available: account.available().to_string(),
+                    held: account.held().to_string(),
+                    total: (account.available() + account.held()).to_string(),
+                    locked: account.is_locked(),
+                })
+        })
+        .collect();
+
+    for batch in summaries.chunks(BATCH_SIZE) {
+        target.upsert_summaries(&tables.summaries, batch)?;
+        export.summaries += batch.len();
+    }
+
+    let events: Vec<_> =
+        database
+            .tenants()
+            .flat_map(|(tenant, accounts)| {
+                let first = first_events.get(tenant).copied().unwrap_or_default();
+
+                accounts.events().iter().enumerate().skip(first).filter_map(
+                    move |(sequence, event)| match *event {
+                        LedgerEvent::TransactionApplied {
+                            transaction,
+                            disputed_amount,
+                            date,
+                        } => Some(LedgerExportRow {
+                            tenant,
+                            sequence: sequence as i64,
+                            kind: transaction.kind(),
+                            client_id: transaction.id().client_id.0 as i32,
+                            tx: transaction.id().transaction_id.number().map(i64::from),
+                            tx_uuid: (transaction.id().transaction_id.number().is_none())
+                                .then(|| transaction.id().transaction_id.to_string()),
+                            amount: signed_amount(&transaction),
+                            disputed_amount: disputed_amount.to_string(),
+                            date: date.map(|date| date.to_string()),
+                            memo: accounts.memo(sequence),
+                        }),
+                        LedgerEvent::AccountOpened { client_id } => None,
+                        LedgerEvent::BehaviorSelected { version } => None,
+                        LedgerEvent::LowBalance { .. } => None,
+                    },
+                )
+            })
+            .collect();
+
+    for batch in events.chunks(BATCH_SIZE) {
+        target.append_ledger(&tables.ledger, batch)?;
+        export.ledger_rows += batch.len();
+    }
+
+    Ok(export)
+}
+
+/*
+Negative adjustments are the only records whose amount comes off the account.
+*/
+fn signed_amount(record: &TransactionRecord) -> String {
+    match *record {
+        TransactionRecord::Adjustment {
+            id,
+            amount,
+            negative: true,
+            operator,
+        } => format!("-{}", amount),
+        _ => record.amount().to_string(),
+    }
+}
+
+#[cfg(feature = "sqlx")]
+pub use postgres::export_db;
+
+#[cfg(feature = "sqlx")]
+mod postgres {
+    use std::{collections::BTreeMap, error::Error};
+
+    use sqlx::{Connection, PgConnection, Postgres, QueryBuilder};
+    use tokio::runtime::Runtime;
+
+    use super::{export, Export, ExportTables, ExportTarget, LedgerExportRow, SummaryExportRow};
+    use crate::tenants::TenantDatabase;
+
+    /*
+    Writes a run's results to Postgres -- see export.
+    */
+    pub fn export_db(
+        url: &str,
+        tables: &ExportTables,
+        database: &TenantDatabase,
+        first_events: &BTreeMap<String, usize>,
+        changed_only: bool,
+    ) -> Result<Export, Box<dyn Error>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let connection = runtime.block_on(PgConnection::connect(url))?;
+        let mut target = PostgresTarget {
+            runtime,
+            connection,
+        };
+        let export = export(&mut target, tables, database, first_events, changed_only)?;
+
+        target.runtime.block_on(target.connection.close())?;
+
+        Ok(export)
+    }
+
+    struct PostgresTarget {
+        runtime: Runtime,
+        connection: PgConnection,
+    }
+
+    impl ExportTarget for PostgresTarget {
+        fn create_tables(&mut self, tables: &ExportTables) -> Result<(), Box<dyn Error>> {
+            self.runtime.block_on(
+                sqlx::raw_sql(&format!(
+                    "CREATE TABLE IF NOT EXISTS {summaries} (
+                        tenant TEXT NOT NULL,
+                        client_id INTEGER NOT NULL,
+                        available NUMERIC NOT NULL,
+                        held NUMERIC NOT NULL,
+                        total NUMERIC NOT NULL,
+                        locked BOOLEAN NOT NULL,
+                        PRIMARY KEY (tenant, client_id)
+                    );
+                    CREATE TABLE IF NOT EXISTS {ledger} (
+                        tenant TEXT NOT NULL,
+                        sequence BIGINT NOT NULL,
+                        type TEXT NOT NULL,
+                        client_id INTEGER NOT NULL,
+                        tx BIGINT,
+                        tx_uuid UUID,
+                        amount NUMERIC NOT NULL,
+                        disputed_amount NUMERIC NOT NULL,
+                        date DATE,
+                        memo TEXT
+                    );
+                    ALTER TABLE {ledger} ADD COLUMN IF NOT EXISTS memo TEXT;
+                    ALTER TABLE {ledger} ADD COLUMN IF NOT EXISTS tx_uuid UUID;
+                    ALTER TABLE {ledger} ALTER COLUMN tx DROP NOT NULL",
+                    summaries = tables.summaries,
+                    ledger = tables.ledger
+                ))
+                .execute(&mut self.connection),
+            )?;
+
+            Ok(())
+        }
+
+        fn upsert_summaries(
+            &mut self,
+            table: &str,
+            rows: &[SummaryExportRow],
+        ) -> Result<(), Box<dyn Error>> {
+            let mut query: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+                "INSERT INTO {} (tenant, client_id, available, held, total, locked) ",
+                table
+            ));
+
+            query.push_values(rows, |mut row, summary| {
+                row.push_bind(summary.tenant)
+                    .push_bind(summary.client_id)
+                    .push_bind(&summary.available)
+                    .push_unseparated("::numeric")
+                    .push_bind(&summary.held)
+                    .push_unseparated("::numeric")
+                    .push_bind(&summary.total)
+                    .push_unseparated("::numeric")
+                    .push_bind(summary.locked);
+            });
+            query.push(
+                " ON CONFLICT (tenant, client_id) DO UPDATE SET available = EXCLUDED.available, \
+                 held = EXCLUDED.held, total = EXCLUDED.total, locked = EXCLUDED.locked",
+            );
+
+            self.runtime.block_on(async {
+                let mut transaction = self.connection.begin().await?;
+
+                query.build().execute(&mut *transaction).await?;
+                transaction.commit().await
+            })?;
+
+            Ok(())
+        }
+
+        fn append_ledger(
+            &mut self,
+            table: &str,
+            rows: &[LedgerExportRow],
+        ) -> Result<(), Box<dyn Error>> {
+            let mut query: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+                "INSERT INTO {} \
+                 (tenant, sequence, type, client_id, tx, tx_uuid, amount, disputed_amount, date, \
+                  memo) ",
+                table
+            ));
+
+            query.push_values(rows, |mut row, event| {
+                row.push_bind(event.tenant)
+                    .push_bind(event.sequence)
+                    .push_bind(event.kind)
+                    .push_bind(event.client_id)
+                    .push_bind(event.tx)
+                    .push_bind(&event.tx_uuid)
+                    .push_unseparated("::uuid")
+                    .push_bind(&event.amount)
+                    .push_unseparated("::numeric")
+                    .push_bind(&event.disputed_amount)
+                    .push_unseparated("::numeric")
+                    .push_bind(&event.date)
+                    .push_unseparated("::date")
+                    .push_bind(event.memo);
+            });
+
+            self.runtime.block_on(async {
+                let mut transaction = self.connection.begin().await?;
+
+                query.build().execute(&mut *transaction).await?;
+                transaction.commit().await
+            })?;
+
+            Ok(())
+        }
+    }
+}
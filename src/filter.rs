@@ -0,0 +1,181 @@
+use std::{collections::HashMap, error::Error, io, str::FromStr};
+
+use csv::{Reader, Writer};
+
+use crate::{
+    dates::Date,
+    transactions::{ClientId, TxId},
+};
+
+/*
+Every transaction type the engine reads, for checking --kind against.
+*/
+pub const KINDS: [&str; 12] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "opening",
+    "tombstone",
+    "adjustment",
+    "approve",
+    "open",
+    "kyc_approved",
+    "kyc_rejected",
+];
+
+/*
+Client ids from `first` to `last`, inclusive -- `7` on its own, or `1-1000`.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ClientRange {
+    pub first: ClientId,
+    pub last: ClientId,
+}
+
+impl ClientRange {
+    pub fn contains(&self, client_id: ClientId) -> bool {
+        self.first <= client_id && client_id <= self.last
+    }
+}
+
+impl FromStr for ClientRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, last) = s.split_once('-').unwrap_or((s, s));
+        let first: ClientId = first.trim().parse().map_err(|_| ())?;
+        let last: ClientId = last.trim().parse().map_err(|_| ())?;
+
+        if first > last {
+            return Err(());
+        }
+
+        Ok(ClientRange { first, last })
+    }
+}
+
+/*
+Which rows `filter` picks out.  An empty `kinds` takes every type; a row without a date never
+passes `from`.
+*/
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct TransactionFilter {
+    pub kinds: Vec<String>,
+    pub clients: Option<ClientRange>,
+    pub from: Option<Date>,
+}
+
+impl TransactionFilter {
+    /*
+    A comma-separated list of types, as --kind takes it.
+    */
+    pub fn parse_kinds(text: &str) -> Result<Vec<String>, String> {
+        text.split(',')
+            .map(|kind| kind.trim().to_lowercase())
+            .map(|kind| match KINDS.contains(&kind.as_str()) {
+                true => Ok(kind),
+                false => Err(format!("unknown transaction type: {}", kind)),
+            })
+            .collect()
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Filtered {
+    pub matched_rows: usize,
+    pub referenced_rows: usize,
+}
+
+/*
+Writes the rows of a transaction log that `filter` picks out, as a log that still replays the
+way those rows did in the original -- for cutting a production dump down to a reproduction.
+
+A picked row that reuses an earlier row's transaction id -- a dispute, resolve or chargeback,
+an approval, a rejected duplicate -- only means what it meant with those earlier rows, so every
+earlier row with that id comes along too, whatever its type, client or date.  A chargeback
+brings its deposit and the dispute before it.  Rows keep their input order and columns.
+*/
+pub fn filter<R: io::Read, W: io::Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    filter: &TransactionFilter,
+) -> Result<Filtered, Box<dyn Error>> {
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let kind_column = column("type").ok_or("input has no type column")?;
+    let client_column = column("client").ok_or("input has no client column")?;
+    let tx_column = column("tx").ok_or("input has no tx column")?;
+    let tenant_column = column("tenant");
+    let date_column = column("date");
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let mut keys = Vec::with_capacity(records.len());
+    let mut matched = Vec::with_capacity(records.len());
+
+    for record in &records {
+        let field = |index: usize| record.get(index).unwrap_or_default().trim();
+        let client_id: ClientId = field(client_column)
+            .parse()
+            .map_err(|_| format!("invalid client: {}", field(client_column)))?;
+        let tx: TxId = field(tx_column)
+            .parse()
+            .map_err(|_| format!("invalid tx: {}", field(tx_column)))?;
+        let date = match date_column.map(field).filter(|date| !date.is_empty()) {
+            Some(date) => Some(
+                date.parse::<Date>()
+                    .map_err(|_| format!("invalid date: {}", date))?,
+            ),
+            None => None,
+        };
+        let kind = field(kind_column).to_lowercase();
+
+        keys.push((tenant_column.map(field).unwrap_or_default().to_owned(), tx));
+        matched.push(
+            (filter.kinds.is_empty() || filter.kinds.contains(&kind))
+                && filter
+                    .clients
+                    .is_none_or(|clients| clients.contains(client_id))
+                && filter
+                    .from
+                    .is_none_or(|from| date.is_some_and(|date| date >= from)),
+        );
+    }
+
+    /*
+    The last picked row for each id -- everything with that id up to it is needed.
+    */
+    let mut last_matched: HashMap<&(String, TxId), usize> = HashMap::new();
+
+    for (index, key) in keys.iter().enumerate() {
+        if matched[index] {
+            last_matched.insert(key, index);
+        }
+    }
+
+    let mut filtered = Filtered {
+        matched_rows: 0,
+        referenced_rows: 0,
+    };
+
+    writer.write_record(&headers)?;
+
+    for (index, record) in records.iter().enumerate() {
+        if matched[index] {
+            filtered.matched_rows += 1;
+        } else if last_matched
+            .get(&keys[index])
+            .is_some_and(|&last| index < last)
+        {
+            filtered.referenced_rows += 1;
+        } else {
+            continue;
+        }
+
+        writer.write_record(record)?;
+    }
+
+    writer.flush()?;
+
+    Ok(filtered)
+}
@@ -0,0 +1,296 @@
+use std::{cmp::Reverse, collections::BinaryHeap, error::Error, io, str::FromStr};
+
+use csv::Writer;
+use serde::Serialize;
+
+use crate::{
+    transactions::{ClientId, TxId},
+    Money,
+};
+
+/*
+A named shape of workload for synthetic input, so benchmarks and load tests see something like
+production traffic rather than uniform noise.
+
+retail: many clients with modest activity, small amounts, and rare disputes.
+high-dispute: fewer clients, and disputes (half of them charged back) on one deposit in twelve.
+whales: a handful of clients carry most of the traffic, with amounts spread over many orders of
+    magnitude.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Profile {
+    Retail,
+    HighDispute,
+    Whales,
+}
+
+/*
+How a profile's transactions are drawn:
+
+- Clients are picked by a Zipf distribution over `clients` ranks: the client of rank k is
+  picked in proportion to 1 / k^zipf_exponent, so a higher exponent piles traffic onto fewer
+  clients.
+- Amounts are log-normal around `median_amount`, with `amount_spread` the standard deviation of
+  their logarithm, rounded to cents.
+- `dispute_rate` of deposits are later disputed, and `chargeback_rate` of those disputes are
+  charged back rather than resolved.
+*/
+struct ProfileParams {
+    clients: u16,
+    zipf_exponent: f64,
+    median_amount: f64,
+    amount_spread: f64,
+    withdrawal_rate: f64,
+    dispute_rate: f64,
+    chargeback_rate: f64,
+}
+
+impl Profile {
+    fn params(self) -> ProfileParams {
+        match self {
+            Profile::Retail => ProfileParams {
+                clients: 5000,
+                zipf_exponent: 0.8,
+                median_amount: 25.0,
+                amount_spread: 0.9,
+                withdrawal_rate: 0.35,
+                dispute_rate: 0.005,
+                chargeback_rate: 0.2,
+            },
+            Profile::HighDispute => ProfileParams {
+                clients: 1000,
+                zipf_exponent: 1.0,
+                median_amount: 60.0,
+                amount_spread: 1.1,
+                withdrawal_rate: 0.3,
+                dispute_rate: 0.08,
+                chargeback_rate: 0.5,
+            },
+            Profile::Whales => ProfileParams {
+                clients: 2000,
+                zipf_exponent: 1.4,
+                median_amount: 40.0,
+                amount_spread: 2.2,
+                withdrawal_rate: 0.4,
+                dispute_rate: 0.01,
+                chargeback_rate: 0.3,
+            },
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "retail" => Ok(Profile::Retail),
+            "high-dispute" => Ok(Profile::HighDispute),
+            "whales" => Ok(Profile::Whales),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct GeneratedRow {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub amount: Option<Money>,
+}
+
+/*
+What happens to a deposit after it's made, if anything: it's disputed, and later the dispute is
+resolved or charged back.
+*/
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+enum FollowUp {
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+/*
+The most rows between a deposit and its dispute, or a dispute and its outcome.
+*/
+const FOLLOW_UP_WINDOW: u64 = 200;
+
+/*
+Largest amount we'll generate, however far out the distribution's tail reaches.
+*/
+const MAX_AMOUNT_CENTS: u64 = 100_000_000;
+
+/*
+Writes `count` rows of synthetic input in the given profile.  The same profile, count and seed
+always give the same rows.
+
+Withdrawals never exceed what the generator has deposited for the client, so most succeed; the
+ones that don't are those made after a dispute or chargeback took the funds away, as they would
+in production.  Disputes still pending when the rows run out are left open.
+*/
+pub fn generate<W: io::Write>(
+    profile: Profile,
+    count: usize,
+    seed: u64,
+    writer: &mut Writer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    let params = profile.params();
+    let mut random = SplitMix64(seed);
+    let clients = ZipfClients::new(params.clients, params.zipf_exponent);
+    let mut balances = vec![0u64; params.clients as usize + 1];
+    let mut pending: BinaryHeap<Reverse<(u64, FollowUp, ClientId, TxId)>> = BinaryHeap::new();
+    let mut next_tx = 1;
+
+    for row in 0..count as u64 {
+        let due = match pending.peek() {
+            Some(Reverse((due, ..))) => *due <= row,
+            None => false,
+        };
+
+        if due {
+            let Some(Reverse((_, follow_up, client, tx))) = pending.pop() else {
+                unreachable!()
+            };
+            let kind = match follow_up {
+                FollowUp::Dispute => {
+                    let outcome = if random.chance(params.chargeback_rate) {
+                        FollowUp::Chargeback
+                    } else {
+                        FollowUp::Resolve
+                    };
+
+                    pending.push(Reverse((
+                        row + 1 + random.below(FOLLOW_UP_WINDOW),
+                        outcome,
+                        client,
+                        tx,
+                    )));
+                    "dispute"
+                }
+                FollowUp::Resolve => "resolve",
+                FollowUp::Chargeback => "chargeback",
+            };
+
+            writer.serialize(GeneratedRow {
+                kind,
+                client,
+                tx,
+                amount: None,
+            })?;
+            continue;
+        }
+
+        let client = clients.sample(&mut random);
+        let balance = &mut balances[client.0 as usize];
+        let cents = random.log_normal(params.median_amount * 100.0, params.amount_spread);
+        let tx = TxId::Number(next_tx);
+
+        next_tx += 1;
+
+        let (kind, cents) = if *balance > 0 && random.chance(params.withdrawal_rate) {
+            let cents = cents.min(*balance);
+
+            *balance -= cents;
+            ("withdrawal", cents)
+        } else {
+            *balance += cents;
+
+            if random.chance(params.dispute_rate) {
+                pending.push(Reverse((
+                    row + 1 + random.below(FOLLOW_UP_WINDOW),
+                    FollowUp::Dispute,
+                    client,
+                    tx,
+                )));
+            }
+            ("deposit", cents)
+        };
+
+        writer.serialize(GeneratedRow {
+            kind,
+            client,
+            tx,
+            amount: Some(Money(cents * 100)),
+        })?;
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/*
+Client ids drawn by rank from a Zipf distribution, by binary search over the cumulative
+weights.
+*/
+struct ZipfClients {
+    cumulative: Vec<f64>,
+}
+
+impl ZipfClients {
+    fn new(clients: u16, exponent: f64) -> ZipfClients {
+        let mut total = 0.0;
+        let cumulative = (1..=clients)
+            .map(|rank| {
+                total += 1.0 / (rank as f64).powf(exponent);
+                total
+            })
+            .collect();
+
+        ZipfClients { cumulative }
+    }
+
+    fn sample(&self, random: &mut SplitMix64) -> ClientId {
+        let target = random.unit() * self.cumulative.last().copied().unwrap_or_default();
+        let index = self
+            .cumulative
+            .partition_point(|&weight| weight <= target)
+            .min(self.cumulative.len() - 1);
+
+        ClientId(index as u16 + 1)
+    }
+}
+
+/*
+A small, fast, seedable generator -- plenty for test data, and it saves depending on a random
+number crate for one command.
+*/
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /*
+    Uniform in [0, 1).
+    */
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.unit() < probability
+    }
+
+    /*
+    A whole number of cents, at least one, by the Box-Muller transform.
+    */
+    fn log_normal(&mut self, median: f64, spread: f64) -> u64 {
+        let normal = (-2.0 * (1.0 - self.unit()).ln()).sqrt()
+            * (2.0 * std::f64::consts::PI * self.unit()).cos();
+
+        ((median.ln() + spread * normal).exp().round() as u64).clamp(1, MAX_AMOUNT_CENTS)
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::{
+    accounts::AccountDatabase, metadata::ClientMetadata, tenants::TenantDatabase,
+    transactions::ClientId, Money,
+};
+
+/*
+Accounts linked into groups -- a household, a company's accounts -- by a `group` column in the
+metadata file.  Clients with no group, or an empty one, stand alone.
+
+Metadata is keyed by client id alone, so a group names the same clients in every tenant, and its
+limits and totals are taken within each tenant separately.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct AccountGroups {
+    group_of: HashMap<ClientId, String>,
+    members: BTreeMap<String, Vec<ClientId>>,
+}
+
+impl AccountGroups {
+    pub fn from_metadata(metadata: &ClientMetadata) -> AccountGroups {
+        let mut groups = AccountGroups::default();
+
+        for (client_id, group) in metadata.values("group") {
+            let group = group.trim();
+
+            if group.is_empty() {
+                continue;
+            }
+
+            groups.group_of.insert(client_id, group.to_owned());
+            groups
+                .members
+                .entry(group.to_owned())
+                .or_default()
+                .push(client_id);
+        }
+
+        for members in groups.members.values_mut() {
+            members.sort();
+        }
+
+        groups
+    }
+
+    pub fn group(&self, client_id: ClientId) -> Option<&str> {
+        self.group_of.get(&client_id).map(String::as_str)
+    }
+
+    pub fn members(&self, group: &str) -> &[ClientId] {
+        self.members.get(group).map_or(&[], Vec::as_slice)
+    }
+
+    /*
+    Everything the group's accounts hold between them, available and held together.
+    */
+    pub fn total(&self, accounts: &AccountDatabase, group: &str) -> Money {
+        self.members(group)
+            .iter()
+            .filter_map(|&client_id| accounts.account(client_id))
+            .map(|account| account.available() + account.held())
+            .sum()
+    }
+}
+
+/*
+The most a group's accounts may hold between them.  A deposit that would take the group over
+is rejected outright, on top of any cap on the single account.
+*/
+#[derive(Clone, Debug)]
+pub struct GroupCap {
+    pub groups: AccountGroups,
+    pub cap: Money,
+}
+
+impl GroupCap {
+    pub fn allows(
+        &self,
+        accounts: Option<&AccountDatabase>,
+        client_id: ClientId,
+        amount: Money,
+    ) -> bool {
+        let Some(group) = self.groups.group(client_id) else {
+            return true;
+        };
+        let total = accounts.map_or(Money::zero(), |accounts| self.groups.total(accounts, group));
+
+        total
+            .checked_add(amount)
+            .is_some_and(|after| after <= self.cap)
+    }
+}
+
+/*
+One group's accounts added together, for the group summary written alongside the per-client
+one.  Only groups with at least one account in the tenant appear.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct GroupSummary {
+    pub tenant: String,
+    pub group: String,
+    pub clients: usize,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked_clients: usize,
+}
+
+pub fn group_summaries(database: &TenantDatabase, groups: &AccountGroups) -> Vec<GroupSummary> {
+    let mut summaries = Vec::new();
+
+    for (tenant, accounts) in database.tenants() {
+        for (group, members) in &groups.members {
+            let members: Vec<_> = members
+                .iter()
+                .filter_map(|&client_id| accounts.account(client_id))
+                .filter(|account| !account.is_erased())
+                .collect();
+
+            if members.is_empty() {
+                continue;
+            }
+
+            let available: Money = members.iter().map(|account| account.available()).sum();
+            let held: Money = members.iter().map(|account| account.held()).sum();
+
+            summaries.push(GroupSummary {
+                tenant: tenant.to_owned(),
+                group: group.clone(),
+                clients: members.len(),
+                available,
+                held,
+                total: available + held,
+                locked_clients: members.iter().filter(|account| account.is_locked()).count(),
+            });
+        }
+    }
+
+    summaries
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    accounts::Balances,
+    dates::Date,
+    ledger::{LedgerEvent, Projection},
+    pseudonym::ClientLabel,
+    transactions::ClientId,
+    Money,
+};
+
+/*
+End-of-day available and held funds per client, for interest calculations and statements.
+
+This replays the ledger through its own copy of the Balances projection, and after every dated
+transaction records where the affected account ended up.  Later transactions on the same day
+overwrite earlier ones, so what's left is the end-of-day position.
+
+Only days on which a client actually had activity get a row -- a client's balance on any other
+day is the balance from their most recent row before it.  Undated transactions still move the
+balances, but can't be attributed to a day so don't produce rows of their own.
+*/
+pub struct DailyBalances {
+    balances: Balances,
+    history: BTreeMap<(ClientId, Date), (Money, Money)>,
+
+    /*
+    The memos on each client's transactions that day, in ledger order.
+    */
+    memos: BTreeMap<(ClientId, Date), Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct DailyBalanceSummary {
+    pub client_id: ClientLabel,
+    date: String,
+    available: String,
+    held: String,
+    total: String,
+    memos: String,
+}
+
+impl DailyBalances {
+    pub fn new() -> DailyBalances {
+        DailyBalances {
+            balances: Balances::new(),
+            history: BTreeMap::new(),
+            memos: BTreeMap::new(),
+        }
+    }
+
+    /*
+    Attaches a memo to the day of the event it belongs to, once the event has been projected.
+    */
+    pub fn annotate(&mut self, event: &LedgerEvent, memo: &str) {
+        if let LedgerEvent::TransactionApplied {
+            transaction,
+            date: Some(date),
+            ..
+        } = event
+        {
+            self.memos
+                .entry((transaction.id().client_id, *date))
+                .or_default()
+                .push(memo.to_owned());
+        }
+    }
+
+    pub fn history(
+        &self,
+        client_id: Option<ClientId>,
+    ) -> impl Iterator<Item = DailyBalanceSummary> + '_ {
+        self.history
+            .iter()
+            .filter(move |((id, _), _)| client_id.is_none_or(|client_id| client_id == *id))
+            .map(
+                |(&(client_id, date), &(available, held))| DailyBalanceSummary {
+                    client_id: ClientLabel::Id(client_id),
+                    date: date.to_string(),
+                    available: available.to_string(),
+                    held: held.to_string(),
+                    total: (available + held).to_string(),
+                    memos: self
+                        .memos
+                        .get(&(client_id, date))
+                        .map(|memos| memos.join("; "))
+                        .unwrap_or_default(),
+                },
+            )
+    }
+}
+
+impl Projection for DailyBalances {
+    fn project(&mut self, event: &LedgerEvent) {
+        self.balances.project(event);
+
+        if let LedgerEvent::TransactionApplied {
+            transaction,
+            date: Some(date),
+            ..
+        } = event
+        {
+            let client_id = transaction.id().client_id;
+
+            if let Some(account) = self.balances.get(client_id) {
+                self.history
+                    .insert((client_id, *date), (account.available(), account.held()));
+            }
+        }
+    }
+}
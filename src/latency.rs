@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::Serialize;
+
+/*
+Below this many nanoseconds, every value gets a bucket of its own.  Above it, each power of two
+is split into eight buckets, so a bucket is never more than an eighth wider than its lower
+bound -- plenty to tell a deposit from a dispute -- and the whole range of u64 fits in a few
+hundred counters.
+*/
+const EXACT_BELOW: u64 = 16;
+const BUCKETS: usize = 16 + 60 * 8;
+
+/*
+A count of durations, bucketed as above.  Quantiles come back as the lower bound of the bucket
+they fall in.
+*/
+#[derive(Clone, Debug)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> LatencyHistogram {
+        LatencyHistogram {
+            counts: vec![0; BUCKETS],
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+
+        self.counts[bucket(nanos)] += 1;
+        self.total += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /*
+    The duration at `quantile` (between 0 and 1) of those recorded, in nanoseconds.  Zero when
+    nothing was recorded.
+    */
+    pub fn quantile(&self, quantile: f64) -> u64 {
+        let rank = ((self.total as f64 * quantile).ceil() as u64).max(1);
+        let mut seen = 0;
+
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                return lower_bound(index);
+            }
+        }
+
+        0
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram::new()
+    }
+}
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < EXACT_BELOW {
+        return nanos as usize;
+    }
+
+    let exponent = 63 - nanos.leading_zeros() as usize;
+    let mantissa = (nanos >> (exponent - 3)) as usize & 7;
+
+    16 + (exponent - 4) * 8 + mantissa
+}
+
+fn lower_bound(index: usize) -> u64 {
+    if index < EXACT_BELOW as usize {
+        return index as u64;
+    }
+
+    let exponent = (index - 16) / 8 + 4;
+    let mantissa = ((index - 16) % 8) as u64;
+
+    (8 + mantissa) << (exponent - 3)
+}
+
+/*
+How long applying each transaction took, by its type and by what the engine did with it -- see
+TenantDatabase::apply_untimed for the outcomes.  A dispute has to find the deposit it disputes, and a rejected row often stops
+short of touching the account at all, so lumping them in with deposits would hide both.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct ApplyLatencies {
+    histograms: BTreeMap<(&'static str, &'static str), LatencyHistogram>,
+}
+
+/*
+One type and outcome's latencies, for the stats output.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct LatencySummary {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub outcome: &'static str,
+    pub count: u64,
+    pub p50_nanos: u64,
+    pub p95_nanos: u64,
+    pub p99_nanos: u64,
+}
+
+impl ApplyLatencies {
+    pub fn new() -> ApplyLatencies {
+        ApplyLatencies::default()
+    }
+
+    pub fn record(&mut self, kind: &'static str, outcome: &'static str, elapsed: Duration) {
+        self.histograms
+            .entry((kind, outcome))
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn summaries(&self) -> Vec<LatencySummary> {
+        self.histograms
+            .iter()
+            .map(|(&(kind, outcome), histogram)| LatencySummary {
+                kind,
+                outcome,
+                count: histogram.count(),
+                p50_nanos: histogram.quantile(0.5),
+                p95_nanos: histogram.quantile(0.95),
+                p99_nanos: histogram.quantile(0.99),
+            })
+            .collect()
+    }
+}
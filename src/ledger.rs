@@ -0,0 +1,141 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    accounts::{BehaviorVersion, LowBalanceResponse},
+    dates::Date,
+    provenance::hex,
+    transactions::{ClientId, TransactionRecord, TxId},
+    Money,
+};
+
+/*
+The ledger is an append-only log of everything the database has accepted.  Nothing in it is
+ever modified or removed, so replaying it from the start always reproduces the same state.
+
+Account balances are just one view over this log.  Anything else we want to know about the
+ledger (balance history, dispute statistics, ...) can be built as another Projection over the
+same events without touching the write path in AccountDatabase.
+*/
+#[derive(Clone, Copy, Debug)]
+pub enum LedgerEvent {
+    AccountOpened {
+        client_id: ClientId,
+    },
+
+    /*
+    Disputes, resolves, and chargebacks don't carry an amount of their own, so we capture the
+    amount of the referenced transaction at the time it was applied.  This keeps each event
+    self-contained -- a projection never needs to look anything else up.
+
+    The date is whatever the input said, if it said anything at all.
+    */
+    TransactionApplied {
+        transaction: TransactionRecord,
+        disputed_amount: Money,
+        date: Option<Date>,
+    },
+
+    /*
+    Which rules every later event was recorded under -- see accounts::BehaviorVersion.  A ledger
+    without one was recorded under v1.
+    */
+    BehaviorSelected {
+        version: BehaviorVersion,
+    },
+
+    /*
+    A transaction took an account's available funds below the low-balance threshold, and this
+    was the response -- see accounts::LowBalanceResponse.  Recorded by the engine, not read from
+    the input, right after the transaction that caused it.
+    */
+    LowBalance {
+        client_id: ClientId,
+        transaction_id: TxId,
+        available: Money,
+        response: LowBalanceResponse,
+        date: Option<Date>,
+    },
+}
+
+pub trait Projection {
+    fn project(&mut self, event: &LedgerEvent);
+
+    fn replay<'a, I: IntoIterator<Item = &'a LedgerEvent>>(&mut self, events: I)
+    where
+        Self: Sized,
+    {
+        for event in events {
+            self.project(event);
+        }
+    }
+}
+
+/*
+A rolling SHA-256 over every transaction the ledger accepted, in order: each one is hashed together
+with the digest of everything before it, so the final digest pins down the whole sequence.
+Two parties who applied exactly the same ledger get the same hash, without exchanging it.
+
+Each transaction is hashed in a canonical form -- type, client, tx, signed amount, the amount
+it disputed, and date -- so formatting in the input (whitespace, "1.50" against "1.5") makes no
+difference, while anything that changes what was applied does.
+*/
+pub struct LedgerHash {
+    digest: [u8; 32],
+}
+
+impl LedgerHash {
+    pub fn new() -> LedgerHash {
+        LedgerHash { digest: [0; 32] }
+    }
+
+    /*
+    Marks the start of a tenant's ledger, when hashing several tenants into one digest.
+    */
+    pub fn start_tenant(&mut self, tenant: &str) {
+        self.chain(&format!("tenant,{}\n", tenant));
+    }
+
+    pub fn hex(&self) -> String {
+        hex(&self.digest)
+    }
+
+    fn chain(&mut self, line: &str) {
+        let mut hasher = Sha256::new();
+
+        hasher.update(self.digest);
+        hasher.update(line);
+        self.digest = hasher.finalize().into();
+    }
+}
+
+impl Projection for LedgerHash {
+    fn project(&mut self, event: &LedgerEvent) {
+        if let LedgerEvent::TransactionApplied {
+            transaction,
+            disputed_amount,
+            date,
+        } = event
+        {
+            let sign = match transaction {
+                TransactionRecord::Adjustment {
+                    id,
+                    amount,
+                    negative: true,
+                    operator,
+                } => "-",
+                _ => "",
+            };
+
+            self.chain(&format!(
+                "{},{},{},{}{},{},{}\n",
+                transaction.kind(),
+                transaction.id().client_id,
+                transaction.id().transaction_id,
+                sign,
+                transaction.amount(),
+                disputed_amount,
+                date.map(|date| date.to_string()).unwrap_or_default()
+            ));
+        }
+    }
+}
@@ -1,17 +1,62 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use accounts::{AccountDatabase, AccountSummary};
-use csv::{Reader, ReaderBuilder, Writer};
-use std::fmt::Debug;
+use accounts::{AccountOptions, ConflictPolicy, DisputeAmountPolicy};
+use admin::run_script;
+use aggregate::{aggregate, CohortSummary, Grouping};
+use audit::audit_log;
+use backfill::backfill;
+use cdc::write_changes;
+use cli::{Command, ErrorFormat, OutputFormat};
+use clock::{Clock, SystemClock};
+use compact::compact;
+use completions::{write_completions, write_man};
+use csv::{Reader, ReaderBuilder, StringRecord, Writer};
+use dates::{Date, DateBasis};
+use diff::{AccountSummaryDiff, DiffRow, Snapshot};
+use erase::erase_client;
+use errors::{ErrorCode, ErrorReport, RowError, RunReport};
+use filter::filter;
+use generate::generate;
+use groups::{group_summaries, AccountGroups, GroupCap};
+use history::DailyBalances;
+use ledger::{LedgerEvent, Projection};
+use memmap2::Mmap;
+use merge::merge;
+use merkle::BalanceTree;
+use metadata::{ClientMetadata, TagFilter};
+use middleware::Pipeline;
+use minimize::minimize;
+use mirror::write_mirror;
+use movers::{top_movers, SummarySnapshot};
+use output::ChunkedWriter;
+use partition::split;
+use perf::Allocations;
+use provenance::Provenance;
+use pseudonym::Pseudonymizer;
+use regress::regress;
+use schema::{AccountSummaryV1, SchemaVersion, SummaryRow, TenantAccountSummaryV1};
+use seal::{sealed_through, write_late_transactions, PeriodSeal};
+use search::{search, AccountFilter};
+use simulate::{load_schedule, simulate, SimulationOptions};
+use sinks::{FanOut, SummarySink};
+use stats::{write_client_mismatches, write_conflicting_duplicates, write_shortfalls, RunStats};
+use status::StatusHistory;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display};
 use std::fs::File;
-use std::ops::Sub;
-use std::path::Path;
+use std::io::Write;
+use std::iter::Sum;
+use std::ops::{Div, Sub};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::str::FromStr;
 use std::{env, io};
 use std::{error::Error, ops::Add};
-use transactions::{TransactionRecord, TransactionText};
+use tenants::TenantDatabase;
+use transactions::{ClientId, TransactionRecord, TransactionText};
+use tx_index::{export_tx_index, load_tx_index};
+use tx_map::TxMap;
 
 /*
     This is a fixed precision integer representation of money.
@@ -42,6 +87,20 @@ impl Sub for Money {
     }
 }
 
+impl Div<u64> for Money {
+    type Output = Money;
+
+    fn div(self, rhs: u64) -> Self::Output {
+        Money(self.0 / rhs)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Self {
+        iter.fold(Money::zero(), |total, amount| total + amount)
+    }
+}
+
 #[derive(Debug)]
 pub enum MoneyParseError {
     ExceededPrecision,
@@ -52,9 +111,136 @@ impl FromStr for Money {
     type Err = MoneyParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Money::parse_strict(s.as_bytes()) {
+            Some(money) => Ok(money),
+            None => Money::parse_lenient(s),
+        }
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.format(&mut [0; MONEY_TEXT_LEN]))
+    }
+}
+
+/*
+Summaries are serialized a row at a time, so formatting amounts straight into the output
+rather than through `to_string()` keeps very large outputs from allocating per row.
+*/
+impl serde::Serialize for Money {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.format(&mut [0; MONEY_TEXT_LEN]))
+    }
+}
+
+impl Debug for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.to_string().as_str())
+    }
+}
+
+/*
+The longest amount there is: sixteen whole digits, the point, and four decimals.
+*/
+const MONEY_TEXT_LEN: usize = 21;
+
+impl Money {
+    pub fn zero() -> Money {
+        Money(0)
+    }
+
+    /*
+    Writes the amount into `buffer`, back to front, and returns the part that was used -- the
+    same text as `Display`, without allocating: whole units, a point, then the decimals with
+    trailing zeros dropped (but always at least one digit).
+    */
+    fn format(self, buffer: &mut [u8; MONEY_TEXT_LEN]) -> &str {
+        let mut start = buffer.len();
+        let mut decimal = self.0 % 10000;
+        let mut digits = 4;
+
+        while decimal > 0 && decimal.is_multiple_of(10) {
+            decimal /= 10;
+            digits -= 1;
+        }
+
+        if decimal == 0 {
+            digits = 1;
+        }
+
+        for _ in 0..digits {
+            start -= 1;
+            buffer[start] = b'0' + (decimal % 10) as u8;
+            decimal /= 10;
+        }
+
+        start -= 1;
+        buffer[start] = b'.';
+
+        let mut whole = self.0 / 10000;
+
+        loop {
+            start -= 1;
+            buffer[start] = b'0' + (whole % 10) as u8;
+            whole /= 10;
+
+            if whole == 0 {
+                break;
+            }
+        }
+
+        std::str::from_utf8(&buffer[start..]).expect("amounts format as ASCII")
+    }
+
+    pub fn checked_add(self, rhs: Money) -> Option<Money> {
+        self.0.checked_add(rhs.0).map(Money)
+    }
+
+    /*
+    Splits the amount in proportion to `ratios`, down to the smallest unit we track, so the
+    parts always add back up to the whole.  Each part gets its exact share rounded down, and
+    the units left over go one each to the parts that lost the most to rounding (the earlier
+    part, on a tie).
+
+    Panics if there are ratios but every one of them is zero -- there's no proportion to split by.
+    */
+    pub fn allocate(&self, ratios: &[u32]) -> Vec<Money> {
+        if ratios.is_empty() {
+            return Vec::new();
+        }
+
+        let total: u128 = ratios.iter().map(|&ratio| ratio as u128).sum();
+
+        assert!(total > 0, "cannot allocate by ratios that are all zero");
+
+        let mut parts: Vec<Money> = Vec::with_capacity(ratios.len());
+        let mut remainders: Vec<(u128, usize)> = Vec::with_capacity(ratios.len());
+
+        for (index, &ratio) in ratios.iter().enumerate() {
+            let exact = self.0 as u128 * ratio as u128;
+
+            parts.push(Money((exact / total) as u64));
+            remainders.push((exact % total, index));
+        }
+
+        let leftover = self.0 - parts.iter().map(|part| part.0).sum::<u64>();
+
+        remainders.sort_by(|(left, left_index), (right, right_index)| {
+            right.cmp(left).then(left_index.cmp(right_index))
+        });
+
+        for &(_, index) in remainders.iter().take(leftover as usize) {
+            parts[index].0 += 1;
+        }
+
+        parts
+    }
+
+    fn parse_lenient(s: &str) -> Result<Money, MoneyParseError> {
         let trimmed = s.trim();
 
-        if trimmed.len() == 0 {
+        if trimmed.is_empty() {
             Err(MoneyParseError::Malformed)
         } else {
             let parts: Vec<&str> = trimmed.split('.').collect();
@@ -69,35 +255,48 @@ impl FromStr for Money {
             }
         }
     }
-}
 
-impl ToString for Money {
-    fn to_string(&self) -> String {
-        let mut s = String::new();
-        s.push_str((self.0 / 10000).to_string().as_str());
-        s.push('.');
+    /*
+    Amount parsing is hot, and nearly every amount we see is in the strict form: digits,
+    optionally followed by a point and one to four more digits.  This scans those bytes once,
+    without allocating, and gives up on anything else -- whitespace, signs, too many digits --
+    so the lenient parser can decide what it means.
+    */
+    fn parse_strict(bytes: &[u8]) -> Option<Money> {
+        let point = bytes
+            .iter()
+            .position(|&byte| byte == b'.')
+            .unwrap_or(bytes.len());
+        let (whole, decimal) = bytes.split_at(point);
+        let decimal = decimal.get(1..).unwrap_or_default();
 
-        let mut decimal = self.0 % 10000;
+        /*
+        Fifteen whole digits always fit; anything longer might not, so let the lenient parser
+        decide.
+        */
+        if whole.is_empty() || whole.len() > 15 || decimal.len() > 4 {
+            return None;
+        }
 
-        while decimal > 0 && decimal % 10 == 0 {
-            decimal = decimal / 10;
+        if point < bytes.len() && decimal.is_empty() {
+            return None;
         }
 
-        s.push_str((decimal).to_string().as_str());
+        let mut units: u64 = 0;
 
-        s
-    }
-}
+        for &byte in whole.iter().chain(decimal) {
+            if !byte.is_ascii_digit() {
+                return None;
+            }
 
-impl Debug for Money {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.to_string().as_str())
-    }
-}
+            units = units * 10 + (byte - b'0') as u64;
+        }
 
-impl Money {
-    pub fn zero() -> Money {
-        Money(0)
+        for _ in decimal.len()..4 {
+            units *= 10;
+        }
+
+        Some(Money(units))
     }
 
     fn parse_whole_part(text: &str) -> Result<u64, MoneyParseError> {
@@ -123,19 +322,118 @@ impl Money {
     }
 }
 
+mod admin;
+
+mod aggregate;
+
+mod audit;
+
+mod backfill;
+
+mod canary;
+
+mod cdc;
+
+mod cli;
+
+mod clock;
+
+mod compact;
+
+mod completions;
+
+mod dates;
+
+mod diff;
+
+mod erase;
+
+mod errors;
+
+mod export_db;
+
+mod filter;
+
+mod generate;
+
+mod groups;
+
+mod history;
+
+mod latency;
+
 mod transactions;
 
 mod accounts;
 
+mod ledger;
+
+mod memo;
+
+mod merge;
+
+mod merkle;
+
+mod metadata;
+
+mod middleware;
+
+mod minimize;
+
+mod mirror;
+
+mod movers;
+
+mod output;
+
+mod partition;
+
+mod perf;
+
+mod provenance;
+
+#[cfg(feature = "sql")]
+mod query;
+
+mod pseudonym;
+
+mod publish;
+
+mod regress;
+
+mod rounding;
+
+mod rules;
+
+mod schema;
+
+mod seal;
+
+mod search;
+
+mod seen;
+
+mod shared;
+
+mod simulate;
+
+mod sinks;
+
+mod stats;
+
+mod status;
+
+mod tenants;
+
+mod tx_index;
+
+mod tx_map;
+
 #[cfg(test)]
 mod tests;
 
 fn read_transactions_from_text(text: &str) -> Result<String, Box<dyn Error>> {
-    let mut reader = ReaderBuilder::default()
-        .trim(csv::Trim::All)
-        .flexible(true)
-        .has_headers(true)
-        .from_reader(text.as_bytes());
+    let mut reader = csv_reader(text.as_bytes());
     let mut writer = Writer::from_writer(vec![]);
 
     read_transactions(&mut reader, &mut writer)?;
@@ -149,17 +447,109 @@ fn read_transactions<I: io::Read, W: io::Write>(
     reader: &mut Reader<I>,
     writer: &mut Writer<W>,
 ) -> Result<(), Box<dyn Error>> {
-    let mut accounts = AccountDatabase::new();
+    summarize(
+        &mut TenantDatabase::new(),
+        reader,
+        writer,
+        &SummaryOptions::default(),
+    )?;
 
-    for record_result in reader.deserialize() {
-        let transaction_text: TransactionText = record_result?;
-        let transaction: TransactionRecord = transaction_text.into();
+    Ok(())
+}
 
-        accounts.apply(&transaction);
+#[derive(Default)]
+struct SummaryOptions<'a> {
+    changed_only: bool,
+    pseudonymizer: Option<&'a Pseudonymizer>,
+    pipeline: Option<&'a Pipeline>,
+    schema: SchemaVersion,
+}
+
+/*
+Applies the input on top of whatever is already in the database -- normally nothing, but for an
+incremental run, the transactions from earlier batches.  Only accounts touched by this input
+count as changed.
+*/
+fn summarize<I: io::Read>(
+    database: &mut TenantDatabase,
+    reader: &mut Reader<I>,
+    sink: &mut dyn SummarySink,
+    options: &SummaryOptions,
+) -> Result<RunStats, Box<dyn Error>> {
+    let multi_tenant = reader.headers()?.iter().any(|header| header == "tenant")
+        || database.tenants().any(|(tenant, _)| !tenant.is_empty());
+
+    database.clear_changes();
+    let start = Allocations::start();
+    let transactions = match options.pipeline {
+        Some(pipeline) => apply_transactions_through(database, reader, pipeline)?,
+        None => apply_transactions(database, reader)?,
+    };
+    let allocations = Allocations::since(start);
+
+    for (tenant, accounts) in database.tenants() {
+        let mut statuses = StatusHistory::new();
+
+        if matches!(options.schema, SchemaVersion::V3 | SchemaVersion::V4) {
+            statuses.replay(accounts.events());
+        }
+
+        for account in accounts.accounts() {
+            if options.changed_only && !account.is_changed() {
+                continue;
+            }
+
+            let mut row = SummaryRow::new(
+                options.schema,
+                multi_tenant.then_some(tenant),
+                account,
+                accounts.is_near_cap(account),
+                statuses.locked_reason(account.client_id()),
+                accounts.pending(account.client_id()),
+            );
+
+            if let (Some(pseudonymizer), Some(client_id)) =
+                (options.pseudonymizer, row.client_id_mut())
+            {
+                pseudonymizer.label(tenant, client_id);
+            }
+
+            sink.write(&row)?;
+        }
     }
+    sink.finish()?;
 
-    for account in accounts.accounts() {
-        let summary: AccountSummary = account.into();
+    Ok(RunStats {
+        allocations,
+        ..RunStats::collect(database, transactions)
+    })
+}
+
+fn read_balance_history<I: io::Read, W: io::Write>(
+    reader: &mut Reader<I>,
+    writer: &mut Writer<W>,
+    tenant: &str,
+    client_id: Option<ClientId>,
+    pseudonymizer: Option<&Pseudonymizer>,
+    date_basis: DateBasis,
+) -> Result<(), Box<dyn Error>> {
+    let database = load_transactions_dated(reader, date_basis)?;
+    let mut history = DailyBalances::new();
+
+    if let Some(accounts) = database.tenant(tenant) {
+        for (sequence, event) in accounts.events().iter().enumerate() {
+            history.project(event);
+
+            if let Some(memo) = accounts.memo(sequence) {
+                history.annotate(event, memo);
+            }
+        }
+    }
+
+    for mut summary in history.history(client_id) {
+        if let Some(pseudonymizer) = pseudonymizer {
+            pseudonymizer.label(tenant, &mut summary.client_id);
+        }
 
         writer.serialize(summary)?;
     }
@@ -168,24 +558,1343 @@ fn read_transactions<I: io::Read, W: io::Write>(
     Ok(())
 }
 
-fn main() -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("usage: notfizzbuzz input.csv > output.csv");
-        exit(0);
+fn read_status_history<I: io::Read, W: io::Write>(
+    reader: &mut Reader<I>,
+    writer: &mut Writer<W>,
+    tenant: &str,
+    client_id: Option<ClientId>,
+    pseudonymizer: Option<&Pseudonymizer>,
+) -> Result<(), Box<dyn Error>> {
+    let database = load_transactions(reader)?;
+    let mut history = StatusHistory::new();
+
+    if let Some(accounts) = database.tenant(tenant) {
+        history.replay(accounts.events());
+    }
+
+    for mut summary in history.changes(client_id) {
+        if let Some(pseudonymizer) = pseudonymizer {
+            pseudonymizer.label(tenant, &mut summary.client_id);
+        }
+
+        writer.serialize(summary)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn read_aggregate<I: io::Read>(
+    reader: &mut Reader<I>,
+    grouping: &Grouping,
+    metadata: &ClientMetadata,
+    tenant: &str,
+) -> Result<Vec<CohortSummary>, Box<dyn Error>> {
+    let database = load_transactions(reader)?;
+
+    Ok(database
+        .tenant(tenant)
+        .map(|accounts| aggregate(accounts, grouping, metadata))
+        .unwrap_or_default())
+}
+
+fn read_movers<I: io::Read, W: io::Write>(
+    reader: &mut Reader<I>,
+    writer: &mut Writer<W>,
+    snapshots: &[SummarySnapshot],
+    count: usize,
+    threshold: f64,
+    tenant: &str,
+    pseudonymizer: Option<&Pseudonymizer>,
+) -> Result<(), Box<dyn Error>> {
+    let database = load_transactions(reader)?;
+
+    if let Some(accounts) = database.tenant(tenant) {
+        for mut mover in top_movers(accounts, snapshots, count, threshold) {
+            if let Some(pseudonymizer) = pseudonymizer {
+                pseudonymizer.label(tenant, &mut mover.client_id);
+            }
+
+            writer.serialize(mover)?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+Writes one page of matching accounts as v1 summary rows, and returns the cursor for the next.
+*/
+fn read_search<I: io::Read, W: io::Write>(
+    reader: &mut Reader<I>,
+    writer: &mut Writer<W>,
+    filter: &AccountFilter,
+    after: Option<ClientId>,
+    limit: usize,
+    tenant: &str,
+) -> Result<Option<ClientId>, Box<dyn Error>> {
+    let database = load_transactions(reader)?;
+    let mut next = None;
+
+    if let Some(accounts) = database.tenant(tenant) {
+        let page = search(accounts, filter, after, limit);
+
+        for account in page.accounts {
+            let summary: AccountSummaryV1 = account.into();
+
+            writer.serialize(summary)?;
+        }
+        next = page.next;
+    }
+    writer.flush()?;
+
+    Ok(next)
+}
+
+/*
+The projection starts from the latest date in the ledger, or today if nothing in it is dated.
+*/
+fn read_simulation<I: io::Read, S: io::Read, W: io::Write>(
+    reader: &mut Reader<I>,
+    schedule_reader: &mut Reader<S>,
+    writer: &mut Writer<W>,
+    tenant: &str,
+    options: &SimulationOptions,
+    date_basis: DateBasis,
+    clock: &dyn Clock,
+) -> Result<(), Box<dyn Error>> {
+    let database = load_transactions_dated(reader, date_basis)?;
+    let schedule = load_schedule(schedule_reader)?;
+    let events = database
+        .tenant(tenant)
+        .map_or(&[][..], |accounts| accounts.events());
+    let from = events
+        .iter()
+        .filter_map(|event| match *event {
+            LedgerEvent::TransactionApplied { date, .. } => date,
+            _ => None,
+        })
+        .max()
+        .unwrap_or_else(|| clock.today());
+
+    for projected in simulate(events, &schedule, from, options) {
+        writer.serialize(projected)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn load_transactions<I: io::Read>(
+    reader: &mut Reader<I>,
+) -> Result<TenantDatabase, Box<dyn Error>> {
+    let mut database = TenantDatabase::new();
+
+    apply_transactions(&mut database, reader)?;
+
+    Ok(database)
+}
+
+/*
+Loads transactions with their ledger dated by `date_basis` -- see dates::DateBasis.
+*/
+fn load_transactions_dated<I: io::Read>(
+    reader: &mut Reader<I>,
+    date_basis: DateBasis,
+) -> Result<TenantDatabase, Box<dyn Error>> {
+    let mut database = TenantDatabase::with_options(AccountOptions {
+        date_basis,
+        ..Default::default()
+    });
+
+    apply_transactions(&mut database, reader)?;
+
+    Ok(database)
+}
+
+fn apply_transactions<I: io::Read>(
+    database: &mut TenantDatabase,
+    reader: &mut Reader<I>,
+) -> Result<usize, Box<dyn Error>> {
+    apply_transactions_through(database, reader, &Pipeline::new())
+}
+
+/*
+Counts every input row, including those the pipeline drops.
+
+Under ConflictPolicy::DisputeFirst, each withdrawal is held back until the next row has been
+read, in case that row is a dispute that should go first.
+*/
+fn apply_transactions_through<I: io::Read>(
+    database: &mut TenantDatabase,
+    reader: &mut Reader<I>,
+    pipeline: &Pipeline,
+) -> Result<usize, Box<dyn Error>> {
+    let conflicts = database.options().conflicts;
+    let tx_ids = database.options().tx_ids;
+    let date_basis = database.options().date_basis;
+    let mut count = 0;
+    let mut withdrawal: Option<(String, TransactionRecord, Option<Date>, Option<String>)> = None;
+    /*
+    Read into one record, rather than deserialized straight off the reader, to know which
+    line each row came from.
+    */
+    let headers = reader.headers()?.clone();
+    let mut record = StringRecord::new();
+
+    while reader.read_record(&mut record)? {
+        count += 1;
+
+        let line = record.position().map_or(0, |position| position.line());
+        let transaction_text: TransactionText = record.deserialize(Some(&headers))?;
+        let Some(transaction_text) = pipeline.process(transaction_text) else {
+            continue;
+        };
+
+        transaction_text.validate(line)?;
+
+        let tenant = transaction_text.tenant().to_owned();
+        let date = transaction_text.effective_date(date_basis)?;
+
+        if date.is_some_and(|date| database.is_sealed(date)) {
+            database.refuse_late(transaction_text);
+            continue;
+        }
+
+        let memo = transaction_text.memo().map(str::to_owned);
+        let transaction: TransactionRecord = transaction_text.into();
+
+        if !tx_ids.allows(transaction.id().transaction_id) {
+            return Err(RowError {
+                line,
+                column: "tx",
+                code: ErrorCode::NonNumericTx,
+                message: format!(
+                    "tx {} is not numeric; run with --tx-ids uuid to accept UUIDs",
+                    transaction.id().transaction_id
+                ),
+            }
+            .into());
+        }
+
+        if let Some((held_tenant, held, held_date, held_memo)) = withdrawal.take() {
+            if conflicts.reorders(
+                (&held_tenant, &held, held_date),
+                (&tenant, &transaction, date),
+            ) {
+                database.apply_with_memo(&tenant, &transaction, date, memo.as_deref());
+                database.apply_with_memo(&held_tenant, &held, held_date, held_memo.as_deref());
+                continue;
+            }
+
+            database.apply_with_memo(&held_tenant, &held, held_date, held_memo.as_deref());
+        }
+
+        if conflicts == ConflictPolicy::DisputeFirst
+            && matches!(transaction, TransactionRecord::Withdrawl { .. })
+        {
+            withdrawal = Some((tenant, transaction, date, memo));
+            continue;
+        }
+
+        database.apply_with_memo(&tenant, &transaction, date, memo.as_deref());
+    }
+
+    if let Some((tenant, transaction, date, memo)) = withdrawal {
+        database.apply_with_memo(&tenant, &transaction, date, memo.as_deref());
     }
 
-    let path = Path::new(&args[1]);
+    Ok(count)
+}
+
+#[cfg(feature = "sql")]
+fn run_query(input: &Path, sql: &str) -> Result<(), Box<dyn Error>> {
+    let database = load_transactions(&mut open_csv(input)?)?;
+    let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+    query::query(&database, sql, &mut writer)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sql"))]
+fn run_query(input: &Path, sql: &str) -> Result<(), Box<dyn Error>> {
+    Err("query requires building with the sql feature".into())
+}
+
+#[cfg(feature = "sqlx")]
+fn export_to_db(
+    url: &str,
+    tables: &export_db::ExportTables,
+    database: &TenantDatabase,
+    first_events: &BTreeMap<String, usize>,
+    changed_only: bool,
+) {
+    let export = export_db::export_db(url, tables, database, first_events, changed_only)
+        .expect("Failed to export to the database");
+
+    eprintln!(
+        "exported {} summaries and {} ledger rows",
+        export.summaries, export.ledger_rows
+    );
+}
+
+#[cfg(feature = "scripting")]
+fn load_rules(path: &Path) -> Box<dyn rules::AcceptanceRule> {
+    Box::new(rules::ScriptRules::load(path).expect("Failed to load rules script"))
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_rules(path: &Path) -> Box<dyn rules::AcceptanceRule> {
+    unreachable!("cli refuses --rules without the scripting feature")
+}
+
+/*
+Filters a pipeline by the clients' tags.  Production keeps sandboxed clients out exactly like
+excluded ones; the sandbox keeps only sandboxed clients, less any that are also excluded.
+*/
+fn push_tag_filters(
+    pipeline: &mut Pipeline,
+    metadata: &ClientMetadata,
+    exclude_tags: &[String],
+    sandbox_tags: &[String],
+    sandbox: bool,
+) {
+    let excludes: Vec<String> = match sandbox {
+        true => {
+            pipeline.push(TagFilter {
+                metadata: metadata.clone(),
+                tags: sandbox_tags.to_vec(),
+                keep_tagged: true,
+            });
+            exclude_tags.to_vec()
+        }
+        false => exclude_tags.iter().chain(sandbox_tags).cloned().collect(),
+    };
+
+    if !excludes.is_empty() {
+        pipeline.push(TagFilter {
+            metadata: metadata.clone(),
+            tags: excludes,
+            keep_tagged: false,
+        });
+    }
+}
+
+/*
+Drops rows dated after `through`.  A row whose date doesn't parse is kept, for validation to
+report like any other malformed row.
+*/
+fn push_through_filter(pipeline: &mut Pipeline, through: Date, date_basis: DateBasis) {
+    pipeline.push(move |transaction: TransactionText| {
+        (!matches!(
+            transaction.effective_date(date_basis),
+            Ok(Some(date)) if date > through
+        ))
+        .then_some(transaction)
+    });
+}
+
+fn load_pipeline(mapping: Option<&Path>) -> io::Result<Pipeline> {
+    Ok(match mapping {
+        Some(path) => Pipeline::load(&mut open_csv(path)?).expect("Failed to read mapping file"),
+        None => Pipeline::new(),
+    })
+}
+
+/*
+Unwraps the result of reading an input, or reports its error the way --errors-format asks and
+stops: as text, by panicking with `context` just as `expect` would, or as one ErrorReport.
+*/
+fn or_report<T, I: io::Read>(
+    result: Result<T, Box<dyn Error>>,
+    format: ErrorFormat,
+    reader: &mut Reader<I>,
+    context: &str,
+) -> T {
+    match (result, format) {
+        (Ok(value), _) => value,
+        (Err(error), ErrorFormat::Text) => panic!("{}: {:?}", context, error),
+        (Err(error), ErrorFormat::Json) => {
+            let headers = reader.headers().ok().cloned();
+            let report = ErrorReport::new(&*error, headers.as_ref());
+
+            eprintln!(
+                "{}",
+                serde_json::to_string(&report).expect("Failed to write error")
+            );
+            exit(1);
+        }
+    }
+}
+
+fn open_csv(path: &Path) -> std::io::Result<Reader<File>> {
     let file = File::open(path)?;
 
-    let mut reader = ReaderBuilder::default()
+    Ok(csv_reader(file))
+}
+
+/*
+With `mmap`, reads a regular file through a memory mapping instead, which saves copying every
+byte through a read buffer on the way to the CSV parser.  Anything that can't be mapped -- a
+pipe, a device, an empty file -- is quietly read the normal way.
+*/
+fn open_input(path: &Path, mmap: bool) -> std::io::Result<Reader<Box<dyn io::Read>>> {
+    let file = File::open(path)?;
+
+    if mmap && file.metadata()?.is_file() {
+        /*
+        Safety: the mapping is only valid as long as nobody truncates or rewrites the file
+        underneath us.  Inputs are batches that are written once and then handed to us, so we
+        accept that risk for the speed -- it's opt-in for that reason.
+        */
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(csv_reader(Box::new(io::Cursor::new(map))));
+        }
+    }
+
+    Ok(csv_reader(Box::new(file)))
+}
+
+/*
+Writes the accounts an operation would change, as `diff` would show them, and returns how many
+there are.
+*/
+fn write_preview<W: io::Write>(
+    before: &TenantDatabase,
+    after: &TenantDatabase,
+    writer: &mut Writer<W>,
+) -> csv::Result<usize> {
+    let diff = AccountSummaryDiff::between(
+        &Snapshot::from_database(before),
+        &Snapshot::from_database(after),
+    );
+
+    for client in &diff.clients {
+        writer.serialize(DiffRow::from(client))?;
+    }
+    writer.flush()?;
+
+    Ok(diff.clients.len())
+}
+
+fn csv_reader<R: io::Read>(reader: R) -> Reader<R> {
+    ReaderBuilder::default()
         .trim(csv::Trim::All)
         .flexible(true)
         .has_headers(true)
-        .from_reader(file);
-    let mut writer = Writer::from_writer(io::stdout());
+        .from_reader(reader)
+}
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{}", message);
+            exit(2);
+        }
+    };
+    match command {
+        Command::Summarize {
+            input,
+            prior,
+            changed_only,
+            stats,
+            salt,
+            account_options,
+            writer_thread,
+            mmap,
+            latencies,
+            errors_format,
+            client_mismatches,
+            conflicting_duplicates,
+            shortfalls,
+            metadata,
+            exclude_tags,
+            sandbox_tags,
+            sandbox_summary,
+            group_cap,
+            group_summary,
+            canary_behavior,
+            canary_report,
+            mapping,
+            rules,
+            schema,
+            provenance,
+            now,
+            tx_index,
+            tx_map,
+            seal,
+            late_transactions,
+            export_db,
+            export_tables,
+            publish_redis,
+            redis_prefix,
+            cdc,
+            mirror,
+            sinks,
+            sink_salt,
+        } => {
+            let pseudonymizer = salt.as_deref().map(Pseudonymizer::new);
+            let mut pipeline = load_pipeline(mapping.as_deref())?;
+            /*
+            A map file that doesn't exist yet is where the first run's mapping gets written.
+            */
+            let references = match &tx_map {
+                Some(path) if path.exists() => {
+                    TxMap::load(&mut open_csv(path)?).expect("Failed to read tx map")
+                }
+                _ => TxMap::new(),
+            };
+
+            if tx_map.is_some() {
+                pipeline.push(references.clone());
+            }
+
+            let client_metadata = match &metadata {
+                Some(path) => ClientMetadata::load(&mut open_csv(path)?)
+                    .expect("Failed to read client metadata"),
+                None => ClientMetadata::new(),
+            };
+
+            push_tag_filters(
+                &mut pipeline,
+                &client_metadata,
+                &exclude_tags,
+                &sandbox_tags,
+                false,
+            );
+
+            let sealed_through = match &seal {
+                Some(path) => Some(sealed_through(File::open(path)?).expect("Failed to read seal")),
+                None => None,
+            };
+            let groups = AccountGroups::from_metadata(&client_metadata);
+            let group_cap = group_cap.map(|cap| GroupCap {
+                groups: groups.clone(),
+                cap,
+            });
+            let mut database = TenantDatabase::with_options(account_options);
+
+            if let Some(path) = &rules {
+                database.set_rule(load_rules(path));
+            }
+
+            if let Some(group_cap) = &group_cap {
+                database.set_group_cap(group_cap.clone());
+            }
+
+            if let Some(path) = &tx_index {
+                load_tx_index(&mut database, &mut open_csv(path)?)
+                    .expect("Failed to read tx index");
+            }
+
+            if let Some(path) = &prior {
+                let mut reader = open_csv(path)?;
+                let result = apply_transactions_through(&mut database, &mut reader, &pipeline);
+
+                or_report(
+                    result,
+                    errors_format,
+                    &mut reader,
+                    "Failed to read prior transactions",
+                );
+            }
+
+            /*
+            Prior transactions are history, sealed periods included, so the seal only applies
+            from here on.
+            */
+            if let Some(through) = sealed_through {
+                database.set_sealed_through(through);
+            }
+
+            if latencies {
+                database.record_latencies();
+            }
+
+            let mut reader = open_input(&input, mmap)?;
+            /*
+            With `--cdc -` the change feed goes to stdout in place of the summary.
+            */
+            let cdc_replaces_summary = cdc.as_deref() == Some(Path::new("-"));
+            let summary_output: Box<dyn io::Write + Send> = if cdc_replaces_summary {
+                Box::new(io::sink())
+            } else {
+                Box::new(io::stdout())
+            };
+            let writer = Writer::from_writer(ChunkedWriter::new(summary_output, writer_thread));
+
+            let options = SummaryOptions {
+                changed_only,
+                pseudonymizer: pseudonymizer.as_ref(),
+                pipeline: Some(&pipeline),
+                schema,
+            };
+
+            let first_events: BTreeMap<String, usize> = database
+                .tenants()
+                .map(|(tenant, accounts)| (tenant.to_owned(), accounts.events().len()))
+                .collect();
+            /*
+            Given any --sink, the summary goes to those sinks alone, stdout included only if one of
+            them names it.
+            */
+            let mut sink: Box<dyn SummarySink> = if sinks.is_empty() {
+                Box::new(writer)
+            } else {
+                let mut fan_out = FanOut::new();
+
+                for spec in &sinks {
+                    fan_out.push(
+                        spec.open(sink_salt.as_deref())
+                            .expect("Failed to open sink"),
+                    );
+                }
+
+                Box::new(fan_out)
+            };
+            let result = summarize(&mut database, &mut reader, &mut *sink, &options);
+            let run_stats = or_report(result, errors_format, &mut reader, "Failed to conduct I/O");
+
+            if let Some(path) = &cdc {
+                let output: Box<dyn io::Write + Send> = if cdc_replaces_summary {
+                    Box::new(io::stdout())
+                } else {
+                    Box::new(File::create(path)?)
+                };
+                let mut output = ChunkedWriter::new(output, false);
+
+                for (tenant, accounts) in database.tenants() {
+                    let first = first_events.get(tenant).copied().unwrap_or_default();
+
+                    write_changes(tenant, accounts.events(), first, &mut output)?;
+                }
+                output.flush()?;
+            }
+
+            if let Some(path) = &mirror {
+                write_mirror(&database, &first_events, &mut Writer::from_path(path)?)
+                    .expect("Failed to write mirror");
+            }
+
+            #[cfg(feature = "sqlx")]
+            if let Some(url) = &export_db {
+                export_to_db(url, &export_tables, &database, &first_events, changed_only);
+            }
+
+            #[cfg(feature = "redis")]
+            if let Some(url) = &publish_redis {
+                let (written, announced) = publish::publish_balances(url, &redis_prefix, &database)
+                    .expect("Failed to publish balances");
+
+                eprintln!(
+                    "published {} balances, announced {} changes",
+                    written, announced
+                );
+            }
+
+            if account_options.dispute_amounts == DisputeAmountPolicy::Warn
+                && run_stats.unexpected_dispute_amounts > 0
+            {
+                let message = format!(
+                    "ignored the amount on {} dispute, resolve or chargeback rows",
+                    run_stats.unexpected_dispute_amounts
+                );
+
+                match errors_format {
+                    ErrorFormat::Text => eprintln!("Warning: {}", message),
+                    ErrorFormat::Json => eprintln!(
+                        "{}",
+                        serde_json::to_string(&ErrorReport {
+                            line: None,
+                            column: None,
+                            code: ErrorCode::UnexpectedDisputeAmount,
+                            message,
+                        })?
+                    ),
+                }
+            }
+
+            /*
+            Free text has no closing report beyond the summary itself; tooling gets the stats.
+            */
+            if errors_format == ErrorFormat::Json {
+                eprintln!(
+                    "{}",
+                    serde_json::to_string(&RunReport { report: &run_stats })?
+                );
+            }
+
+            if let Some(path) = stats {
+                serde_json::to_writer_pretty(File::create(path)?, &run_stats)?;
+            }
+
+            if let Some(path) = provenance {
+                let inputs: Vec<&Path> = [
+                    Some(&input),
+                    prior.as_ref(),
+                    tx_index.as_ref(),
+                    mapping.as_ref(),
+                    rules.as_ref(),
+                    metadata.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .map(PathBuf::as_path)
+                .collect();
+                let clock: &dyn Clock = match &now {
+                    Some(clock) => clock,
+                    None => &SystemClock,
+                };
+                let mut sidecar = Provenance::new(&args, &inputs, clock)?;
+
+                sidecar.transactions = run_stats.transactions;
+                sidecar.ledger_hash = run_stats.ledger_hash.clone();
+                sidecar.summary_rows = if changed_only {
+                    run_stats.changed
+                } else {
+                    run_stats.accounts
+                };
+                serde_json::to_writer_pretty(File::create(path)?, &sidecar)?;
+            }
+
+            if let Some(path) = client_mismatches {
+                write_client_mismatches(&database, &mut Writer::from_path(path)?)?;
+            }
+
+            if let Some(path) = conflicting_duplicates {
+                write_conflicting_duplicates(&database, &mut Writer::from_path(path)?)?;
+            }
+
+            if let Some(path) = shortfalls {
+                write_shortfalls(&database, &mut Writer::from_path(path)?)?;
+            }
 
-    read_transactions(&mut reader, &mut writer).expect("Failed to conduct I/O");
+            if let Some(path) = late_transactions {
+                write_late_transactions(&database, &mut Writer::from_path(path)?)?;
+            }
+
+            if let Some(path) = group_summary {
+                let mut writer = Writer::from_path(path)?;
+
+                for summary in group_summaries(&database, &groups) {
+                    writer.serialize(summary)?;
+                }
+                writer.flush()?;
+            }
+
+            /*
+            The canary is a second database with the same options bar the behavior version, fed
+            the same rows by a second pass over the input.
+            */
+            if let (Some(behavior), Some(path)) = (canary_behavior, canary_report) {
+                let mut canary = TenantDatabase::with_options(AccountOptions {
+                    behavior,
+                    ..account_options
+                });
+
+                if let Some(path) = &rules {
+                    canary.set_rule(load_rules(path));
+                }
+
+                if let Some(group_cap) = &group_cap {
+                    canary.set_group_cap(group_cap.clone());
+                }
+
+                if let Some(path) = &tx_index {
+                    load_tx_index(&mut canary, &mut open_csv(path)?)
+                        .expect("Failed to read tx index");
+                }
+
+                if let Some(path) = &prior {
+                    apply_transactions_through(&mut canary, &mut open_csv(path)?, &pipeline)
+                        .expect("Failed to read prior transactions");
+                }
+
+                if let Some(through) = sealed_through {
+                    canary.set_sealed_through(through);
+                }
+
+                apply_transactions_through(&mut canary, &mut open_input(&input, mmap)?, &pipeline)
+                    .expect("Failed to conduct I/O");
+
+                let divergences = canary::compare(&database, &canary);
+                let mut writer = Writer::from_path(path)?;
+
+                for divergence in &divergences {
+                    writer.serialize(divergence)?;
+                }
+                writer.flush()?;
+
+                eprintln!("{} canary divergences", divergences.len());
+            }
+
+            /*
+            The sandbox gets a ledger of its own, built from a second pass over the same input
+            that keeps only the sandboxed clients.  The tx index describes production
+            transactions, so it isn't loaded here.
+            */
+            if let Some(path) = sandbox_summary {
+                let mut sandbox_pipeline = load_pipeline(mapping.as_deref())?;
+                let mut sandbox = TenantDatabase::with_options(account_options);
+
+                if tx_map.is_some() {
+                    sandbox_pipeline.push(references.clone());
+                }
+
+                push_tag_filters(
+                    &mut sandbox_pipeline,
+                    &client_metadata,
+                    &exclude_tags,
+                    &sandbox_tags,
+                    true,
+                );
+
+                if let Some(path) = &rules {
+                    sandbox.set_rule(load_rules(path));
+                }
+
+                if let Some(group_cap) = &group_cap {
+                    sandbox.set_group_cap(group_cap.clone());
+                }
+
+                if let Some(path) = &prior {
+                    apply_transactions_through(
+                        &mut sandbox,
+                        &mut open_csv(path)?,
+                        &sandbox_pipeline,
+                    )
+                    .expect("Failed to read prior transactions");
+                }
+
+                if let Some(through) = sealed_through {
+                    sandbox.set_sealed_through(through);
+                }
+
+                let sandbox_stats = summarize(
+                    &mut sandbox,
+                    &mut open_input(&input, mmap)?,
+                    &mut Writer::from_path(path)?,
+                    &SummaryOptions {
+                        pipeline: Some(&sandbox_pipeline),
+                        ..options
+                    },
+                )
+                .expect("Failed to conduct I/O");
+
+                eprintln!("sandbox: {} accounts", sandbox_stats.accounts);
+            }
+
+            if let Some(path) = &tx_map {
+                references
+                    .write(&mut Writer::from_path(path)?)
+                    .expect("Failed to write tx map");
+            }
+        }
+        Command::BalanceHistory {
+            input,
+            client_id,
+            tenant,
+            salt,
+            date_basis,
+        } => {
+            let pseudonymizer = salt.as_deref().map(Pseudonymizer::new);
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            read_balance_history(
+                &mut reader,
+                &mut writer,
+                &tenant,
+                client_id,
+                pseudonymizer.as_ref(),
+                date_basis,
+            )
+            .expect("Failed to conduct I/O");
+        }
+        Command::StatusHistory {
+            input,
+            client_id,
+            tenant,
+            salt,
+        } => {
+            let pseudonymizer = salt.as_deref().map(Pseudonymizer::new);
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            read_status_history(
+                &mut reader,
+                &mut writer,
+                &tenant,
+                client_id,
+                pseudonymizer.as_ref(),
+            )
+            .expect("Failed to conduct I/O");
+        }
+        Command::Aggregate {
+            input,
+            grouping,
+            metadata,
+            format,
+            tenant,
+        } => {
+            let metadata = match metadata {
+                Some(path) => ClientMetadata::load(&mut open_csv(&path)?)
+                    .expect("Failed to read client metadata"),
+                None => ClientMetadata::new(),
+            };
+            let mut reader = open_csv(&input)?;
+            let cohorts = read_aggregate(&mut reader, &grouping, &metadata, &tenant)
+                .expect("Failed to conduct I/O");
+
+            match format {
+                OutputFormat::Csv => {
+                    let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+                    for cohort in cohorts {
+                        writer.serialize(cohort)?;
+                    }
+                    writer.flush()?;
+                }
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(
+                        ChunkedWriter::new(io::stdout(), false),
+                        &cohorts,
+                    )?;
+                    println!();
+                }
+            }
+        }
+        Command::Movers {
+            input,
+            snapshots,
+            count,
+            threshold,
+            tenant,
+            salt,
+        } => {
+            let pseudonymizer = salt.as_deref().map(Pseudonymizer::new);
+            let snapshots = snapshots
+                .iter()
+                .map(|path| {
+                    Ok(SummarySnapshot::load(&mut open_csv(path)?)
+                        .expect("Failed to read summary snapshot"))
+                })
+                .collect::<std::io::Result<Vec<_>>>()?;
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            read_movers(
+                &mut reader,
+                &mut writer,
+                &snapshots,
+                count,
+                threshold,
+                &tenant,
+                pseudonymizer.as_ref(),
+            )
+            .expect("Failed to conduct I/O");
+        }
+        Command::Diff { before, after } => {
+            let load = |path: &Path| -> std::io::Result<Snapshot> {
+                Ok(Snapshot::load(&mut open_csv(path)?).expect("Failed to read summary"))
+            };
+            let diff = AccountSummaryDiff::between(&load(&before)?, &load(&after)?);
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            for client in &diff.clients {
+                writer.serialize(DiffRow::from(client))?;
+            }
+            writer.flush()?;
+
+            eprintln!("{} accounts differ", diff.clients.len());
+        }
+        Command::Search {
+            input,
+            filter,
+            after,
+            limit,
+            tenant,
+        } => {
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            let next = read_search(&mut reader, &mut writer, &filter, after, limit, &tenant)
+                .expect("Failed to conduct I/O");
+
+            if let Some(client_id) = next {
+                eprintln!("next page: --after {}", client_id);
+            }
+        }
+        Command::Prove {
+            input,
+            client_id,
+            tenant,
+        } => {
+            let database =
+                load_transactions(&mut open_csv(&input)?).expect("Failed to read transactions");
+            let Some(proof) = BalanceTree::build(&database).prove(&tenant, client_id) else {
+                eprintln!("no account for client {}", client_id);
+                exit(1);
+            };
+
+            serde_json::to_writer_pretty(io::stdout(), &proof)?;
+            println!();
+        }
+        Command::EraseClient {
+            input,
+            client_id,
+            tenant,
+            action,
+            preview,
+        } => {
+            let mut reader = open_csv(&input)?;
+
+            if preview {
+                let mut rewritten = Writer::from_writer(vec![]);
+
+                erase_client(&mut reader, &mut rewritten, client_id, &tenant, &action)
+                    .expect("Failed to conduct I/O");
+
+                let rewritten = rewritten.into_inner().expect("Failed to conduct I/O");
+                let before =
+                    load_transactions(&mut open_csv(&input)?).expect("Failed to read transactions");
+                let after = load_transactions(&mut csv_reader(rewritten.as_slice()))
+                    .expect("Failed to read transactions");
+
+                let changed = write_preview(
+                    &before,
+                    &after,
+                    &mut Writer::from_writer(ChunkedWriter::new(io::stdout(), false)),
+                )?;
+
+                eprintln!("{} accounts would change", changed);
+                return Ok(());
+            }
+
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            erase_client(&mut reader, &mut writer, client_id, &tenant, &action)
+                .expect("Failed to conduct I/O");
+        }
+        Command::Query { input, sql } => {
+            if let Err(error) = run_query(&input, &sql) {
+                eprintln!("{}", error);
+                exit(1);
+            }
+        }
+        Command::Admin {
+            input,
+            script,
+            report,
+            all_or_nothing,
+        } => {
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            let results = run_script(
+                &mut open_csv(&input)?,
+                &mut open_csv(&script)?,
+                &mut writer,
+                all_or_nothing,
+            )
+            .expect("Failed to run admin script");
+
+            let mut report = Writer::from_path(report)?;
+
+            for result in &results {
+                report.serialize(result)?;
+            }
+            report.flush()?;
+
+            let failed = results
+                .iter()
+                .filter(|result| result.status == "failed")
+                .count();
+
+            eprintln!(
+                "{} of {} operations failed{}",
+                failed,
+                results.len(),
+                if all_or_nothing && failed > 0 {
+                    ", none applied"
+                } else {
+                    ""
+                }
+            );
+
+            if failed > 0 {
+                exit(1);
+            }
+        }
+        Command::Audit { input, low_balance } => {
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+            let options = AccountOptions {
+                low_balance,
+                ..Default::default()
+            };
+
+            audit_log(&mut reader, &mut writer, options).expect("Failed to conduct I/O");
+        }
+        Command::Backfill {
+            input,
+            corrections,
+            delta,
+            preview,
+        } => {
+            let backfill = backfill(&mut open_csv(&input)?, &mut open_csv(&corrections)?)
+                .expect("Failed to backfill corrections");
+
+            if preview {
+                let changed = write_preview(
+                    &backfill.original,
+                    &backfill.corrected,
+                    &mut Writer::from_writer(ChunkedWriter::new(io::stdout(), false)),
+                )?;
+
+                eprintln!("{} accounts would change", changed);
+                return Ok(());
+            }
+
+            let multi_tenant = backfill
+                .corrected
+                .tenants()
+                .any(|(tenant, _)| !tenant.is_empty());
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            for (tenant, accounts) in backfill.corrected.tenants() {
+                for account in accounts.accounts() {
+                    if multi_tenant {
+                        writer.serialize(TenantAccountSummaryV1::from((tenant, account)))?;
+                    } else {
+                        writer.serialize(AccountSummaryV1::from(account))?;
+                    }
+                }
+            }
+            writer.flush()?;
+
+            let mut report =
+                Writer::from_path(delta.expect("--delta is required without --preview"))?;
+
+            for account_delta in &backfill.deltas {
+                report.serialize(account_delta)?;
+            }
+            report.flush()?;
+
+            eprintln!("{} accounts affected", backfill.deltas.len());
+        }
+        Command::Compact {
+            input,
+            horizon,
+            options,
+        } => {
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            let compaction =
+                compact(&mut reader, &mut writer, horizon, options).expect("Failed to conduct I/O");
+
+            eprintln!(
+                "folded {} rows into {} opening balances, kept {} rows",
+                compaction.folded_rows, compaction.opening_rows, compaction.kept_rows
+            );
+        }
+        Command::Finalize {
+            input,
+            through,
+            date_basis,
+        } => {
+            let mut pipeline = Pipeline::new();
+
+            push_through_filter(&mut pipeline, through, date_basis);
+
+            let mut database = TenantDatabase::with_options(AccountOptions {
+                date_basis,
+                ..Default::default()
+            });
+
+            apply_transactions_through(&mut database, &mut open_csv(&input)?, &pipeline)
+                .expect("Failed to read transactions");
+            serde_json::to_writer_pretty(io::stdout(), &PeriodSeal::finalize(&database, through))?;
+            println!();
+        }
+        Command::Simulate {
+            input,
+            schedule,
+            until,
+            period_days,
+            rounding,
+            tenant,
+            date_basis,
+        } => {
+            let mut reader = open_csv(&input)?;
+            let mut schedule_reader = open_csv(&schedule)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            read_simulation(
+                &mut reader,
+                &mut schedule_reader,
+                &mut writer,
+                &tenant,
+                &SimulationOptions {
+                    until,
+                    period_days,
+                    rounding: &rounding,
+                },
+                date_basis,
+                &SystemClock,
+            )
+            .expect("Failed to conduct I/O");
+        }
+        Command::Reidentify {
+            tokens,
+            salt,
+            tenant,
+        } => {
+            let pseudonymizer = Pseudonymizer::new(&salt);
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            writer.write_record(["token", "client_id"])?;
+            for token in tokens {
+                let client_id = pseudonymizer
+                    .reidentify(&tenant, &token)
+                    .map(|client_id| client_id.to_string())
+                    .unwrap_or_default();
+
+                writer.write_record([token, client_id])?;
+            }
+            writer.flush()?;
+        }
+        Command::ExportTxIndex { input, tx_index } => {
+            let mut database = TenantDatabase::new();
+
+            if let Some(path) = &tx_index {
+                load_tx_index(&mut database, &mut open_csv(path)?)
+                    .expect("Failed to read tx index");
+            }
+
+            apply_transactions(&mut database, &mut open_csv(&input)?)
+                .expect("Failed to read transactions");
+
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            export_tx_index(&database, &mut writer).expect("Failed to conduct I/O");
+        }
+        Command::Split {
+            input,
+            shards,
+            output,
+        } => {
+            let mut reader = open_csv(&input)?;
+            let mut writers = (0..shards)
+                .map(|shard| Writer::from_path(output.join(format!("shard-{}.csv", shard))))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let counts = split(&mut reader, &mut writers).expect("Failed to split input");
+
+            for (shard, count) in counts.iter().enumerate() {
+                eprintln!("shard {}: {} rows", shard, count);
+            }
+        }
+        Command::Filter {
+            input,
+            filter: transaction_filter,
+        } => {
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            let filtered = filter(&mut reader, &mut writer, &transaction_filter)
+                .expect("Failed to filter transactions");
+
+            eprintln!(
+                "kept {} rows, and {} earlier rows they reference",
+                filtered.matched_rows, filtered.referenced_rows
+            );
+        }
+        Command::Minimize {
+            input,
+            predicate,
+            tenant,
+        } => {
+            let mut reader = open_csv(&input)?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            let minimized = minimize(&mut reader, &mut writer, predicate, &tenant)
+                .expect("Failed to minimize transactions");
+
+            eprintln!(
+                "kept {} of {} rows after {} runs",
+                minimized.kept_rows, minimized.original_rows, minimized.runs
+            );
+        }
+        Command::Merge {
+            summaries,
+            conflicts,
+        } => {
+            let mut readers = summaries
+                .iter()
+                .map(|path| open_csv(path))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            let merged = merge(&mut readers, &mut writer).expect("Failed to merge summaries");
+
+            if let Some(path) = conflicts {
+                let mut report = Writer::from_path(path)?;
+
+                for conflict in &merged.conflicts {
+                    report.serialize(conflict)?;
+                }
+                report.flush()?;
+            }
+
+            eprintln!(
+                "merged {} accounts, {} clients in more than one shard",
+                merged.accounts,
+                merged.conflicts.len()
+            );
+        }
+        Command::Generate {
+            profile,
+            transactions,
+            seed,
+        } => {
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            generate(profile, transactions, seed, &mut writer)
+                .expect("Failed to generate transactions");
+        }
+        Command::Regress {
+            baseline,
+            candidate,
+            inputs,
+        } => {
+            let divergences =
+                regress(&baseline, &candidate, &inputs).expect("Failed to run regression");
+            let mut writer = Writer::from_writer(ChunkedWriter::new(io::stdout(), false));
+
+            for divergence in &divergences {
+                writer.serialize(divergence)?;
+            }
+            writer.flush()?;
+
+            eprintln!("{} divergences", divergences.len());
+        }
+        Command::Completions { shell } => write_completions(shell, &mut io::stdout())?,
+        Command::Man => write_man(&mut io::stdout())?,
+        Command::Explain { code: None } => {
+            for code in ErrorCode::ALL {
+                println!("{} {}: {}", code, code.name(), code.summary());
+            }
+        }
+        Command::Explain { code: Some(code) } => {
+            println!("{} {}\n", code, code.name());
+            print!("{}", code.explanation());
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,36 @@
+/*
+How long a memo may be, in characters, unless configured otherwise.
+*/
+pub const DEFAULT_MEMO_LENGTH: usize = 256;
+
+/*
+Makes a memo safe to carry into every output that shows it.  Control characters -- newlines
+included -- become spaces, so a memo can't break a CSV row or a log line, and surrounding
+whitespace is dropped.  What's left is cut to `max_length` characters.
+
+Spreadsheets treat a cell starting with `=`, `+`, `-` or `@` as a formula, and support teams
+open these files in spreadsheets, so such a memo gets a leading apostrophe to keep it text.
+
+Returns None for a memo that's empty once cleaned up.
+*/
+pub fn sanitize(memo: &str, max_length: usize) -> Option<String> {
+    let cleaned: String = memo
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let cleaned = cleaned.trim();
+
+    if cleaned.is_empty() || max_length == 0 {
+        return None;
+    }
+
+    let mut sanitized = String::with_capacity(cleaned.len() + 1);
+
+    if cleaned.starts_with(['=', '+', '-', '@']) {
+        sanitized.push('\'');
+    }
+
+    sanitized.extend(cleaned.chars().take(max_length - sanitized.len()));
+
+    Some(sanitized)
+}
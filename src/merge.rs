@@ -0,0 +1,116 @@
+use std::{collections::BTreeMap, error::Error, io};
+
+use csv::{Reader, StringRecord, Writer};
+use serde::Serialize;
+
+/*
+A client whose account turned up in the summaries of more than one shard.  Each shard only saw
+part of the client's history, so none of their figures is the client's balance.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct MergeConflict {
+    pub tenant: String,
+    pub client_id: String,
+    /*
+    The shards the client appeared in, by position on the command line, separated by spaces.
+    */
+    pub shards: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Merge {
+    pub accounts: usize,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/*
+Tenant, numeric client id if it is one, and the client id as written.
+*/
+type AccountKey = (String, Option<u64>, String);
+
+/*
+Merges the summaries of the shards of a `split` input into the summary of the whole, ordered by
+tenant and client as a single run would order it.  Every summary must have been written with
+the same options, so the columns match.
+
+A client found in more than one shard is a conflict: the input was split by something other
+than `split`, or shards were summarized with the wrong inputs.  Conflicting clients are left out
+of the merged summary and returned instead, since any figure we wrote for them would be wrong.
+
+This is the coordinator's half of distributed mode, and only that: there is no protocol for
+workers to stream their summaries to it over the network.  They arrive as files, moved by
+whatever runs the workers.
+*/
+pub fn merge<R: io::Read, W: io::Write>(
+    readers: &mut [Reader<R>],
+    writer: &mut Writer<W>,
+) -> Result<Merge, Box<dyn Error>> {
+    let mut headers: Option<StringRecord> = None;
+    let mut accounts: BTreeMap<AccountKey, (Vec<usize>, StringRecord)> = BTreeMap::new();
+
+    for (shard, reader) in readers.iter_mut().enumerate() {
+        let shard_headers = reader.headers()?.clone();
+
+        match &headers {
+            Some(headers) if *headers != shard_headers => {
+                return Err(format!("shard {} has different columns from shard 0", shard).into())
+            }
+            Some(headers) => {}
+            None => headers = Some(shard_headers.clone()),
+        }
+
+        let column = |name: &str| shard_headers.iter().position(|header| header == name);
+        let client_column = column("client_id").ok_or("summary has no client_id column")?;
+        let tenant_column = column("tenant");
+
+        for record_result in reader.records() {
+            let record = record_result?;
+            let field = |index: usize| record.get(index).unwrap_or_default().to_owned();
+            let client_id = field(client_column);
+
+            /*
+            Numeric ids sort numerically, as they do in a single run.  Pseudonymized labels hide
+            that order, so they sort as text.
+            */
+            let key = (
+                tenant_column.map(field).unwrap_or_default(),
+                client_id.parse().ok(),
+                client_id,
+            );
+
+            accounts
+                .entry(key)
+                .or_insert_with(|| (Vec::new(), record.clone()))
+                .0
+                .push(shard);
+        }
+    }
+
+    let mut merge = Merge::default();
+
+    if let Some(headers) = &headers {
+        writer.write_record(headers)?;
+    }
+
+    for ((tenant, _, client_id), (shards, record)) in accounts {
+        if shards.len() > 1 {
+            merge.conflicts.push(MergeConflict {
+                tenant,
+                client_id,
+                shards: shards
+                    .iter()
+                    .map(|shard| shard.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            });
+            continue;
+        }
+
+        writer.write_record(&record)?;
+        merge.accounts += 1;
+    }
+
+    writer.flush()?;
+
+    Ok(merge)
+}
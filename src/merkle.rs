@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{provenance::hex, tenants::TenantDatabase, transactions::ClientId};
+
+type Hash = [u8; 32];
+
+/*
+A Merkle tree over every account's balance, in tenant and client order.  Publishing the root
+commits to every balance at once; a proof for one account is the handful of hashes needed to
+rebuild the root from that account alone, so an auditor can check one balance against the
+published root without seeing anyone else's.
+
+Leaves and interior nodes are hashed with different prefixes, so one can never pass for the
+other.  A level with an odd node out carries it up to the next level unchanged.
+*/
+pub struct BalanceTree {
+    keys: Vec<(String, ClientId)>,
+    leaves: Vec<String>,
+    levels: Vec<Vec<Hash>>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/*
+The sibling to hash with at one level on the way up, and which side of us it's on.
+*/
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ProofStep {
+    pub side: Side,
+    pub hash: String,
+}
+
+/*
+An account's leaf, as text, and the path from it to the root.  The leaf is the account's
+tenant, client id, available and held funds, and whether it's locked, separated by commas.
+*/
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct BalanceProof {
+    pub root: String,
+    pub leaf: String,
+    pub path: Vec<ProofStep>,
+}
+
+impl BalanceTree {
+    pub fn build(database: &TenantDatabase) -> BalanceTree {
+        let mut keys = Vec::new();
+        let mut leaves = Vec::new();
+
+        for (tenant, accounts) in database.tenants() {
+            for account in accounts.accounts() {
+                keys.push((tenant.to_owned(), account.client_id()));
+                leaves.push(format!(
+                    "{},{},{},{},{}",
+                    tenant,
+                    account.client_id(),
+                    account.available(),
+                    account.held(),
+                    account.is_locked()
+                ));
+            }
+        }
+
+        let mut levels: Vec<Vec<Hash>> = vec![leaves.iter().map(|leaf| leaf_hash(leaf)).collect()];
+
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [odd] => *odd,
+                    _ => unreachable!("chunks of two"),
+                })
+                .collect();
+
+            levels.push(next);
+        }
+
+        BalanceTree {
+            keys,
+            leaves,
+            levels,
+        }
+    }
+
+    /*
+    The root of an empty tree is all zeroes.
+    */
+    pub fn root(&self) -> String {
+        hex(self
+            .levels
+            .last()
+            .and_then(|level| level.first())
+            .unwrap_or(&[0; 32]))
+    }
+
+    pub fn prove(&self, tenant: &str, client_id: ClientId) -> Option<BalanceProof> {
+        let mut index = self
+            .keys
+            .binary_search(&(tenant.to_owned(), client_id))
+            .ok()?;
+        let leaf = self.leaves[index].clone();
+        let mut path = Vec::new();
+
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+
+            if let Some(hash) = level.get(sibling) {
+                path.push(ProofStep {
+                    side: if sibling < index {
+                        Side::Left
+                    } else {
+                        Side::Right
+                    },
+                    hash: hex(hash),
+                });
+            }
+            index /= 2;
+        }
+
+        Some(BalanceProof {
+            root: self.root(),
+            leaf,
+            path,
+        })
+    }
+}
+
+impl BalanceProof {
+    /*
+    Whether the path really leads from the leaf to `root`.  Pass the root that was published,
+    not the one in the proof -- anyone can make a proof that agrees with itself.
+    */
+    pub fn verify(&self, root: &str) -> bool {
+        let mut hash = leaf_hash(&self.leaf);
+
+        for step in &self.path {
+            let Some(sibling) = unhex(&step.hash) else {
+                return false;
+            };
+
+            hash = match step.side {
+                Side::Left => node_hash(&sibling, &hash),
+                Side::Right => node_hash(&hash, &sibling),
+            };
+        }
+
+        hex(&hash) == root
+    }
+}
+
+fn leaf_hash(leaf: &str) -> Hash {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(leaf)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+fn unhex(text: &str) -> Option<Hash> {
+    let mut hash = [0; 32];
+
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+
+    for (byte, digits) in hash.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+
+    Some(hash)
+}
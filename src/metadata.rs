@@ -0,0 +1,103 @@
+use std::{collections::HashMap, error::Error, io};
+
+use csv::Reader;
+
+use crate::{
+    middleware::TransactionMiddleware,
+    transactions::{ClientId, TransactionText},
+};
+
+/*
+Freeform per-client attributes (region, tier, ...) that live outside the transaction stream.
+
+The metadata file is a CSV with a `client` column and any number of other columns; every other
+column becomes a field name.  Clients that aren't listed simply have no fields.
+
+A `tags` column holds labels for the account, separated by spaces -- `vip`, `test-account` and
+the like.
+*/
+#[derive(Clone)]
+pub struct ClientMetadata {
+    fields: HashMap<ClientId, HashMap<String, String>>,
+}
+
+impl ClientMetadata {
+    pub fn new() -> ClientMetadata {
+        ClientMetadata {
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn load<R: io::Read>(reader: &mut Reader<R>) -> Result<ClientMetadata, Box<dyn Error>> {
+        let headers = reader.headers()?.clone();
+        let client_column = headers
+            .iter()
+            .position(|header| header == "client")
+            .ok_or("metadata file has no client column")?;
+        let mut metadata = ClientMetadata::new();
+
+        for record_result in reader.records() {
+            let record = record_result?;
+            let client_id: ClientId = record
+                .get(client_column)
+                .ok_or("metadata row has no client")?
+                .parse()?;
+            let fields = metadata.fields.entry(client_id).or_default();
+
+            for (index, (name, value)) in headers.iter().zip(record.iter()).enumerate() {
+                if index != client_column {
+                    fields.insert(name.to_owned(), value.to_owned());
+                }
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    pub fn field(&self, client_id: ClientId, name: &str) -> Option<&str> {
+        self.fields
+            .get(&client_id)
+            .and_then(|fields| fields.get(name))
+            .map(|value| value.as_str())
+    }
+
+    /*
+    Every client with a value for the field, and the value.
+    */
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (ClientId, &'a str)> {
+        self.fields.iter().filter_map(move |(&client_id, fields)| {
+            fields.get(name).map(|value| (client_id, value.as_str()))
+        })
+    }
+
+    pub fn has_tag(&self, client_id: ClientId, tag: &str) -> bool {
+        self.field(client_id, "tags")
+            .is_some_and(|tags| tags.split_whitespace().any(|candidate| candidate == tag))
+    }
+}
+
+/*
+Splits rows by whether their client carries any of the given tags.  Excluding tags drops every
+row for those clients, so e.g. synthetic test clients never reach the balances, the summary or
+the stats; keeping only tagged clients is how the sandbox gets those same rows instead.
+*/
+pub struct TagFilter {
+    pub metadata: ClientMetadata,
+    pub tags: Vec<String>,
+    pub keep_tagged: bool,
+}
+
+impl TransactionMiddleware for TagFilter {
+    fn process(&self, transaction: TransactionText) -> Option<TransactionText> {
+        let tagged = transaction
+            .field("client")
+            .and_then(|client| client.parse().ok())
+            .is_some_and(|client_id| {
+                self.tags
+                    .iter()
+                    .any(|tag| self.metadata.has_tag(client_id, tag))
+            });
+
+        (tagged == self.keep_tagged).then_some(transaction)
+    }
+}
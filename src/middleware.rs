@@ -0,0 +1,156 @@
+use std::{collections::HashMap, error::Error, io};
+
+use csv::Reader;
+
+use crate::transactions::TransactionText;
+
+/*
+A step that sees every input row before it's applied, and can rewrite it or drop it entirely
+by returning None.  Rows are handled as text, so a middleware can fix up anything that would
+otherwise fail to parse -- legacy kind names, odd id formats and the like.
+
+Plain closures work as middleware, for use from code.
+*/
+pub trait TransactionMiddleware {
+    fn process(&self, transaction: TransactionText) -> Option<TransactionText>;
+}
+
+impl<F: Fn(TransactionText) -> Option<TransactionText>> TransactionMiddleware for F {
+    fn process(&self, transaction: TransactionText) -> Option<TransactionText> {
+        self(transaction)
+    }
+}
+
+/*
+Replaces exact values of one field, leaving any other value alone.
+*/
+pub struct FieldMapping {
+    field: String,
+    values: HashMap<String, String>,
+}
+
+impl TransactionMiddleware for FieldMapping {
+    fn process(&self, mut transaction: TransactionText) -> Option<TransactionText> {
+        let replacement = transaction
+            .field(&self.field)
+            .and_then(|value| self.values.get(value))
+            .cloned();
+
+        if let Some(replacement) = replacement {
+            transaction.set_field(&self.field, replacement);
+        }
+
+        Some(transaction)
+    }
+}
+
+/*
+Fills in a field that is missing or empty.
+*/
+pub struct DefaultValue {
+    field: String,
+    value: String,
+}
+
+impl TransactionMiddleware for DefaultValue {
+    fn process(&self, mut transaction: TransactionText) -> Option<TransactionText> {
+        if transaction.field(&self.field).is_none_or(str::is_empty) {
+            transaction.set_field(&self.field, self.value.clone());
+        }
+
+        Some(transaction)
+    }
+}
+
+/*
+Middleware run in order, each seeing the output of the one before.
+*/
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn TransactionMiddleware>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    pub fn push<M: TransactionMiddleware + 'static>(&mut self, middleware: M) {
+        self.stages.push(Box::new(middleware));
+    }
+
+    pub fn process(&self, transaction: TransactionText) -> Option<TransactionText> {
+        self.stages
+            .iter()
+            .try_fold(transaction, |transaction, stage| stage.process(transaction))
+    }
+
+    /*
+    Builds a pipeline from a mapping file: a CSV with `field`, `from` and `to` columns.  Each
+    row rewrites `from` to `to` in the named field, or with an empty `from`, fills in `to`
+    wherever the field is missing.
+
+        field,from,to
+        type,credit,deposit
+        type,debit,withdrawal
+        tenant,,acme
+
+    Missing fields are filled in first, so a default can itself be rewritten.  Rewrites for the
+    same field are applied together, in the order each field first appears.
+    */
+    pub fn load<R: io::Read>(reader: &mut Reader<R>) -> Result<Pipeline, Box<dyn Error>> {
+        let mut mappings: Vec<FieldMapping> = Vec::new();
+        let mut defaults: Vec<DefaultValue> = Vec::new();
+
+        for record_result in reader.records() {
+            let record = record_result?;
+            let (field, from, to) = match (record.get(0), record.get(1), record.get(2)) {
+                (Some(field), Some(from), Some(to)) => (field, from, to),
+                _ => {
+                    return Err(
+                        format!("mapping row needs field, from and to: {:?}", record).into(),
+                    )
+                }
+            };
+
+            if !TransactionText::FIELDS.contains(&field) {
+                return Err(format!("unknown field in mapping: {}", field).into());
+            }
+
+            if from.is_empty() {
+                defaults.push(DefaultValue {
+                    field: field.to_owned(),
+                    value: to.to_owned(),
+                });
+                continue;
+            }
+
+            let index = match mappings.iter().position(|mapping| mapping.field == field) {
+                Some(index) => index,
+                None => {
+                    mappings.push(FieldMapping {
+                        field: field.to_owned(),
+                        values: HashMap::new(),
+                    });
+                    mappings.len() - 1
+                }
+            };
+
+            mappings[index]
+                .values
+                .insert(from.to_owned(), to.to_owned());
+        }
+
+        let mut pipeline = Pipeline::new();
+
+        for default in defaults {
+            pipeline.push(default);
+        }
+
+        for mapping in mappings {
+            pipeline.push(mapping);
+        }
+
+        Ok(pipeline)
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    error::Error,
+    io,
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+};
+
+use csv::{Reader, StringRecord, Writer};
+
+use crate::{apply_transactions, csv_reader, tenants::TenantDatabase, transactions::ClientId};
+
+/*
+What the minimized input has to keep doing:
+
+    panics:          applying it panics.
+    fails:           applying it stops with an error.
+    locked:<client>: the client's account ends up locked.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Predicate {
+    Panics,
+    Fails,
+    Locked(ClientId),
+}
+
+impl FromStr for Predicate {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "panics" => Ok(Predicate::Panics),
+            None if s == "fails" => Ok(Predicate::Fails),
+            Some(("locked", client_id)) => {
+                Ok(Predicate::Locked(client_id.trim().parse().map_err(|_| ())?))
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+impl Predicate {
+    /*
+    Applies `rows` to an empty database and checks the outcome.  A panic counts as the outcome
+    only for `panics` -- any other predicate just doesn't hold.
+    */
+    pub fn holds(&self, headers: &StringRecord, rows: &[StringRecord], tenant: &str) -> bool {
+        let mut writer = Writer::from_writer(vec![]);
+
+        if writer.write_record(headers).is_err()
+            || rows.iter().any(|row| writer.write_record(row).is_err())
+        {
+            return false;
+        }
+
+        let Ok(text) = writer.into_inner() else {
+            return false;
+        };
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut database = TenantDatabase::new();
+            let failed =
+                apply_transactions(&mut database, &mut csv_reader(text.as_slice())).is_err();
+
+            (database, failed)
+        }));
+
+        match (self, outcome) {
+            (Predicate::Panics, outcome) => outcome.is_err(),
+            (Predicate::Fails, Ok((database, failed))) => failed,
+            (Predicate::Locked(client_id), Ok((database, failed))) => database
+                .tenant(tenant)
+                .and_then(|accounts| accounts.account(*client_id))
+                .is_some_and(|account| account.is_locked()),
+            (_, Err(_)) => false,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Minimized {
+    pub original_rows: usize,
+    pub kept_rows: usize,
+    pub runs: usize,
+}
+
+/*
+Writes a minimal subset of a transaction log's rows that the predicate still holds for, as found
+by delta debugging (Zeller and Hildebrandt's ddmin): try the rows in n chunks, keep any
+chunk, or any complement of one, that still reproduces, and split finer when none does.  The
+result is 1-minimal -- taking out any single row loses the behavior -- though not necessarily
+the smallest such subset.  Rows keep their input order.
+
+Each run replays the subset from scratch, so a huge input takes a while; the panic hook is
+silenced for the duration so each panicking run doesn't print a backtrace.
+*/
+pub fn minimize<R: io::Read, W: io::Write>(
+    reader: &mut Reader<R>,
+    writer: &mut Writer<W>,
+    predicate: Predicate,
+    tenant: &str,
+) -> Result<Minimized, Box<dyn Error>> {
+    let headers = reader.headers()?.clone();
+    let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let hook = panic::take_hook();
+    let mut runs = 0;
+
+    panic::set_hook(Box::new(|info| {}));
+
+    let result = if predicate.holds(&headers, &rows, tenant) {
+        Ok(ddmin(&rows, |subset| {
+            runs += 1;
+            predicate.holds(&headers, subset, tenant)
+        }))
+    } else {
+        Err("the predicate doesn't hold for the whole input")
+    };
+
+    panic::set_hook(hook);
+
+    let minimal = result?;
+
+    writer.write_record(&headers)?;
+
+    for row in &minimal {
+        writer.write_record(row)?;
+    }
+
+    writer.flush()?;
+
+    Ok(Minimized {
+        original_rows: rows.len(),
+        kept_rows: minimal.len(),
+        runs: runs + 1,
+    })
+}
+
+/*
+`holds` is never asked about the empty subset, nor about `items` itself, which it's assumed to
+hold for.
+*/
+pub fn ddmin<T: Clone>(items: &[T], mut holds: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut current = items.to_vec();
+    let mut granularity = 2;
+
+    while current.len() >= 2 {
+        let chunk_length = current.len().div_ceil(granularity);
+        let chunks: Vec<Vec<T>> = current
+            .chunks(chunk_length)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+
+        if let Some(chunk) = chunks.iter().find(|chunk| holds(chunk)) {
+            current = chunk.clone();
+            granularity = 2;
+            continue;
+        }
+
+        /*
+        With two chunks, each one's complement is the other, already tried.
+        */
+        let complement = |skipped: usize| -> Vec<T> {
+            chunks
+                .iter()
+                .enumerate()
+                .filter(|(index, chunk)| *index != skipped)
+                .flat_map(|(index, chunk)| chunk.iter().cloned())
+                .collect()
+        };
+
+        if chunks.len() > 2 {
+            if let Some(complement) = (0..chunks.len())
+                .map(complement)
+                .find(|complement| holds(complement))
+            {
+                current = complement;
+                granularity = (granularity - 1).max(2);
+                continue;
+            }
+        }
+
+        if granularity >= current.len() {
+            break;
+        }
+
+        granularity = (granularity * 2).min(current.len());
+    }
+
+    current
+}
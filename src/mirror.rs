@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, error::Error, io};
+
+use csv::Writer;
+use serde::Serialize;
+
+use crate::{
+    dates::Date,
+    ledger::LedgerEvent,
+    tenants::TenantDatabase,
+    transactions::{ClientId, TransactionRecord, TxId},
+    Money,
+};
+
+/*
+One accepted transaction, as the engine applied it, in the input's own columns plus its
+position in the tenant's ledger.  Rows the engine rejected never appear, and a deposit trimmed
+by the balance cap appears with the amount that was credited.  Disputes, resolves and
+chargebacks carry the amount they held or released.
+
+That makes the mirror an input in its own right: an independent verifier can summarize it --
+with the same behavior version, and approving adjustments automatically, since only approved
+ones are mirrored -- and must arrive at the engine's balances.  `sequence` lets it spot a gap.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct MirroredTransaction<'a> {
+    pub sequence: usize,
+    pub tenant: &'a str,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub amount: String,
+    pub date: Option<String>,
+    pub memo: Option<&'a str>,
+}
+
+impl<'a> MirroredTransaction<'a> {
+    fn new(
+        tenant: &'a str,
+        sequence: usize,
+        transaction: TransactionRecord,
+        disputed_amount: Money,
+        date: Option<Date>,
+        memo: Option<&'a str>,
+    ) -> MirroredTransaction<'a> {
+        let amount = match transaction {
+            TransactionRecord::Dispute { id, amount }
+            | TransactionRecord::Resolve { id, amount }
+            | TransactionRecord::Chargeback { id, amount } => disputed_amount.to_string(),
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative: true,
+                operator,
+            } => format!("-{}", amount),
+            _ => transaction.amount().to_string(),
+        };
+
+        MirroredTransaction {
+            sequence,
+            tenant,
+            kind: transaction.kind(),
+            client: transaction.id().client_id,
+            tx: transaction.id().transaction_id,
+            amount,
+            date: date.map(|date| date.to_string()),
+            memo,
+        }
+    }
+}
+
+/*
+Mirrors every transaction accepted during the run -- those after `first_events`, the number of
+events each tenant had beforehand.  Returns the number of rows written.
+*/
+pub fn write_mirror<W: io::Write>(
+    database: &TenantDatabase,
+    first_events: &BTreeMap<String, usize>,
+    writer: &mut Writer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+
+    for (tenant, accounts) in database.tenants() {
+        let first = first_events.get(tenant).copied().unwrap_or_default();
+
+        for (sequence, event) in accounts.events().iter().enumerate().skip(first) {
+            if let LedgerEvent::TransactionApplied {
+                transaction,
+                disputed_amount,
+                date,
+            } = *event
+            {
+                writer.serialize(MirroredTransaction::new(
+                    tenant,
+                    sequence,
+                    transaction,
+                    disputed_amount,
+                    date,
+                    accounts.memo(sequence),
+                ))?;
+                count += 1;
+            }
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
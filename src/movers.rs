@@ -0,0 +1,161 @@
+use std::{collections::BTreeMap, error::Error, io};
+
+use csv::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::{accounts::AccountDatabase, pseudonym::ClientLabel, transactions::ClientId, Money};
+
+/*
+A previously written account summary, as produced by a normal run.  We only need the totals, so
+every other column is ignored.
+*/
+#[derive(Deserialize)]
+struct SnapshotRow {
+    client_id: ClientId,
+    total: String,
+}
+
+pub struct SummarySnapshot {
+    totals: BTreeMap<ClientId, Money>,
+}
+
+impl SummarySnapshot {
+    pub fn load<R: io::Read>(reader: &mut Reader<R>) -> Result<SummarySnapshot, Box<dyn Error>> {
+        let mut totals = BTreeMap::new();
+
+        for record_result in reader.deserialize() {
+            let row: SnapshotRow = record_result?;
+            let total: Money = row
+                .total
+                .parse()
+                .map_err(|_| format!("invalid total for client {}", row.client_id))?;
+
+            totals.insert(row.client_id, total);
+        }
+
+        Ok(SummarySnapshot { totals })
+    }
+
+    pub fn from_accounts(accounts: &AccountDatabase) -> SummarySnapshot {
+        SummarySnapshot {
+            totals: accounts
+                .accounts()
+                .map(|account| (account.client_id(), account.available() + account.held()))
+                .collect(),
+        }
+    }
+
+    fn total(&self, client_id: ClientId) -> Money {
+        self.totals
+            .get(&client_id)
+            .copied()
+            .unwrap_or(Money::zero())
+    }
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+pub struct Mover {
+    pub client_id: ClientLabel,
+    pub previous_total: String,
+    pub total: String,
+    pub change: String,
+    pub z_score: Option<f64>,
+    pub anomalous: bool,
+}
+
+/*
+The N accounts whose total moved the most since the most recent snapshot, largest swings first.
+
+Given older snapshots too, each client's change in this batch is compared against the changes
+between consecutive historical snapshots.  A change more than `threshold` standard deviations
+from that client's historical mean is flagged as anomalous.  With fewer than two historical
+changes there isn't a distribution to compare against, so no z-score is reported.
+
+Snapshots are expected oldest first.  Without any, every account is compared against zero.
+*/
+pub fn top_movers(
+    accounts: &AccountDatabase,
+    snapshots: &[SummarySnapshot],
+    count: usize,
+    threshold: f64,
+) -> Vec<Mover> {
+    let current = SummarySnapshot::from_accounts(accounts);
+    let baseline = snapshots.last();
+    let mut client_ids: Vec<ClientId> = current.totals.keys().copied().collect();
+
+    if let Some(baseline) = baseline {
+        client_ids.extend(baseline.totals.keys());
+        client_ids.sort();
+        client_ids.dedup();
+    }
+
+    let mut movers: Vec<(i128, Mover)> = client_ids
+        .into_iter()
+        .map(|client_id| {
+            let previous = baseline.map_or(Money::zero(), |baseline| baseline.total(client_id));
+            let total = current.total(client_id);
+            let change = units(total) - units(previous);
+            let z_score = z_score(client_id, snapshots, change);
+
+            (
+                change,
+                Mover {
+                    client_id: ClientLabel::Id(client_id),
+                    previous_total: previous.to_string(),
+                    total: total.to_string(),
+                    change: signed_to_string(change),
+                    z_score,
+                    anomalous: z_score.is_some_and(|z| z.abs() >= threshold),
+                },
+            )
+        })
+        .collect();
+
+    movers.sort_by_key(|(change, _)| std::cmp::Reverse(change.abs()));
+    movers.truncate(count);
+
+    movers.into_iter().map(|(_, mover)| mover).collect()
+}
+
+fn z_score(client_id: ClientId, snapshots: &[SummarySnapshot], change: i128) -> Option<f64> {
+    let history: Vec<f64> = snapshots
+        .windows(2)
+        .map(|pair| (units(pair[1].total(client_id)) - units(pair[0].total(client_id))) as f64)
+        .collect();
+
+    if history.len() < 2 {
+        return None;
+    }
+
+    let mean = history.iter().sum::<f64>() / history.len() as f64;
+    let variance = history
+        .iter()
+        .map(|delta| (delta - mean).powi(2))
+        .sum::<f64>()
+        / history.len() as f64;
+    let deviation = change as f64 - mean;
+
+    if variance == 0.0 {
+        Some(if deviation == 0.0 {
+            0.0
+        } else {
+            f64::INFINITY.copysign(deviation)
+        })
+    } else {
+        Some(deviation / variance.sqrt())
+    }
+}
+
+fn units(amount: Money) -> i128 {
+    amount.0 as i128
+}
+
+fn signed_to_string(units: i128) -> String {
+    let magnitude = Money(units.unsigned_abs() as u64);
+
+    if units < 0 {
+        format!("-{}", magnitude)
+    } else {
+        magnitude.to_string()
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    io::{self, Write},
+    mem,
+    sync::mpsc::{sync_channel, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+/*
+How much output to gather before handing it to the underlying writer.  Large enough that
+writing millions of summary rows turns into a few hundred writes rather than millions.
+*/
+const CHUNK_SIZE: usize = 1 << 20;
+
+/*
+Chunks in flight to the writer thread.  Bounds how far serialization can run ahead of the
+output, and so how much memory the pipeline can take.
+*/
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/*
+Buffers output into large chunks and writes each one in a single call, either directly or from
+a separate writer thread so serialization doesn't wait on the output.
+
+Output is written in order either way.  Dropping the writer flushes whatever is left and waits
+for the writer thread to finish.
+*/
+pub struct ChunkedWriter<W: Write + Send + 'static> {
+    chunk: Vec<u8>,
+    sink: Sink<W>,
+}
+
+enum Sink<W: Write + Send + 'static> {
+    Direct(W),
+    Threaded {
+        sender: Option<SyncSender<Vec<u8>>>,
+        thread: Option<JoinHandle<io::Result<()>>>,
+    },
+}
+
+impl<W: Write + Send + 'static> ChunkedWriter<W> {
+    pub fn new(inner: W, threaded: bool) -> ChunkedWriter<W> {
+        let sink = if threaded {
+            let (sender, receiver) = sync_channel::<Vec<u8>>(CHUNKS_IN_FLIGHT);
+            let thread = thread::spawn(move || {
+                let mut inner = inner;
+
+                for chunk in receiver {
+                    inner.write_all(&chunk)?;
+                    inner.flush()?;
+                }
+
+                Ok(())
+            });
+
+            Sink::Threaded {
+                sender: Some(sender),
+                thread: Some(thread),
+            }
+        } else {
+            Sink::Direct(inner)
+        };
+
+        ChunkedWriter {
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            sink,
+        }
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+
+        match &mut self.sink {
+            Sink::Direct(inner) => {
+                inner.write_all(&self.chunk)?;
+                self.chunk.clear();
+            }
+            Sink::Threaded { sender, .. } => {
+                let chunk = mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+
+                sender
+                    .as_ref()
+                    .expect("the writer thread is only stopped on drop")
+                    .send(chunk)
+                    .map_err(|_| {
+                        io::Error::new(io::ErrorKind::BrokenPipe, "writer thread failed")
+                    })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write + Send + 'static> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk()?;
+
+        match &mut self.sink {
+            Sink::Direct(inner) => inner.flush(),
+            Sink::Threaded { .. } => Ok(()),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> Drop for ChunkedWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+
+        if let Sink::Threaded { sender, thread } = &mut self.sink {
+            drop(sender.take());
+
+            if let Some(Ok(Err(error))) = thread.take().map(JoinHandle::join) {
+                eprintln!("Failed to write output: {}", error);
+            }
+        }
+    }
+}
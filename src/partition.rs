@@ -0,0 +1,87 @@
+use std::{collections::HashMap, error::Error, io};
+
+use csv::{Reader, Writer};
+
+use crate::transactions::{ClientId, TxId};
+
+/*
+Which of `shards` shards a client belongs to, by jump consistent hashing (Lamping and Veach):
+going from n to n + 1 shards moves only a 1/(n + 1) share of clients, all of them into the new
+shard, so machines can be added without reshuffling everyone else.
+
+`shards` must be at least one.
+*/
+pub fn partition(client_id: ClientId, shards: u32) -> u32 {
+    assert!(shards > 0, "partition needs at least one shard");
+
+    /*
+    Client ids are small and sequential, so spread them over the key space first.
+    */
+    let mut key = client_id.0 as u64;
+    key = (key ^ (key >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    key = (key ^ (key >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    key ^= key >> 31;
+
+    let mut bucket: i64 = -1;
+    let mut next: i64 = 0;
+
+    while next < shards as i64 {
+        bucket = next;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+
+    bucket as u32
+}
+
+/*
+Splits a transaction log into one log per writer, so each can be summarized on its own machine
+and the summaries concatenated.  Every shard gets the input's header and columns unchanged.
+
+Rows go to their client's shard -- except a row reusing a transaction id already seen for its
+tenant, which goes wherever that transaction went.  That keeps a dispute with the transaction
+it references even when it names the wrong client, and a reused id with the transaction that
+first claimed it, so each is rejected or reported exactly as a single run would.
+
+Returns the number of rows written to each shard.
+*/
+pub fn split<R: io::Read, W: io::Write>(
+    reader: &mut Reader<R>,
+    writers: &mut [Writer<W>],
+) -> Result<Vec<usize>, Box<dyn Error>> {
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let client_column = column("client").ok_or("input has no client column")?;
+    let tx_column = column("tx").ok_or("input has no tx column")?;
+    let tenant_column = column("tenant");
+    let mut owners: HashMap<(String, TxId), u32> = HashMap::new();
+    let mut counts = vec![0; writers.len()];
+
+    for writer in writers.iter_mut() {
+        writer.write_record(&headers)?;
+    }
+
+    for record_result in reader.records() {
+        let record = record_result?;
+        let field = |index: usize| record.get(index).unwrap_or_default();
+        let client_id: ClientId = field(client_column)
+            .parse()
+            .map_err(|_| format!("invalid client: {}", field(client_column)))?;
+        let tx: TxId = field(tx_column)
+            .parse()
+            .map_err(|_| format!("invalid tx: {}", field(tx_column)))?;
+        let tenant = tenant_column.map(field).unwrap_or_default();
+        let shard = *owners
+            .entry((tenant.to_owned(), tx))
+            .or_insert_with(|| partition(client_id, writers.len() as u32));
+
+        writers[shard as usize].write_record(&record)?;
+        counts[shard as usize] += 1;
+    }
+
+    for writer in writers.iter_mut() {
+        writer.flush()?;
+    }
+
+    Ok(counts)
+}
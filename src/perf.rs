@@ -0,0 +1,90 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use serde::Serialize;
+
+/*
+The system allocator, counting what each thread allocates.  Built with the perf feature, it's the
+global allocator, so a run can report how much its hot loop allocated -- which should be nothing
+per transaction once the accounts it touches exist, and a test can hold it to that.
+
+Counts are kept per thread, so work on the writer thread, or on other tests running alongside,
+doesn't show up in a measurement.  A reallocation counts as an allocation of the new size.
+*/
+pub struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    static ALLOCATED_BYTES: Cell<u64> = const { Cell::new(0) };
+}
+
+#[cfg(feature = "perf")]
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn count(size: usize) {
+    /*
+    A thread being torn down can still allocate, after its counters are gone.
+    */
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+    let _ = ALLOCATED_BYTES.try_with(|bytes| bytes.set(bytes.get() + size as u64));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/*
+What the current thread allocated between a `start` and the matching `since`.
+*/
+#[derive(Serialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Allocations {
+    pub allocations: u64,
+    pub bytes: u64,
+}
+
+impl Allocations {
+    /*
+    The counts so far, to measure from -- None unless built with the perf feature, as nothing's
+    counting otherwise.
+    */
+    pub fn start() -> Option<Allocations> {
+        if !cfg!(feature = "perf") {
+            return None;
+        }
+
+        Some(Allocations {
+            allocations: ALLOCATIONS.with(Cell::get),
+            bytes: ALLOCATED_BYTES.with(Cell::get),
+        })
+    }
+
+    pub fn since(start: Option<Allocations>) -> Option<Allocations> {
+        let start = start?;
+        let now = Allocations::start()?;
+
+        Some(Allocations {
+            allocations: now.allocations - start.allocations,
+            bytes: now.bytes - start.bytes,
+        })
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{clock::Clock, dates::Date};
+
+/*
+Everything needed to trace a summary file back to how it was produced, written as a JSON
+sidecar next to it.
+*/
+#[derive(Serialize)]
+pub struct Provenance {
+    pub engine_version: &'static str,
+
+    /*
+    A hash over the command line, with secrets blanked out.  Two runs with the same hash were
+    invoked identically, though the files they read may have changed in between -- that's what
+    the input checksums are for.
+    */
+    pub config_hash: String,
+    pub inputs: Vec<InputChecksum>,
+    pub transactions: usize,
+    pub summary_rows: usize,
+
+    /*
+    See ledger::LedgerHash -- the same as the run stats report, so whoever holds the summary
+    can check it against another party's without rerunning anything.
+    */
+    pub ledger_hash: String,
+    pub generated_at: String,
+}
+
+#[derive(Serialize)]
+pub struct InputChecksum {
+    pub path: String,
+    pub sha256: String,
+}
+
+impl Provenance {
+    pub fn new(args: &[String], inputs: &[&Path], clock: &dyn Clock) -> io::Result<Provenance> {
+        Ok(Provenance {
+            engine_version: env!("CARGO_PKG_VERSION"),
+            config_hash: config_hash(args),
+            inputs: inputs
+                .iter()
+                .map(|path| {
+                    Ok(InputChecksum {
+                        path: path.display().to_string(),
+                        sha256: checksum(path)?,
+                    })
+                })
+                .collect::<io::Result<_>>()?,
+            transactions: 0,
+            summary_rows: 0,
+            ledger_hash: String::new(),
+            generated_at: timestamp(clock.now()),
+        })
+    }
+}
+
+pub fn config_hash(args: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    let mut secret = false;
+
+    for arg in args {
+        hasher.update(if secret { "<secret>" } else { arg.as_str() });
+        hasher.update([0]);
+        secret = arg == "--salt";
+    }
+
+    hex(&hasher.finalize())
+}
+
+pub fn checksum(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hex(&hasher.finalize()))
+}
+
+/*
+RFC 3339 in UTC, to the second.
+*/
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let date = Date::from_days((seconds / 86400) as i32);
+    let time_of_day = seconds % 86400;
+
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60
+    )
+}
+
+/*
+The inverse of `timestamp`: only the exact form it writes is accepted.
+*/
+pub fn parse_timestamp(text: &str) -> Option<SystemTime> {
+    let (date, time_of_day) = text.strip_suffix('Z')?.split_once('T')?;
+    let days = date.parse::<Date>().ok()?.days_since(Date::from_days(0));
+    let parts: Vec<u64> = time_of_day
+        .split(':')
+        .map(|part| match part.len() {
+            2 => part.parse().ok(),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+
+    match parts[..] {
+        [hours, minutes, seconds] if days >= 0 && hours < 24 && minutes < 60 && seconds < 60 => {
+            Some(
+                UNIX_EPOCH
+                    + Duration::from_secs(
+                        days as u64 * 86400 + hours * 3600 + minutes * 60 + seconds,
+                    ),
+            )
+        }
+        _ => None,
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,68 @@
+use std::fmt::Write;
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::transactions::ClientId;
+
+/*
+How a client is identified in output: either by their real id, or by a pseudonym that can be
+shared with third parties.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+#[serde(untagged)]
+pub enum ClientLabel {
+    Id(ClientId),
+    Pseudonym(String),
+}
+
+/*
+Replaces client ids with stable tokens: HMAC-SHA256 over the tenant and client id, keyed by a
+secret salt and truncated to 64 bits of hex.
+
+The same client always gets the same token under the same salt, so pseudonymized outputs can
+still be joined against each other, but without the salt there's no way back to the client id.
+With it, there are only 65536 client ids per tenant, so reidentifying a token is a matter of
+trying them all.
+*/
+pub struct Pseudonymizer {
+    salt: Vec<u8>,
+}
+
+impl Pseudonymizer {
+    pub fn new(salt: &str) -> Pseudonymizer {
+        Pseudonymizer {
+            salt: salt.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn token(&self, tenant: &str, client_id: ClientId) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.salt).expect("HMAC accepts keys of any length");
+
+        mac.update(tenant.as_bytes());
+        mac.update(b":");
+        mac.update(client_id.to_string().as_bytes());
+
+        let mut token = String::with_capacity(16);
+
+        for byte in &mac.finalize().into_bytes()[..8] {
+            write!(token, "{:02x}", byte).expect("writing to a String can't fail");
+        }
+
+        token
+    }
+
+    pub fn label(&self, tenant: &str, label: &mut ClientLabel) {
+        if let ClientLabel::Id(client_id) = label {
+            *label = ClientLabel::Pseudonym(self.token(tenant, *client_id));
+        }
+    }
+
+    pub fn reidentify(&self, tenant: &str, token: &str) -> Option<ClientId> {
+        (0..=u16::MAX)
+            .map(ClientId)
+            .find(|&client_id| self.token(tenant, client_id) == token)
+    }
+}
@@ -0,0 +1,154 @@
+use std::error::Error;
+
+use serde::Serialize;
+
+use crate::{accounts::Account, tenants::TenantDatabase, transactions::ClientId};
+
+/*
+Commands per pipeline, so a huge run doesn't build one enormous request.
+*/
+const BATCH_SIZE: usize = 1000;
+
+/*
+What a balance-check service reads for one account: a Redis hash at `<prefix>:<tenant>:<client>`
+with these fields, and the same thing as JSON on the `<prefix>:changes` channel whenever a run
+changes the account.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct BalanceUpdate<'a> {
+    pub tenant: &'a str,
+    pub client_id: ClientId,
+    pub available: String,
+    pub held: String,
+    pub locked: bool,
+}
+
+impl BalanceUpdate<'_> {
+    pub fn new<'a>(tenant: &'a str, account: &Account) -> BalanceUpdate<'a> {
+        BalanceUpdate {
+            tenant,
+            client_id: account.client_id(),
+            available: account.available().to_string(),
+            held: account.held().to_string(),
+            locked: account.is_locked(),
+        }
+    }
+
+    pub fn key(&self, prefix: &str) -> String {
+        format!("{}:{}:{}", prefix, self.tenant, self.client_id)
+    }
+}
+
+pub fn changes_channel(prefix: &str) -> String {
+    format!("{}:changes", prefix)
+}
+
+/*
+Where balances are published: Redis, or anything else that takes hashes and channel messages.
+Commands are queued, and sent together by `flush`.
+*/
+pub trait BalanceStore {
+    /*
+    Writes the hash at `key` whole, so a reader never sees half an update.
+    */
+    fn set(&mut self, key: String, fields: Vec<(&'static str, String)>);
+
+    fn publish(&mut self, channel: &str, message: String);
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+/*
+Writes every account's latest balance to `store`, and announces the ones this run changed.  A
+reader may see some accounts from this run and others from the last until we're done.
+
+Returns the number of accounts written and the number announced.
+*/
+pub fn publish_to(
+    store: &mut dyn BalanceStore,
+    prefix: &str,
+    database: &TenantDatabase,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    let channel = changes_channel(prefix);
+    let accounts: Vec<_> = database
+        .tenants()
+        .flat_map(|(tenant, accounts)| {
+            accounts
+                .accounts()
+                .map(move |account| (BalanceUpdate::new(tenant, account), account.is_changed()))
+        })
+        .collect();
+    let mut announced = 0;
+
+    for batch in accounts.chunks(BATCH_SIZE) {
+        for (update, changed) in batch {
+            store.set(
+                update.key(prefix),
+                vec![
+                    ("available", update.available.clone()),
+                    ("held", update.held.clone()),
+                    ("locked", update.locked.to_string()),
+                ],
+            );
+
+            if *changed {
+                store.publish(&channel, serde_json::to_string(update)?);
+                announced += 1;
+            }
+        }
+
+        store.flush()?;
+    }
+
+    Ok((accounts.len(), announced))
+}
+
+#[cfg(feature = "redis")]
+pub use redis_publisher::publish_balances;
+
+#[cfg(feature = "redis")]
+mod redis_publisher {
+    use std::error::Error;
+
+    use super::{publish_to, BalanceStore};
+    use crate::tenants::TenantDatabase;
+
+    /*
+    Publishes to Redis -- see publish_to.
+    */
+    pub fn publish_balances(
+        url: &str,
+        prefix: &str,
+        database: &TenantDatabase,
+    ) -> Result<(usize, usize), Box<dyn Error>> {
+        let client = redis::Client::open(url)?;
+        let mut store = RedisStore {
+            connection: client.get_connection()?,
+            pipeline: redis::pipe(),
+        };
+
+        publish_to(&mut store, prefix, database)
+    }
+
+    struct RedisStore {
+        connection: redis::Connection,
+        pipeline: redis::Pipeline,
+    }
+
+    impl BalanceStore for RedisStore {
+        fn set(&mut self, key: String, fields: Vec<(&'static str, String)>) {
+            self.pipeline.hset_multiple(key, &fields).ignore();
+        }
+
+        fn publish(&mut self, channel: &str, message: String) {
+            self.pipeline.publish(channel, message).ignore();
+        }
+
+        fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+            self.pipeline.query::<()>(&mut self.connection)?;
+            self.pipeline.clear();
+
+            Ok(())
+        }
+    }
+}
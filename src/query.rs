@@ -0,0 +1,89 @@
+use std::{error::Error, io};
+
+use csv::Writer;
+use rusqlite::{params, types::ValueRef, Connection};
+
+use crate::{tenants::TenantDatabase, Money};
+
+/*
+Runs an ad-hoc SQL query over the summary, using an in-memory SQLite database with one table:
+
+    summary(tenant TEXT, client_id INTEGER, available REAL, held REAL, total REAL,
+            locked INTEGER)
+
+so that e.g. `SELECT client_id, total FROM summary WHERE locked` works as you'd expect.
+
+Amounts are loaded as floats so they compare and sort numerically.  That's exact for any
+realistic balance at four decimal places, but sums over many rows can pick up float noise --
+use the summary itself when the exact figure matters.
+
+Results are written as CSV with the query's own column names.  Returns the number of rows.
+*/
+pub fn query<W: io::Write>(
+    database: &TenantDatabase,
+    sql: &str,
+    writer: &mut Writer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    let connection = Connection::open_in_memory()?;
+
+    connection.execute_batch(
+        "CREATE TABLE summary (
+            tenant TEXT NOT NULL,
+            client_id INTEGER NOT NULL,
+            available REAL NOT NULL,
+            held REAL NOT NULL,
+            total REAL NOT NULL,
+            locked INTEGER NOT NULL
+        )",
+    )?;
+
+    {
+        let mut insert = connection.prepare("INSERT INTO summary VALUES (?, ?, ?, ?, ?, ?)")?;
+
+        for (tenant, accounts) in database.tenants() {
+            for account in accounts.accounts() {
+                insert.execute(params![
+                    tenant,
+                    account.client_id().0,
+                    float(account.available()),
+                    float(account.held()),
+                    float(account.available() + account.held()),
+                    account.is_locked(),
+                ])?;
+            }
+        }
+    }
+
+    let mut statement = connection.prepare(sql)?;
+    let columns = statement.column_count();
+
+    writer.write_record(statement.column_names())?;
+
+    let mut rows = statement.query([])?;
+    let mut count = 0;
+
+    while let Some(row) = rows.next()? {
+        let mut record = Vec::with_capacity(columns);
+
+        for index in 0..columns {
+            record.push(match row.get_ref(index)? {
+                ValueRef::Null => String::new(),
+                ValueRef::Integer(value) => value.to_string(),
+                ValueRef::Real(value) => format!("{:?}", value),
+                ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                ValueRef::Blob(_) => return Err("query returned a blob".into()),
+            });
+        }
+
+        writer.write_record(&record)?;
+        count += 1;
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
+
+fn float(amount: Money) -> f64 {
+    amount.0 as f64 / 10000.0
+}
@@ -0,0 +1,143 @@
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+/*
+One way a candidate build disagreed with the baseline on one input.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    pub input: String,
+    pub kind: &'static str,
+    pub location: String,
+    pub baseline: String,
+    pub candidate: String,
+}
+
+struct RunOutput {
+    status: String,
+    stdout: String,
+    stats: Value,
+}
+
+/*
+Runs two builds of this tool over every CSV file in a directory and reports everywhere they
+disagree: exit status, the summary itself (line by line), and the run stats (key by key).
+
+Both builds are run as a plain summarize with `--stats`, so the baseline needs to be recent
+enough to support that.
+*/
+pub fn regress(
+    baseline: &Path,
+    candidate: &Path,
+    inputs: &Path,
+) -> Result<Vec<Divergence>, Box<dyn Error>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(inputs)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "csv"));
+    paths.sort();
+
+    let mut divergences = Vec::new();
+
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let expected = run(baseline, &path, "baseline")?;
+        let actual = run(candidate, &path, "candidate")?;
+
+        if expected.status != actual.status {
+            divergences.push(Divergence {
+                input: name.clone(),
+                kind: "status",
+                location: String::new(),
+                baseline: expected.status,
+                candidate: actual.status,
+            });
+        }
+
+        divergences.extend(diff_outputs(&name, &expected.stdout, &actual.stdout));
+        divergences.extend(diff_stats(&name, &expected.stats, &actual.stats));
+    }
+
+    Ok(divergences)
+}
+
+fn run(binary: &Path, input: &Path, role: &str) -> Result<RunOutput, Box<dyn Error>> {
+    let stats_path = std::env::temp_dir().join(format!(
+        "fizzbuzz-regress-{}-{}.json",
+        std::process::id(),
+        role
+    ));
+    let output = Command::new(binary)
+        .arg("--stats")
+        .arg(&stats_path)
+        .arg(input)
+        .output()
+        .map_err(|error| format!("failed to run {}: {}", binary.display(), error))?;
+    let stats = fs::read_to_string(&stats_path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or(Value::Null);
+
+    let _ = fs::remove_file(&stats_path);
+
+    Ok(RunOutput {
+        status: output.status.to_string(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stats,
+    })
+}
+
+pub fn diff_outputs(input: &str, baseline: &str, candidate: &str) -> Vec<Divergence> {
+    let expected: Vec<&str> = baseline.lines().collect();
+    let actual: Vec<&str> = candidate.lines().collect();
+
+    (0..expected.len().max(actual.len()))
+        .filter_map(|index| {
+            let expected = expected.get(index).copied().unwrap_or_default();
+            let actual = actual.get(index).copied().unwrap_or_default();
+
+            (expected != actual).then(|| Divergence {
+                input: input.to_owned(),
+                kind: "output",
+                location: format!("line {}", index + 1),
+                baseline: expected.to_owned(),
+                candidate: actual.to_owned(),
+            })
+        })
+        .collect()
+}
+
+pub fn diff_stats(input: &str, baseline: &Value, candidate: &Value) -> Vec<Divergence> {
+    let keys = |stats: &Value| -> BTreeSet<String> {
+        stats
+            .as_object()
+            .map(|fields| fields.keys().cloned().collect())
+            .unwrap_or_default()
+    };
+    let render =
+        |stats: &Value, key: &str| stats.get(key).map(Value::to_string).unwrap_or_default();
+
+    keys(baseline)
+        .union(&keys(candidate))
+        .filter(|key| baseline.get(key.as_str()) != candidate.get(key.as_str()))
+        .map(|key| Divergence {
+            input: input.to_owned(),
+            kind: "stats",
+            location: key.clone(),
+            baseline: render(baseline, key),
+            candidate: render(candidate, key),
+        })
+        .collect()
+}
@@ -0,0 +1,54 @@
+use std::str::FromStr;
+
+/*
+How to round a division that doesn't come out even to the smallest unit of money -- which
+rules an institution follows is usually mandated, not chosen.  Anything computing an amount
+rather than reading one (interest, so far) goes through one of these.
+*/
+pub trait RoundingStrategy {
+    fn divide(&self, numerator: u128, denominator: u128) -> u128;
+}
+
+/*
+The strategies that can be picked from the command line or per schedule row.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Rounding {
+    #[default]
+    TowardZero,
+    HalfUp,
+
+    /*
+    Banker's rounding: exact halves go to the even neighbour, so they don't bias totals upward.
+    */
+    HalfEven,
+}
+
+impl RoundingStrategy for Rounding {
+    fn divide(&self, numerator: u128, denominator: u128) -> u128 {
+        let quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        let round_up = match self {
+            Rounding::TowardZero => false,
+            Rounding::HalfUp => remainder * 2 >= denominator,
+            Rounding::HalfEven => {
+                remainder * 2 > denominator || (remainder * 2 == denominator && quotient % 2 == 1)
+            }
+        };
+
+        quotient + round_up as u128
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toward-zero" => Ok(Rounding::TowardZero),
+            "half-up" => Ok(Rounding::HalfUp),
+            "half-even" => Ok(Rounding::HalfEven),
+            _ => Err(()),
+        }
+    }
+}
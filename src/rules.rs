@@ -0,0 +1,111 @@
+use crate::{accounts::Account, transactions::TransactionRecord};
+
+/*
+A custom policy deciding whether a transaction may be applied at all, given the state of the
+account it's for (None if the client has no account yet).  Rejected transactions are dropped
+before they reach the ledger, exactly as if they were never in the input.
+*/
+pub trait AcceptanceRule {
+    fn accept(
+        &self,
+        tenant: &str,
+        transaction: &TransactionRecord,
+        account: Option<&Account>,
+    ) -> bool;
+}
+
+#[cfg(feature = "scripting")]
+pub use script::ScriptRules;
+
+#[cfg(feature = "scripting")]
+mod script {
+    use std::{error::Error, path::Path};
+
+    use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+    use super::AcceptanceRule;
+    use crate::{accounts::Account, transactions::TransactionRecord, Money};
+
+    /*
+    Acceptance rules written as a Rhai script, so they can be changed without rebuilding.  The
+    script defines one function, called for every transaction:
+
+        fn accept(tx, account) {
+            // tx:      #{ type, client, tx, amount, tenant }
+            // account: #{ available, held, locked }, or () for a new client
+            !(tx.type == "withdrawal" && tx.amount > 1000.0)
+        }
+
+    Amounts are passed as floats for convenience -- fine for comparing against thresholds, but
+    the script never gets to change an amount, so no precision is lost in the ledger.
+
+    Lifecycle rows reach the script too, as type "open", "kyc_approved" or "kyc_rejected" with an
+    amount of 0.0, so a rule can e.g. refuse to open accounts for some clients.
+    */
+    pub struct ScriptRules {
+        engine: Engine,
+        ast: AST,
+    }
+
+    impl ScriptRules {
+        pub fn load(path: &Path) -> Result<ScriptRules, Box<dyn Error>> {
+            let engine = Engine::new();
+            let ast = engine.compile_file(path.into())?;
+
+            Ok(ScriptRules { engine, ast })
+        }
+
+        pub fn from_source(source: &str) -> Result<ScriptRules, Box<dyn Error>> {
+            let engine = Engine::new();
+            let ast = engine.compile(source)?;
+
+            Ok(ScriptRules { engine, ast })
+        }
+    }
+
+    impl AcceptanceRule for ScriptRules {
+        fn accept(
+            &self,
+            tenant: &str,
+            transaction: &TransactionRecord,
+            account: Option<&Account>,
+        ) -> bool {
+            let mut tx = Map::new();
+
+            tx.insert("type".into(), transaction.kind().into());
+            tx.insert(
+                "client".into(),
+                (transaction.id().client_id.0 as i64).into(),
+            );
+            tx.insert(
+                "tx".into(),
+                match transaction.id().transaction_id.number() {
+                    Some(number) => (number as i64).into(),
+                    None => transaction.id().transaction_id.to_string().into(),
+                },
+            );
+            tx.insert("amount".into(), float(transaction.amount()).into());
+            tx.insert("tenant".into(), tenant.into());
+
+            let account: Dynamic = match account {
+                Some(account) => {
+                    let mut state = Map::new();
+
+                    state.insert("available".into(), float(account.available()).into());
+                    state.insert("held".into(), float(account.held()).into());
+                    state.insert("locked".into(), account.is_locked().into());
+                    state.into()
+                }
+                None => Dynamic::UNIT,
+            };
+
+            self.engine
+                .call_fn::<bool>(&mut Scope::new(), &self.ast, "accept", (tx, account))
+                .unwrap_or_else(|error| panic!("Rules script failed: {}", error))
+        }
+    }
+
+    fn float(amount: Money) -> f64 {
+        amount.0 as f64 / 10000.0
+    }
+}
@@ -0,0 +1,352 @@
+use std::str::FromStr;
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::{
+    accounts::{Account, AccountSummary},
+    pseudonym::ClientLabel,
+    tenants::TenantAccountSummary,
+    Money,
+};
+
+/*
+The layout of the summary output.  Once a version is published its columns never change --
+new columns land in a new version, and downstream parsers pin the version they understand with
+`--schema-version`.
+
+v1: client_id, available, held, total, locked (with a leading tenant column for multi-tenant
+    input).
+v2: v1 plus near_cap, set for accounts within 5% of the balance cap.
+v3: v2 plus locked_reason, what locked the account (e.g. "chargeback of tx 4"), empty unless
+    it's locked.
+v4: v3 plus pending, deposits parked until the client clears its lifecycle gate -- neither
+    available nor held, and not part of the total.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum SchemaVersion {
+    #[default]
+    V1,
+    V2,
+    V3,
+    V4,
+}
+
+pub type AccountSummaryV1 = AccountSummary;
+
+pub type TenantAccountSummaryV1 = TenantAccountSummary;
+
+/*
+One account's summary in whichever layout the run writes, so every sink takes the same type.
+Untagged, it serializes exactly as the layout it holds.
+*/
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum SummaryRow {
+    V1(AccountSummaryV1),
+    TenantV1(TenantAccountSummaryV1),
+    V2(AccountSummaryV2),
+    TenantV2(TenantAccountSummaryV2),
+    V3(AccountSummaryV3),
+    TenantV3(TenantAccountSummaryV3),
+    V4(AccountSummaryV4),
+    TenantV4(TenantAccountSummaryV4),
+    Redacted(RedactedRow),
+}
+
+impl SummaryRow {
+    /*
+    One account's summary in the layout `schema` names, led by a tenant column if `tenant` is
+    given.  Columns the layout doesn't have are ignored.
+    */
+    pub fn new(
+        schema: SchemaVersion,
+        tenant: Option<&str>,
+        account: &Account,
+        near_cap: bool,
+        locked_reason: Option<&str>,
+        pending: Money,
+    ) -> SummaryRow {
+        match (schema, tenant) {
+            (SchemaVersion::V1, Some(tenant)) => SummaryRow::TenantV1((tenant, account).into()),
+            (SchemaVersion::V1, None) => SummaryRow::V1(account.into()),
+            (SchemaVersion::V2, Some(tenant)) => {
+                SummaryRow::TenantV2(TenantAccountSummaryV2::new(tenant, account, near_cap))
+            }
+            (SchemaVersion::V2, None) => SummaryRow::V2(AccountSummaryV2::new(account, near_cap)),
+            (SchemaVersion::V3, Some(tenant)) => SummaryRow::TenantV3(TenantAccountSummaryV3::new(
+                tenant,
+                account,
+                near_cap,
+                locked_reason,
+            )),
+            (SchemaVersion::V3, None) => {
+                SummaryRow::V3(AccountSummaryV3::new(account, near_cap, locked_reason))
+            }
+            (SchemaVersion::V4, Some(tenant)) => SummaryRow::TenantV4(TenantAccountSummaryV4::new(
+                tenant,
+                account,
+                near_cap,
+                locked_reason,
+                pending,
+            )),
+            (SchemaVersion::V4, None) => SummaryRow::V4(AccountSummaryV4::new(
+                account,
+                near_cap,
+                locked_reason,
+                pending,
+            )),
+        }
+    }
+
+    /*
+    The client id column, to pseudonymize whichever layout the row is in.
+    */
+    pub fn client_id_mut(&mut self) -> Option<&mut ClientLabel> {
+        match self {
+            SummaryRow::V1(summary) => Some(&mut summary.client_id),
+            SummaryRow::TenantV1(summary) => Some(&mut summary.client_id),
+            SummaryRow::V2(summary) => Some(&mut summary.client_id),
+            SummaryRow::TenantV2(summary) => Some(&mut summary.client_id),
+            SummaryRow::V3(summary) => Some(&mut summary.client_id),
+            SummaryRow::TenantV3(summary) => Some(&mut summary.client_id),
+            SummaryRow::V4(summary) => Some(&mut summary.client_id),
+            SummaryRow::TenantV4(summary) => Some(&mut summary.client_id),
+            SummaryRow::Redacted(row) => None,
+        }
+    }
+}
+
+/*
+Every column any version writes, in the order they're written.
+*/
+pub const SUMMARY_COLUMNS: [&str; 9] = [
+    "tenant",
+    "client_id",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "near_cap",
+    "locked_reason",
+    "pending",
+];
+
+/*
+A summary row after a sink's redaction -- see sinks::Redacted -- with the columns it kept, in
+the order of SUMMARY_COLUMNS.
+*/
+#[derive(PartialEq, Debug)]
+pub struct RedactedRow {
+    pub fields: Vec<(&'static str, Value)>,
+}
+
+impl Serialize for RedactedRow {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut row = serializer.serialize_struct("RedactedRow", self.fields.len())?;
+
+        for (name, value) in &self.fields {
+            row.serialize_field(name, value)?;
+        }
+
+        row.end()
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccountSummaryV2 {
+    pub client_id: ClientLabel,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
+    pub near_cap: bool,
+}
+
+#[derive(Serialize)]
+pub struct TenantAccountSummaryV2 {
+    pub tenant: String,
+    pub client_id: ClientLabel,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
+    pub near_cap: bool,
+}
+
+impl AccountSummaryV2 {
+    pub fn new(account: &Account, near_cap: bool) -> AccountSummaryV2 {
+        let summary: AccountSummaryV1 = account.into();
+
+        AccountSummaryV2 {
+            client_id: summary.client_id,
+            available: summary.available,
+            held: summary.held,
+            total: summary.total,
+            locked: summary.locked,
+            near_cap,
+        }
+    }
+}
+
+impl TenantAccountSummaryV2 {
+    pub fn new(tenant: &str, account: &Account, near_cap: bool) -> TenantAccountSummaryV2 {
+        let summary = AccountSummaryV2::new(account, near_cap);
+
+        TenantAccountSummaryV2 {
+            tenant: tenant.to_owned(),
+            client_id: summary.client_id,
+            available: summary.available,
+            held: summary.held,
+            total: summary.total,
+            locked: summary.locked,
+            near_cap: summary.near_cap,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccountSummaryV3 {
+    pub client_id: ClientLabel,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
+    pub near_cap: bool,
+    pub locked_reason: String,
+}
+
+#[derive(Serialize)]
+pub struct TenantAccountSummaryV3 {
+    pub tenant: String,
+    pub client_id: ClientLabel,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
+    pub near_cap: bool,
+    pub locked_reason: String,
+}
+
+impl AccountSummaryV3 {
+    pub fn new(account: &Account, near_cap: bool, locked_reason: Option<&str>) -> AccountSummaryV3 {
+        let summary = AccountSummaryV2::new(account, near_cap);
+
+        AccountSummaryV3 {
+            client_id: summary.client_id,
+            available: summary.available,
+            held: summary.held,
+            total: summary.total,
+            locked: summary.locked,
+            near_cap: summary.near_cap,
+            locked_reason: locked_reason.unwrap_or_default().to_owned(),
+        }
+    }
+}
+
+impl TenantAccountSummaryV3 {
+    pub fn new(
+        tenant: &str,
+        account: &Account,
+        near_cap: bool,
+        locked_reason: Option<&str>,
+    ) -> TenantAccountSummaryV3 {
+        let summary = AccountSummaryV3::new(account, near_cap, locked_reason);
+
+        TenantAccountSummaryV3 {
+            tenant: tenant.to_owned(),
+            client_id: summary.client_id,
+            available: summary.available,
+            held: summary.held,
+            total: summary.total,
+            locked: summary.locked,
+            near_cap: summary.near_cap,
+            locked_reason: summary.locked_reason,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccountSummaryV4 {
+    pub client_id: ClientLabel,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
+    pub near_cap: bool,
+    pub locked_reason: String,
+    pub pending: Money,
+}
+
+#[derive(Serialize)]
+pub struct TenantAccountSummaryV4 {
+    pub tenant: String,
+    pub client_id: ClientLabel,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
+    pub near_cap: bool,
+    pub locked_reason: String,
+    pub pending: Money,
+}
+
+impl AccountSummaryV4 {
+    pub fn new(
+        account: &Account,
+        near_cap: bool,
+        locked_reason: Option<&str>,
+        pending: Money,
+    ) -> AccountSummaryV4 {
+        let summary = AccountSummaryV3::new(account, near_cap, locked_reason);
+
+        AccountSummaryV4 {
+            client_id: summary.client_id,
+            available: summary.available,
+            held: summary.held,
+            total: summary.total,
+            locked: summary.locked,
+            near_cap: summary.near_cap,
+            locked_reason: summary.locked_reason,
+            pending,
+        }
+    }
+}
+
+impl TenantAccountSummaryV4 {
+    pub fn new(
+        tenant: &str,
+        account: &Account,
+        near_cap: bool,
+        locked_reason: Option<&str>,
+        pending: Money,
+    ) -> TenantAccountSummaryV4 {
+        let summary = AccountSummaryV4::new(account, near_cap, locked_reason, pending);
+
+        TenantAccountSummaryV4 {
+            tenant: tenant.to_owned(),
+            client_id: summary.client_id,
+            available: summary.available,
+            held: summary.held,
+            total: summary.total,
+            locked: summary.locked,
+            near_cap: summary.near_cap,
+            locked_reason: summary.locked_reason,
+            pending: summary.pending,
+        }
+    }
+}
+
+impl FromStr for SchemaVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches('v') {
+            "1" => Ok(SchemaVersion::V1),
+            "2" => Ok(SchemaVersion::V2),
+            "3" => Ok(SchemaVersion::V3),
+            "4" => Ok(SchemaVersion::V4),
+            _ => Err(()),
+        }
+    }
+}
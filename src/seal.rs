@@ -0,0 +1,103 @@
+use std::{error::Error, io};
+
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dates::Date,
+    tenants::TenantDatabase,
+    transactions::{ClientId, TransactionText},
+    Money,
+};
+
+/*
+One account as it stood when its period was closed.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct SealedBalance {
+    pub tenant: String,
+    pub client_id: ClientId,
+    pub available: Money,
+    pub held: Money,
+    pub locked: bool,
+}
+
+/*
+A closed accounting period: everything dated on or before `through`.  The ledger hash and the
+closing balances are what the books said at the close, for anyone who later needs to show they
+weren't changed.
+
+Once a period is sealed, a summarize run given the seal refuses any row dated within it -- the
+row is counted, and can be written to a bucket of late transactions to be booked as adjustments
+in an open period instead.  Undated rows can't be placed in a period, so they always apply.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct PeriodSeal {
+    pub through: String,
+    pub ledger_hash: String,
+    pub balances: Vec<SealedBalance>,
+}
+
+#[derive(Deserialize)]
+struct SealedPeriod {
+    through: String,
+}
+
+impl PeriodSeal {
+    /*
+    Seals the period `database` was built from, which should hold nothing dated after
+    `through`.
+    */
+    pub fn finalize(database: &TenantDatabase, through: Date) -> PeriodSeal {
+        let balances = database
+            .tenants()
+            .flat_map(|(tenant, accounts)| {
+                accounts.accounts().map(move |account| SealedBalance {
+                    tenant: tenant.to_owned(),
+                    client_id: account.client_id(),
+                    available: account.available(),
+                    held: account.held(),
+                    locked: account.is_locked(),
+                })
+            })
+            .collect();
+
+        PeriodSeal {
+            through: through.to_string(),
+            ledger_hash: database.ledger_hash(),
+            balances,
+        }
+    }
+}
+
+/*
+The last day of the period a seal closes -- all a later run needs from it.
+*/
+pub fn sealed_through<R: io::Read>(reader: R) -> Result<Date, Box<dyn Error>> {
+    let seal: SealedPeriod = serde_json::from_reader(reader)?;
+
+    seal.through
+        .parse()
+        .map_err(|_| format!("invalid date in seal: {}", seal.through).into())
+}
+
+/*
+Writes the rows refused for falling in a sealed period, exactly as they were read, so they can
+be reviewed and booked again.
+*/
+pub fn write_late_transactions<W: io::Write>(
+    database: &TenantDatabase,
+    writer: &mut Writer<W>,
+) -> csv::Result<()> {
+    writer.write_record(TransactionText::FIELDS)?;
+
+    for transaction in database.late_transactions() {
+        writer.write_record(
+            TransactionText::FIELDS.map(|field| transaction.field(field).unwrap_or_default()),
+        )?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use crate::{
+    accounts::{Account, AccountDatabase},
+    transactions::ClientId,
+    Money,
+};
+
+/*
+Which accounts a search returns.  Every condition that's set must hold; the default matches
+every account.  Balance bounds are on the account's total, and inclusive.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct AccountFilter {
+    pub locked_only: bool,
+    pub open_dispute_only: bool,
+    pub min_total: Option<Money>,
+    pub max_total: Option<Money>,
+}
+
+/*
+One page of a search.  `next` is the cursor for the page after it -- the last client id
+returned -- or None when there's nothing more.
+
+Pages are keyed by client id rather than by offset, so a client opened between two requests
+can't shift later pages and make a caller skip or repeat an account.
+*/
+#[derive(PartialEq, Debug)]
+pub struct AccountPage<'a> {
+    pub accounts: Vec<&'a Account>,
+    pub next: Option<ClientId>,
+}
+
+/*
+Finds up to `limit` accounts matching the filter, in client order, after the client id
+`after` (from the start without one).
+*/
+pub fn search<'a>(
+    database: &'a AccountDatabase,
+    filter: &AccountFilter,
+    after: Option<ClientId>,
+    limit: usize,
+) -> AccountPage<'a> {
+    let disputed = if filter.open_dispute_only {
+        database.clients_with_open_disputes()
+    } else {
+        Default::default()
+    };
+    let mut matching = database.accounts_after(after).filter(|account| {
+        let total = account.available() + account.held();
+
+        (!filter.locked_only || account.is_locked())
+            && (!filter.open_dispute_only || disputed.contains(&account.client_id()))
+            && filter.min_total.is_none_or(|min| total >= min)
+            && filter.max_total.is_none_or(|max| total <= max)
+    });
+    let accounts: Vec<_> = matching.by_ref().take(limit).collect();
+
+    /*
+    Only hand out a cursor if there really is another match, so callers can stop on None.
+    */
+    let next = match matching.next() {
+        Some(_) => accounts.last().map(|account| account.client_id()),
+        None => None,
+    };
+
+    AccountPage { accounts, next }
+}
@@ -0,0 +1,72 @@
+use crate::transactions::TxId;
+
+/*
+A Bloom filter over transaction ids, used to answer "has this id been recorded?" without
+touching the transaction index in the common case where it hasn't.
+
+A negative answer is always right.  A positive answer only means "maybe", so it has to be
+confirmed against the index -- the filter can speed up lookups, but never change their result.
+
+Sized for an expected number of ids at roughly a 1% false positive rate.  Inserting more than
+that still works, the filter just says "maybe" more often.
+*/
+pub struct SeenFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl SeenFilter {
+    pub fn with_capacity(expected: usize) -> SeenFilter {
+        /*
+        For a 1% false positive rate the optimal filter uses about 9.6 bits and 7 hash functions
+        per item.  Round up to 10 bits and whole words.
+        */
+        let words = (expected.max(1) * 10).div_ceil(64);
+
+        SeenFilter {
+            bits: vec![0; words],
+            hashes: 7,
+        }
+    }
+
+    pub fn insert(&mut self, transaction_id: TxId) {
+        for position in self.positions(transaction_id) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    pub fn may_contain(&self, transaction_id: TxId) -> bool {
+        self.positions(transaction_id)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+
+    /*
+    Double hashing: the k positions are h1 + i * h2, which behaves as well as k independent hash
+    functions for a Bloom filter.  Both halves come from one splitmix64 of the id, a UUID's
+    halves folded together first.
+    */
+    fn positions(&self, transaction_id: TxId) -> impl Iterator<Item = usize> {
+        let hash = match transaction_id {
+            TxId::Number(number) => splitmix64(number as u64),
+            TxId::Uuid(bytes) => {
+                let value = u128::from_be_bytes(bytes);
+
+                splitmix64((value >> 64) as u64 ^ splitmix64(value as u64))
+            }
+        };
+        let first = hash & 0xffff_ffff;
+        let second = (hash >> 32) | 1;
+        let length = self.bits.len() as u64 * 64;
+
+        (0..self.hashes as u64)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % length) as usize)
+    }
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, error::Error};
+
+/*
+Built with `--cfg loom`, the locks are loom's, so its model checker can drive them through every
+interleaving -- see tests/concurrency.rs.
+*/
+#[cfg(loom)]
+use loom::sync::{Arc, Mutex};
+#[cfg(not(loom))]
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    accounts::{AccountDatabase, AccountOptions},
+    transactions::{ClientId, TransactionRecord},
+};
+
+/*
+An account database for embedding applications that apply transactions from many threads at
+once.  Each client's account lives in its own database behind its own lock, so threads working
+on different clients never wait on each other, and a client's transactions are applied in the
+order they were handed over.
+
+The map of clients is only locked long enough to find (or open) a client's database, never
+while applying -- so a slow batch for one client can't hold up the rest.
+
+Since nothing is shared between clients, two checks the single-threaded database makes across
+the whole ledger are lost: a transaction id reused by another client isn't caught as a
+duplicate, and a dispute naming another client's transaction is simply ignored rather than
+recorded as a mismatch.
+*/
+pub struct SharedDatabase {
+    options: AccountOptions,
+    clients: Mutex<HashMap<ClientId, Arc<Mutex<AccountDatabase>>>>,
+}
+
+impl SharedDatabase {
+    pub fn with_options(options: AccountOptions) -> SharedDatabase {
+        SharedDatabase {
+            options,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /*
+    Applies a batch of one client's transactions, holding that client's lock for the whole
+    batch so no other thread's transactions for the client land in the middle of it.
+
+    Every record must be for `client_id`; if any isn't, nothing is applied.  Returns the number
+    of records applied.
+    */
+    pub fn apply_for_client(
+        &self,
+        client_id: ClientId,
+        transactions: impl IntoIterator<Item = TransactionRecord>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let transactions: Vec<_> = transactions.into_iter().collect();
+
+        if let Some(foreign) = transactions
+            .iter()
+            .find(|transaction| transaction.id().client_id != client_id)
+        {
+            return Err(format!(
+                "tx {} is for client {}, not client {}",
+                foreign.id().transaction_id,
+                foreign.id().client_id,
+                client_id
+            )
+            .into());
+        }
+
+        let client = self.client(client_id);
+        let mut database = client.lock().unwrap();
+
+        for transaction in &transactions {
+            database.apply(transaction);
+        }
+
+        Ok(transactions.len())
+    }
+
+    /*
+    Runs `f` on a client's database while holding its lock, or returns None if nothing has been
+    applied for the client yet.
+    */
+    pub fn with_client<R>(
+        &self,
+        client_id: ClientId,
+        f: impl FnOnce(&AccountDatabase) -> R,
+    ) -> Option<R> {
+        let client = self.clients.lock().unwrap().get(&client_id).cloned()?;
+        let database = client.lock().unwrap();
+
+        Some(f(&database))
+    }
+
+    pub fn client_ids(&self) -> Vec<ClientId> {
+        let mut client_ids: Vec<_> = self.clients.lock().unwrap().keys().copied().collect();
+
+        client_ids.sort();
+        client_ids
+    }
+
+    fn client(&self, client_id: ClientId) -> Arc<Mutex<AccountDatabase>> {
+        self.clients
+            .lock()
+            .unwrap()
+            .entry(client_id)
+            .or_insert_with(|| Arc::new(Mutex::new(AccountDatabase::with_options(self.options))))
+            .clone()
+    }
+}
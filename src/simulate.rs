@@ -0,0 +1,234 @@
+use std::{error::Error, io};
+
+use csv::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accounts::Balances,
+    dates::Date,
+    ledger::{LedgerEvent, Projection},
+    rounding::{Rounding, RoundingStrategy},
+    transactions::{ClientId, Id, TransactionRecord, TxId},
+    Money,
+};
+
+/*
+One row of a schedule file.  `every_days` is how often the transaction recurs, empty for one
+that happens just once on `start`.  For `interest`, `amount` is a percentage of the account's
+available funds, credited on each occurrence, and the optional `rounding` column overrides how
+it's rounded.
+
+    type,client,amount,start,every_days,rounding
+    deposit,1,2500,2024-04-01,30,
+    withdrawal,1,1800,2024-04-05,30,
+    interest,1,0.4,2024-04-30,30,half-even
+*/
+#[derive(Deserialize)]
+struct ScheduleRow {
+    #[serde(rename = "type")]
+    kind: String,
+    client: ClientId,
+    amount: String,
+    start: String,
+    every_days: Option<u32>,
+    #[serde(default)]
+    rounding: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ScheduledKind {
+    Deposit,
+    Withdrawal,
+    Interest,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ScheduledTransaction {
+    pub kind: ScheduledKind,
+    pub client_id: ClientId,
+    pub amount: Money,
+    pub start: Date,
+    pub every_days: Option<u32>,
+    pub rounding: Option<Rounding>,
+}
+
+pub struct SimulationOptions<'a> {
+    pub until: Date,
+    pub period_days: u32,
+    pub rounding: &'a dyn RoundingStrategy,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct ProjectedBalance {
+    pub date: String,
+    pub client_id: ClientId,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+}
+
+pub fn load_schedule<R: io::Read>(
+    reader: &mut Reader<R>,
+) -> Result<Vec<ScheduledTransaction>, Box<dyn Error>> {
+    let mut schedule = Vec::new();
+
+    for record_result in reader.deserialize() {
+        let row: ScheduleRow = record_result?;
+
+        schedule.push(ScheduledTransaction {
+            kind: match row.kind.as_str() {
+                "deposit" => ScheduledKind::Deposit,
+                "withdrawal" => ScheduledKind::Withdrawal,
+                "interest" => ScheduledKind::Interest,
+                kind => return Err(format!("unexpected type in schedule: {}", kind).into()),
+            },
+            client_id: row.client,
+            amount: row
+                .amount
+                .parse()
+                .map_err(|_| format!("invalid amount in schedule: {}", row.amount))?,
+            start: row
+                .start
+                .parse()
+                .map_err(|_| format!("invalid start date in schedule: {}", row.start))?,
+            every_days: row.every_days.filter(|&days| days > 0),
+            rounding: match row.rounding.as_deref() {
+                None | Some("") => None,
+                Some(text) => Some(
+                    text.parse()
+                        .map_err(|_| format!("invalid rounding in schedule: {}", text))?,
+                ),
+            },
+        });
+    }
+
+    Ok(schedule)
+}
+
+/*
+Projects every account in the ledger forward from `from` to `options.until`, by applying the scheduled transactions
+on top of the current balances with the engine's own rules -- so a scheduled withdrawal the
+account can't cover is refused, just as it would be for real.
+
+Emits each account's balance at the end of every `period_days` after `from`, and on `until`
+itself.  Only occurrences after `from` count; anything scheduled on or before it is taken to be
+in the ledger already.
+
+Interest is rounded to the smallest unit with `rounding`, unless its schedule row says
+otherwise, and compounds: it's worked out from the balance on the day it's paid.
+*/
+pub fn simulate(
+    events: &[LedgerEvent],
+    schedule: &[ScheduledTransaction],
+    from: Date,
+    options: &SimulationOptions,
+) -> Vec<ProjectedBalance> {
+    let SimulationOptions {
+        until,
+        period_days,
+        rounding,
+    } = *options;
+    let mut balances = Balances::new();
+    let mut occurrences = Vec::new();
+    let mut projection = Vec::new();
+
+    balances.replay(events);
+
+    for scheduled in schedule {
+        let mut date = scheduled.start;
+
+        while date <= until {
+            if date > from {
+                occurrences.push((date, scheduled));
+            }
+
+            match scheduled.every_days {
+                Some(days) => date = date.plus_days(days as i32),
+                None => break,
+            }
+        }
+    }
+
+    /*
+    Stable, so occurrences on the same day apply in schedule order.
+    */
+    occurrences.sort_by_key(|&(date, _)| date);
+
+    let mut occurrences = occurrences.into_iter().peekable();
+    let mut period_end = from;
+
+    while period_end < until {
+        period_end = period_end.plus_days(period_days as i32).min(until);
+
+        while let Some((date, scheduled)) = occurrences.next_if(|&(date, _)| date <= period_end) {
+            apply_scheduled(&mut balances, scheduled, date, rounding);
+        }
+
+        for account in balances.accounts() {
+            projection.push(ProjectedBalance {
+                date: period_end.to_string(),
+                client_id: account.client_id(),
+                available: account.available().to_string(),
+                held: account.held().to_string(),
+                total: (account.available() + account.held()).to_string(),
+            });
+        }
+    }
+
+    projection
+}
+
+/*
+Scheduled transactions aren't real ones, so they don't get ids of their own -- the projection
+never looks a transaction up again.
+*/
+fn apply_scheduled(
+    balances: &mut Balances,
+    scheduled: &ScheduledTransaction,
+    date: Date,
+    rounding: &dyn RoundingStrategy,
+) {
+    let id = Id {
+        client_id: scheduled.client_id,
+        transaction_id: TxId::Number(0),
+    };
+    let transaction = match scheduled.kind {
+        ScheduledKind::Deposit => TransactionRecord::Deposit {
+            id,
+            amount: scheduled.amount,
+        },
+        ScheduledKind::Withdrawal => TransactionRecord::Withdrawl {
+            id,
+            amount: scheduled.amount,
+        },
+        ScheduledKind::Interest => TransactionRecord::Deposit {
+            id,
+            amount: interest(
+                balances
+                    .get(scheduled.client_id)
+                    .map_or(Money::zero(), |account| account.available()),
+                scheduled.amount,
+                match &scheduled.rounding {
+                    Some(rounding) => rounding,
+                    None => rounding,
+                },
+            ),
+        },
+    };
+
+    balances.project(&LedgerEvent::AccountOpened {
+        client_id: scheduled.client_id,
+    });
+    balances.project(&LedgerEvent::TransactionApplied {
+        transaction,
+        disputed_amount: Money::zero(),
+        date: Some(date),
+    });
+}
+
+/*
+`rate` is a percentage, so both it and `balance` carry four decimal places of their own.
+*/
+pub fn interest(balance: Money, rate: Money, rounding: &dyn RoundingStrategy) -> Money {
+    Money(rounding.divide(balance.0 as u128 * rate.0 as u128, 1_000_000) as u64)
+}
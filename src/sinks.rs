@@ -0,0 +1,296 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use csv::Writer;
+use serde_json::{Map, Value};
+
+use crate::{
+    pseudonym::Pseudonymizer,
+    schema::{RedactedRow, SummaryRow, SUMMARY_COLUMNS},
+    transactions::ClientId,
+};
+
+/*
+Somewhere the summary goes, one account at a time.  A CSV writer is one, which is how a run with
+no --sink writes its summary to stdout.
+*/
+pub trait SummarySink {
+    fn write(&mut self, row: &SummaryRow) -> Result<(), Box<dyn Error>>;
+
+    /*
+    Called once, after the last row.
+    */
+    fn finish(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+impl<W: io::Write> SummarySink for Writer<W> {
+    fn write(&mut self, row: &SummaryRow) -> Result<(), Box<dyn Error>> {
+        self.serialize(row)?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+
+        Ok(())
+    }
+}
+
+/*
+One JSON object per account, per line, with the same fields as the CSV columns.
+*/
+pub struct JsonLines<W: io::Write> {
+    writer: W,
+}
+
+impl<W: io::Write> JsonLines<W> {
+    pub fn new(writer: W) -> JsonLines<W> {
+        JsonLines { writer }
+    }
+}
+
+impl<W: io::Write> SummarySink for JsonLines<W> {
+    fn write(&mut self, row: &SummaryRow) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer(&mut self.writer, row)?;
+        self.writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/*
+Every sink fed the same rows, in the order they were configured.
+*/
+#[derive(Default)]
+pub struct FanOut {
+    sinks: Vec<Box<dyn SummarySink>>,
+}
+
+impl FanOut {
+    pub fn new() -> FanOut {
+        FanOut::default()
+    }
+
+    pub fn push(&mut self, sink: Box<dyn SummarySink>) {
+        self.sinks.push(sink);
+    }
+}
+
+impl SummarySink for FanOut {
+    fn write(&mut self, row: &SummaryRow) -> Result<(), Box<dyn Error>> {
+        for sink in &mut self.sinks {
+            sink.write(row)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        for sink in &mut self.sinks {
+            sink.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/*
+What one sink leaves out of the summary, so an analytics sink can get pseudonymized client ids
+and no held amounts while the ops sink gets everything.
+*/
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Redaction {
+    pub pseudonymize: bool,
+    pub drop: Vec<&'static str>,
+}
+
+impl Redaction {
+    pub fn is_empty(&self) -> bool {
+        !self.pseudonymize && self.drop.is_empty()
+    }
+}
+
+/*
+A sink behind a redaction: rows are rewritten before the sink sees them.  Client ids are
+pseudonymized as --pseudonymize would, so tokens still join across outputs made with the same
+salt; ids already pseudonymized are left as they are.
+*/
+pub struct Redacted {
+    sink: Box<dyn SummarySink>,
+    pseudonymizer: Option<Pseudonymizer>,
+    drop: Vec<&'static str>,
+}
+
+impl Redacted {
+    pub fn new(
+        sink: Box<dyn SummarySink>,
+        pseudonymizer: Option<Pseudonymizer>,
+        drop: Vec<&'static str>,
+    ) -> Redacted {
+        Redacted {
+            sink,
+            pseudonymizer,
+            drop,
+        }
+    }
+
+    pub fn redact(&self, row: &SummaryRow) -> Result<RedactedRow, Box<dyn Error>> {
+        let mut fields: Map<String, Value> = serde_json::from_value(serde_json::to_value(row)?)?;
+
+        if let Some(pseudonymizer) = &self.pseudonymizer {
+            let tenant = fields
+                .get("tenant")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned();
+            let client_id = fields
+                .get("client_id")
+                .and_then(Value::as_u64)
+                .and_then(|client_id| u16::try_from(client_id).ok());
+
+            if let Some(client_id) = client_id {
+                fields.insert(
+                    "client_id".to_owned(),
+                    Value::String(pseudonymizer.token(&tenant, ClientId(client_id))),
+                );
+            }
+        }
+
+        Ok(RedactedRow {
+            fields: SUMMARY_COLUMNS
+                .into_iter()
+                .filter(|name| !self.drop.contains(name))
+                .filter_map(|name| fields.remove(name).map(|value| (name, value)))
+                .collect(),
+        })
+    }
+}
+
+impl SummarySink for Redacted {
+    fn write(&mut self, row: &SummaryRow) -> Result<(), Box<dyn Error>> {
+        let row = self.redact(row)?;
+
+        self.sink.write(&SummaryRow::Redacted(row))
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        self.sink.finish()
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SinkFormat {
+    Csv,
+    Json,
+}
+
+/*
+A sink as given to --sink: `<format>:<path>`, where a path of `-` is stdout.  The format can
+carry the sink's redaction in brackets, as in `json[pseudonymize,drop=held]:analytics.jsonl`:
+`pseudonymize` for client ids, and `drop=<column>` for each column to leave out.
+*/
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SinkSpec {
+    pub format: SinkFormat,
+    pub path: PathBuf,
+    pub redaction: Redaction,
+}
+
+impl SinkSpec {
+    pub fn is_stdout(&self) -> bool {
+        self.path.as_os_str() == "-"
+    }
+
+    /*
+    `salt` is what a pseudonymizing sink keys its tokens with.
+    */
+    pub fn open(&self, salt: Option<&str>) -> Result<Box<dyn SummarySink>, Box<dyn Error>> {
+        let output: Box<dyn io::Write> = if self.is_stdout() {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(&self.path)?))
+        };
+
+        let sink: Box<dyn SummarySink> = match self.format {
+            SinkFormat::Csv => Box::new(Writer::from_writer(output)),
+            SinkFormat::Json => Box::new(JsonLines::new(output)),
+        };
+
+        if self.redaction.is_empty() {
+            return Ok(sink);
+        }
+
+        let pseudonymizer = match (self.redaction.pseudonymize, salt) {
+            (true, Some(salt)) => Some(Pseudonymizer::new(salt)),
+            (true, None) => return Err("a pseudonymizing sink requires --salt".into()),
+            (false, _) => None,
+        };
+
+        Ok(Box::new(Redacted::new(
+            sink,
+            pseudonymizer,
+            self.redaction.drop.clone(),
+        )))
+    }
+}
+
+/*
+Parquet, database and HTTP sinks would each need a client library the build doesn't carry --
+--export-db already covers the database -- so only CSV and JSON are accepted.
+*/
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once(':')
+            .filter(|(format, path)| !path.is_empty())
+            .ok_or_else(|| format!("a sink is <format>:<path>, not {}", s))?;
+        let (format, options) = match format.split_once('[') {
+            Some((format, options)) => (
+                format,
+                options
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("unclosed sink options: {}", s))?,
+            ),
+            None => (format, ""),
+        };
+        let format = match format {
+            "csv" => SinkFormat::Csv,
+            "json" => SinkFormat::Json,
+            _ => return Err(format!("unsupported sink format: {} (csv or json)", format)),
+        };
+        let mut redaction = Redaction::default();
+
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                None if option == "pseudonymize" => redaction.pseudonymize = true,
+                Some(("drop", column)) => redaction.drop.push(
+                    SUMMARY_COLUMNS
+                        .into_iter()
+                        .find(|&name| name == column)
+                        .ok_or_else(|| format!("no summary column named {}", column))?,
+                ),
+                _ => return Err(format!("unknown sink option: {}", option)),
+            }
+        }
+
+        Ok(SinkSpec {
+            format,
+            path: PathBuf::from(path),
+            redaction,
+        })
+    }
+}
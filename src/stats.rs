@@ -0,0 +1,401 @@
+use std::{collections::HashMap, io};
+
+use csv::Writer;
+use serde::Serialize;
+
+use crate::{
+    accounts::{AccountDatabase, ApplyOutcome},
+    dates::Date,
+    errors::ErrorCode,
+    latency::{ApplyLatencies, LatencySummary},
+    ledger::LedgerEvent,
+    merkle::BalanceTree,
+    perf::Allocations,
+    tenants::TenantDatabase,
+    transactions::{ClientId, TransactionRecord, TxId},
+    Money,
+};
+
+/*
+A machine readable summary of a run, written alongside the account summaries when asked for.
+Counts cover every tenant in the input.
+*/
+#[derive(Serialize, PartialEq, Eq, Debug, Default)]
+pub struct RunStats {
+    pub transactions: usize,
+    pub accounts: usize,
+    pub changed: usize,
+    pub erased_funds: String,
+
+    /*
+    Disputes, resolves and chargebacks ignored because they named a client we'd never seen.
+    */
+    pub unknown_client_references: usize,
+
+    /*
+    Disputes, resolves and chargebacks rejected for naming a different client than the
+    transaction they reference.
+    */
+    pub client_mismatches: usize,
+
+    /*
+    Deposits and withdrawals rejected for reusing an earlier transaction's id with a different
+    type, client or amount.
+    */
+    pub conflicting_duplicates: usize,
+
+    /*
+    Transactions the account's funds only partly covered, or didn't cover at all -- see
+    accounts::ApplyOutcome.
+    */
+    pub partially_applied: usize,
+    pub refused_for_funds: usize,
+
+    /*
+    Disputes, resolves and chargebacks carrying an amount they shouldn't have, when asked to
+    warn about or reject those.
+    */
+    pub unexpected_dispute_amounts: usize,
+
+    pub rejected_by_rules: usize,
+
+    /*
+    Deposits rejected or trimmed for taking an account over the balance cap.
+    */
+    pub capped_deposits: usize,
+
+    /*
+    Deposits rejected for taking a group of linked accounts over the group cap.
+    */
+    pub group_capped_deposits: usize,
+
+    /*
+    Adjustments still waiting for approval at the end of the run.
+    */
+    pub pending_adjustments: usize,
+
+    /*
+    Deposits and withdrawals rejected for arriving before their client was cleared, and those
+    still parked waiting for it at the end of the run -- see accounts::LifecyclePolicy.
+    */
+    pub gated_transactions: usize,
+    pub parked_transactions: usize,
+
+    /*
+    Rows refused for being dated within a sealed period.
+    */
+    pub sealed_transactions: usize,
+
+    /*
+    Times an account fell below the low-balance threshold, and withdrawals refused while one
+    was held there -- see accounts::LowBalanceResponse.
+    */
+    pub low_balance_events: usize,
+    pub held_withdrawals: usize,
+
+    /*
+    Exposure to disputes still open at the end of the run: everything held, how many disputes
+    are holding it, and the most any one of them holds.
+    */
+    pub held_funds: String,
+    pub open_disputes: usize,
+    pub largest_held_amount: String,
+
+    /*
+    In days, as of the latest date in each tenant's ledger.  Only dated disputes count, so this
+    is None if there are none.
+    */
+    pub average_dispute_age_days: Option<String>,
+
+    /*
+    See ledger::LedgerHash: two runs over the same input report the same hash exactly when they
+    accepted the same transactions, in the same order, for the same amounts.
+    */
+    pub ledger_hash: String,
+
+    /*
+    The root of the merkle::BalanceTree over every account at the end of the run, for `prove`
+    to produce proofs against.
+    */
+    pub balance_root: String,
+
+    /*
+    What reading and applying the input allocated, on the thread that did it.  Only counted
+    when built with the perf feature -- see perf::CountingAllocator.
+    */
+    pub allocations: Option<Allocations>,
+
+    /*
+    Apply latency by transaction type and outcome, when the run was asked to record it with
+    --latencies -- see latency::ApplyLatencies.
+    */
+    pub apply_latencies: Option<Vec<LatencySummary>>,
+}
+
+#[derive(Serialize)]
+struct ClientMismatchRow<'a> {
+    code: ErrorCode,
+    tenant: &'a str,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    tx: TxId,
+    original_client_id: ClientId,
+    claimed_client_id: ClientId,
+}
+
+#[derive(Serialize)]
+struct ConflictingDuplicateRow<'a> {
+    code: ErrorCode,
+    tenant: &'a str,
+    tx: TxId,
+    original_type: &'a str,
+    original_client_id: ClientId,
+    original_amount: String,
+    duplicate_type: &'a str,
+    duplicate_client_id: ClientId,
+    duplicate_amount: String,
+}
+
+#[derive(Serialize)]
+struct ShortfallRow<'a> {
+    code: ErrorCode,
+    tenant: &'a str,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    client_id: ClientId,
+    tx: TxId,
+    outcome: &'a str,
+    requested: String,
+    applied: String,
+}
+
+impl RunStats {
+    pub fn collect(database: &TenantDatabase, transactions: usize) -> RunStats {
+        let accounts = || {
+            database
+                .tenants()
+                .flat_map(|(_, accounts)| accounts.accounts())
+        };
+        let shortfalls = || {
+            database
+                .tenants()
+                .flat_map(|(_, accounts)| accounts.shortfalls())
+        };
+
+        RunStats {
+            transactions,
+            accounts: accounts().count(),
+            changed: accounts().filter(|account| account.is_changed()).count(),
+            erased_funds: database
+                .tenants()
+                .map(|(_, accounts)| accounts.erased())
+                .sum::<Money>()
+                .to_string(),
+            unknown_client_references: database
+                .tenants()
+                .map(|(_, accounts)| accounts.unknown_client_references())
+                .sum(),
+            client_mismatches: database
+                .tenants()
+                .map(|(_, accounts)| accounts.client_mismatches().len())
+                .sum(),
+            conflicting_duplicates: database
+                .tenants()
+                .map(|(_, accounts)| accounts.conflicting_duplicates().len())
+                .sum(),
+            partially_applied: shortfalls()
+                .filter(|shortfall| matches!(shortfall.outcome, ApplyOutcome::Partial(_)))
+                .count(),
+            refused_for_funds: shortfalls()
+                .filter(|shortfall| shortfall.outcome == ApplyOutcome::Refused)
+                .count(),
+            unexpected_dispute_amounts: database
+                .tenants()
+                .map(|(_, accounts)| accounts.unexpected_dispute_amounts())
+                .sum(),
+            rejected_by_rules: database.rejected_by_rule(),
+            capped_deposits: database
+                .tenants()
+                .map(|(_, accounts)| accounts.capped_deposits())
+                .sum(),
+            group_capped_deposits: database.rejected_by_group_cap(),
+            pending_adjustments: database
+                .tenants()
+                .map(|(_, accounts)| accounts.pending_adjustments())
+                .sum(),
+            gated_transactions: database
+                .tenants()
+                .map(|(_, accounts)| accounts.gated_transactions())
+                .sum(),
+            parked_transactions: database
+                .tenants()
+                .map(|(_, accounts)| accounts.parked_transactions())
+                .sum(),
+            sealed_transactions: database.late_transactions().len(),
+            low_balance_events: database
+                .tenants()
+                .map(|(_, accounts)| accounts.low_balance_events())
+                .sum(),
+            held_withdrawals: database
+                .tenants()
+                .map(|(_, accounts)| accounts.held_withdrawals())
+                .sum(),
+            held_funds: accounts()
+                .map(|account| account.held())
+                .sum::<Money>()
+                .to_string(),
+            open_disputes: database
+                .tenants()
+                .map(|(_, accounts)| accounts.open_disputes().count())
+                .sum(),
+            largest_held_amount: database
+                .tenants()
+                .flat_map(|(_, accounts)| accounts.open_disputes())
+                .map(|(_, amount)| amount)
+                .max()
+                .unwrap_or(Money::zero())
+                .to_string(),
+            average_dispute_age_days: average(
+                database
+                    .tenants()
+                    .flat_map(|(_, accounts)| dispute_ages(accounts))
+                    .collect(),
+            ),
+            ledger_hash: database.ledger_hash(),
+            balance_root: BalanceTree::build(database).root(),
+            allocations: None,
+            apply_latencies: database.latencies().map(ApplyLatencies::summaries),
+        }
+    }
+}
+
+/*
+The age in days of each open dispute we know the date of.  A dispute that was resolved and
+then raised again is as old as its latest raising.
+*/
+fn dispute_ages(accounts: &AccountDatabase) -> Vec<i32> {
+    let mut raised: HashMap<TxId, Date> = HashMap::new();
+    let mut latest = None;
+
+    for event in accounts.events() {
+        let LedgerEvent::TransactionApplied {
+            transaction,
+            date: Some(date),
+            ..
+        } = *event
+        else {
+            continue;
+        };
+
+        latest = latest.max(Some(date));
+
+        if let TransactionRecord::Dispute { id, amount } = transaction {
+            raised.insert(id.transaction_id, date);
+        }
+    }
+
+    let Some(latest) = latest else {
+        return Vec::new();
+    };
+
+    accounts
+        .open_disputes()
+        .filter_map(|(transaction_id, _)| raised.get(&transaction_id))
+        .map(|&date| latest.days_since(date))
+        .collect()
+}
+
+fn average(ages: Vec<i32>) -> Option<String> {
+    if ages.is_empty() {
+        return None;
+    }
+
+    let total: i64 = ages.iter().map(|&age| age as i64).sum();
+
+    Some(format!("{:.1}", total as f64 / ages.len() as f64))
+}
+
+/*
+One row per rejected client id mismatch, in the order they were seen, naming both the client
+that owns the referenced transaction and the one the row claimed.
+*/
+pub fn write_client_mismatches<W: io::Write>(
+    database: &TenantDatabase,
+    writer: &mut Writer<W>,
+) -> csv::Result<()> {
+    for (tenant, accounts) in database.tenants() {
+        for mismatch in accounts.client_mismatches() {
+            writer.serialize(ClientMismatchRow {
+                code: ErrorCode::ClientMismatch,
+                tenant,
+                kind: mismatch.kind,
+                tx: mismatch.transaction_id,
+                original_client_id: mismatch.original_client_id,
+                claimed_client_id: mismatch.claimed_client_id,
+            })?;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+One row per conflicting duplicate, in the order they were seen, with the version that was kept
+alongside the one that was rejected.
+*/
+pub fn write_conflicting_duplicates<W: io::Write>(
+    database: &TenantDatabase,
+    writer: &mut Writer<W>,
+) -> csv::Result<()> {
+    for (tenant, accounts) in database.tenants() {
+        for conflict in accounts.conflicting_duplicates() {
+            writer.serialize(ConflictingDuplicateRow {
+                code: ErrorCode::DuplicateTx,
+                tenant,
+                tx: conflict.original.id().transaction_id,
+                original_type: conflict.original.kind(),
+                original_client_id: conflict.original.id().client_id,
+                original_amount: conflict.original.amount().to_string(),
+                duplicate_type: conflict.duplicate.kind(),
+                duplicate_client_id: conflict.duplicate.id().client_id,
+                duplicate_amount: conflict.duplicate.amount().to_string(),
+            })?;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/*
+One row per transaction the account's funds didn't fully cover, in the order they were applied.
+*/
+pub fn write_shortfalls<W: io::Write>(
+    database: &TenantDatabase,
+    writer: &mut Writer<W>,
+) -> csv::Result<()> {
+    for (tenant, accounts) in database.tenants() {
+        for shortfall in accounts.shortfalls() {
+            writer.serialize(ShortfallRow {
+                code: match shortfall.outcome {
+                    ApplyOutcome::Refused => ErrorCode::InsufficientFunds,
+                    _ => ErrorCode::PartiallyCovered,
+                },
+                tenant,
+                kind: shortfall.transaction.kind(),
+                client_id: shortfall.transaction.id().client_id,
+                tx: shortfall.transaction.id().transaction_id,
+                outcome: shortfall.outcome.name(),
+                requested: shortfall.requested.to_string(),
+                applied: shortfall.applied().to_string(),
+            })?;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    accounts::Balances,
+    dates::Date,
+    ledger::{LedgerEvent, Projection},
+    pseudonym::ClientLabel,
+    transactions::{ClientId, TransactionRecord},
+};
+
+/*
+Every change in an account's status, and what caused it, so support can tell why an account is
+locked rather than just that it is.
+
+Like DailyBalances, this replays the ledger through its own copy of the Balances projection and
+compares each account's status before and after every transaction.
+*/
+pub struct StatusHistory {
+    balances: Balances,
+    changes: Vec<StatusChange>,
+
+    /*
+    Index into `changes` of each client's most recent change.
+    */
+    latest: BTreeMap<ClientId, usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusChange {
+    pub client_id: ClientId,
+    pub date: Option<Date>,
+    pub from: &'static str,
+    pub to: &'static str,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct StatusChangeSummary {
+    pub client_id: ClientLabel,
+    date: String,
+    from: &'static str,
+    to: &'static str,
+    reason: String,
+}
+
+impl StatusHistory {
+    pub fn new() -> StatusHistory {
+        StatusHistory {
+            balances: Balances::new(),
+            changes: Vec::new(),
+            latest: BTreeMap::new(),
+        }
+    }
+
+    pub fn changes(
+        &self,
+        client_id: Option<ClientId>,
+    ) -> impl Iterator<Item = StatusChangeSummary> + '_ {
+        self.changes
+            .iter()
+            .filter(move |change| client_id.is_none_or(|client_id| client_id == change.client_id))
+            .map(|change| StatusChangeSummary {
+                client_id: ClientLabel::Id(change.client_id),
+                date: change.date.map(|date| date.to_string()).unwrap_or_default(),
+                from: change.from,
+                to: change.to,
+                reason: change.reason.clone(),
+            })
+    }
+
+    /*
+    Why the account is locked, if it currently is.
+    */
+    pub fn locked_reason(&self, client_id: ClientId) -> Option<&str> {
+        self.latest
+            .get(&client_id)
+            .map(|&index| &self.changes[index])
+            .filter(|change| change.to == "locked")
+            .map(|change| change.reason.as_str())
+    }
+}
+
+impl Projection for StatusHistory {
+    fn project(&mut self, event: &LedgerEvent) {
+        let (client_id, date, reason) = match *event {
+            LedgerEvent::TransactionApplied {
+                transaction, date, ..
+            } => (transaction.id().client_id, date, reason(&transaction)),
+            LedgerEvent::LowBalance {
+                client_id,
+                transaction_id,
+                available,
+                response,
+                date,
+            } => (
+                client_id,
+                date,
+                format!("low balance after tx {}", transaction_id),
+            ),
+            _ => {
+                self.balances.project(event);
+                return;
+            }
+        };
+        let status = |balances: &Balances| {
+            balances
+                .get(client_id)
+                .map(|account| account.status().name())
+        };
+        let before = status(&self.balances);
+
+        self.balances.project(event);
+
+        let (Some(from), Some(to)) = (before, status(&self.balances)) else {
+            return;
+        };
+
+        if from != to {
+            self.latest.insert(client_id, self.changes.len());
+            self.changes.push(StatusChange {
+                client_id,
+                date,
+                from,
+                to,
+                reason,
+            });
+        }
+    }
+}
+
+fn reason(transaction: &TransactionRecord) -> String {
+    match transaction {
+        TransactionRecord::Chargeback { id, amount } => {
+            format!("chargeback of tx {}", id.transaction_id)
+        }
+        TransactionRecord::Tombstone { id, amount } => "erased".to_owned(),
+        _ => format!(
+            "{} tx {}",
+            transaction.kind(),
+            transaction.id().transaction_id
+        ),
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::BTreeMap, time::Instant};
+
+use serde::Serialize;
+
+use crate::{
+    accounts::{Account, AccountDatabase, AccountOptions, AccountSummary, ApplyOutcome},
+    dates::Date,
+    groups::GroupCap,
+    latency::ApplyLatencies,
+    ledger::{LedgerEvent, LedgerHash, Projection},
+    pseudonym::ClientLabel,
+    rules::AcceptanceRule,
+    transactions::{TransactionRecord, TransactionText},
+    Money,
+};
+
+/*
+Each tenant gets a completely independent AccountDatabase, so client ids and transaction ids
+only need to be unique within a tenant.  A dispute in one tenant can never reach a deposit
+recorded under another, even if the ids happen to line up.
+
+Inputs without a tenant column all land in the default (empty) tenant.
+*/
+pub struct TenantDatabase {
+    tenants: BTreeMap<String, AccountDatabase>,
+
+    /*
+    Every tenant's database is created with these.
+    */
+    options: AccountOptions,
+
+    rule: Option<Box<dyn AcceptanceRule>>,
+
+    /*
+    Transactions the acceptance rule turned away.
+    */
+    rejected_by_rule: usize,
+
+    group_cap: Option<GroupCap>,
+
+    /*
+    Deposits rejected for taking a group of accounts over its cap.
+    */
+    rejected_by_group_cap: usize,
+
+    /*
+    The last day of a sealed period, and the rows refused for being dated within it -- see
+    seal::PeriodSeal.  The check is made on input rows, before they're parsed.
+    */
+    sealed_through: Option<Date>,
+    late: Vec<TransactionText>,
+
+    /*
+    How long each transaction took to apply, once asked to keep track.
+    */
+    latencies: Option<ApplyLatencies>,
+}
+
+#[derive(Serialize)]
+pub struct TenantAccountSummary {
+    tenant: String,
+    pub client_id: ClientLabel,
+    available: Money,
+    held: Money,
+    total: Money,
+    locked: bool,
+}
+
+impl From<(&str, &Account)> for TenantAccountSummary {
+    fn from((tenant, account): (&str, &Account)) -> TenantAccountSummary {
+        let summary: AccountSummary = account.into();
+
+        TenantAccountSummary {
+            tenant: tenant.to_owned(),
+            client_id: summary.client_id,
+            available: summary.available,
+            held: summary.held,
+            total: summary.total,
+            locked: summary.locked,
+        }
+    }
+}
+
+impl TenantDatabase {
+    pub fn new() -> TenantDatabase {
+        TenantDatabase {
+            tenants: BTreeMap::new(),
+            options: AccountOptions::default(),
+            rule: None,
+            rejected_by_rule: 0,
+            group_cap: None,
+            rejected_by_group_cap: 0,
+            sealed_through: None,
+            late: Vec::new(),
+            latencies: None,
+        }
+    }
+
+    pub fn with_options(options: AccountOptions) -> TenantDatabase {
+        TenantDatabase {
+            options,
+            ..TenantDatabase::new()
+        }
+    }
+
+    pub fn options(&self) -> AccountOptions {
+        self.options
+    }
+
+    pub fn set_rule(&mut self, rule: Box<dyn AcceptanceRule>) {
+        self.rule = Some(rule);
+    }
+
+    pub fn set_group_cap(&mut self, group_cap: GroupCap) {
+        self.group_cap = Some(group_cap);
+    }
+
+    pub fn record_latencies(&mut self) {
+        self.latencies = Some(ApplyLatencies::new());
+    }
+
+    pub fn latencies(&self) -> Option<&ApplyLatencies> {
+        self.latencies.as_ref()
+    }
+
+    pub fn set_sealed_through(&mut self, through: Date) {
+        self.sealed_through = Some(through);
+    }
+
+    pub fn is_sealed(&self, date: Date) -> bool {
+        self.sealed_through.is_some_and(|through| date <= through)
+    }
+
+    pub fn refuse_late(&mut self, transaction: TransactionText) {
+        self.late.push(transaction);
+    }
+
+    pub fn late_transactions(&self) -> &[TransactionText] {
+        &self.late
+    }
+
+    pub fn apply(&mut self, tenant: &str, transaction: &TransactionRecord, date: Option<Date>) {
+        self.apply_with_memo(tenant, transaction, date, None);
+    }
+
+    pub fn apply_with_memo(
+        &mut self,
+        tenant: &str,
+        transaction: &TransactionRecord,
+        date: Option<Date>,
+        memo: Option<&str>,
+    ) {
+        if self.latencies.is_none() {
+            self.apply_untimed(tenant, transaction, date, memo);
+            return;
+        }
+
+        let started = Instant::now();
+        let outcome = self.apply_untimed(tenant, transaction, date, memo);
+        let elapsed = started.elapsed();
+
+        if let Some(latencies) = &mut self.latencies {
+            latencies.record(transaction.kind(), outcome, elapsed);
+        }
+    }
+
+    /*
+    What became of the transaction: `applied`, `partial` or `refused` as its account's funds
+    allowed (see accounts::ApplyOutcome), or `rejected` if it never made it into the ledger.
+    */
+    fn apply_untimed(
+        &mut self,
+        tenant: &str,
+        transaction: &TransactionRecord,
+        date: Option<Date>,
+        memo: Option<&str>,
+    ) -> &'static str {
+        if let Some(rule) = &self.rule {
+            let account = self
+                .tenants
+                .get(tenant)
+                .and_then(|accounts| accounts.account(transaction.id().client_id));
+
+            if !rule.accept(tenant, transaction, account) {
+                self.rejected_by_rule += 1;
+                return "rejected";
+            }
+        }
+
+        if let (Some(group_cap), TransactionRecord::Deposit { id, amount }) =
+            (&self.group_cap, transaction)
+        {
+            if !group_cap.allows(self.tenants.get(tenant), id.client_id, *amount) {
+                self.rejected_by_group_cap += 1;
+                return "rejected";
+            }
+        }
+
+        let options = self.options;
+        let accounts = self
+            .tenants
+            .entry(tenant.to_owned())
+            .or_insert_with(|| AccountDatabase::with_options(options));
+        let events = accounts.events().len();
+        let shortfalls = accounts.shortfalls().len();
+
+        accounts.apply_with_memo(transaction, date, memo);
+
+        let applied = accounts.events()[events..]
+            .iter()
+            .any(|event| matches!(event, LedgerEvent::TransactionApplied { .. }));
+
+        match accounts.shortfalls()[shortfalls..].last() {
+            _ if !applied => "rejected",
+            Some(shortfall) if shortfall.outcome == ApplyOutcome::Refused => "refused",
+            Some(shortfall) => "partial",
+            None => "applied",
+        }
+    }
+
+    pub fn index(
+        &mut self,
+        tenant: &str,
+        transaction: &TransactionRecord,
+        disputed_amount: Option<Money>,
+    ) {
+        let options = self.options;
+
+        self.tenants
+            .entry(tenant.to_owned())
+            .or_insert_with(|| AccountDatabase::with_options(options))
+            .index(transaction, disputed_amount);
+    }
+
+    pub fn clear_changes(&mut self) {
+        for accounts in self.tenants.values_mut() {
+            accounts.clear_changes();
+        }
+    }
+
+    pub fn rejected_by_rule(&self) -> usize {
+        self.rejected_by_rule
+    }
+
+    pub fn rejected_by_group_cap(&self) -> usize {
+        self.rejected_by_group_cap
+    }
+
+    pub fn tenant(&self, tenant: &str) -> Option<&AccountDatabase> {
+        self.tenants.get(tenant)
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = (&str, &AccountDatabase)> {
+        self.tenants
+            .iter()
+            .map(|(tenant, accounts)| (tenant.as_str(), accounts))
+    }
+
+    /*
+    The ledger::LedgerHash of every tenant's ledger, one after another in tenant order.
+    */
+    pub fn ledger_hash(&self) -> String {
+        let mut hash = LedgerHash::new();
+
+        for (tenant, accounts) in self.tenants() {
+            hash.start_tenant(tenant);
+            hash.replay(accounts.events());
+        }
+
+        hash.hex()
+    }
+}
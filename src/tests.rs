@@ -1,15 +1,158 @@
-use crate::{read_transactions_from_text, Money};
+#[cfg(loom)]
+mod concurrency;
+
+mod reference;
+
+use std::{collections::BTreeMap, io::Write, time::Duration};
+
+use csv::ReaderBuilder;
+
+use reference::{random_ledger, Ratio, ReferenceAccount, ReferenceEngine};
+
+use crate::{
+    accounts::{
+        AccountDatabase, AccountOptions, Balances, BehaviorVersion, CapPolicy, ConflictPolicy,
+        DisputeAmountPolicy, LifecyclePolicy, LowBalance, LowBalanceResponse, UnknownClientPolicy,
+    },
+    admin::run_script,
+    aggregate::{aggregate, CohortSummary, Grouping},
+    apply_transactions, apply_transactions_through,
+    audit::{audit_log, AdminAction},
+    backfill::{backfill, AccountDelta},
+    canary::{self, AccountDivergence},
+    cdc::write_changes,
+    cli::{self, Command},
+    clock::{Clock, FixedClock},
+    compact::{compact, Compaction},
+    completions::{commands, write_completions, Shell},
+    dates::{Date, DateBasis},
+    diff::{AccountChange, AccountState, AccountSummaryDiff, Snapshot},
+    erase::erase_client,
+    errors::{ErrorCode, ErrorReport, RowError},
+    export_db::is_valid_table_name,
+    filter::{filter, ClientRange, Filtered, TransactionFilter},
+    generate::{generate, Profile},
+    groups::{group_summaries, AccountGroups, GroupCap, GroupSummary},
+    latency::LatencyHistogram,
+    ledger::Projection,
+    load_transactions,
+    memo::sanitize,
+    merge::{merge, MergeConflict},
+    merkle::BalanceTree,
+    metadata::{ClientMetadata, TagFilter},
+    middleware::Pipeline,
+    minimize::{ddmin, minimize, Predicate},
+    mirror::write_mirror,
+    movers::{top_movers, SummarySnapshot},
+    open_input,
+    output::ChunkedWriter,
+    partition::{partition, split},
+    provenance::{config_hash, timestamp, Provenance},
+    pseudonym::{ClientLabel, Pseudonymizer},
+    publish::{changes_channel, publish_to, BalanceStore, BalanceUpdate},
+    push_tag_filters, push_through_filter, read_balance_history, read_transactions_from_text,
+    regress::{diff_outputs, diff_stats},
+    rounding::Rounding,
+    schema::{SchemaVersion, SummaryRow},
+    seal::{sealed_through, write_late_transactions, PeriodSeal},
+    search::{search, AccountFilter},
+    seen::SeenFilter,
+    shared::SharedDatabase,
+    simulate::{interest, load_schedule, simulate, SimulationOptions},
+    sinks::{FanOut, Redacted, Redaction, SinkSpec},
+    stats::{write_client_mismatches, write_conflicting_duplicates, write_shortfalls, RunStats},
+    status::StatusHistory,
+    summarize,
+    tenants::TenantDatabase,
+    transactions::{
+        ClientId, Id, LifecycleEvent, TransactionRecord, TransactionText, TxId, TxIdFormat,
+    },
+    tx_index::{export_tx_index, load_tx_index},
+    tx_map::TxMap,
+    write_preview, Money, SummaryOptions,
+};
 
 fn test_case(text: &str) -> String {
     read_transactions_from_text(text).unwrap()
 }
 
+fn text_reader(text: &str) -> csv::Reader<&[u8]> {
+    ReaderBuilder::default()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .has_headers(true)
+        .from_reader(text.as_bytes())
+}
+
+fn history_case(text: &str, client_id: Option<ClientId>) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    read_balance_history(
+        &mut text_reader(text),
+        &mut writer,
+        "",
+        client_id,
+        None,
+        DateBasis::Booking,
+    )
+    .unwrap();
+
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+fn summary_case(prior: &str, text: &str, options: &SummaryOptions) -> (String, RunStats) {
+    let mut database = load_transactions(&mut text_reader(prior)).unwrap();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    let stats = summarize(&mut database, &mut text_reader(text), &mut writer, options).unwrap();
+
+    (
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        stats,
+    )
+}
+
+fn database_case(text: &str) -> AccountDatabase {
+    let mut accounts = AccountDatabase::new();
+
+    for record_result in text_reader(text).deserialize() {
+        let transaction_text: TransactionText = record_result.unwrap();
+
+        accounts.apply(&transaction_text.into());
+    }
+
+    accounts
+}
+
 fn from_parts(whole: u32, decimal: u16) -> Money {
     assert!(decimal < 10000);
 
     Money(whole as u64 * 10000 + decimal as u64)
 }
 
+#[test]
+fn allocating_money_neither_loses_nor_creates_units() {
+    assert_eq!(
+        Money(100).allocate(&[1, 1, 1]),
+        vec![Money(34), Money(33), Money(33)]
+    );
+    assert_eq!(Money(5).allocate(&[3, 7]), vec![Money(2), Money(3)]);
+    assert_eq!(
+        from_parts(10, 0).allocate(&[70, 20, 10]),
+        vec![from_parts(7, 0), from_parts(2, 0), from_parts(1, 0)]
+    );
+    assert_eq!(Money(7).allocate(&[0, 1]), vec![Money(0), Money(7)]);
+    assert_eq!(Money(7).allocate(&[]), vec![]);
+
+    let amount = Money(u64::MAX);
+    let parts = amount.allocate(&[u32::MAX, 3, 1]);
+
+    assert_eq!(
+        parts.iter().map(|part| part.0 as u128).sum::<u128>(),
+        amount.0 as u128
+    );
+}
+
 // This is not a sufficient amount of testing for implementing your own fixed point math
 // I should probably have used a library
 // In this case I'm not worrying about sign, as we're not allowing that to happen
@@ -21,6 +164,109 @@ fn money_parses_whole_part_correctly() {
     assert_eq!(actual, from_parts(3, 1400));
 }
 
+#[test]
+fn money_strict_parser_agrees_with_lenient_parser() {
+    let inputs = [
+        "0",
+        "3",
+        "3.14",
+        "3.1415",
+        "42.05",
+        "0.0001",
+        "999999999999999.9999",
+        "3.",
+        ".5",
+        " 3.1",
+        "+3",
+        "3.14159",
+        "1.2.3",
+        "abc",
+        "",
+        "1844674407370955",
+    ];
+
+    for input in inputs {
+        let strict = Money::parse_strict(input.as_bytes());
+
+        if let Some(strict) = strict {
+            assert_eq!(Some(strict), Money::parse_lenient(input).ok(), "{}", input);
+        }
+    }
+
+    assert_eq!(Money::parse_strict(b"42.05"), Some(from_parts(42, 500)));
+    assert_eq!(Money::parse_strict(b" 42.05"), None);
+    assert_eq!("3.".parse::<Money>().ok(), Some(from_parts(3, 0)));
+}
+
+/*
+Not a correctness test -- run it with `cargo test --release -- --ignored --nocapture` to compare
+the strict and lenient amount parsers.
+*/
+#[test]
+#[ignore]
+fn money_parsing_benchmark() {
+    let inputs: Vec<String> = (0..1_000_000u64)
+        .map(|i| format!("{}.{}", i * 7, i % 10000))
+        .collect();
+
+    let started = std::time::Instant::now();
+    let strict: u64 = inputs
+        .iter()
+        .map(|input| Money::parse_strict(input.as_bytes()).unwrap().0)
+        .sum();
+    let strict_elapsed = started.elapsed();
+
+    let started = std::time::Instant::now();
+    let lenient: u64 = inputs
+        .iter()
+        .map(|input| Money::parse_lenient(input).unwrap().0)
+        .sum();
+    let lenient_elapsed = started.elapsed();
+
+    assert_eq!(strict, lenient);
+    println!(
+        "strict: {:?}, lenient: {:?} for {} amounts",
+        strict_elapsed,
+        lenient_elapsed,
+        inputs.len()
+    );
+}
+
+#[test]
+fn money_formats_leading_decimal_zeros() {
+    assert_eq!(from_parts(1, 500).to_string(), "1.05");
+    assert_eq!(from_parts(1, 1).to_string(), "1.0001");
+    assert_eq!(from_parts(1, 1230).to_string(), "1.123");
+    assert_eq!(from_parts(7, 0).to_string(), "7.0");
+}
+
+#[test]
+fn money_formats_without_allocating_the_same_as_before() {
+    for units in [0, 1, 10, 500, 9999, 10000, 31400, 123456789, u64::MAX] {
+        let amount = Money(units);
+        let decimal = format!("{:04}", units % 10000);
+        let expected = format!(
+            "{}.{}",
+            units / 10000,
+            match decimal.trim_end_matches('0') {
+                "" => "0",
+                digits => digits,
+            }
+        );
+
+        assert_eq!(amount.to_string(), expected);
+        assert_eq!(expected.parse::<Money>().ok(), Some(amount));
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize((amount,)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            format!("{}\n", expected)
+        );
+    }
+}
+
 #[test]
 fn money_adds_correctly() {
     let a: Money = "3.14".parse().unwrap();
@@ -406,3 +652,4023 @@ client_id,available,held,total,locked
 "
     );
 }
+
+#[test]
+fn tenants_are_independent() {
+    let output = test_case(
+        "\
+    type, client, tx, amount, tenant
+    deposit, 1, 1, 42, acme
+    deposit, 1, 1, 5, globex
+    dispute, 1, 1,, globex",
+    );
+
+    assert_eq!(
+        output,
+        "\
+tenant,client_id,available,held,total,locked
+acme,1,42.0,0.0,42.0,false
+globex,1,0.0,5.0,5.0,false
+"
+    );
+}
+
+#[test]
+fn tenant_column_is_optional() {
+    let output = test_case(
+        "\
+    type, client, tx, amount, tenant
+    deposit, 1, 1, 42
+    deposit, 2, 2, 5, acme",
+    );
+
+    assert_eq!(
+        output,
+        "\
+tenant,client_id,available,held,total,locked
+,1,42.0,0.0,42.0,false
+acme,2,5.0,0.0,5.0,false
+"
+    );
+}
+
+#[test]
+fn balances_can_be_rebuilt_from_the_ledger() {
+    let accounts = database_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 42
+    deposit, 2, 2, 5
+    withdrawal, 1, 3, 10
+    dispute, 1, 1,
+    dispute, 2, 1,
+    deposit, 2, 4, 7
+    dispute, 2, 2,
+    chargeback, 2, 2,",
+    );
+    let mut rebuilt = Balances::new();
+
+    rebuilt.replay(accounts.events());
+
+    assert_eq!(
+        rebuilt.accounts().collect::<Vec<_>>(),
+        accounts.accounts().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn dates_round_trip_through_text() {
+    for text in ["1970-01-01", "2000-02-29", "2024-12-31", "1969-07-20"] {
+        let date: Date = text.parse().unwrap();
+
+        assert_eq!(date.to_string(), text);
+    }
+}
+
+#[test]
+fn dates_reject_impossible_days() {
+    assert!("2023-02-29".parse::<Date>().is_err());
+    assert!("2024-13-01".parse::<Date>().is_err());
+    assert!("2024-1-01".parse::<Date>().is_err());
+}
+
+#[test]
+fn dates_count_days_between() {
+    let earlier: Date = "2024-02-27".parse().unwrap();
+    let later: Date = "2024-03-01".parse().unwrap();
+
+    assert_eq!(later.days_since(earlier), 3);
+}
+
+#[test]
+fn balance_history_records_end_of_day_balances() {
+    let output = history_case(
+        "\
+    type, client, tx, amount, date, memo
+    deposit, 1, 1, 42, 2024-03-01, payroll
+    withdrawal, 1, 2, 2, 2024-03-01, atm
+    deposit, 2, 3, 5, 2024-03-01,
+    dispute, 1, 1,, 2024-03-03, customer called",
+        None,
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,date,available,held,total,memos
+1,2024-03-01,40.0,0.0,40.0,payroll; atm
+1,2024-03-03,0.0,40.0,40.0,customer called
+2,2024-03-01,5.0,0.0,5.0,
+"
+    );
+}
+
+#[test]
+fn memos_are_sanitized_and_follow_parked_transactions() {
+    assert_eq!(
+        sanitize("  line one\nline two ", 256).as_deref(),
+        Some("line one line two")
+    );
+    assert_eq!(
+        sanitize("=HYPERLINK(\"x\")", 256).as_deref(),
+        Some("'=HYPERLINK(\"x\")")
+    );
+    assert_eq!(sanitize("abcdef", 3).as_deref(), Some("abc"));
+    assert_eq!(sanitize(" \t ", 256), None);
+
+    let mut database = TenantDatabase::with_options(AccountOptions {
+        lifecycle: LifecyclePolicy::Park,
+        memo_max_length: Some(8),
+        ..Default::default()
+    });
+
+    apply_transactions(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount, memo
+    deposit, 1, 1, 10, first paycheck
+    open, 1, 2,, branch
+    kyc_approved, 1, 3,,",
+        ),
+    )
+    .unwrap();
+
+    let accounts = database.tenant("").unwrap();
+    let memos: Vec<_> = (0..accounts.events().len())
+        .filter_map(|sequence| accounts.memo(sequence))
+        .collect();
+
+    assert_eq!(memos, ["branch", "first pa"]);
+}
+
+#[test]
+fn balance_history_filters_by_client() {
+    let output = history_case(
+        "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 42, 2024-03-01
+    deposit, 2, 2, 5, 2024-03-02",
+        Some(ClientId(2)),
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,date,available,held,total,memos
+2,2024-03-02,5.0,0.0,5.0,
+"
+    );
+}
+
+#[test]
+fn cli_parses_balance_history_flags() {
+    let args: Vec<String> = ["balance-history", "--client", "7", "input.csv"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+    assert_eq!(
+        cli::parse(&args),
+        Ok(Command::BalanceHistory {
+            input: "input.csv".into(),
+            client_id: Some(ClientId(7)),
+            tenant: String::new(),
+            salt: None,
+            date_basis: DateBasis::Booking,
+        })
+    );
+}
+
+#[test]
+fn value_dates_drive_history_when_chosen() {
+    let input = "\
+    type, client, tx, amount, date, value_date
+    deposit, 1, 1, 10, 2024-03-01, 2024-02-28
+    deposit, 1, 2, 5, 2024-03-02,";
+    let history = |date_basis| {
+        let mut writer = csv::Writer::from_writer(vec![]);
+
+        read_balance_history(
+            &mut text_reader(input),
+            &mut writer,
+            "",
+            None,
+            None,
+            date_basis,
+        )
+        .unwrap();
+
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    };
+
+    assert_eq!(
+        history(DateBasis::Booking),
+        "\
+client_id,date,available,held,total,memos
+1,2024-03-01,10.0,0.0,10.0,
+1,2024-03-02,15.0,0.0,15.0,
+"
+    );
+    assert_eq!(
+        history(DateBasis::Value),
+        "\
+client_id,date,available,held,total,memos
+1,2024-02-28,10.0,0.0,10.0,
+1,2024-03-02,15.0,0.0,15.0,
+"
+    );
+}
+
+#[test]
+fn aggregate_groups_by_client_range() {
+    let accounts = database_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 20
+    deposit, 5, 3, 40
+    deposit, 12, 4, 7
+    dispute, 12, 4,",
+    );
+
+    let cohorts = aggregate(
+        &accounts,
+        &Grouping::ClientRange(10),
+        &ClientMetadata::new(),
+    );
+
+    assert_eq!(
+        cohorts,
+        vec![
+            CohortSummary {
+                group: "00000-00009".to_owned(),
+                accounts: 3,
+                locked: 0,
+                total_available: "70.0".to_owned(),
+                average_available: "23.3333".to_owned(),
+                median_available: "20.0".to_owned(),
+                total_held: "0.0".to_owned(),
+            },
+            CohortSummary {
+                group: "00010-00019".to_owned(),
+                accounts: 1,
+                locked: 0,
+                total_available: "0.0".to_owned(),
+                average_available: "0.0".to_owned(),
+                median_available: "0.0".to_owned(),
+                total_held: "7.0".to_owned(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn aggregate_groups_by_metadata_field() {
+    let accounts = database_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 20
+    deposit, 3, 3, 40
+    deposit, 4, 4, 1
+    dispute, 3, 3,
+    chargeback, 3, 3,",
+    );
+    let metadata = ClientMetadata::load(
+        &mut ReaderBuilder::default()
+            .trim(csv::Trim::All)
+            .from_reader("client, region\n1, eu\n2, us\n3, eu".as_bytes()),
+    )
+    .unwrap();
+
+    let cohorts = aggregate(&accounts, &Grouping::Field("region".to_owned()), &metadata);
+    let summary: Vec<(&str, usize, usize, &str)> = cohorts
+        .iter()
+        .map(|cohort| {
+            (
+                cohort.group.as_str(),
+                cohort.accounts,
+                cohort.locked,
+                cohort.median_available.as_str(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            ("", 1, 0, "1.0"),
+            ("eu", 2, 1, "25.0"),
+            ("us", 1, 0, "20.0")
+        ]
+    );
+}
+
+fn snapshot_case(text: &str) -> SummarySnapshot {
+    SummarySnapshot::load(
+        &mut ReaderBuilder::default()
+            .trim(csv::Trim::All)
+            .from_reader(text.as_bytes()),
+    )
+    .unwrap()
+}
+
+#[test]
+fn movers_are_ranked_by_largest_change() {
+    let accounts = database_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 20
+    deposit, 3, 3, 40",
+    );
+    let baseline = snapshot_case(
+        "\
+    client_id, available, held, total, locked
+    1, 12, 0, 12, false
+    2, 2, 0, 2, false
+    3, 40, 0, 40, false
+    4, 5, 0, 5, false",
+    );
+
+    let movers = top_movers(&accounts, &[baseline], 3, 3.0);
+    let summary: Vec<(&ClientLabel, &str)> = movers
+        .iter()
+        .map(|mover| (&mover.client_id, mover.change.as_str()))
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            (&ClientLabel::Id(ClientId(2)), "18.0"),
+            (&ClientLabel::Id(ClientId(4)), "-5.0"),
+            (&ClientLabel::Id(ClientId(1)), "-2.0")
+        ]
+    );
+    assert!(movers.iter().all(|mover| mover.z_score.is_none()));
+}
+
+#[test]
+fn movers_flag_changes_far_from_history() {
+    let accounts = database_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 130
+    deposit, 2, 2, 41",
+    );
+    let snapshots = [
+        snapshot_case("client_id, total\n1, 0\n2, 0"),
+        snapshot_case("client_id, total\n1, 10\n2, 9"),
+        snapshot_case("client_id, total\n1, 20\n2, 20"),
+        snapshot_case("client_id, total\n1, 30\n2, 30"),
+    ];
+
+    let movers = top_movers(&accounts, &snapshots, 10, 3.0);
+    let summary: Vec<(&ClientLabel, &str, bool)> = movers
+        .iter()
+        .map(|mover| (&mover.client_id, mover.change.as_str(), mover.anomalous))
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            (&ClientLabel::Id(ClientId(1)), "100.0", true),
+            (&ClientLabel::Id(ClientId(2)), "11.0", false)
+        ]
+    );
+}
+
+#[test]
+fn changed_only_skips_untouched_accounts() {
+    let (output, stats) = summary_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 42
+    deposit, 2, 2, 5
+    deposit, 3, 3, 7",
+        "\
+    type, client, tx, amount
+    withdrawal, 2, 4, 1
+    dispute, 1, 1,
+    deposit, 4, 5, 1",
+        &SummaryOptions {
+            changed_only: true,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,0.0,42.0,42.0,false
+2,4.0,0.0,4.0,false
+4,1.0,0.0,1.0,false
+"
+    );
+    assert_eq!(
+        stats,
+        RunStats {
+            transactions: 3,
+            accounts: 4,
+            changed: 3,
+            erased_funds: "0.0".to_owned(),
+            unknown_client_references: 0,
+            client_mismatches: 0,
+            conflicting_duplicates: 0,
+            partially_applied: 0,
+            refused_for_funds: 0,
+            unexpected_dispute_amounts: 0,
+            rejected_by_rules: 0,
+            capped_deposits: 0,
+            group_capped_deposits: 0,
+            pending_adjustments: 0,
+            gated_transactions: 0,
+            parked_transactions: 0,
+            sealed_transactions: 0,
+            low_balance_events: 0,
+            held_withdrawals: 0,
+            held_funds: "42.0".to_owned(),
+            open_disputes: 1,
+            largest_held_amount: "42.0".to_owned(),
+            average_dispute_age_days: None,
+            ledger_hash: stats.ledger_hash.clone(),
+            balance_root: stats.balance_root.clone(),
+            allocations: stats.allocations,
+            apply_latencies: None,
+        }
+    );
+}
+
+#[test]
+fn erasing_a_client_leaves_a_tombstone() {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let erased = erase_client(
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 42
+    deposit, 2, 2, 5
+    withdrawal, 1, 3, 2
+    deposit, 1, 4, 10
+    dispute, 1, 4,",
+        ),
+        &mut writer,
+        ClientId(1),
+        "",
+        &AdminAction::new("alice", "gdpr-request").unwrap(),
+    )
+    .unwrap();
+    let log = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+    assert_eq!(erased, from_parts(50, 0));
+    assert_eq!(
+        log,
+        "\
+type,client,tx,amount,operator,reason
+deposit,2,2,5,,
+tombstone,1,0,50.0,alice,gdpr-request
+"
+    );
+
+    let mut audit = csv::Writer::from_writer(vec![]);
+
+    assert_eq!(
+        audit_log(
+            &mut text_reader(&log),
+            &mut audit,
+            AccountOptions::default()
+        )
+        .unwrap(),
+        1
+    );
+    assert_eq!(
+        String::from_utf8(audit.into_inner().unwrap()).unwrap(),
+        "\
+tenant,type,client,tx,date,operator,reason,shortfall
+,tombstone,1,0,,alice,gdpr-request,
+"
+    );
+
+    let (output, stats) = summary_case(
+        "type, client, tx, amount",
+        &log,
+        &SummaryOptions {
+            changed_only: true,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+2,5.0,0.0,5.0,false
+"
+    );
+    assert_eq!(stats.erased_funds, "50.0");
+}
+
+#[test]
+fn erasing_refuses_to_uncover_rows_that_reused_the_client_s_tx_ids() {
+    let erase = |text: &str| {
+        let mut writer = csv::Writer::from_writer(vec![]);
+
+        erase_client(
+            &mut text_reader(text),
+            &mut writer,
+            ClientId(1),
+            "",
+            &AdminAction::new("alice", "gdpr-request").unwrap(),
+        )
+        .map_err(|error| error.to_string())
+    };
+
+    assert_eq!(
+        erase(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 5.0
+    deposit, 2, 1, 7.0
+    deposit, 2, 2, 1.0"
+        ),
+        Err("line 3 reuses tx 1 of client 1, and would be applied once it's erased".to_owned())
+    );
+
+    /*
+    Where the other client took the id first, it's the erased client's row that was rejected.
+    */
+    assert_eq!(
+        erase(
+            "\
+    type, client, tx, amount
+    deposit, 2, 1, 7.0
+    deposit, 1, 1, 5.0
+    dispute, 2, 1,"
+        ),
+        Ok(Money::zero())
+    );
+}
+
+#[test]
+fn erased_clients_reject_further_transactions() {
+    let output = test_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 42
+    tombstone, 1, 0,
+    deposit, 1, 2, 5
+    deposit, 2, 3, 5",
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+2,5.0,0.0,5.0,false
+"
+    );
+}
+
+fn compact_case(text: &str, horizon: &str, options: AccountOptions) -> (String, Compaction) {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let compaction = compact(
+        &mut text_reader(text),
+        &mut writer,
+        horizon.parse().unwrap(),
+        options,
+    )
+    .unwrap();
+
+    (
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        compaction,
+    )
+}
+
+#[test]
+fn compaction_keeps_clients_whose_history_is_still_referenced() {
+    let log = "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 42.05, 2024-01-01
+    withdrawal, 1, 2, 2, 2024-01-02
+    deposit, 2, 3, 5, 2024-01-02
+    deposit, 3, 4, 9, 2024-01-03
+    dispute, 3, 4,, 2024-01-04
+    deposit, 4, 5, 1, 2024-01-04
+    deposit, 1, 6, 3, 2024-02-01
+    dispute, 2, 3,, 2024-02-02
+    deposit, 4, 2, 100, 2024-02-03";
+
+    let (compacted, compaction) = compact_case(log, "2024-02-01", AccountOptions::default());
+
+    assert_eq!(
+        compacted,
+        "\
+type,client,tx,amount,date
+opening,4,0,1.0,
+deposit,1,1,42.05,2024-01-01
+withdrawal,1,2,2,2024-01-02
+deposit,2,3,5,2024-01-02
+deposit,3,4,9,2024-01-03
+dispute,3,4,,2024-01-04
+deposit,1,6,3,2024-02-01
+dispute,2,3,,2024-02-02
+deposit,4,2,100,2024-02-03
+"
+    );
+    assert_eq!(
+        compaction,
+        Compaction {
+            folded_rows: 1,
+            kept_rows: 8,
+            opening_rows: 1,
+        }
+    );
+    assert_eq!(test_case(&compacted), test_case(log));
+}
+
+#[test]
+fn compaction_folds_settled_history_into_opening_balances() {
+    let log = "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 42.05, 2024-01-01
+    withdrawal, 1, 2, 2, 2024-01-02
+    deposit, 2, 3, 5, 2024-01-02
+    deposit, 1, 4, 3, 2024-02-01";
+
+    let (compacted, _) = compact_case(log, "2024-02-01", AccountOptions::default());
+
+    assert_eq!(
+        compacted,
+        "\
+type,client,tx,amount,date
+opening,1,0,40.05,
+opening,2,0,5.0,
+deposit,1,4,3,2024-02-01
+"
+    );
+    assert_eq!(test_case(&compacted), test_case(log));
+}
+
+#[test]
+fn compaction_replays_the_same_under_the_lifecycle_gate() {
+    let log = "\
+    type, client, tx, amount, date
+    open, 1, 1,, 2024-01-01
+    kyc_approved, 1, 2,, 2024-01-01
+    deposit, 1, 3, 10, 2024-01-02
+    deposit, 2, 4, 5, 2024-01-02
+    deposit, 3, 5, 4, 2024-01-02
+    open, 3, 6,, 2024-01-03
+    deposit, 1, 7, 3, 2024-02-01
+    kyc_approved, 3, 8,, 2024-02-02
+    deposit, 2, 9, 1, 2024-02-03";
+
+    for lifecycle in [
+        LifecyclePolicy::Ungated,
+        LifecyclePolicy::Reject,
+        LifecyclePolicy::Park,
+    ] {
+        let options = AccountOptions {
+            lifecycle,
+            ..Default::default()
+        };
+        let (compacted, _) = compact_case(log, "2024-02-01", options);
+
+        assert!(compacted.contains("kyc_approved,1,2"), "{:?}", lifecycle);
+        assert_eq!(
+            options_case(&compacted, options).0,
+            options_case(log, options).0,
+            "{:?}",
+            lifecycle
+        );
+    }
+}
+
+#[test]
+fn rewriting_commands_report_malformed_rows() {
+    let log = "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 10, 2024-01-01
+    refund, 1, 2, 10, 2024-01-02
+    deposit, 1, 3, ten, 2024-01-03";
+    let row_error = |error: Box<dyn std::error::Error>| {
+        error
+            .downcast_ref::<RowError>()
+            .map(|error| (error.line, error.code))
+    };
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    assert_eq!(
+        row_error(
+            compact(
+                &mut text_reader(log),
+                &mut writer,
+                "2024-02-01".parse().unwrap(),
+                AccountOptions::default()
+            )
+            .unwrap_err()
+        ),
+        Some((3, ErrorCode::UnknownType))
+    );
+    assert_eq!(
+        row_error(
+            erase_client(
+                &mut text_reader(log),
+                &mut writer,
+                ClientId(1),
+                "",
+                &AdminAction::new("alice", "gdpr-request").unwrap(),
+            )
+            .unwrap_err()
+        ),
+        Some((3, ErrorCode::UnknownType))
+    );
+    assert_eq!(
+        row_error(
+            audit_log(
+                &mut text_reader(&log.replace("refund", "deposit")),
+                &mut writer,
+                AccountOptions::default()
+            )
+            .unwrap_err()
+        ),
+        Some((4, ErrorCode::InvalidAmount))
+    );
+}
+
+#[test]
+fn pseudonymized_summary_replaces_client_ids_with_tokens() {
+    let pseudonymizer = Pseudonymizer::new("pepper");
+    let (output, _) = summary_case(
+        "type, client, tx, amount",
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 42",
+        &SummaryOptions {
+            pseudonymizer: Some(&pseudonymizer),
+            ..Default::default()
+        },
+    );
+    let token = pseudonymizer.token("", ClientId(1));
+
+    assert_eq!(
+        output,
+        format!(
+            "client_id,available,held,total,locked\n{},42.0,0.0,42.0,false\n",
+            token
+        )
+    );
+    assert_eq!(token, Pseudonymizer::new("pepper").token("", ClientId(1)));
+    assert_ne!(token, Pseudonymizer::new("salt").token("", ClientId(1)));
+    assert_ne!(token, pseudonymizer.token("acme", ClientId(1)));
+}
+
+#[test]
+fn reidentify_recovers_client_ids_from_tokens() {
+    let pseudonymizer = Pseudonymizer::new("pepper");
+
+    assert_eq!(
+        pseudonymizer.reidentify("acme", &pseudonymizer.token("acme", ClientId(4242))),
+        Some(ClientId(4242))
+    );
+    assert_eq!(
+        Pseudonymizer::new("salt").reidentify("acme", &pseudonymizer.token("acme", ClientId(4242))),
+        None
+    );
+}
+
+#[test]
+fn cli_requires_salt_to_pseudonymize() {
+    let args: Vec<String> = ["--pseudonymize", "input.csv"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+    assert_eq!(
+        cli::parse(&args),
+        Err("--pseudonymize requires --salt".to_owned())
+    );
+}
+
+#[test]
+fn seen_filter_never_forgets_a_transaction() {
+    let mut seen = SeenFilter::with_capacity(1000);
+
+    for transaction_id in (0..1000).map(|i| TxId::Number(i * 7919)) {
+        seen.insert(transaction_id);
+    }
+
+    assert!((0..1000).all(|i| seen.may_contain(TxId::Number(i * 7919))));
+    assert!(
+        (1_000_000..1_010_000)
+            .filter(|&i| seen.may_contain(TxId::Number(i)))
+            .count()
+            < 500
+    );
+}
+
+#[test]
+fn seen_filter_does_not_change_duplicate_handling() {
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 1, 1, 10
+    deposit, 2, 2, 5
+    withdrawal, 2, 2, 5
+    dispute, 1, 1,
+    chargeback, 1, 1,";
+    let mut database = TenantDatabase::with_options(AccountOptions {
+        expected_transactions: Some(1),
+        ..Default::default()
+    });
+
+    apply_transactions(&mut database, &mut text_reader(text)).unwrap();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    summarize(
+        &mut database,
+        &mut text_reader("type, client, tx, amount"),
+        &mut writer,
+        &SummaryOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        test_case(text)
+    );
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn chunked_writer_preserves_output_order() {
+    let expected: String = (0..200_000).map(|i| format!("{},{}.0\n", i, i)).collect();
+
+    for threaded in [false, true] {
+        let buffer = SharedBuffer::default();
+
+        {
+            let mut writer = ChunkedWriter::new(buffer.clone(), threaded);
+
+            for line in expected.lines() {
+                writeln!(writer, "{}", line).unwrap();
+            }
+        }
+
+        assert_eq!(
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn mmap_input_reads_the_same_transactions() {
+    let text = "type, client, tx, amount\ndeposit, 1, 1, 42.05\nwithdrawal, 1, 2, 2\n";
+    let path = std::env::temp_dir().join(format!("fizzbuzz-mmap-{}.csv", std::process::id()));
+
+    std::fs::write(&path, text).unwrap();
+
+    let summaries: Vec<String> = [false, true]
+        .into_iter()
+        .map(|mmap| {
+            let mut writer = csv::Writer::from_writer(vec![]);
+
+            summarize(
+                &mut TenantDatabase::new(),
+                &mut open_input(&path, mmap).unwrap(),
+                &mut writer,
+                &SummaryOptions::default(),
+            )
+            .unwrap();
+
+            String::from_utf8(writer.into_inner().unwrap()).unwrap()
+        })
+        .collect();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(summaries[0], test_case(text));
+    assert_eq!(summaries[1], test_case(text));
+}
+
+#[test]
+fn references_to_unknown_clients_do_not_open_accounts() {
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    dispute, 2, 7,
+    chargeback, 3, 8,";
+
+    assert_eq!(
+        test_case(text),
+        "\
+client_id,available,held,total,locked
+1,10.0,0.0,10.0,false
+"
+    );
+
+    let mut database = TenantDatabase::with_options(AccountOptions {
+        unknown_clients: UnknownClientPolicy::OpenAccount,
+        ..Default::default()
+    });
+
+    apply_transactions(&mut database, &mut text_reader(text)).unwrap();
+
+    let stats = RunStats::collect(&database, 3);
+
+    assert_eq!(stats.accounts, 3);
+    assert_eq!(stats.unknown_client_references, 0);
+    assert_eq!(
+        RunStats::collect(&load_transactions(&mut text_reader(text)).unwrap(), 3)
+            .unknown_client_references,
+        2
+    );
+}
+
+#[test]
+fn client_mismatches_are_counted_and_reported() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 5
+    dispute, 2, 1,
+    dispute, 3, 2,
+    dispute, 1, 1,
+    resolve, 4, 1,",
+    ))
+    .unwrap();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    write_client_mismatches(&database, &mut writer).unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+code,tenant,type,tx,original_client_id,claimed_client_id
+E2001,,dispute,1,1,2
+E2001,,dispute,2,2,3
+E2001,,resolve,1,1,4
+"
+    );
+
+    let stats = RunStats::collect(&database, 6);
+
+    assert_eq!(stats.client_mismatches, 3);
+    assert_eq!(stats.unknown_client_references, 0);
+    assert_eq!(stats.accounts, 2);
+}
+
+#[test]
+fn conflicting_duplicates_are_reported_with_both_versions() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 1, 1, 10
+    deposit, 1, 1, 12
+    withdrawal, 2, 1, 10
+    withdrawal, 1, 2, 3
+    deposit, 1, 2, 3",
+    ))
+    .unwrap();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    write_conflicting_duplicates(&database, &mut writer).unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+code,tenant,tx,original_type,original_client_id,original_amount,duplicate_type,duplicate_client_id,duplicate_amount
+E1001,,1,deposit,1,10.0,deposit,1,12.0
+E1001,,1,deposit,1,10.0,withdrawal,2,10.0
+E1001,,2,withdrawal,1,3.0,deposit,1,3.0
+"
+    );
+    assert_eq!(RunStats::collect(&database, 6).conflicting_duplicates, 3);
+    assert_eq!(
+        database
+            .tenant("")
+            .unwrap()
+            .account(ClientId(1))
+            .unwrap()
+            .available(),
+        from_parts(7, 0)
+    );
+}
+
+#[test]
+fn stats_report_exposure_to_open_disputes() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 10, 2024-03-01
+    deposit, 1, 2, 25, 2024-03-01
+    deposit, 2, 3, 4, 2024-03-02
+    dispute, 1, 1,, 2024-03-02
+    dispute, 1, 2,, 2024-03-05
+    dispute, 2, 3,, 2024-03-06
+    resolve, 2, 3,, 2024-03-07
+    deposit, 2, 4, 1, 2024-03-10",
+    ))
+    .unwrap();
+    let stats = RunStats::collect(&database, 8);
+
+    assert_eq!(stats.held_funds, "35.0");
+    assert_eq!(stats.open_disputes, 2);
+    assert_eq!(stats.largest_held_amount, "25.0");
+    assert_eq!(stats.average_dispute_age_days.as_deref(), Some("6.5"));
+}
+
+#[test]
+fn ledger_hash_only_depends_on_what_was_applied() {
+    let hash = |text: &str| {
+        load_transactions(&mut text_reader(text))
+            .unwrap()
+            .ledger_hash()
+    };
+    let original = hash(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    withdrawal, 1, 2, 4
+    withdrawal, 1, 3, 100",
+    );
+
+    assert_eq!(
+        original,
+        hash(
+            "\
+    type,client,tx,amount
+    deposit,1,1,10.0000
+    withdrawal,1,2,4.0
+    withdrawal,1,3,100"
+        )
+    );
+    assert_ne!(
+        original,
+        hash(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    withdrawal, 1, 2, 3"
+        )
+    );
+    assert_ne!(
+        original,
+        hash(
+            "\
+    type, client, tx, amount, tenant
+    deposit, 1, 1, 10, acme
+    withdrawal, 1, 2, 4, acme"
+        )
+    );
+    assert_eq!(original.len(), 64);
+}
+
+#[test]
+fn balance_proofs_verify_against_the_root() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 20
+    deposit, 3, 3, 30
+    deposit, 4, 4, 40
+    deposit, 5, 5, 50
+    dispute, 3, 3,",
+    ))
+    .unwrap();
+    let tree = BalanceTree::build(&database);
+    let root = RunStats::collect(&database, 6).balance_root;
+
+    assert_eq!(tree.root(), root);
+
+    for client_id in 1..=5 {
+        let proof = tree.prove("", ClientId(client_id)).unwrap();
+
+        assert!(proof.verify(&root), "client {}", client_id);
+    }
+
+    let mut proof = tree.prove("", ClientId(3)).unwrap();
+
+    assert_eq!(proof.leaf, ",3,0.0,30.0,false");
+
+    proof.leaf = ",3,30.0,0.0,false".to_owned();
+    assert!(!proof.verify(&root));
+    assert!(tree.prove("", ClientId(6)).is_none());
+}
+
+#[test]
+fn shortfalls_are_reported_instead_of_silently_clamped() {
+    let text = "\
+    type, client, tx, amount, operator, reason
+    deposit, 1, 1, 10,,
+    withdrawal, 1, 2, 4,,
+    dispute, 1, 1,,,
+    deposit, 2, 3, 5,,
+    withdrawal, 2, 4, 5,,
+    adjustment, 2, 5, -8, alice, duplicate-credit
+    approve, 2, 5,, bob,";
+    let database = load_transactions(&mut text_reader(text)).unwrap();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    write_shortfalls(&database, &mut writer).unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+code,tenant,type,client_id,tx,outcome,requested,applied
+E2003,,dispute,1,1,partial,10.0,6.0
+E2002,,withdrawal,2,4,refused,5.0,0.0
+E2002,,adjustment,2,5,refused,8.0,0.0
+"
+    );
+
+    let stats = RunStats::collect(&database, 7);
+
+    assert_eq!((stats.partially_applied, stats.refused_for_funds), (1, 2));
+
+    let mut audit = csv::Writer::from_writer(vec![]);
+
+    audit_log(
+        &mut text_reader(text),
+        &mut audit,
+        AccountOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(audit.into_inner().unwrap()).unwrap(),
+        "\
+tenant,type,client,tx,date,operator,reason,shortfall
+,adjustment,2,5,,alice,duplicate-credit,
+,approve,2,5,,bob,,refused
+"
+    );
+}
+
+fn options_case(text: &str, options: AccountOptions) -> (String, RunStats) {
+    let mut database = TenantDatabase::with_options(options);
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    let stats = summarize(
+        &mut database,
+        &mut text_reader(text),
+        &mut writer,
+        &SummaryOptions::default(),
+    )
+    .unwrap();
+
+    (
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        stats,
+    )
+}
+
+#[test]
+fn partial_disputes_hold_only_the_disputed_amount() {
+    let (output, stats) = options_case(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 10
+    dispute, 1, 1, 4
+    dispute, 2, 2, 25",
+        AccountOptions {
+            partial_disputes: true,
+            dispute_amounts: DisputeAmountPolicy::Reject,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,6.0,4.0,10.0,false
+2,0.0,10.0,10.0,false
+"
+    );
+    assert_eq!(stats.unexpected_dispute_amounts, 0);
+}
+
+#[test]
+fn dispute_amounts_can_be_rejected_or_warned_about() {
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    dispute, 1, 1, 4
+    resolve, 1, 1, 4";
+
+    let (output, stats) = options_case(
+        text,
+        AccountOptions {
+            dispute_amounts: DisputeAmountPolicy::Reject,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,10.0,0.0,10.0,false
+"
+    );
+    assert_eq!(stats.unexpected_dispute_amounts, 2);
+
+    let (output, stats) = options_case(
+        text,
+        AccountOptions {
+            dispute_amounts: DisputeAmountPolicy::Warn,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(output, test_case(text));
+    assert_eq!(stats.unexpected_dispute_amounts, 2);
+}
+
+#[test]
+fn pipeline_rewrites_rows_before_they_are_applied() {
+    let mut pipeline = Pipeline::load(&mut text_reader(
+        "\
+    field, from, to
+    type, credit, deposit
+    type, debit, withdrawal
+    client, 0042, 7",
+    ))
+    .unwrap();
+
+    pipeline.push(|transaction: TransactionText| {
+        (transaction.field("tx") != Some("99")).then_some(transaction)
+    });
+
+    let mut database = TenantDatabase::new();
+    let count = apply_transactions_through(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    credit, 0042, 1, 10
+    debit, 7, 2, 3
+    deposit, 7, 99, 1000",
+        ),
+        &pipeline,
+    )
+    .unwrap();
+    let accounts = database.tenant("").unwrap();
+
+    assert_eq!(count, 3);
+    assert_eq!(
+        accounts
+            .accounts()
+            .map(|account| (account.client_id(), account.available()))
+            .collect::<Vec<_>>(),
+        vec![(ClientId(7), from_parts(7, 0))]
+    );
+}
+
+#[test]
+fn tagged_clients_can_be_excluded_from_processing() {
+    let mut pipeline = Pipeline::new();
+
+    pipeline.push(TagFilter {
+        metadata: ClientMetadata::load(&mut text_reader(
+            "\
+    client, tags
+    1, vip
+    2, test-account vip
+    3,",
+        ))
+        .unwrap(),
+        tags: vec!["test-account".to_owned()],
+        keep_tagged: false,
+    });
+
+    let mut database = TenantDatabase::new();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    let stats = summarize(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 20
+    deposit, 3, 3, 30",
+        ),
+        &mut writer,
+        &SummaryOptions {
+            pipeline: Some(&pipeline),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+client_id,available,held,total,locked
+1,10.0,0.0,10.0,false
+3,30.0,0.0,30.0,false
+"
+    );
+    assert_eq!(stats.accounts, 2);
+}
+
+#[test]
+fn sandboxed_clients_are_kept_apart_from_production() {
+    let metadata = ClientMetadata::load(&mut text_reader(
+        "\
+    client, tags
+    2, test-account
+    3, test-account closed
+    4, closed",
+    ))
+    .unwrap();
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 20
+    withdrawal, 2, 3, 5
+    deposit, 3, 4, 1
+    deposit, 4, 5, 1";
+    let clients = |sandbox| {
+        let mut pipeline = Pipeline::new();
+        let mut database = TenantDatabase::new();
+
+        push_tag_filters(
+            &mut pipeline,
+            &metadata,
+            &["closed".to_owned()],
+            &["test-account".to_owned()],
+            sandbox,
+        );
+        apply_transactions_through(&mut database, &mut text_reader(text), &pipeline).unwrap();
+
+        database
+            .tenant("")
+            .unwrap()
+            .accounts()
+            .map(|account| (account.client_id(), account.available()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(clients(false), [(ClientId(1), from_parts(10, 0))]);
+    assert_eq!(clients(true), [(ClientId(2), from_parts(15, 0))]);
+
+    let args: Vec<String> = ["--sandbox-tag", "test-account", "input.csv"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+    assert_eq!(
+        cli::parse(&args),
+        Err("--sandbox-tag requires --sandbox-summary".to_owned())
+    );
+}
+
+#[test]
+fn group_cap_limits_what_linked_accounts_hold_together() {
+    let metadata = ClientMetadata::load(&mut text_reader(
+        "\
+    client, group
+    1, smith
+    2, smith
+    3,",
+    ))
+    .unwrap();
+    let groups = AccountGroups::from_metadata(&metadata);
+    let mut database = TenantDatabase::new();
+
+    database.set_group_cap(GroupCap {
+        groups: groups.clone(),
+        cap: from_parts(100, 0),
+    });
+    apply_transactions_through(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 60
+    deposit, 2, 2, 50
+    deposit, 2, 3, 40
+    deposit, 3, 4, 500
+    dispute, 1, 1,",
+        ),
+        &Pipeline::new(),
+    )
+    .unwrap();
+
+    assert_eq!(groups.members("smith"), [ClientId(1), ClientId(2)]);
+    assert_eq!(groups.group(ClientId(3)), None);
+    assert_eq!(database.rejected_by_group_cap(), 1);
+    assert_eq!(
+        group_summaries(&database, &groups),
+        vec![GroupSummary {
+            tenant: String::new(),
+            group: "smith".to_owned(),
+            clients: 2,
+            available: from_parts(40, 0),
+            held: from_parts(60, 0),
+            total: from_parts(100, 0),
+            locked_clients: 0,
+        }]
+    );
+}
+
+#[test]
+fn lifecycle_gates_deposits_until_the_client_is_cleared() {
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    open, 1, 2,
+    deposit, 1, 3, 5
+    kyc_approved, 1, 4,
+    withdrawal, 1, 5, 2
+    deposit, 2, 6, 7
+    open, 2, 7,
+    kyc_rejected, 2, 8,";
+    let run = |lifecycle| {
+        let mut database = TenantDatabase::with_options(AccountOptions {
+            lifecycle,
+            ..Default::default()
+        });
+
+        apply_transactions(&mut database, &mut text_reader(text)).unwrap();
+
+        let accounts = database.tenant("").unwrap();
+        let balances: Vec<_> = accounts
+            .accounts()
+            .map(|account| (account.client_id(), account.available()))
+            .collect();
+
+        (balances, accounts.gated_transactions())
+    };
+
+    assert_eq!(
+        run(LifecyclePolicy::Ungated),
+        (
+            vec![
+                (ClientId(1), from_parts(13, 0)),
+                (ClientId(2), from_parts(7, 0))
+            ],
+            0
+        )
+    );
+    assert_eq!(
+        run(LifecyclePolicy::Reject),
+        (
+            vec![(ClientId(1), Money::zero()), (ClientId(2), Money::zero())],
+            3
+        )
+    );
+    assert_eq!(
+        run(LifecyclePolicy::Park),
+        (
+            vec![
+                (ClientId(1), from_parts(13, 0)),
+                (ClientId(2), Money::zero())
+            ],
+            1
+        )
+    );
+}
+
+#[test]
+fn sealed_periods_refuse_late_transactions() {
+    let through: Date = "2024-03-31".parse().unwrap();
+    let period = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 10, 2024-03-02
+    deposit, 2, 2, 4, 2024-03-30",
+    ))
+    .unwrap();
+    let seal = PeriodSeal::finalize(&period, through);
+    let json = serde_json::to_string(&seal).unwrap();
+
+    assert_eq!(seal.balances.len(), 2);
+    assert_eq!(seal.ledger_hash, period.ledger_hash());
+    assert_eq!(sealed_through(json.as_bytes()).unwrap(), through);
+
+    let mut database = TenantDatabase::new();
+
+    database.set_sealed_through(through);
+    apply_transactions(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount, date
+    deposit, 1, 3, 5, 2024-04-01
+    withdrawal, 1, 4, 2, 2024-03-31
+    deposit, 1, 5, 1,",
+        ),
+    )
+    .unwrap();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    write_late_transactions(&database, &mut writer).unwrap();
+
+    assert_eq!(
+        database
+            .tenant("")
+            .unwrap()
+            .account(ClientId(1))
+            .unwrap()
+            .available(),
+        from_parts(6, 0)
+    );
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+type,client,tx,amount,tenant,date,value_date,operator,reason,memo
+withdrawal,1,4,2,,2024-03-31,,,,
+"
+    );
+}
+
+#[test]
+fn finalizing_reports_a_malformed_date_instead_of_panicking() {
+    let mut pipeline = Pipeline::new();
+    let mut database = TenantDatabase::new();
+
+    push_through_filter(
+        &mut pipeline,
+        "2024-01-31".parse().unwrap(),
+        DateBasis::Booking,
+    );
+
+    let error = apply_transactions_through(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 10, 2024-01-02
+    deposit, 1, 2, 10, 2024-02-02
+    deposit, 1, 3, 10, 2024-13-45",
+        ),
+        &pipeline,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        error
+            .downcast_ref::<RowError>()
+            .map(|error| (error.line, error.code)),
+        Some((4, ErrorCode::InvalidDate))
+    );
+}
+
+#[test]
+fn low_balances_trigger_their_configured_response() {
+    let input = "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 100, 2024-03-01
+    withdrawal, 1, 2, 95, 2024-03-02
+    withdrawal, 1, 3, 1, 2024-03-03
+    deposit, 1, 4, 1, 2024-03-04";
+    let run = |response| {
+        let mut database = TenantDatabase::with_options(AccountOptions {
+            low_balance: Some(LowBalance {
+                threshold: from_parts(10, 0),
+                response,
+            }),
+            ..Default::default()
+        });
+
+        apply_transactions(&mut database, &mut text_reader(input)).unwrap();
+
+        let accounts = database.tenant("").unwrap();
+        let account = accounts.account(ClientId(1)).unwrap();
+
+        (
+            account.available(),
+            account.is_locked(),
+            accounts.low_balance_events(),
+            accounts.held_withdrawals(),
+        )
+    };
+
+    assert_eq!(
+        run(LowBalanceResponse::Alert),
+        (from_parts(5, 0), false, 1, 0)
+    );
+    assert_eq!(
+        run(LowBalanceResponse::Hold),
+        (from_parts(6, 0), false, 1, 1)
+    );
+    assert_eq!(
+        run(LowBalanceResponse::Lock),
+        (from_parts(5, 0), true, 1, 0)
+    );
+
+    let mut audit = csv::Writer::from_writer(vec![]);
+    let options = AccountOptions {
+        low_balance: Some(LowBalance {
+            threshold: from_parts(10, 0),
+            response: LowBalanceResponse::Lock,
+        }),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        audit_log(&mut text_reader(input), &mut audit, options).unwrap(),
+        1
+    );
+    assert_eq!(
+        String::from_utf8(audit.into_inner().unwrap()).unwrap(),
+        "\
+tenant,type,client,tx,date,operator,reason,shortfall
+,low_balance,1,2,2024-03-02,rule,low-balance-lock,
+"
+    );
+
+    let disputed = "\
+    type, client, tx, amount
+    deposit, 1, 1, 100
+    dispute, 1, 1,";
+    let mut database = TenantDatabase::with_options(options);
+
+    apply_transactions(&mut database, &mut text_reader(disputed)).unwrap();
+
+    let accounts = database.tenant("").unwrap();
+
+    assert_eq!(accounts.low_balance_events(), 0);
+    assert!(!accounts.account(ClientId(1)).unwrap().is_locked());
+}
+
+#[test]
+fn previews_show_changes_without_making_them() {
+    let log = "\
+    type, client, tx, amount
+    deposit, 1, 1, 50
+    deposit, 2, 2, 5";
+    let mut rewritten = csv::Writer::from_writer(vec![]);
+
+    erase_client(
+        &mut text_reader(log),
+        &mut rewritten,
+        ClientId(1),
+        "",
+        &AdminAction::new("alice", "gdpr-request").unwrap(),
+    )
+    .unwrap();
+
+    let before = load_transactions(&mut text_reader(log)).unwrap();
+    let rewritten = String::from_utf8(rewritten.into_inner().unwrap()).unwrap();
+    let after = load_transactions(&mut text_reader(&rewritten)).unwrap();
+    let mut preview = csv::Writer::from_writer(vec![]);
+
+    assert_eq!(write_preview(&before, &after, &mut preview).unwrap(), 1);
+    assert_eq!(
+        String::from_utf8(preview.into_inner().unwrap()).unwrap(),
+        "\
+tenant,client_id,change,available_before,available_after,held_before,held_after,locked_before,locked_after
+,1,removed,50.0,,0.0,,false,
+"
+    );
+
+    let backfilled = backfill(
+        &mut text_reader(log),
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    correction, 2, 2, 7",
+        ),
+    )
+    .unwrap();
+    let mut preview = csv::Writer::from_writer(vec![]);
+
+    assert_eq!(
+        write_preview(&backfilled.original, &backfilled.corrected, &mut preview).unwrap(),
+        1
+    );
+    assert_eq!(
+        String::from_utf8(preview.into_inner().unwrap()).unwrap(),
+        "\
+tenant,client_id,change,available_before,available_after,held_before,held_after,locked_before,locked_after
+,2,changed,5.0,7.0,0.0,0.0,false,false
+"
+    );
+
+    let args = |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+
+    assert!(cli::parse(&args(&[
+        "backfill",
+        "--corrections",
+        "fix.csv",
+        "input.csv"
+    ]))
+    .is_err());
+    assert!(matches!(
+        cli::parse(&args(&[
+            "backfill",
+            "--corrections",
+            "fix.csv",
+            "--preview",
+            "input.csv"
+        ])),
+        Ok(Command::Backfill {
+            delta: None,
+            preview: true,
+            ..
+        })
+    ));
+}
+
+fn admin_case(log: &str, script: &str, all_or_nothing: bool) -> (String, Vec<String>) {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let results = run_script(
+        &mut text_reader(log),
+        &mut text_reader(script),
+        &mut writer,
+        all_or_nothing,
+    )
+    .unwrap();
+
+    (
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        results
+            .iter()
+            .map(|result| {
+                format!(
+                    "{} {} {} {}",
+                    result.line, result.op, result.status, result.error
+                )
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn admin_scripts_report_each_line_and_can_be_all_or_nothing() {
+    let log = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 5
+    dispute, 2, 2,
+    chargeback, 2, 2,
+    deposit, 1, 3, 7";
+    let script = "\
+    op, client, tx, amount, operator, reason
+    adjustment, 1, 10, -2.5, alice, incident-42
+    approve, 1, 10,, bob, incident-42
+    reversal, 1, 3,, alice, incident-42
+    unlock, 2, 0,, alice, incident-42
+    approve, 1, 11,, bob, incident-42
+    reversal, 2, 1,, alice, incident-42";
+
+    let (updated, results) = admin_case(log, script, false);
+
+    assert_eq!(
+        results,
+        [
+            "2 adjustment applied ",
+            "3 approve applied ",
+            "4 reversal applied ",
+            "5 unlock failed a chargeback's lock can't be lifted",
+            "6 approve failed no adjustment with tx 11 for client 1 awaiting another operator's approval",
+            "7 reversal failed tx 1 isn't for client 2",
+        ]
+    );
+    assert_eq!(
+        updated,
+        "\
+type,client,tx,amount,operator,reason
+deposit,1,1,10,,
+deposit,2,2,5,,
+dispute,2,2,,,
+chargeback,2,2,,,
+adjustment,1,10,-2.5,alice,incident-42
+approve,1,10,,bob,incident-42
+"
+    );
+    assert_eq!(
+        test_case(&updated),
+        "\
+client_id,available,held,total,locked
+1,7.5,0.0,7.5,false
+2,5.0,0.0,5.0,true
+"
+    );
+
+    let (unchanged, results) = admin_case(log, script, true);
+
+    assert_eq!(results[0], "2 adjustment rolled-back ");
+    assert_eq!(test_case(&unchanged), test_case(log));
+
+    let (_, results) = admin_case(
+        log,
+        "\
+    op, client, tx, amount, operator, reason
+    adjustment, 1, 10, +-5, alice, incident-42
+    adjustment, 1, 11, --5, alice, incident-42
+    adjustment, 1, 12, +5, alice, incident-42",
+        false,
+    );
+
+    assert_eq!(
+        results,
+        [
+            "2 adjustment failed invalid amount: \"+-5\"",
+            "3 adjustment failed invalid amount: \"--5\"",
+            "4 adjustment applied ",
+        ]
+    );
+}
+
+#[test]
+fn tx_map_gives_references_stable_ids() {
+    let map = TxMap::load(&mut text_reader(
+        "\
+    tenant, reference, tx
+    , 9f1c-deposit, 7",
+    ))
+    .unwrap();
+    let mut pipeline = Pipeline::new();
+    let mut database = TenantDatabase::new();
+
+    pipeline.push(map.clone());
+    apply_transactions_through(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    deposit, 1, 9f1c-deposit, 10
+    deposit, 1, 3b2e-deposit, 5
+    dispute, 1, 3b2e-deposit,
+    withdrawal, 1, 12, 1",
+        ),
+        &pipeline,
+    )
+    .unwrap();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    map.write(&mut writer).unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+tenant,reference,tx
+,9f1c-deposit,7
+,3b2e-deposit,8
+,12,9
+"
+    );
+    assert_eq!(
+        database
+            .tenant("")
+            .unwrap()
+            .open_disputes()
+            .collect::<Vec<_>>(),
+        [(TxId::Number(8), from_parts(5, 0))]
+    );
+    assert!(TxMap::load(&mut text_reader("tenant, reference, tx\n, a, 1\n, b, 1")).is_err());
+}
+
+#[test]
+fn pipeline_rejects_unknown_fields() {
+    assert!(Pipeline::load(&mut text_reader("field, from, to\nkind, credit, deposit")).is_err());
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+fn script_rules_see_the_account_they_apply_to() {
+    let rules = crate::rules::ScriptRules::from_source(
+        r#"
+        fn accept(tx, account) {
+            if tx.type != "withdrawal" { return true; }
+            account != () && tx.amount <= account.available / 2.0
+        }
+        "#,
+    )
+    .unwrap();
+    let mut database = TenantDatabase::new();
+
+    database.set_rule(Box::new(rules));
+    apply_transactions(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    withdrawal, 1, 2, 6
+    withdrawal, 1, 3, 5
+    withdrawal, 2, 4, 1",
+        ),
+    )
+    .unwrap();
+
+    let stats = RunStats::collect(&database, 4);
+
+    assert_eq!(stats.rejected_by_rules, 2);
+    assert_eq!(
+        database
+            .tenant("")
+            .unwrap()
+            .account(ClientId(1))
+            .unwrap()
+            .available(),
+        from_parts(5, 0)
+    );
+}
+
+#[test]
+fn regress_reports_differing_lines_and_stats() {
+    let divergences = diff_outputs(
+        "batch.csv",
+        "client_id,total\n1,10.0\n2,5.0\n",
+        "client_id,total\n1,10.0\n2,5.5\n3,1.0\n",
+    );
+
+    assert_eq!(
+        divergences
+            .iter()
+            .map(|divergence| (
+                divergence.location.as_str(),
+                divergence.baseline.as_str(),
+                divergence.candidate.as_str()
+            ))
+            .collect::<Vec<_>>(),
+        vec![("line 3", "2,5.0", "2,5.5"), ("line 4", "", "3,1.0")]
+    );
+
+    let divergences = diff_stats(
+        "batch.csv",
+        &serde_json::json!({ "transactions": 3, "accounts": 2 }),
+        &serde_json::json!({ "transactions": 3, "accounts": 3, "changed": 1 }),
+    );
+
+    assert_eq!(
+        divergences
+            .iter()
+            .map(|divergence| divergence.location.as_str())
+            .collect::<Vec<_>>(),
+        vec!["accounts", "changed"]
+    );
+}
+
+#[test]
+fn cli_accepts_only_known_schema_versions() {
+    let parse = |version: &str| {
+        let args: Vec<String> = ["--schema-version", version, "input.csv"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        cli::parse(&args).map(|command| match command {
+            Command::Summarize { schema, .. } => schema,
+            _ => panic!("expected a summarize command"),
+        })
+    };
+
+    assert_eq!(parse("1"), Ok(SchemaVersion::V1));
+    assert_eq!(parse("v1"), Ok(SchemaVersion::V1));
+    assert_eq!(parse("2"), Ok(SchemaVersion::V2));
+    assert_eq!(parse("v3"), Ok(SchemaVersion::V3));
+    assert_eq!(parse("4"), Ok(SchemaVersion::V4));
+    assert_eq!(
+        parse("5"),
+        Err("invalid value for --schema-version: 5".to_owned())
+    );
+}
+
+#[test]
+fn provenance_timestamps_are_rfc3339() {
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_709_337_845);
+
+    assert_eq!(timestamp(time), "2024-03-02T00:04:05Z");
+}
+
+#[test]
+fn provenance_uses_the_clock_it_is_given() {
+    let clock: FixedClock = "2024-03-02T00:04:05Z".parse().unwrap();
+    let sidecar = Provenance::new(&[], &[], &clock).unwrap();
+
+    assert_eq!(sidecar.generated_at, "2024-03-02T00:04:05Z");
+    assert_eq!(timestamp(clock.now()), "2024-03-02T00:04:05Z");
+    assert!("2024-03-02T24:00:00Z".parse::<FixedClock>().is_err());
+    assert!("2024-03-02 00:04:05".parse::<FixedClock>().is_err());
+}
+
+#[test]
+fn provenance_config_hash_ignores_the_salt() {
+    let args = |salt: &str| -> Vec<String> {
+        ["--pseudonymize", "--salt", salt, "input.csv"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect()
+    };
+
+    assert_eq!(config_hash(&args("one")), config_hash(&args("two")));
+    assert_ne!(
+        config_hash(&args("one")),
+        config_hash(&["input.csv".to_owned()])
+    );
+}
+
+#[test]
+fn balance_cap_rejects_or_trims_deposits() {
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 90
+    deposit, 1, 2, 20
+    deposit, 2, 3, 50
+    dispute, 1, 2,";
+    let options = |cap_policy| AccountOptions {
+        balance_cap: Some(from_parts(100, 0)),
+        cap_policy,
+        ..Default::default()
+    };
+
+    let (output, stats) = options_case(text, options(CapPolicy::Reject));
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,90.0,0.0,90.0,false
+2,50.0,0.0,50.0,false
+"
+    );
+    assert_eq!(stats.capped_deposits, 1);
+
+    let (output, stats) = options_case(text, options(CapPolicy::Partial));
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,90.0,10.0,100.0,false
+2,50.0,0.0,50.0,false
+"
+    );
+    assert_eq!(stats.capped_deposits, 1);
+}
+
+#[test]
+fn schema_v2_flags_accounts_near_their_cap() {
+    let mut database = TenantDatabase::with_options(AccountOptions {
+        balance_cap: Some(from_parts(100, 0)),
+        ..Default::default()
+    });
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    summarize(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 95
+    deposit, 2, 2, 94.9999",
+        ),
+        &mut writer,
+        &SummaryOptions {
+            schema: SchemaVersion::V2,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+client_id,available,held,total,locked,near_cap
+1,95.0,0.0,95.0,false,true
+2,94.9999,0.0,94.9999,false,false
+"
+    );
+}
+
+fn tx_index_case(database: &TenantDatabase) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    export_tx_index(database, &mut writer).unwrap();
+
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+#[test]
+fn tx_index_round_trips_dispute_state() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount
+    deposit, 1, 2, 10
+    withdrawal, 1, 1, 4
+    deposit, 2, 3, 5
+    dispute, 2, 3,
+    dispute, 1, 2,",
+    ))
+    .unwrap();
+    let index = tx_index_case(&database);
+
+    assert_eq!(
+        index,
+        "\
+tenant,type,client,tx,amount,disputed
+,withdrawal,1,1,4.0,
+,deposit,1,2,10.0,10.0
+,deposit,2,3,5.0,5.0
+"
+    );
+
+    let mut reloaded = TenantDatabase::new();
+
+    assert_eq!(
+        load_tx_index(&mut reloaded, &mut text_reader(&index)).unwrap(),
+        3
+    );
+    assert_eq!(tx_index_case(&reloaded), index);
+    assert_eq!(reloaded.tenant("").unwrap().accounts().count(), 0);
+}
+
+#[test]
+fn tx_index_resolves_disputes_on_earlier_deposits() {
+    let day_two = "\
+    type, client, tx, amount
+    opening, 1, 100, 10
+    dispute, 1, 1,
+    deposit, 1, 2, 3";
+    let summarize_with = |index: &str| {
+        let mut database = TenantDatabase::new();
+        let mut writer = csv::Writer::from_writer(vec![]);
+
+        load_tx_index(&mut database, &mut text_reader(index)).unwrap();
+        summarize(
+            &mut database,
+            &mut text_reader(day_two),
+            &mut writer,
+            &SummaryOptions::default(),
+        )
+        .unwrap();
+
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    };
+
+    assert_eq!(
+        summarize_with("tenant,type,client,tx,amount,disputed\n"),
+        "\
+client_id,available,held,total,locked
+1,13.0,0.0,13.0,false
+"
+    );
+    assert_eq!(
+        summarize_with(
+            "\
+tenant,type,client,tx,amount,disputed
+,deposit,1,1,10.0,
+,deposit,1,2,3.0,
+"
+        ),
+        "\
+client_id,available,held,total,locked
+1,0.0,10.0,10.0,false
+"
+    );
+}
+
+#[test]
+fn ids_parse_and_display_as_plain_numbers() {
+    assert_eq!("0042".parse(), Ok(ClientId(42)));
+    assert_eq!("7".parse(), Ok(TxId::Number(7)));
+    assert!("70000".parse::<ClientId>().is_err());
+    assert_eq!(ClientId(42).to_string(), "42");
+    assert_eq!(serde_json::to_string(&TxId::Number(7)).unwrap(), "7");
+}
+
+#[test]
+fn uuid_tx_ids_work_end_to_end_when_enabled() {
+    let uuid: TxId = "6F9619FF-8B86-D011-B42D-00C04FC964FF".parse().unwrap();
+
+    assert_eq!(uuid.to_string(), "6f9619ff-8b86-d011-b42d-00c04fc964ff");
+    assert_eq!(uuid.number(), None);
+    assert_eq!(
+        serde_json::to_string(&uuid).unwrap(),
+        "\"6f9619ff-8b86-d011-b42d-00c04fc964ff\""
+    );
+    assert!("6f9619ff8b86d011b42d00c04fc964ff".parse::<TxId>().is_err());
+    assert!("6f9619ff-8b86-d011-b42d-00c04fc964fg"
+        .parse::<TxId>()
+        .is_err());
+
+    let input = "\
+    type, client, tx, amount
+    deposit, 1, 6f9619ff-8b86-d011-b42d-00c04fc964ff, 10
+    deposit, 1, 7, 3
+    dispute, 1, 6F9619FF-8B86-D011-B42D-00C04FC964FF,";
+    let mut numeric = TenantDatabase::new();
+
+    assert!(apply_transactions(&mut numeric, &mut text_reader(input)).is_err());
+
+    let mut database = TenantDatabase::with_options(AccountOptions {
+        tx_ids: TxIdFormat::Uuid,
+        ..Default::default()
+    });
+
+    apply_transactions(&mut database, &mut text_reader(input)).unwrap();
+
+    let accounts = database.tenant("").unwrap();
+
+    assert_eq!(
+        accounts.open_disputes().collect::<Vec<_>>(),
+        [(uuid, from_parts(10, 0))]
+    );
+}
+
+#[test]
+fn status_history_records_why_accounts_changed_status() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 10, 2024-03-01
+    deposit, 2, 2, 5, 2024-03-01
+    dispute, 1, 1,, 2024-03-02
+    chargeback, 1, 1,, 2024-03-03
+    tombstone, 2, 3, 0, 2024-03-04",
+    ))
+    .unwrap();
+    let mut history = StatusHistory::new();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    history.replay(database.tenant("").unwrap().events());
+    for change in history.changes(None) {
+        writer.serialize(change).unwrap();
+    }
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+client_id,date,from,to,reason
+1,2024-03-03,active,locked,chargeback of tx 1
+2,2024-03-04,active,erased,erased
+"
+    );
+    assert_eq!(
+        history.locked_reason(ClientId(1)),
+        Some("chargeback of tx 1")
+    );
+    assert_eq!(history.locked_reason(ClientId(2)), None);
+}
+
+#[test]
+fn schema_v3_explains_locked_accounts() {
+    let (output, _) = summary_case(
+        "type, client, tx, amount",
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 5
+    dispute, 1, 1,
+    chargeback, 1, 1,",
+        &SummaryOptions {
+            schema: SchemaVersion::V3,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked,near_cap,locked_reason
+1,10.0,0.0,10.0,true,false,chargeback of tx 1
+2,5.0,0.0,5.0,false,false,
+"
+    );
+}
+
+#[test]
+fn schema_v4_shows_deposits_pending_clearance() {
+    let mut database = TenantDatabase::with_options(AccountOptions {
+        lifecycle: LifecyclePolicy::Park,
+        ..Default::default()
+    });
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    summarize(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    open, 1, 1,
+    deposit, 1, 2, 10
+    deposit, 1, 3, 2.5
+    open, 2, 4,
+    kyc_approved, 2, 5,
+    deposit, 2, 6, 5",
+        ),
+        &mut writer,
+        &SummaryOptions {
+            schema: SchemaVersion::V4,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+client_id,available,held,total,locked,near_cap,locked_reason,pending
+1,0.0,0.0,0.0,false,false,,12.5
+2,5.0,0.0,5.0,false,false,,0.0
+"
+    );
+
+    database.apply(
+        "",
+        &TransactionRecord::Lifecycle {
+            id: Id {
+                client_id: ClientId(1),
+                transaction_id: TxId::Number(7),
+            },
+            event: LifecycleEvent::KycApproved,
+        },
+        None,
+    );
+
+    let accounts = database.tenant("").unwrap();
+
+    assert_eq!(accounts.pending(ClientId(1)), Money::zero());
+    assert_eq!(
+        accounts.account(ClientId(1)).unwrap().available(),
+        from_parts(12, 5000)
+    );
+}
+
+#[test]
+fn cli_requires_an_operator_and_reason_to_erase() {
+    let parse =
+        |args: &[&str]| cli::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+
+    assert_eq!(
+        parse(&["erase-client", "1", "input.csv"]),
+        Err("administrative commands require --operator and --reason".to_owned())
+    );
+    assert_eq!(
+        parse(&[
+            "erase-client",
+            "1",
+            "--operator",
+            "alice",
+            "--reason",
+            "because I said so",
+            "input.csv"
+        ]),
+        Err("invalid reason code: \"because I said so\"".to_owned())
+    );
+    assert!(parse(&[
+        "erase-client",
+        "1",
+        "--operator",
+        "alice",
+        "--reason",
+        "gdpr-request",
+        "input.csv"
+    ])
+    .is_ok());
+}
+
+#[test]
+fn adjustments_wait_for_approval_from_another_operator() {
+    let text = "\
+    type, client, tx, amount, operator, reason
+    deposit, 1, 1, 10,,
+    adjustment, 1, 2, -2.5, alice, duplicate-credit
+    adjustment, 1, 3, 4, alice, missed-credit
+    approve, 1, 2,, alice,
+    approve, 1, 3,, bob,
+    approve, 2, 2,, bob,
+    deposit, 1, 3, 100,,";
+
+    let (output, stats) = options_case(text, AccountOptions::default());
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,14.0,0.0,14.0,false
+"
+    );
+    assert_eq!(stats.pending_adjustments, 1);
+
+    let (output, stats) = options_case(
+        text,
+        AccountOptions {
+            auto_approve_adjustments: true,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,11.5,0.0,11.5,false
+"
+    );
+    assert_eq!(stats.pending_adjustments, 0);
+
+    for unsigned in [
+        "adjustment, 1, 2, 4,,",
+        "approve, 1, 2,,,",
+        "approve, 1, 2,, operator-with-an-id-over-32-bytes,",
+    ] {
+        let text = format!(
+            "type, client, tx, amount, operator, reason\ndeposit, 1, 1, 10,,\n{}",
+            unsigned
+        );
+        let error = load_transactions(&mut text_reader(&text)).err().unwrap();
+
+        assert_eq!(
+            error
+                .downcast_ref::<RowError>()
+                .map(|error| (error.line, error.code)),
+            Some((3, ErrorCode::InvalidOperator)),
+            "{}",
+            unsigned
+        );
+    }
+
+    for amount in ["+-5", "--5", "++5", "-+5"] {
+        let text = format!(
+            "type, client, tx, amount, operator, reason\ndeposit, 1, 1, 10,,\nadjustment, 1, 2, {}, alice,",
+            amount
+        );
+        let error = load_transactions(&mut text_reader(&text)).err().unwrap();
+
+        assert_eq!(
+            error
+                .downcast_ref::<RowError>()
+                .map(|error| (error.line, error.code)),
+            Some((3, ErrorCode::InvalidAmount)),
+            "{}",
+            amount
+        );
+    }
+}
+
+#[test]
+fn behavior_versions_replay_the_rules_a_batch_was_processed_with() {
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    withdrawal, 1, 2, 10
+    deposit, 2, 3, 5
+    dispute, 2, 3,
+    chargeback, 2, 3,
+    deposit, 2, 4, 7";
+
+    let (output, _) = options_case(text, AccountOptions::default());
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,10.0,0.0,10.0,false
+2,12.0,0.0,12.0,true
+"
+    );
+
+    let options = AccountOptions {
+        behavior: BehaviorVersion::V2,
+        ..Default::default()
+    };
+    let (output, _) = options_case(text, options);
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,0.0,0.0,0.0,false
+2,5.0,0.0,5.0,true
+"
+    );
+
+    let mut database = TenantDatabase::with_options(options);
+
+    apply_transactions(&mut database, &mut text_reader(text)).unwrap();
+
+    let accounts = database.tenant("").unwrap();
+    let mut rebuilt = Balances::new();
+
+    rebuilt.replay(accounts.events());
+
+    assert_eq!(
+        rebuilt.accounts().collect::<Vec<_>>(),
+        accounts.accounts().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn canary_reports_accounts_the_candidate_behavior_changes() {
+    let text = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    withdrawal, 1, 2, 10
+    deposit, 2, 3, 5
+    withdrawal, 2, 4, 1";
+    let run = |behavior| {
+        let mut database = TenantDatabase::with_options(AccountOptions {
+            behavior,
+            ..Default::default()
+        });
+
+        apply_transactions(&mut database, &mut text_reader(text)).unwrap();
+        database
+    };
+
+    assert_eq!(
+        canary::compare(&run(BehaviorVersion::V1), &run(BehaviorVersion::V2)),
+        [AccountDivergence {
+            tenant: String::new(),
+            client_id: ClientId(1),
+            field: "available",
+            current: "10.0".to_owned(),
+            canary: "0.0".to_owned(),
+        }]
+    );
+    assert!(canary::compare(&run(BehaviorVersion::V1), &run(BehaviorVersion::V1)).is_empty());
+}
+
+#[test]
+fn cli_accepts_only_known_behavior_versions() {
+    let parse = |version: &str| {
+        let args: Vec<String> = ["--behavior-version", version, "input.csv"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        cli::parse(&args).map(|command| match command {
+            Command::Summarize {
+                account_options, ..
+            } => account_options.behavior,
+            _ => panic!("expected a summarize command"),
+        })
+    };
+
+    assert_eq!(parse("1"), Ok(BehaviorVersion::V1));
+    assert_eq!(parse("v2"), Ok(BehaviorVersion::V2));
+    assert_eq!(
+        parse("3"),
+        Err("invalid value for --behavior-version: 3".to_owned())
+    );
+}
+
+#[test]
+fn conflict_policy_decides_between_a_withdrawal_and_a_simultaneous_dispute() {
+    let text = "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 10, 2024-03-01
+    deposit, 2, 2, 10, 2024-03-01
+    withdrawal, 1, 3, 8, 2024-03-02
+    dispute, 1, 1,, 2024-03-02
+    withdrawal, 2, 4, 8, 2024-03-02
+    dispute, 2, 2,, 2024-03-03";
+
+    let (output, _) = options_case(text, AccountOptions::default());
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,0.0,2.0,2.0,false
+2,0.0,2.0,2.0,false
+"
+    );
+
+    let (output, _) = options_case(
+        text,
+        AccountOptions {
+            conflicts: ConflictPolicy::DisputeFirst,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        output,
+        "\
+client_id,available,held,total,locked
+1,0.0,10.0,10.0,false
+2,0.0,2.0,2.0,false
+"
+    );
+}
+
+#[test]
+fn simulation_projects_scheduled_transactions_per_period() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 100, 2024-03-31
+    deposit, 2, 2, 5, 2024-03-20",
+    ))
+    .unwrap();
+    let schedule = load_schedule(&mut text_reader(
+        "\
+    type, client, amount, start, every_days
+    deposit, 1, 2500, 2024-04-01, 30
+    withdrawal, 1, 1800, 2024-04-05, 30
+    interest, 1, 0.5, 2024-04-30, 30
+    withdrawal, 2, 10, 2024-04-10,
+    deposit, 3, 1, 2024-03-01,",
+    ))
+    .unwrap();
+    let projection = simulate(
+        database.tenant("").unwrap().events(),
+        &schedule,
+        "2024-03-31".parse().unwrap(),
+        &SimulationOptions {
+            until: "2024-06-15".parse().unwrap(),
+            period_days: 30,
+            rounding: &Rounding::TowardZero,
+        },
+    );
+    let rows: Vec<_> = projection
+        .iter()
+        .map(|row| format!("{} {} {}", row.date, row.client_id, row.total))
+        .collect();
+
+    assert_eq!(
+        rows,
+        [
+            "2024-04-30 1 804.0",
+            "2024-04-30 2 5.0",
+            "2024-05-30 1 1511.52",
+            "2024-05-30 2 5.0",
+            "2024-06-15 1 2211.52",
+            "2024-06-15 2 5.0",
+        ]
+    );
+}
+
+#[test]
+fn completions_cover_every_command_in_the_usage() {
+    let commands = commands();
+    let simulate = commands
+        .iter()
+        .find(|command| command.name == Some("simulate"))
+        .unwrap();
+
+    assert_eq!(commands[0].name, None);
+    assert!(commands[0].flags.contains(&"--behavior-version"));
+    assert_eq!(
+        simulate.flags,
+        [
+            "--schedule",
+            "--until",
+            "--period-days",
+            "--rounding",
+            "--tenant",
+            "--date-basis"
+        ]
+    );
+
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        let mut script = vec![];
+
+        write_completions(shell, &mut script).unwrap();
+
+        let script = String::from_utf8(script).unwrap();
+
+        assert!(script.contains("export-tx-index"));
+        assert!(script.contains("seed"));
+        assert!(script.contains("period-days"));
+    }
+}
+
+#[test]
+fn rounding_strategies_differ_on_halves() {
+    let rate = from_parts(0, 1250);
+    let rounded = |balance, rounding: Rounding| interest(from_parts(balance, 0), rate, &rounding);
+
+    assert_eq!(rounded(1, Rounding::TowardZero), from_parts(0, 12));
+    assert_eq!(rounded(1, Rounding::HalfUp), from_parts(0, 13));
+    assert_eq!(rounded(1, Rounding::HalfEven), from_parts(0, 12));
+    assert_eq!(rounded(3, Rounding::TowardZero), from_parts(0, 37));
+    assert_eq!(rounded(3, Rounding::HalfUp), from_parts(0, 38));
+    assert_eq!(rounded(3, Rounding::HalfEven), from_parts(0, 38));
+    assert_eq!("half-even".parse(), Ok(Rounding::HalfEven));
+}
+
+#[cfg(feature = "sql")]
+#[test]
+fn sql_queries_filter_the_summary() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 2, 2, 5.25
+    deposit, 3, 3, 7
+    dispute, 1, 1,
+    chargeback, 1, 1,",
+    ))
+    .unwrap();
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let rows = crate::query::query(
+        &database,
+        "SELECT client_id, total FROM summary WHERE NOT locked ORDER BY total DESC",
+        &mut writer,
+    )
+    .unwrap();
+
+    assert_eq!(rows, 2);
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+client_id,total
+3,7.0
+2,5.25
+"
+    );
+}
+
+#[test]
+fn cli_accepts_query_only_with_the_sql_feature() {
+    let args: Vec<String> = ["query", "input.csv", "SELECT * FROM summary"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+    assert_eq!(cli::parse(&args).is_ok(), cfg!(feature = "sql"));
+}
+
+#[test]
+fn cli_accepts_rules_only_with_the_scripting_feature() {
+    let args: Vec<String> = ["--rules", "rules.rhai", "input.csv"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+
+    assert_eq!(cli::parse(&args).is_ok(), cfg!(feature = "scripting"));
+}
+
+/*
+SQLite standing in for the reporting database, to see what an export writes.
+*/
+#[cfg(feature = "sql")]
+impl crate::export_db::ExportTarget for rusqlite::Connection {
+    fn create_tables(
+        &mut self,
+        tables: &crate::export_db::ExportTables,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                tenant TEXT, client_id INTEGER, available TEXT, held TEXT, total TEXT,
+                locked INTEGER, PRIMARY KEY (tenant, client_id)
+            );
+            CREATE TABLE IF NOT EXISTS {} (
+                tenant TEXT, sequence INTEGER, type TEXT, client_id INTEGER, tx INTEGER,
+                tx_uuid TEXT, amount TEXT, disputed_amount TEXT, date TEXT, memo TEXT
+            );",
+            tables.summaries, tables.ledger
+        ))?;
+
+        Ok(())
+    }
+
+    fn upsert_summaries(
+        &mut self,
+        table: &str,
+        rows: &[crate::export_db::SummaryExportRow],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction = self.transaction()?;
+
+        for row in rows {
+            transaction.execute(
+                &format!("INSERT OR REPLACE INTO {} VALUES (?, ?, ?, ?, ?, ?)", table),
+                rusqlite::params![
+                    row.tenant,
+                    row.client_id,
+                    row.available,
+                    row.held,
+                    row.total,
+                    row.locked
+                ],
+            )?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn append_ledger(
+        &mut self,
+        table: &str,
+        rows: &[crate::export_db::LedgerExportRow],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction = self.transaction()?;
+
+        for row in rows {
+            transaction.execute(
+                &format!(
+                    "INSERT INTO {} VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    table
+                ),
+                rusqlite::params![
+                    row.tenant,
+                    row.sequence,
+                    row.kind,
+                    row.client_id,
+                    row.tx,
+                    row.tx_uuid,
+                    row.amount,
+                    row.disputed_amount,
+                    row.date,
+                    row.memo
+                ],
+            )?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sql")]
+#[test]
+fn exports_write_the_summary_and_the_run_s_ledger() {
+    let mut database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount, tenant, date, memo
+    deposit, 1, 1, 10, acme, 2024-03-01, opening deposit
+    deposit, 2, 2, 5,, 2024-03-01,",
+    ))
+    .unwrap();
+    let first_events: BTreeMap<String, usize> = database
+        .tenants()
+        .map(|(tenant, accounts)| (tenant.to_owned(), accounts.events().len()))
+        .collect();
+
+    apply_transactions(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount, tenant, date, memo
+    withdrawal, 1, 3, 2.5, acme, 2024-03-02,
+    dispute, 2, 2,,, 2024-03-03, chargeback pending",
+        ),
+    )
+    .unwrap();
+
+    let mut connection = rusqlite::Connection::open_in_memory().unwrap();
+    let tables = crate::export_db::ExportTables::default();
+    let export =
+        crate::export_db::export(&mut connection, &tables, &database, &first_events, false)
+            .unwrap();
+
+    assert_eq!((export.summaries, export.ledger_rows), (2, 2));
+
+    let rows = |sql: &str| -> Vec<String> {
+        let mut statement = connection.prepare(sql).unwrap();
+        let columns = statement.column_count();
+
+        statement
+            .query_map([], |row| {
+                (0..columns)
+                    .map(|index| {
+                        row.get::<_, rusqlite::types::Value>(index)
+                            .map(|value| match value {
+                                rusqlite::types::Value::Null => String::new(),
+                                rusqlite::types::Value::Integer(value) => value.to_string(),
+                                rusqlite::types::Value::Text(value) => value,
+                                value => format!("{:?}", value),
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|fields| fields.join(","))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    };
+
+    assert_eq!(
+        rows("SELECT * FROM account_summaries ORDER BY tenant, client_id"),
+        [",2,0.0,5.0,5.0,0", "acme,1,7.5,0.0,7.5,0"]
+    );
+    assert_eq!(
+        rows("SELECT * FROM ledger_events ORDER BY tenant, sequence"),
+        [
+            ",2,dispute,2,2,,0.0,5.0,2024-03-03,chargeback pending",
+            "acme,2,withdrawal,1,3,,2.5,0.0,2024-03-02,",
+        ]
+    );
+}
+
+#[test]
+fn export_table_names_must_be_plain_identifiers() {
+    assert!(is_valid_table_name("account_summaries"));
+    assert!(is_valid_table_name("reporting.ledger_events"));
+    assert!(!is_valid_table_name(""));
+    assert!(!is_valid_table_name("1summaries"));
+    assert!(!is_valid_table_name("summaries; DROP TABLE ledger"));
+    assert!(!is_valid_table_name("reporting."));
+}
+
+#[test]
+fn balance_updates_are_keyed_by_tenant_and_client() {
+    let database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount, tenant
+    deposit, 7, 1, 10, acme
+    dispute, 7, 1,, acme",
+    ))
+    .unwrap();
+    let account = database
+        .tenant("acme")
+        .unwrap()
+        .account(ClientId(7))
+        .unwrap();
+    let update = BalanceUpdate::new("acme", account);
+
+    assert_eq!(update.key("balances"), "balances:acme:7");
+    assert_eq!(changes_channel("balances"), "balances:changes");
+    assert_eq!(
+        serde_json::to_string(&update).unwrap(),
+        r#"{"tenant":"acme","client_id":7,"available":"0.0","held":"10.0","locked":false}"#
+    );
+}
+
+/*
+A BalanceStore that writes down the commands it's sent, one line each, as a batch is flushed.
+*/
+#[derive(Default)]
+struct RecordedStore {
+    queued: Vec<String>,
+    sent: Vec<String>,
+}
+
+impl BalanceStore for RecordedStore {
+    fn set(&mut self, key: String, fields: Vec<(&'static str, String)>) {
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(field, value)| format!("{} {}", field, value))
+            .collect();
+
+        self.queued
+            .push(format!("HSET {} {}", key, fields.join(" ")));
+    }
+
+    fn publish(&mut self, channel: &str, message: String) {
+        self.queued.push(format!("PUBLISH {} {}", channel, message));
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.sent.append(&mut self.queued);
+
+        Ok(())
+    }
+}
+
+#[test]
+fn published_balances_announce_only_what_the_run_changed() {
+    let mut database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount, tenant
+    deposit, 1, 1, 10,
+    deposit, 7, 2, 10, acme",
+    ))
+    .unwrap();
+
+    database.clear_changes();
+    apply_transactions(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount, tenant
+    dispute, 7, 2,, acme",
+        ),
+    )
+    .unwrap();
+
+    let mut store = RecordedStore::default();
+
+    assert_eq!(
+        publish_to(&mut store, "balances", &database).unwrap(),
+        (2, 1)
+    );
+    assert!(store.queued.is_empty());
+    assert_eq!(
+        store.sent,
+        [
+            "HSET balances::1 available 10.0 held 0.0 locked false",
+            "HSET balances:acme:7 available 0.0 held 10.0 locked false",
+            r#"PUBLISH balances:changes {"tenant":"acme","client_id":7,"available":"0.0","held":"10.0","locked":false}"#,
+        ]
+    );
+}
+
+#[test]
+fn change_feed_reports_balances_around_each_transaction() {
+    let mut database = load_transactions(&mut text_reader(
+        "\
+    type, client, tx, amount
+    deposit, 1, 1, 10",
+    ))
+    .unwrap();
+    let first = database.tenant("").unwrap().events().len();
+
+    apply_transactions(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount, date
+    withdrawal, 1, 2, 4, 2024-03-01
+    dispute, 1, 1,, 2024-03-02",
+        ),
+    )
+    .unwrap();
+
+    let mut feed = vec![];
+    let count = write_changes("", database.tenant("").unwrap().events(), first, &mut feed).unwrap();
+
+    assert_eq!(count, 2);
+    assert_eq!(
+        String::from_utf8(feed).unwrap(),
+        r#"{"tenant":"","sequence":2,"type":"withdrawal","client_id":1,"tx":2,"date":"2024-03-01","before":{"available":"10.0","held":"0.0","locked":false},"after":{"available":"6.0","held":"0.0","locked":false}}
+{"tenant":"","sequence":3,"type":"dispute","client_id":1,"tx":1,"date":"2024-03-02","before":{"available":"6.0","held":"0.0","locked":false},"after":{"available":"0.0","held":"6.0","locked":false}}
+"#
+    );
+}
+
+/*
+Threads applying batches for the same clients at once: whatever order the batches land in, none
+is lost and each is applied in order, so every client ends up with exactly what the batches add
+up to.
+*/
+#[test]
+#[cfg_attr(loom, ignore = "loom's locks only work inside loom::model")]
+fn shared_database_applies_concurrent_batches_for_a_client_whole() {
+    const THREADS: u32 = 8;
+    const ROUNDS: u32 = 50;
+    const CLIENTS: u16 = 4;
+
+    let database = SharedDatabase::with_options(AccountOptions::default());
+
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let database = &database;
+
+            scope.spawn(move || {
+                for round in 0..ROUNDS {
+                    for client in 1..=CLIENTS {
+                        let client_id = ClientId(client);
+                        let id = |offset: u32| Id {
+                            client_id,
+                            transaction_id: TxId::Number(
+                                ((thread * ROUNDS + round) * CLIENTS as u32 + client as u32) * 10
+                                    + offset,
+                            ),
+                        };
+                        let batch = [
+                            TransactionRecord::Deposit {
+                                id: id(1),
+                                amount: from_parts(10, 0),
+                            },
+                            TransactionRecord::Dispute {
+                                id: id(1),
+                                amount: None,
+                            },
+                            TransactionRecord::Resolve {
+                                id: id(1),
+                                amount: None,
+                            },
+                            TransactionRecord::Withdrawl {
+                                id: id(2),
+                                amount: from_parts(3, 0),
+                            },
+                            TransactionRecord::Withdrawl {
+                                id: id(3),
+                                amount: from_parts(3, 0),
+                            },
+                        ];
+
+                        assert_eq!(database.apply_for_client(client_id, batch).unwrap(), 5);
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(
+        database.client_ids(),
+        (1..=CLIENTS).map(ClientId).collect::<Vec<_>>()
+    );
+
+    for client in 1..=CLIENTS {
+        let (available, held) = database
+            .with_client(ClientId(client), |accounts| {
+                let account = accounts.account(ClientId(client)).unwrap();
+
+                (account.available(), account.held())
+            })
+            .unwrap();
+
+        assert_eq!(available, from_parts(4 * THREADS * ROUNDS, 0));
+        assert_eq!(held, Money::zero());
+    }
+
+    let foreign = TransactionRecord::Deposit {
+        id: Id {
+            client_id: ClientId(2),
+            transaction_id: TxId::Number(1),
+        },
+        amount: from_parts(1, 0),
+    };
+
+    assert!(database.apply_for_client(ClientId(1), [foreign]).is_err());
+    assert_eq!(database.with_client(ClientId(9), |accounts| ()), None);
+}
+
+fn generated(profile: Profile, count: usize, seed: u64) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    assert_eq!(generate(profile, count, seed, &mut writer).unwrap(), count);
+
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+#[test]
+fn generated_profiles_are_repeatable_and_shaped_as_described() {
+    assert_eq!(
+        generated(Profile::Retail, 500, 3),
+        generated(Profile::Retail, 500, 3)
+    );
+    assert_ne!(
+        generated(Profile::Retail, 500, 3),
+        generated(Profile::Retail, 500, 4)
+    );
+
+    let disputes = |text: &str| text.matches("dispute,").count();
+    let busiest_share = |text: &str| {
+        let mut counts = std::collections::HashMap::new();
+
+        for line in text.lines().skip(1) {
+            *counts.entry(line.split(',').nth(1).unwrap()).or_insert(0) += 1;
+        }
+
+        *counts.values().max().unwrap() as f64 / text.lines().count() as f64
+    };
+    let retail = generated(Profile::Retail, 20000, 1);
+    let high_dispute = generated(Profile::HighDispute, 20000, 1);
+    let whales = generated(Profile::Whales, 20000, 1);
+
+    assert!(disputes(&high_dispute) > 5 * disputes(&retail));
+    assert!(busiest_share(&whales) > 2.0 * busiest_share(&retail));
+    assert!(test_case(&whales).lines().count() > 1);
+}
+
+#[test]
+fn partitioning_moves_only_clients_for_the_new_shard() {
+    for client in 0..2000 {
+        let client_id = ClientId(client);
+
+        assert_eq!(partition(client_id, 1), 0);
+
+        for shards in 1..8 {
+            let before = partition(client_id, shards);
+            let after = partition(client_id, shards + 1);
+
+            assert!(after == before || after == shards);
+        }
+    }
+
+    let shares: Vec<usize> = (0..4)
+        .map(|shard| {
+            (0..4000)
+                .filter(|&client| partition(ClientId(client), 4) == shard)
+                .count()
+        })
+        .collect();
+
+    assert!(shares.iter().all(|&share| share > 800), "{:?}", shares);
+}
+
+#[test]
+fn split_keeps_reused_transaction_ids_with_their_first_owner() {
+    let text = "\
+        type,       client, tx, amount
+        deposit,    1,      1,  5.0
+        deposit,    2,      2,  3.0
+        dispute,    2,      1,
+        deposit,    2,      1,  9.0
+        resolve,    1,      1,";
+    let mut writers: Vec<_> = (0..4).map(|_| csv::Writer::from_writer(vec![])).collect();
+
+    let counts = split(&mut text_reader(text), &mut writers).unwrap();
+
+    let shards: Vec<String> = writers
+        .into_iter()
+        .map(|writer| String::from_utf8(writer.into_inner().unwrap()).unwrap())
+        .collect();
+    let rows = |shard: usize| -> Vec<&str> { shards[shard].lines().skip(1).collect() };
+
+    assert_eq!(counts.iter().sum::<usize>(), 5);
+    assert_eq!(
+        rows(partition(ClientId(1), 4) as usize)
+            .into_iter()
+            .filter(|row| row.split(',').nth(2) == Some("1"))
+            .collect::<Vec<_>>(),
+        [
+            "deposit,1,1,5.0",
+            "dispute,2,1,",
+            "deposit,2,1,9.0",
+            "resolve,1,1,"
+        ]
+    );
+    assert!(rows(partition(ClientId(2), 4) as usize).contains(&"deposit,2,2,3.0"));
+    assert!(shards
+        .iter()
+        .all(|shard| shard.starts_with("type,client,tx,amount\n")));
+}
+
+#[test]
+fn filter_keeps_the_earlier_rows_its_picks_depend_on() {
+    let text = "\
+        type,       client, tx, amount, date
+        deposit,    1,      1,  10,     2024-01-01
+        deposit,    2000,   2,  5,      2024-01-02
+        dispute,    1,      1,  ,       2024-02-01
+        withdrawal, 1,      3,  1,      2024-02-02
+        chargeback, 1,      1,  ,       2024-03-01
+        deposit,    3,      4,  1,      2024-03-02
+        deposit,    4,      5,  1,";
+    let transaction_filter = TransactionFilter {
+        kinds: TransactionFilter::parse_kinds("chargeback, Deposit").unwrap(),
+        clients: "1-1000".parse().ok(),
+        from: "2024-02-15".parse().ok(),
+    };
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    let filtered = filter(&mut text_reader(text), &mut writer, &transaction_filter).unwrap();
+
+    assert_eq!(
+        filtered,
+        Filtered {
+            matched_rows: 2,
+            referenced_rows: 2,
+        }
+    );
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "\
+type,client,tx,amount,date
+deposit,1,1,10,2024-01-01
+dispute,1,1,,2024-02-01
+chargeback,1,1,,2024-03-01
+deposit,3,4,1,2024-03-02
+"
+    );
+
+    assert_eq!(
+        "7".parse(),
+        Ok(ClientRange {
+            first: ClientId(7),
+            last: ClientId(7),
+        })
+    );
+    assert_eq!("9-3".parse::<ClientRange>(), Err(()));
+    assert_eq!(
+        TransactionFilter::parse_kinds("deposit,refund"),
+        Err("unknown transaction type: refund".to_owned())
+    );
+}
+
+#[test]
+fn minimize_cuts_a_log_down_to_what_reproduces() {
+    let numbers: Vec<u32> = (0..40).collect();
+
+    assert_eq!(
+        ddmin(&numbers, |subset| subset.contains(&3)
+            && subset.contains(&31)),
+        [3, 31]
+    );
+
+    let text = "\
+        type,       client, tx, amount
+        deposit,    1,      1,  10
+        deposit,    2,      2,  5
+        deposit,    2,      3,  4
+        dispute,    2,      2,
+        withdrawal, 1,      4,  3
+        deposit,    3,      5,  1
+        chargeback, 2,      2,
+        deposit,    2,      6,  1";
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    let minimized = minimize(
+        &mut text_reader(text),
+        &mut writer,
+        Predicate::Locked(ClientId(2)),
+        "",
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "type,client,tx,amount\ndeposit,2,2,5\ndispute,2,2,\nchargeback,2,2,\n"
+    );
+    assert_eq!((minimized.original_rows, minimized.kept_rows), (8, 3));
+
+    assert!(minimize(
+        &mut text_reader(text),
+        &mut csv::Writer::from_writer(vec![]),
+        Predicate::Locked(ClientId(1)),
+        "",
+    )
+    .is_err());
+    assert_eq!("locked:7".parse(), Ok(Predicate::Locked(ClientId(7))));
+    assert_eq!("panics".parse(), Ok(Predicate::Panics));
+    assert_eq!("locked".parse::<Predicate>(), Err(()));
+}
+
+#[test]
+fn merging_shard_summaries_orders_clients_and_drops_conflicts() {
+    let shards = [
+        "tenant,client_id,available,held,total,locked\n\
+         a,10,1.0,0.0,1.0,false\n\
+         b,2,5.0,0.0,5.0,false\n",
+        "tenant,client_id,available,held,total,locked\n\
+         a,9,3.0,0.0,3.0,true\n\
+         b,2,1.0,0.0,1.0,false\n",
+    ];
+    let mut readers: Vec<_> = shards.iter().map(|shard| text_reader(shard)).collect();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    let merged = merge(&mut readers, &mut writer).unwrap();
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        "tenant,client_id,available,held,total,locked\n\
+         a,9,3.0,0.0,3.0,true\n\
+         a,10,1.0,0.0,1.0,false\n"
+    );
+    assert_eq!(merged.accounts, 2);
+    assert_eq!(
+        merged.conflicts,
+        [MergeConflict {
+            tenant: "b".to_owned(),
+            client_id: "2".to_owned(),
+            shards: "0 1".to_owned(),
+        }]
+    );
+
+    let mut readers = vec![
+        text_reader("client_id,available\n1,1.0\n"),
+        text_reader("tenant,client_id,available\na,1,1.0\n"),
+    ];
+
+    assert!(merge(&mut readers, &mut csv::Writer::from_writer(vec![])).is_err());
+}
+
+#[test]
+fn mirror_holds_only_accepted_transactions_and_replays_to_the_same_balances() {
+    let text = "\
+        type,       client, tx, amount, date
+        deposit,    1,      1,  5.0,    2024-01-01
+        deposit,    1,      1,  7.0,    2024-01-01
+        deposit,    2,      2,  3.0,    2024-01-02
+        dispute,    1,      9,,         2024-01-02
+        dispute,    2,      2,,         2024-01-03
+        withdrawal, 1,      3,  1.5,    2024-01-04";
+    let database = load_transactions(&mut text_reader(text)).unwrap();
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    assert_eq!(
+        write_mirror(&database, &Default::default(), &mut writer).unwrap(),
+        4
+    );
+
+    let mirror = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+    assert_eq!(
+        mirror,
+        "sequence,tenant,type,client,tx,amount,date,memo\n\
+         1,,deposit,1,1,5.0,2024-01-01,\n\
+         3,,deposit,2,2,3.0,2024-01-02,\n\
+         4,,dispute,2,2,3.0,2024-01-03,\n\
+         5,,withdrawal,1,3,1.5,2024-01-04,\n"
+    );
+
+    let replayed = load_transactions(&mut text_reader(&mirror)).unwrap();
+    let balances = |database: &TenantDatabase| -> Vec<(Money, Money)> {
+        database
+            .tenant("")
+            .unwrap()
+            .accounts()
+            .map(|account| (account.available(), account.held()))
+            .collect()
+    };
+
+    assert_eq!(balances(&replayed), balances(&database));
+}
+
+#[test]
+fn search_pages_through_matching_accounts_by_client() {
+    let accounts = database_case(
+        "\
+        type,       client, tx, amount
+        deposit,    1,      1,  5.0
+        deposit,    2,      2,  50.0
+        deposit,    3,      3,  20.0
+        deposit,    4,      4,  30.0
+        deposit,    5,      5,  1.0
+        dispute,    4,      4,
+        dispute,    3,      3,
+        chargeback, 3,      3,",
+    );
+    let client_ids = |filter: &AccountFilter, after: Option<u16>, limit: usize| {
+        let page = search(&accounts, filter, after.map(ClientId), limit);
+
+        (
+            page.accounts
+                .iter()
+                .map(|account| account.client_id().0)
+                .collect::<Vec<_>>(),
+            page.next.map(|client_id| client_id.0),
+        )
+    };
+    let large = AccountFilter {
+        min_total: Some(from_parts(5, 0)),
+        ..AccountFilter::default()
+    };
+
+    assert_eq!(client_ids(&large, None, 2), (vec![1, 2], Some(2)));
+    assert_eq!(client_ids(&large, Some(2), 2), (vec![3, 4], None));
+    assert_eq!(
+        client_ids(&AccountFilter::default(), Some(3), 5),
+        (vec![4, 5], None)
+    );
+    assert_eq!(
+        client_ids(
+            &AccountFilter {
+                locked_only: true,
+                ..AccountFilter::default()
+            },
+            None,
+            5
+        ),
+        (vec![3], None)
+    );
+    assert_eq!(
+        client_ids(
+            &AccountFilter {
+                open_dispute_only: true,
+                max_total: Some(from_parts(30, 0)),
+                ..AccountFilter::default()
+            },
+            None,
+            5
+        ),
+        (vec![4], None)
+    );
+}
+
+#[test]
+fn backfill_replays_corrections_where_the_originals_were() {
+    let log = "\
+        type,       client, tx, amount
+        deposit,    1,      1,  10.0
+        withdrawal, 1,      2,  8.0
+        deposit,    2,      3,  5.0
+        dispute,    2,      3,
+        deposit,    3,      4,  1.0";
+    let corrections = "\
+        type,       client, tx, amount
+        correction, 1,      1,  6.0
+        correction, 2,      3,  7.5";
+
+    let backfilled = backfill(&mut text_reader(log), &mut text_reader(corrections)).unwrap();
+
+    assert_eq!(
+        backfilled.deltas,
+        [
+            AccountDelta {
+                tenant: String::new(),
+                client_id: ClientId(1),
+                available_before: "2.0".to_owned(),
+                available_after: "6.0".to_owned(),
+                held_before: "0.0".to_owned(),
+                held_after: "0.0".to_owned(),
+                locked_before: false,
+                locked_after: false,
+            },
+            AccountDelta {
+                tenant: String::new(),
+                client_id: ClientId(2),
+                available_before: "0.0".to_owned(),
+                available_after: "0.0".to_owned(),
+                held_before: "5.0".to_owned(),
+                held_after: "7.5".to_owned(),
+                locked_before: false,
+                locked_after: false,
+            },
+        ]
+    );
+
+    let reversal = "\
+        type,     client, tx, amount
+        reversal, 3,      4,";
+    let backfilled = backfill(&mut text_reader(log), &mut text_reader(reversal)).unwrap();
+
+    assert_eq!(backfilled.deltas.len(), 1);
+    assert_eq!(backfilled.deltas[0].available_after, "0.0");
+
+    for wrong in [
+        "type, client, tx, amount\nreversal, 2, 4,",
+        "type, client, tx, amount\nreversal, 2, 99,",
+        "type, client, tx, amount\ncorrection, 2, 3,",
+    ] {
+        assert!(backfill(&mut text_reader(log), &mut text_reader(wrong)).is_err());
+    }
+}
+
+#[test]
+fn backfill_and_admin_report_malformed_rows() {
+    let log = "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    refund, 1, 2, 10";
+    let corrections = "\
+    type, client, tx, amount
+    correction, 1, 1, 6";
+    let error = backfill(&mut text_reader(log), &mut text_reader(corrections))
+        .err()
+        .unwrap();
+
+    assert_eq!(
+        error
+            .downcast_ref::<RowError>()
+            .map(|error| (error.line, error.code)),
+        Some((3, ErrorCode::UnknownType))
+    );
+
+    let script = "\
+    op, client, tx, amount, operator, reason
+    adjustment, 1, 10, 2, alice, incident-42
+    approve, 1, 10,, bob, incident-42";
+    let (_, results) = admin_case(log, script, false);
+
+    assert_eq!(
+        results,
+        [
+            "2 adjustment applied ",
+            "3 approve failed line 3: E1005 unknown transaction type: refund",
+        ]
+    );
+}
+
+#[test]
+fn summary_diff_reports_created_removed_and_changed_accounts() {
+    let before = Snapshot::load(&mut text_reader(
+        "\
+        client_id, available, held, total, locked
+        1,         5.0,       0.0,  5.0,   false
+        2,         3.0,       1.0,  4.0,   false
+        3,         7.0,       0.0,  7.0,   false
+        4,         2.0,       0.0,  2.0,   false",
+    ))
+    .unwrap();
+    let after = Snapshot::from_database(
+        &load_transactions(&mut text_reader(
+            "\
+            type,       client, tx, amount
+            deposit,    1,      1,  5.0
+            deposit,    2,      2,  4.0
+            dispute,    2,      2,
+            chargeback, 2,      2,
+            deposit,    3,      3,  7.0
+            withdrawal, 3,      4,  1.0
+            deposit,    5,      5,  1.0",
+        ))
+        .unwrap(),
+    );
+    let state = |available: u32, held: u32, locked: bool| AccountState {
+        available: from_parts(available, 0),
+        held: from_parts(held, 0),
+        locked,
+    };
+
+    let diff = AccountSummaryDiff::between(&before, &after);
+    let changes: Vec<(u16, AccountChange)> = diff
+        .clients
+        .iter()
+        .map(|client| (client.client_id.0, client.change))
+        .collect();
+
+    assert_eq!(
+        changes,
+        [
+            (
+                2,
+                AccountChange::Changed {
+                    before: state(3, 1, false),
+                    after: state(4, 0, true),
+                }
+            ),
+            (
+                3,
+                AccountChange::Changed {
+                    before: state(7, 0, false),
+                    after: state(6, 0, false),
+                }
+            ),
+            (4, AccountChange::Removed(state(2, 0, false))),
+            (5, AccountChange::Created(state(1, 0, false))),
+        ]
+    );
+    assert!(changes[0].1.status_changed() && changes[0].1.balance_changed());
+    assert!(!changes[1].1.status_changed());
+    assert_eq!(AccountSummaryDiff::between(&after, &after).clients, []);
+}
+
+/*
+Parsing a row still allocates its fields, so the loop isn't allocation-free yet; this holds it
+to what it costs today, so a change that adds to it has to say so here.
+*/
+#[cfg(feature = "perf")]
+#[test]
+fn summarizing_stays_within_its_allocation_budget() {
+    let allocations = |transactions: u32| {
+        let mut text = "type, client, tx, amount\n".to_owned();
+
+        for tx in 1..=transactions {
+            text += &format!("deposit, {}, {}, 1\n", tx % 10, tx);
+        }
+
+        let (output, stats) = summary_case(
+            "type, client, tx, amount",
+            &text,
+            &SummaryOptions::default(),
+        );
+
+        stats.allocations.unwrap().allocations
+    };
+
+    /*
+    The difference leaves out what a run costs however long it is.
+    */
+    let per_transaction = (allocations(2000) - allocations(1000)) / 1000;
+
+    assert!(per_transaction <= 14, "{} per transaction", per_transaction);
+}
+
+#[test]
+fn latencies_are_kept_by_type_and_outcome() {
+    let mut histogram = LatencyHistogram::new();
+
+    for nanos in 1..=100 {
+        histogram.record(Duration::from_nanos(nanos * 1000));
+    }
+
+    /*
+    Buckets are at most an eighth wider than their lower bound.
+    */
+    for (quantile, exact) in [(0.5, 50_000), (0.95, 95_000), (0.99, 99_000)] {
+        let reported = histogram.quantile(quantile);
+
+        assert!(reported <= exact && exact - reported <= reported / 8);
+    }
+
+    let mut database = TenantDatabase::new();
+
+    database.record_latencies();
+    apply_transactions(
+        &mut database,
+        &mut text_reader(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    deposit, 1, 2, 5
+    withdrawal, 1, 3, 50
+    dispute, 1, 9,
+    dispute, 1, 1,",
+        ),
+    )
+    .unwrap();
+
+    let counts: Vec<_> = RunStats::collect(&database, 5)
+        .apply_latencies
+        .unwrap()
+        .iter()
+        .map(|summary| (summary.kind, summary.outcome, summary.count))
+        .collect();
+
+    assert_eq!(
+        counts,
+        [
+            ("deposit", "applied", 2),
+            ("dispute", "applied", 1),
+            ("dispute", "rejected", 1),
+            ("withdrawal", "refused", 1),
+        ]
+    );
+}
+
+#[test]
+fn row_errors_report_their_line_column_and_code() {
+    let report = |text: &str| {
+        let mut reader = text_reader(text);
+        let error = load_transactions(&mut reader).err().unwrap();
+
+        ErrorReport::new(&*error, reader.headers().ok())
+    };
+
+    assert_eq!(
+        report(
+            "\
+    type, client, tx, amount
+    deposit, 1, 1, 10
+    withdrawal, 1, 2, ten"
+        ),
+        ErrorReport {
+            line: Some(3),
+            column: Some("amount".to_owned()),
+            code: ErrorCode::InvalidAmount,
+            message: "invalid amount for a withdrawal: ten".to_owned(),
+        }
+    );
+    assert_eq!(
+        report(
+            "\
+    type, client, tx, amount
+    refund, 1, 1, 10"
+        )
+        .code,
+        ErrorCode::UnknownType
+    );
+    assert_eq!(
+        report(
+            "\
+    type, client, tx, amount, date
+    deposit, 1, 1, 10, 2024-01-01
+    deposit, 1, 2, 10, yesterday"
+        )
+        .column
+        .as_deref(),
+        Some("date")
+    );
+
+    let bytes = b"type,client,tx,amount\ndeposit,1,\xff,10\n";
+    let mut reader = ReaderBuilder::default().from_reader(&bytes[..]);
+    let error = load_transactions(&mut reader).err().unwrap();
+
+    assert_eq!(
+        ErrorReport::new(&*error, reader.headers().ok()).column,
+        Some("tx".to_owned())
+    );
+}
+
+#[test]
+fn error_codes_are_unique_and_parse_back() {
+    let mut codes: Vec<_> = ErrorCode::ALL.iter().map(|code| code.code()).collect();
+
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), ErrorCode::ALL.len());
+
+    for code in ErrorCode::ALL {
+        assert_eq!(code.code().parse(), Ok(code));
+        assert_eq!(code.name().parse(), Ok(code));
+    }
+
+    assert_eq!(ErrorCode::DuplicateTx.to_string(), "E1001");
+    assert_eq!("e2001".parse(), Ok(ErrorCode::ClientMismatch));
+    assert_eq!("E9999".parse::<ErrorCode>(), Err(()));
+}
+
+#[test]
+fn every_error_code_is_explained() {
+    for code in ErrorCode::ALL {
+        let explanation = code.explanation();
+
+        assert!(explanation.contains("Common causes:"), "{}", code);
+        assert!(explanation.contains("Remediation:"), "{}", code);
+        assert!(explanation.ends_with('\n'), "{}", code);
+    }
+
+    let parse = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+
+        cli::parse(&args)
+    };
+
+    assert_eq!(
+        parse(&["explain", "E2003"]),
+        Ok(Command::Explain {
+            code: Some(ErrorCode::PartiallyCovered)
+        })
+    );
+    assert_eq!(parse(&["explain"]), Ok(Command::Explain { code: None }));
+    assert_eq!(
+        parse(&["explain", "E0000"]),
+        Err("unknown error code: E0000".to_owned())
+    );
+}
+
+#[test]
+fn sinks_each_get_every_summary_row() {
+    let text = "type, client, tx, amount\ndeposit, 1, 1, 42.05\nwithdrawal, 1, 2, 2\n";
+    let path = |extension: &str| {
+        std::env::temp_dir().join(format!(
+            "fizzbuzz-sink-{}.{}",
+            std::process::id(),
+            extension
+        ))
+    };
+    let specs: Vec<SinkSpec> = [
+        format!("csv:{}", path("csv").display()),
+        format!("json:{}", path("jsonl").display()),
+    ]
+    .iter()
+    .map(|spec| spec.parse().unwrap())
+    .collect();
+    let mut fan_out = FanOut::new();
+
+    for spec in &specs {
+        fan_out.push(spec.open(None).unwrap());
+    }
+
+    summarize(
+        &mut TenantDatabase::new(),
+        &mut text_reader(text),
+        &mut fan_out,
+        &SummaryOptions::default(),
+    )
+    .unwrap();
+
+    let csv = std::fs::read_to_string(path("csv")).unwrap();
+    let json = std::fs::read_to_string(path("jsonl")).unwrap();
+
+    std::fs::remove_file(path("csv")).unwrap();
+    std::fs::remove_file(path("jsonl")).unwrap();
+
+    assert_eq!(csv, test_case(text));
+    assert_eq!(
+        json,
+        "{\"client_id\":1,\"available\":\"40.05\",\"held\":\"0.0\",\"total\":\"40.05\",\"locked\":false}\n"
+    );
+
+    assert_eq!(
+        "json:-".parse::<SinkSpec>().map(|spec| spec.is_stdout()),
+        Ok(true)
+    );
+    assert_eq!(
+        "parquet:summary.parquet".parse::<SinkSpec>(),
+        Err("unsupported sink format: parquet (csv or json)".to_owned())
+    );
+    assert!("summary.csv".parse::<SinkSpec>().is_err());
+}
+
+#[test]
+fn sinks_can_each_redact_the_summary() {
+    let text = "type, client, tx, amount\ndeposit, 1, 1, 10\ndeposit, 1, 2, 5\ndispute, 1, 2,\n";
+    let spec: SinkSpec = "csv[pseudonymize,drop=held]:analytics.csv".parse().unwrap();
+
+    assert_eq!(
+        spec.redaction,
+        Redaction {
+            pseudonymize: true,
+            drop: vec!["held"],
+        }
+    );
+
+    let redacted = Redacted::new(
+        Box::new(csv::Writer::from_writer(vec![])),
+        Some(Pseudonymizer::new("salt")),
+        spec.redaction.drop.clone(),
+    );
+    let database = load_transactions(&mut text_reader(text)).unwrap();
+    let account = database.tenant("").unwrap().accounts().next().unwrap();
+    let row = redacted.redact(&SummaryRow::V1(account.into())).unwrap();
+    let token = Pseudonymizer::new("salt").token("", ClientId(1));
+
+    assert_eq!(
+        row.fields,
+        vec![
+            ("client_id", serde_json::json!(token)),
+            ("available", serde_json::json!("10.0")),
+            ("total", serde_json::json!("15.0")),
+            ("locked", serde_json::json!(false)),
+        ]
+    );
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    writer.serialize(SummaryRow::Redacted(row)).unwrap();
+    assert_eq!(
+        String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+        format!(
+            "client_id,available,total,locked\n{},10.0,15.0,false\n",
+            token
+        )
+    );
+
+    assert_eq!(
+        "json[drop=balance]:-".parse::<SinkSpec>(),
+        Err("no summary column named balance".to_owned())
+    );
+    assert_eq!(
+        "json[hash]:-".parse::<SinkSpec>(),
+        Err("unknown sink option: hash".to_owned())
+    );
+    assert!("csv[pseudonymize]:-"
+        .parse::<SinkSpec>()
+        .unwrap()
+        .open(None)
+        .is_err());
+}
+
+/*
+Summarizes `text` with the engine and with the reference model, and compares them account by
+account.  Returns how many accounts there were.
+*/
+fn differential_case(text: &str) -> usize {
+    let mut reference = ReferenceEngine::default();
+
+    for record in text_reader(text).records() {
+        let record = record.unwrap();
+
+        reference.apply(
+            &record[0],
+            record[1].parse().unwrap(),
+            record[2].parse().unwrap(),
+            Ratio::parse(&record[3]),
+        );
+    }
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    summarize(
+        &mut TenantDatabase::new(),
+        &mut text_reader(text),
+        &mut writer,
+        &SummaryOptions::default(),
+    )
+    .unwrap();
+
+    let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+    let engine: BTreeMap<u16, ReferenceAccount> = text_reader(&output)
+        .records()
+        .map(|record| {
+            let record = record.unwrap();
+            let account = ReferenceAccount {
+                available: Ratio::parse(&record[1]).unwrap(),
+                held: Ratio::parse(&record[2]).unwrap(),
+                locked: record[4].parse().unwrap(),
+            };
+
+            assert_eq!(
+                Ratio::parse(&record[3]),
+                Some(account.available + account.held),
+                "total of client {}",
+                &record[0]
+            );
+            (record[0].parse().unwrap(), account)
+        })
+        .collect();
+
+    assert_eq!(&engine, reference.accounts(), "summarizing:\n{}", text);
+
+    engine.len()
+}
+
+#[test]
+fn engine_agrees_with_the_reference_model() {
+    for seed in 0..300 {
+        differential_case(&random_ledger(seed, 60));
+    }
+
+    for profile in [Profile::Retail, Profile::HighDispute, Profile::Whales] {
+        let mut writer = csv::Writer::from_writer(vec![]);
+
+        generate(profile, 3000, 7, &mut writer).unwrap();
+
+        assert!(differential_case(&String::from_utf8(writer.into_inner().unwrap()).unwrap()) > 0);
+    }
+}
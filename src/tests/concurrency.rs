@@ -0,0 +1,140 @@
+/*
+Model checks of SharedDatabase under loom, which runs each test through every interleaving of
+its threads' lock operations.  They only build with loom's locks in place:
+
+    RUSTFLAGS="--cfg loom" cargo test --release concurrency
+
+The scenarios are kept to two or three threads and a few transactions each -- loom's search
+grows with every lock operation.
+*/
+use loom::{sync::Arc, thread};
+
+use crate::{
+    accounts::AccountOptions,
+    shared::SharedDatabase,
+    transactions::{ClientId, Id, TransactionRecord, TxId},
+    Money,
+};
+
+fn id(client: u16, tx: u32) -> Id {
+    Id {
+        client_id: ClientId(client),
+        transaction_id: TxId::Number(tx),
+    }
+}
+
+fn deposit(client: u16, tx: u32, amount: u64) -> TransactionRecord {
+    TransactionRecord::Deposit {
+        id: id(client, tx),
+        amount: Money(amount * 10000),
+    }
+}
+
+fn balances(database: &SharedDatabase, client: u16) -> Option<(Money, Money)> {
+    database
+        .with_client(ClientId(client), |accounts| {
+            accounts
+                .account(ClientId(client))
+                .map(|account| (account.available(), account.held()))
+        })
+        .flatten()
+}
+
+/*
+Two threads depositing for the same client at once: both deposits land, whichever order they
+take the lock in.
+*/
+#[test]
+fn concurrency_same_client_deposits_are_never_lost() {
+    loom::model(|| {
+        let database = Arc::new(SharedDatabase::with_options(AccountOptions::default()));
+        let threads: Vec<_> = [(1, 5), (2, 7)]
+            .into_iter()
+            .map(|(tx, amount)| {
+                let database = database.clone();
+
+                thread::spawn(move || {
+                    database
+                        .apply_for_client(ClientId(1), [deposit(1, tx, amount)])
+                        .unwrap();
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(balances(&database, 1), Some((Money(120000), Money(0))));
+    });
+}
+
+/*
+Two threads opening different clients at once: neither client's database is lost to the other's
+insert into the map of clients.
+*/
+#[test]
+fn concurrency_clients_opened_at_once_are_both_kept() {
+    loom::model(|| {
+        let database = Arc::new(SharedDatabase::with_options(AccountOptions::default()));
+        let threads: Vec<_> = [1, 2]
+            .into_iter()
+            .map(|client| {
+                let database = database.clone();
+
+                thread::spawn(move || {
+                    database
+                        .apply_for_client(ClientId(client), [deposit(client, client as u32, 1)])
+                        .unwrap();
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(database.client_ids(), [ClientId(1), ClientId(2)]);
+    });
+}
+
+/*
+A reader snapshotting a client while a batch moves its funds from available to held: it sees the
+account before the batch or after it, never halfway -- funds are never counted twice or not at
+all, and a snapshot never has the dispute without the deposit it disputes.
+*/
+#[test]
+fn concurrency_snapshots_never_see_a_batch_half_applied() {
+    loom::model(|| {
+        let database = Arc::new(SharedDatabase::with_options(AccountOptions::default()));
+        let writer = {
+            let database = database.clone();
+
+            thread::spawn(move || {
+                database
+                    .apply_for_client(
+                        ClientId(1),
+                        [
+                            deposit(1, 1, 10),
+                            TransactionRecord::Dispute {
+                                id: id(1, 1),
+                                amount: None,
+                            },
+                        ],
+                    )
+                    .unwrap();
+            })
+        };
+
+        let snapshot = balances(&database, 1);
+
+        writer.join().unwrap();
+
+        assert!(
+            matches!(snapshot, None | Some((Money(0), Money(100000)))),
+            "torn snapshot: {:?}",
+            snapshot
+        );
+        assert_eq!(balances(&database, 1), Some((Money(0), Money(100000))));
+    });
+}
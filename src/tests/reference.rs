@@ -0,0 +1,241 @@
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    ops::{Add, Sub},
+};
+
+use crate::generate::SplitMix64;
+
+/*
+A slow, plain model of what the engine does with deposits, withdrawals and disputes under the
+default options, to check the engine against: a BTreeMap of accounts, a BTreeMap of
+transactions, and exact fractions for amounts, so nothing about fixed-point money can leak in.
+
+The rules, as the engine has them:
+
+- A dispute, resolve or chargeback for a client with no account is dropped.  Any other row
+  opens the client's account, even if it then does nothing.
+- A deposit or withdrawal whose tx id is taken does nothing.  Otherwise the id is taken --
+  even by a withdrawal that's refused.
+- A withdrawal goes through only if the account has more available than it asks for.
+- A dispute names a deposit or withdrawal of the same client that isn't already disputed, and
+  holds as much of its amount as is available.
+- A resolve or chargeback names a disputed transaction of the same client, and moves as much
+  of the disputed amount as is held back to available.  A chargeback of a non-zero amount
+  locks the account; a locked account goes on taking transactions.
+*/
+#[derive(Default)]
+pub struct ReferenceEngine {
+    accounts: BTreeMap<u16, ReferenceAccount>,
+    transactions: BTreeMap<u32, (u16, Ratio)>,
+    disputes: BTreeMap<u32, Ratio>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct ReferenceAccount {
+    pub available: Ratio,
+    pub held: Ratio,
+    pub locked: bool,
+}
+
+impl ReferenceEngine {
+    pub fn apply(&mut self, kind: &str, client: u16, tx: u32, amount: Option<Ratio>) {
+        let references = matches!(kind, "dispute" | "resolve" | "chargeback");
+        let owner = self.transactions.get(&tx).map(|&(owner, _)| owner);
+
+        if references && owner != Some(client) && !self.accounts.contains_key(&client) {
+            return;
+        }
+
+        let account = self.accounts.entry(client).or_default();
+
+        match kind {
+            "deposit" | "withdrawal" if owner.is_some() => {}
+            "deposit" => {
+                let amount = amount.unwrap_or_default();
+
+                self.transactions.insert(tx, (client, amount));
+                account.available = account.available + amount;
+            }
+            "withdrawal" => {
+                let amount = amount.unwrap_or_default();
+
+                self.transactions.insert(tx, (client, amount));
+
+                if amount < account.available {
+                    account.available = account.available - amount;
+                }
+            }
+            _ if owner != Some(client) => {}
+            "dispute" if !self.disputes.contains_key(&tx) => {
+                let disputed = self.transactions[&tx].1;
+                let moved = disputed.min(account.available);
+
+                self.disputes.insert(tx, disputed);
+                account.available = account.available - moved;
+                account.held = account.held + moved;
+            }
+            "resolve" | "chargeback" if self.disputes.contains_key(&tx) => {
+                let disputed = self.disputes.remove(&tx).unwrap_or_default();
+                let moved = disputed.min(account.held);
+
+                account.held = account.held - moved;
+                account.available = account.available + moved;
+
+                if kind == "chargeback" && disputed > Ratio::default() {
+                    account.locked = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<u16, ReferenceAccount> {
+        &self.accounts
+    }
+}
+
+/*
+An exact fraction, kept in lowest terms with a positive denominator so equal values compare
+equal.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Ratio {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Ratio {
+    pub fn new(numerator: i128, denominator: i128) -> Ratio {
+        assert!(denominator != 0);
+
+        let divisor = gcd(numerator.abs(), denominator.abs()).max(1) * denominator.signum();
+
+        Ratio {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    /*
+    A decimal as the engine writes it, e.g. "12.5" or "0.0001".
+    */
+    pub fn parse(text: &str) -> Option<Ratio> {
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        let digits = format!("{}{}", whole, fraction);
+
+        Some(Ratio::new(
+            digits.parse().ok()?,
+            10i128.pow(fraction.len() as u32),
+        ))
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Default for Ratio {
+    fn default() -> Ratio {
+        Ratio::new(0, 1)
+    }
+}
+
+impl PartialEq for Ratio {
+    fn eq(&self, other: &Ratio) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ratio {}
+
+impl PartialOrd for Ratio {
+    fn partial_cmp(&self, other: &Ratio) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ratio {
+    fn cmp(&self, other: &Ratio) -> Ordering {
+        (self.numerator * other.denominator).cmp(&(other.numerator * self.denominator))
+    }
+}
+
+impl Add for Ratio {
+    type Output = Ratio;
+
+    fn add(self, other: Ratio) -> Ratio {
+        Ratio::new(
+            self.numerator * other.denominator + other.numerator * self.denominator,
+            self.denominator * other.denominator,
+        )
+    }
+}
+
+impl Sub for Ratio {
+    type Output = Ratio;
+
+    fn sub(self, other: Ratio) -> Ratio {
+        Ratio::new(
+            self.numerator * other.denominator - other.numerator * self.denominator,
+            self.denominator * other.denominator,
+        )
+    }
+}
+
+/*
+A random ledger of `rows` rows over a few clients, meant to find the corners: withdrawals of
+exactly what's available or more, reused tx ids, disputes of withdrawals and of other clients'
+transactions, resolves and chargebacks without a dispute, and transactions after a lock.
+Amounts have up to four decimal places, as the engine keeps.
+*/
+pub fn random_ledger(seed: u64, rows: usize) -> String {
+    let mut random = SplitMix64(seed);
+    let mut text = "type,client,tx,amount\n".to_owned();
+    let mut next_tx = 1;
+
+    for _ in 0..rows {
+        let client = 1 + random.below(5);
+        let roll = random.below(100);
+        /*
+        A third of the time, an id that's been used before -- or one that hasn't, yet.
+        */
+        let tx = if random.below(3) == 0 {
+            1 + random.below(next_tx + 1)
+        } else {
+            next_tx += 1;
+            next_tx - 1
+        };
+        let mut amount = || {
+            let scale = [1, 100, 10_000][random.below(3) as usize];
+            let units = random.below(50 * scale) + 1;
+
+            Ratio::new(units as i128, scale as i128)
+        };
+        let line = match roll {
+            0..=39 => format!("deposit,{},{},{}", client, tx, decimal(amount())),
+            40..=64 => format!("withdrawal,{},{},{}", client, tx, decimal(amount())),
+            65..=79 => format!("dispute,{},{},", client, tx),
+            80..=89 => format!("resolve,{},{},", client, tx),
+            _ => format!("chargeback,{},{},", client, tx),
+        };
+
+        text.push_str(&line);
+        text.push('\n');
+    }
+
+    text
+}
+
+/*
+An amount with at most four decimal places, as a decimal.
+*/
+fn decimal(amount: Ratio) -> String {
+    let units = amount.numerator * 10_000 / amount.denominator;
+
+    format!("{}.{:04}", units / 10_000, units % 10_000)
+}
@@ -1,6 +1,20 @@
-use serde::Deserialize;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    num::ParseIntError,
+    str::FromStr,
+};
 
-use crate::{Money, MoneyParseError};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    dates::{Date, DateBasis, DateParseError},
+    errors::{ErrorCode, RowError},
+    Money, MoneyParseError,
+};
 
 #[derive(Deserialize, PartialEq, Eq, Debug)]
 pub struct TransactionText {
@@ -13,22 +27,424 @@ pub struct TransactionText {
     #[serde(rename = "tx")]
     transaction_id: String,
     amount: Option<String>,
+
+    #[serde(default)]
+    tenant: Option<String>,
+
+    #[serde(default)]
+    date: Option<String>,
+
+    #[serde(default)]
+    value_date: Option<String>,
+
+    /*
+    Who wrote an administrative row, and why -- see audit::AdminAction.  Empty on rows from
+    upstream.
+    */
+    #[serde(default)]
+    operator: Option<String>,
+
+    #[serde(default)]
+    reason: Option<String>,
+
+    /*
+    Free text for people reading the ledger later -- see memo::sanitize for what's kept.
+    */
+    #[serde(default)]
+    memo: Option<String>,
+}
+
+impl TransactionText {
+    /*
+    The input columns, by the names they have in the header.
+    */
+    pub const FIELDS: [&'static str; 10] = [
+        "type",
+        "client",
+        "tx",
+        "amount",
+        "tenant",
+        "date",
+        "value_date",
+        "operator",
+        "reason",
+        "memo",
+    ];
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        match name {
+            "type" => Some(&self.kind),
+            "client" => Some(&self.client_id),
+            "tx" => Some(&self.transaction_id),
+            "amount" => self.amount.as_deref(),
+            "tenant" => self.tenant.as_deref(),
+            "date" => self.date.as_deref(),
+            "value_date" => self.value_date.as_deref(),
+            "operator" => self.operator.as_deref(),
+            "reason" => self.reason.as_deref(),
+            "memo" => self.memo.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn set_field(&mut self, name: &str, value: String) {
+        match name {
+            "type" => self.kind = value,
+            "client" => self.client_id = value,
+            "tx" => self.transaction_id = value,
+            "amount" => self.amount = Some(value),
+            "tenant" => self.tenant = Some(value),
+            "date" => self.date = Some(value),
+            "value_date" => self.value_date = Some(value),
+            "operator" => self.operator = Some(value),
+            "reason" => self.reason = Some(value),
+            "memo" => self.memo = Some(value),
+            _ => panic!("no such transaction field: {}", name),
+        }
+    }
+
+    pub fn tenant(&self) -> &str {
+        self.tenant.as_deref().unwrap_or_default()
+    }
+
+    pub fn date(&self) -> Result<Option<Date>, DateParseError> {
+        self.date.as_deref().map(str::parse).transpose()
+    }
+
+    pub fn value_date(&self) -> Result<Option<Date>, DateParseError> {
+        self.value_date.as_deref().map(str::parse).transpose()
+    }
+
+    /*
+    The date that places this row in time, by `basis`.
+    */
+    pub fn effective_date(&self, basis: DateBasis) -> Result<Option<Date>, DateParseError> {
+        match basis {
+            DateBasis::Booking => self.date(),
+            DateBasis::Value => match self.value_date()? {
+                Some(date) => Ok(Some(date)),
+                None => self.date(),
+            },
+        }
+    }
+
+    pub fn memo(&self) -> Option<&str> {
+        self.memo.as_deref()
+    }
+
+    pub fn operator(&self) -> &str {
+        self.operator.as_deref().unwrap_or_default().trim()
+    }
+
+    /*
+    Checks for everything that would otherwise panic on the way to a TransactionRecord, or in
+    dating the row, and says which column is at fault.  `line` is where the row was read.
+    */
+    pub fn validate(&self, line: u64) -> Result<(), RowError> {
+        let error = |column, code, message| RowError {
+            line,
+            column,
+            code,
+            message,
+        };
+
+        if self.client_id.parse::<ClientId>().is_err() {
+            return Err(error(
+                "client",
+                ErrorCode::InvalidClient,
+                format!("invalid client id: {}", self.client_id),
+            ));
+        }
+
+        if self.transaction_id.parse::<TxId>().is_err() {
+            return Err(error(
+                "tx",
+                ErrorCode::InvalidTx,
+                format!("invalid tx id: {}", self.transaction_id),
+            ));
+        }
+
+        let kind = self.kind.to_lowercase();
+        let amount_parses = match kind.as_str() {
+            "deposit" | "withdrawal" | "opening" | "tombstone" => self
+                .amount
+                .as_deref()
+                .is_none_or(|amount| amount.parse::<Money>().is_ok()),
+            "adjustment" => self
+                .amount
+                .as_deref()
+                .and_then(parse_signed_amount)
+                .is_some(),
+            "dispute" | "resolve" | "chargeback" | "approve" | "open" | "kyc_approved"
+            | "kyc_rejected" => true,
+            _ => {
+                return Err(error(
+                    "type",
+                    ErrorCode::UnknownType,
+                    format!("unknown transaction type: {}", self.kind),
+                ))
+            }
+        };
+
+        if !amount_parses {
+            return Err(error(
+                "amount",
+                ErrorCode::InvalidAmount,
+                format!(
+                    "invalid amount for a {}: {}",
+                    self.kind,
+                    self.amount.as_deref().unwrap_or_default()
+                ),
+            ));
+        }
+
+        if matches!(kind.as_str(), "adjustment" | "approve")
+            && self.operator().parse::<Operator>().is_err()
+        {
+            return Err(error(
+                "operator",
+                ErrorCode::InvalidOperator,
+                format!(
+                    "a {} needs an operator id of 1 to {} bytes, not {:?}",
+                    self.kind,
+                    Operator::MAX_LENGTH,
+                    self.operator()
+                ),
+            ));
+        }
+
+        for (column, date) in [("date", &self.date), ("value_date", &self.value_date)] {
+            if let Some(date) = date.as_deref().filter(|date| date.parse::<Date>().is_err()) {
+                return Err(error(
+                    column,
+                    ErrorCode::InvalidDate,
+                    format!("invalid {}: {}", column, date),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/*
+Client and transaction ids are both small integers, and they sit next to each other in most
+signatures.  Distinct types mean swapping them is a compile error rather than a lookup that
+quietly finds nothing.
+*/
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+#[serde(transparent)]
+pub struct ClientId(pub u16);
+
+/*
+Transaction ids are usually numbers, but payment APIs hand out UUIDs, and mapping those down to
+numbers loses the way back.  A UUID id is kept whole.  The bytes, rather than a u128, keep an id
+at 20 bytes instead of 32 in every map keyed by it.
+
+Numbers sort before UUIDs; the two never compare equal, even for a UUID whose value is small.
+*/
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub enum TxId {
+    Number(u32),
+    Uuid([u8; 16]),
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct TxIdParseError(pub String);
+
+/*
+Which transaction ids an input may use.  With `numeric`, the default, a UUID id is an error, as
+it always was -- downstream systems that store ids as integers can't take one.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum TxIdFormat {
+    #[default]
+    Numeric,
+    Uuid,
+}
+
+impl Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl TxId {
+    pub fn number(&self) -> Option<u32> {
+        match self {
+            TxId::Number(number) => Some(*number),
+            TxId::Uuid(bytes) => None,
+        }
+    }
+}
+
+/*
+A UUID is written in its usual lowercase, hyphenated form, whatever case it was read in.
+*/
+impl Display for TxId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxId::Number(number) => Display::fmt(number, f),
+            TxId::Uuid(bytes) => {
+                for (i, byte) in bytes.iter().enumerate() {
+                    if matches!(i, 4 | 6 | 8 | 10) {
+                        f.write_str("-")?;
+                    }
+                    write!(f, "{:02x}", byte)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for TxIdParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid tx id: {}", self.0)
+    }
+}
+
+impl Error for TxIdParseError {}
+
+impl FromStr for ClientId {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(ClientId)
+    }
+}
+
+/*
+A number, or a UUID as 32 hex digits with the usual hyphens.
+*/
+impl FromStr for TxId {
+    type Err = TxIdParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(number) = s.parse() {
+            return Ok(TxId::Number(number));
+        }
+
+        let error = || TxIdParseError(s.to_owned());
+        let groups: Vec<&str> = s.split('-').collect();
+
+        if groups.iter().map(|group| group.len()).ne([8, 4, 4, 4, 12]) {
+            return Err(error());
+        }
+
+        let digits: String = groups.concat();
+        let mut bytes = [0; 16];
+
+        for (byte, pair) in bytes.iter_mut().zip(digits.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| error())?;
+
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| error())?;
+        }
+
+        Ok(TxId::Uuid(bytes))
+    }
+}
+
+/*
+A numeric id is written as a number, as it always was; a UUID as a string.
+*/
+impl Serialize for TxId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TxId::Number(number) => serializer.serialize_u32(*number),
+            TxId::Uuid(bytes) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TxId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TxIdVisitor;
+
+        impl Visitor<'_> for TxIdVisitor {
+            type Value = TxId;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a numeric or UUID transaction id")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<TxId, E> {
+                u32::try_from(value)
+                    .map(TxId::Number)
+                    .map_err(|_| E::custom(TxIdParseError(value.to_string())))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<TxId, E> {
+                u32::try_from(value)
+                    .map(TxId::Number)
+                    .map_err(|_| E::custom(TxIdParseError(value.to_string())))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<TxId, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(TxIdVisitor)
+    }
+}
+
+impl TxIdFormat {
+    pub fn allows(&self, transaction_id: TxId) -> bool {
+        *self == TxIdFormat::Uuid || transaction_id.number().is_some()
+    }
+}
+
+impl FromStr for TxIdFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "numeric" => Ok(TxIdFormat::Numeric),
+            "uuid" => Ok(TxIdFormat::Uuid),
+            _ => Err(()),
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct Id {
-    pub client_id: u16,
-    pub transaction_id: u32,
+    pub client_id: ClientId,
+    pub transaction_id: TxId,
 }
 
-impl Into<TransactionRecord> for TransactionText {
-    fn into(self) -> TransactionRecord {
-        let kind = self.kind.to_lowercase();
+/*
+An adjustment's amount, which may carry a single sign: whether it's negative, and how much.  A
+second sign makes it malformed rather than cancelling or doubling the first.
+*/
+pub fn parse_signed_amount(text: &str) -> Option<(bool, Money)> {
+    let (negative, magnitude) = match text.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+
+    if magnitude.trim_start().starts_with(['-', '+']) {
+        return None;
+    }
+
+    magnitude
+        .parse()
+        .ok()
+        .map(|amount: Money| (negative, amount))
+}
+
+impl From<TransactionText> for TransactionRecord {
+    fn from(text: TransactionText) -> TransactionRecord {
+        let kind = text.kind.to_lowercase();
         let id = Id {
-            client_id: self.client_id.parse().unwrap(),
-            transaction_id: self.transaction_id.parse().unwrap(),
+            client_id: text.client_id.parse().unwrap(),
+            transaction_id: text.transaction_id.parse().unwrap(),
         };
-        let amount: Result<Money, MoneyParseError> = match self.amount {
+        let claimed_amount: Option<Money> = text.amount.as_ref().and_then(|text| text.parse().ok());
+        let signed_amount = text.amount.clone().unwrap_or_default();
+        let operator = text.operator().parse::<Operator>();
+        let amount: Result<Money, MoneyParseError> = match text.amount {
             Some(text) => text.parse(),
             None => Ok(Money::zero()),
         };
@@ -42,21 +458,170 @@ impl Into<TransactionRecord> for TransactionText {
                 id,
                 amount: amount.unwrap(),
             },
-            "dispute" => TransactionRecord::Dispute { id },
-            "resolve" => TransactionRecord::Resolve { id },
-            "chargeback" => TransactionRecord::Chargeback { id },
+            "dispute" => TransactionRecord::Dispute {
+                id,
+                amount: claimed_amount,
+            },
+            "resolve" => TransactionRecord::Resolve {
+                id,
+                amount: claimed_amount,
+            },
+            "chargeback" => TransactionRecord::Chargeback {
+                id,
+                amount: claimed_amount,
+            },
+            "opening" => TransactionRecord::Opening {
+                id,
+                amount: amount.unwrap(),
+            },
+            "tombstone" => TransactionRecord::Tombstone {
+                id,
+                amount: amount.unwrap(),
+            },
+            "adjustment" => {
+                let (negative, amount) = parse_signed_amount(&signed_amount).unwrap();
+
+                TransactionRecord::Adjustment {
+                    id,
+                    amount,
+                    negative,
+                    operator: operator.unwrap(),
+                }
+            }
+            "approve" => TransactionRecord::Approve {
+                id,
+                operator: operator.unwrap(),
+            },
+            "open" => TransactionRecord::Lifecycle {
+                id,
+                event: LifecycleEvent::Open,
+            },
+            "kyc_approved" => TransactionRecord::Lifecycle {
+                id,
+                event: LifecycleEvent::KycApproved,
+            },
+            "kyc_rejected" => TransactionRecord::Lifecycle {
+                id,
+                event: LifecycleEvent::KycRejected,
+            },
             _ => todo!("Add error handling"),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum TransactionRecord {
-    Deposit { id: Id, amount: Money },
-    Withdrawl { id: Id, amount: Money },
-    Dispute { id: Id },
-    Resolve { id: Id },
-    Chargeback { id: Id },
+    Deposit {
+        id: Id,
+        amount: Money,
+    },
+    Withdrawl {
+        id: Id,
+        amount: Money,
+    },
+
+    /*
+    Disputes and friends take their amount from the transaction they reference.  Any amount on
+    the row itself is only kept so the database can decide what to make of it -- see
+    accounts::DisputeAmountPolicy.
+    */
+    Dispute {
+        id: Id,
+        amount: Option<Money>,
+    },
+    Resolve {
+        id: Id,
+        amount: Option<Money>,
+    },
+    Chargeback {
+        id: Id,
+        amount: Option<Money>,
+    },
+
+    /*
+    An opening balance credited straight to available funds, standing in for history that
+    has been compacted away -- see compact::compact.  Like a tombstone, it can't be disputed.
+    */
+    Opening {
+        id: Id,
+        amount: Money,
+    },
+
+    /*
+    Marks a client as erased.  The amount is whatever balance they held in transactions that
+    have since been removed from the log -- see erase::erase_client.
+    */
+    Tombstone {
+        id: Id,
+        amount: Money,
+    },
+
+    /*
+    A manual correction by an operator, credited to or (if negative) debited from available
+    funds.  Unless the database trusts its input, an adjustment waits for an `approve` row with
+    the same transaction id from a different operator before it applies.
+    */
+    Adjustment {
+        id: Id,
+        amount: Money,
+        negative: bool,
+        operator: Operator,
+    },
+    Approve {
+        id: Id,
+        operator: Operator,
+    },
+
+    /*
+    A step in the client's life outside of money: the account being opened, or the outcome of
+    a KYC check.  These move no funds, but may gate deposits and withdrawals -- see
+    accounts::LifecyclePolicy.
+    */
+    Lifecycle {
+        id: Id,
+        event: LifecycleEvent,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LifecycleEvent {
+    Open,
+    KycApproved,
+    KycRejected,
+}
+
+/*
+The operator id on an adjustment or approval, kept inline rather than in a String so the
+records that carry one stay Copy.  Ids are compared byte for byte, and must be 1 to MAX_LENGTH
+bytes long.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Operator {
+    length: u8,
+    bytes: [u8; Operator::MAX_LENGTH],
+}
+
+impl Operator {
+    pub const MAX_LENGTH: usize = 32;
+}
+
+impl FromStr for Operator {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.len() > Operator::MAX_LENGTH {
+            return Err(());
+        }
+
+        let mut bytes = [0; Operator::MAX_LENGTH];
+
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+
+        Ok(Operator {
+            length: s.len() as u8,
+            bytes,
+        })
+    }
 }
 
 impl TransactionRecord {
@@ -64,9 +629,50 @@ impl TransactionRecord {
         *match &self {
             TransactionRecord::Deposit { id, amount } => id,
             TransactionRecord::Withdrawl { id, amount } => id,
-            TransactionRecord::Dispute { id } => id,
-            TransactionRecord::Resolve { id } => id,
-            TransactionRecord::Chargeback { id } => id,
+            TransactionRecord::Dispute { id, amount } => id,
+            TransactionRecord::Resolve { id, amount } => id,
+            TransactionRecord::Chargeback { id, amount } => id,
+            TransactionRecord::Opening { id, amount } => id,
+            TransactionRecord::Tombstone { id, amount } => id,
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } => id,
+            TransactionRecord::Approve { id, operator } => id,
+            TransactionRecord::Lifecycle { id, event } => id,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransactionRecord::Deposit { id, amount } => "deposit",
+            TransactionRecord::Withdrawl { id, amount } => "withdrawal",
+            TransactionRecord::Dispute { id, amount } => "dispute",
+            TransactionRecord::Resolve { id, amount } => "resolve",
+            TransactionRecord::Chargeback { id, amount } => "chargeback",
+            TransactionRecord::Opening { id, amount } => "opening",
+            TransactionRecord::Tombstone { id, amount } => "tombstone",
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } => "adjustment",
+            TransactionRecord::Approve { id, operator } => "approve",
+            TransactionRecord::Lifecycle {
+                id,
+                event: LifecycleEvent::Open,
+            } => "open",
+            TransactionRecord::Lifecycle {
+                id,
+                event: LifecycleEvent::KycApproved,
+            } => "kyc_approved",
+            TransactionRecord::Lifecycle {
+                id,
+                event: LifecycleEvent::KycRejected,
+            } => "kyc_rejected",
         }
     }
 
@@ -74,9 +680,19 @@ impl TransactionRecord {
         match self {
             TransactionRecord::Deposit { id, amount } => *amount,
             TransactionRecord::Withdrawl { id, amount } => *amount,
-            TransactionRecord::Dispute { id } => Money::zero(),
-            TransactionRecord::Resolve { id } => Money::zero(),
-            TransactionRecord::Chargeback { id } => Money::zero(),
+            TransactionRecord::Dispute { id, amount } => Money::zero(),
+            TransactionRecord::Resolve { id, amount } => Money::zero(),
+            TransactionRecord::Chargeback { id, amount } => Money::zero(),
+            TransactionRecord::Opening { id, amount } => *amount,
+            TransactionRecord::Tombstone { id, amount } => *amount,
+            TransactionRecord::Adjustment {
+                id,
+                amount,
+                negative,
+                operator,
+            } => *amount,
+            TransactionRecord::Approve { id, operator } => Money::zero(),
+            TransactionRecord::Lifecycle { id, event } => Money::zero(),
         }
     }
 }
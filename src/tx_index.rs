@@ -0,0 +1,96 @@
+use std::{error::Error, io};
+
+use csv::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tenants::TenantDatabase,
+    transactions::{ClientId, Id, TransactionRecord, TxId},
+    Money,
+};
+
+/*
+One deposit or withdrawal that later disputes may still reference.  `disputed` is what its
+dispute holds, empty unless it's currently under dispute.
+
+An index is all a day's run needs to know about earlier days to resolve disputes correctly --
+far smaller than the history it stands in for, since it keeps no balances or dispute rows.
+*/
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct TxIndexEntry {
+    pub tenant: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub amount: String,
+    pub disputed: Option<String>,
+}
+
+/*
+Writes the index of every tenant in the database.
+*/
+pub fn export_tx_index<W: io::Write>(
+    database: &TenantDatabase,
+    writer: &mut Writer<W>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+
+    for (tenant, accounts) in database.tenants() {
+        for (transaction, disputed_amount) in accounts.indexed_transactions() {
+            writer.serialize(TxIndexEntry {
+                tenant: tenant.to_owned(),
+                kind: transaction.kind().to_owned(),
+                client: transaction.id().client_id,
+                tx: transaction.id().transaction_id,
+                amount: transaction.amount().to_string(),
+                disputed: disputed_amount.map(|amount| amount.to_string()),
+            })?;
+            count += 1;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/*
+Loads an index written by `export_tx_index` into the database, ahead of the transactions that
+may reference it.
+*/
+pub fn load_tx_index<R: io::Read>(
+    database: &mut TenantDatabase,
+    reader: &mut Reader<R>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+
+    for record_result in reader.deserialize() {
+        let entry: TxIndexEntry = record_result?;
+        let id = Id {
+            client_id: entry.client,
+            transaction_id: entry.tx,
+        };
+        let amount: Money = entry
+            .amount
+            .parse()
+            .map_err(|_| format!("invalid amount in tx index: {}", entry.amount))?;
+        let transaction = match entry.kind.as_str() {
+            "deposit" => TransactionRecord::Deposit { id, amount },
+            "withdrawal" => TransactionRecord::Withdrawl { id, amount },
+            kind => return Err(format!("unexpected type in tx index: {}", kind).into()),
+        };
+        let disputed_amount = match entry.disputed.as_deref() {
+            None | Some("") => None,
+            Some(text) => Some(
+                text.parse()
+                    .map_err(|_| format!("invalid disputed amount in tx index: {}", text))?,
+            ),
+        };
+
+        database.index(&entry.tenant, &transaction, disputed_amount);
+        count += 1;
+    }
+
+    Ok(count)
+}
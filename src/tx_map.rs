@@ -0,0 +1,152 @@
+use std::{cell::RefCell, collections::HashMap, error::Error, io, rc::Rc};
+
+use csv::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    middleware::TransactionMiddleware,
+    transactions::{TransactionText, TxId},
+};
+
+/*
+One upstream reference and the tx id it was given, as stored in the map file.
+*/
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct TxMapRow {
+    pub tenant: String,
+    pub reference: String,
+    pub tx: TxId,
+}
+
+#[derive(Default)]
+struct References {
+    ids: HashMap<(String, String), TxId>,
+
+    /*
+    The last id given out in each tenant.
+    */
+    last: HashMap<String, u32>,
+}
+
+/*
+Translates upstream transaction references -- UUIDs and other strings that don't fit a TxId --
+into the engine's numeric ids.  Every tx value is treated as a reference, numeric or not, so a
+reference that happens to look like a number can't collide with an id given to another.
+
+A reference seen for the first time gets the next id after the highest in its tenant, so the
+same map and the same input always produce the same ids.  A dispute quoting the reference of
+the deposit it disputes gets the deposit's id, exactly as with numeric ids.
+
+Copies share one map, so a second pass over the input (the sandbox, the canary) sees the ids the
+first pass gave out, and the map can be written out once every pass is done.
+*/
+#[derive(Clone, Default)]
+pub struct TxMap {
+    references: Rc<RefCell<References>>,
+}
+
+impl TxMap {
+    pub fn new() -> TxMap {
+        TxMap::default()
+    }
+
+    /*
+    Loads a map written by an earlier run.  Within a tenant, a reference may only map to one id
+    and an id to one reference.
+    */
+    pub fn load<R: io::Read>(reader: &mut Reader<R>) -> Result<TxMap, Box<dyn Error>> {
+        let map = TxMap::new();
+        let mut references = map.references.borrow_mut();
+        let mut taken = HashMap::new();
+
+        for row_result in reader.deserialize() {
+            let row: TxMapRow = row_result?;
+
+            if let Some(reference) =
+                taken.insert((row.tenant.clone(), row.tx), row.reference.clone())
+            {
+                return Err(format!(
+                    "tx {} is mapped to both {} and {}",
+                    row.tx, reference, row.reference
+                )
+                .into());
+            }
+
+            let last = references.last.entry(row.tenant.clone()).or_default();
+
+            *last = (*last).max(row.tx.number().unwrap_or_default());
+
+            if references
+                .ids
+                .insert((row.tenant, row.reference.clone()), row.tx)
+                .is_some()
+            {
+                return Err(format!("{} is mapped more than once", row.reference).into());
+            }
+        }
+
+        drop(references);
+
+        Ok(map)
+    }
+
+    /*
+    The id for a reference, giving it the next free one if it's new.
+    */
+    pub fn id(&self, tenant: &str, reference: &str) -> TxId {
+        let mut references = self.references.borrow_mut();
+        let key = (tenant.to_owned(), reference.to_owned());
+
+        if let Some(&id) = references.ids.get(&key) {
+            return id;
+        }
+
+        let last = references.last.entry(tenant.to_owned()).or_default();
+
+        *last = last
+            .checked_add(1)
+            .expect("ran out of tx ids for new references");
+
+        let id = TxId::Number(*last);
+
+        references.ids.insert(key, id);
+        id
+    }
+
+    /*
+    Writes every mapping, in tenant and id order -- both the map to load next time, and the
+    way back from an id in the output to the upstream reference.
+    */
+    pub fn write<W: io::Write>(&self, writer: &mut Writer<W>) -> csv::Result<()> {
+        let references = self.references.borrow();
+        let mut rows: Vec<TxMapRow> = references
+            .ids
+            .iter()
+            .map(|((tenant, reference), &tx)| TxMapRow {
+                tenant: tenant.clone(),
+                reference: reference.clone(),
+                tx,
+            })
+            .collect();
+
+        rows.sort_by(|left, right| (&left.tenant, left.tx).cmp(&(&right.tenant, right.tx)));
+
+        for row in rows {
+            writer.serialize(row)?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl TransactionMiddleware for TxMap {
+    fn process(&self, mut transaction: TransactionText) -> Option<TransactionText> {
+        let id = self.id(transaction.tenant(), transaction.field("tx")?.trim());
+
+        transaction.set_field("tx", id.to_string());
+
+        Some(transaction)
+    }
+}